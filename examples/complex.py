@@ -0,0 +1,42 @@
+import surfer
+
+translators = ["ComplexTranslator"]
+
+
+class ComplexTranslator(surfer.Translator):
+    """Splits a value into a real part in the upper half and an imaginary part
+    in the lower half of the bits"""
+
+    name = "Complex (Python)"
+
+    @staticmethod
+    def translates(variable: surfer.VariableMeta):
+        if variable.num_bits is not None and variable.num_bits % 2 == 0:
+            return surfer.TranslationPreference.Yes
+        return surfer.TranslationPreference.No
+
+    @staticmethod
+    def variable_info(variable: surfer.VariableMeta):
+        return surfer.VariableInfo.Compound(
+            subfields=[
+                ("re", surfer.VariableInfo.Bits()),
+                ("im", surfer.VariableInfo.Bits()),
+            ]
+        )
+
+    @staticmethod
+    def translate(variable: surfer.VariableMeta, value: str):
+        half = len(value) // 2
+        subfields = [
+            surfer.SubFieldTranslationResult(
+                name,
+                surfer.TranslationResult(
+                    surfer.ValueRepr.Bits(num_bits=half, bits=bits),
+                    surfer.ValueKind.Normal(),
+                ),
+            )
+            for name, bits in [("re", value[:half]), ("im", value[half:])]
+        ]
+        return surfer.TranslationResult(
+            surfer.ValueRepr.Struct(), surfer.ValueKind.Normal(), subfields
+        )
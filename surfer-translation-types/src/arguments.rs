@@ -0,0 +1,140 @@
+//! Arguments that configure a translator for a single variable, for example the number
+//! of fractional bits of a fixed-point value.
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+/// Description of an argument accepted by a translator.
+#[derive(Clone, Debug)]
+pub struct TranslatorArgument {
+    pub name: String,
+    pub description: String,
+    pub kind: TranslatorArgumentKind,
+    /// Value used if the argument is not set for the variable
+    pub default: TranslatorArgumentValue,
+}
+
+#[derive(Clone, Debug)]
+pub enum TranslatorArgumentKind {
+    Integer {
+        min: i64,
+        max: i64,
+    },
+    Float,
+    /// One of the listed strings
+    Choice(Vec<String>),
+}
+
+#[derive(Clone, Debug, Display, PartialEq, Serialize, Deserialize)]
+pub enum TranslatorArgumentValue {
+    #[display(fmt = "{_0}")]
+    Integer(i64),
+    #[display(fmt = "{_0}")]
+    Float(f64),
+    #[display(fmt = "{_0}")]
+    String(String),
+}
+
+impl TranslatorArgument {
+    pub fn integer(name: &str, description: &str, min: i64, max: i64, default: i64) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            kind: TranslatorArgumentKind::Integer { min, max },
+            default: TranslatorArgumentValue::Integer(default),
+        }
+    }
+
+    pub fn float(name: &str, description: &str, default: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            kind: TranslatorArgumentKind::Float,
+            default: TranslatorArgumentValue::Float(default),
+        }
+    }
+
+    pub fn choice(name: &str, description: &str, choices: &[&str], default: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            kind: TranslatorArgumentKind::Choice(
+                choices.iter().map(|c| (*c).to_string()).collect(),
+            ),
+            default: TranslatorArgumentValue::String(default.to_string()),
+        }
+    }
+
+    /// Parse and validate a value for this argument, e.g. from the command prompt.
+    pub fn parse_value(&self, value: &str) -> Result<TranslatorArgumentValue> {
+        let value = value.trim();
+        match &self.kind {
+            TranslatorArgumentKind::Integer { min, max } => {
+                let v = value
+                    .parse::<i64>()
+                    .with_context(|| format!("{} expects an integer, got '{value}'", self.name))?;
+                if v < *min || v > *max {
+                    bail!("{} must be in the range {min}..={max}, got {v}", self.name);
+                }
+                Ok(TranslatorArgumentValue::Integer(v))
+            }
+            TranslatorArgumentKind::Float => value
+                .parse::<f64>()
+                .map(TranslatorArgumentValue::Float)
+                .with_context(|| format!("{} expects a number, got '{value}'", self.name)),
+            TranslatorArgumentKind::Choice(choices) => {
+                if choices.iter().any(|c| c == value) {
+                    Ok(TranslatorArgumentValue::String(value.to_string()))
+                } else {
+                    bail!("{} must be one of {}", self.name, choices.join(", "))
+                }
+            }
+        }
+    }
+}
+
+/// The arguments set for a variable. Arguments that are not set take the default
+/// value from the [`TranslatorArgument`] describing them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TranslatorArguments(pub Vec<(String, TranslatorArgumentValue)>);
+
+impl TranslatorArguments {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TranslatorArgumentValue> {
+        self.0.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    pub fn set(&mut self, name: &str, value: TranslatorArgumentValue) {
+        match self.0.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value,
+            None => self.0.push((name.to_string(), value)),
+        }
+    }
+
+    pub fn get_integer(&self, name: &str) -> Option<i64> {
+        match self.get(name)? {
+            TranslatorArgumentValue::Integer(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Get a numeric argument. Integers are converted to floats
+    pub fn get_float(&self, name: &str) -> Option<f64> {
+        match self.get(name)? {
+            TranslatorArgumentValue::Float(v) => Some(*v),
+            TranslatorArgumentValue::Integer(v) => Some(*v as f64),
+            TranslatorArgumentValue::String(_) => None,
+        }
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        match self.get(name)? {
+            TranslatorArgumentValue::String(v) => Some(v.as_str()),
+            _ => None,
+        }
+    }
+}
@@ -1,3 +1,4 @@
+mod arguments;
 mod field_ref;
 #[cfg(feature = "pyo3")]
 pub mod python;
@@ -11,7 +12,11 @@ use std::collections::HashMap;
 use derive_more::Display;
 use ecolor::Color32;
 use num::BigUint;
+use serde::{Deserialize, Serialize};
 
+pub use crate::arguments::{
+    TranslatorArgument, TranslatorArgumentKind, TranslatorArgumentValue, TranslatorArguments,
+};
 pub use crate::field_ref::FieldRef;
 pub use crate::result::{
     HierFormatResult, SubFieldFlatTranslationResult, SubFieldTranslationResult, TranslatedValue,
@@ -21,7 +26,7 @@ pub use crate::scope_ref::ScopeRef;
 pub use crate::translator::{translates_all_bit_types, BasicTranslator, Translator};
 pub use crate::variable_ref::VariableRef;
 
-#[derive(Debug, PartialEq, Clone, Display)]
+#[derive(Debug, PartialEq, Clone, Display, Serialize, Deserialize)]
 pub enum VariableValue {
     #[display(fmt = "{_0}")]
     BigUint(BigUint),
@@ -29,7 +34,7 @@ pub enum VariableValue {
     String(String),
 }
 
-#[derive(Clone, PartialEq, Copy, Debug)]
+#[derive(Clone, PartialEq, Copy, Debug, Serialize, Deserialize)]
 pub enum ValueKind {
     Normal,
     Undef,
@@ -40,7 +45,7 @@ pub enum ValueKind {
     Weak,
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Serialize, Deserialize)]
 pub enum TranslationPreference {
     /// This translator prefers translating the variable, so it will be selected
     /// as the default translator for the variable
@@ -52,7 +57,7 @@ pub enum TranslationPreference {
 }
 
 /// Static information about the structure of a variable.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub enum VariableInfo {
     Compound {
         subfields: Vec<(String, VariableInfo)>,
@@ -66,7 +71,7 @@ pub enum VariableInfo {
     Real,
 }
 
-#[derive(Debug, Display, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Display, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum VariableType {
     // VCD-specific types
     #[display(fmt = "event")]
@@ -147,7 +152,7 @@ pub enum VariableType {
     StdULogicVector,
 }
 
-#[derive(Clone, Display, Copy)]
+#[derive(Clone, Display, Copy, Serialize, Deserialize)]
 pub enum VariableDirection {
     #[display(fmt = "unknown")]
     Unknown,
@@ -165,8 +170,9 @@ pub enum VariableDirection {
     Linkage,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct VariableMeta<VarId, ScopeId> {
+    #[serde(bound(deserialize = "VarId: Default, ScopeId: Default", serialize = ""))]
     pub var: VariableRef<VarId, ScopeId>,
     pub num_bits: Option<u32>,
     /// Type of the variable in the HDL (on a best effort basis).
@@ -179,7 +185,7 @@ pub struct VariableMeta<VarId, ScopeId> {
     pub encoding: VariableEncoding,
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum VariableEncoding {
     String,
     Real,
@@ -1,12 +1,24 @@
+use std::collections::HashMap;
+
 use ecolor::Color32;
 
-use crate::ValueKind;
+use crate::{
+    SubFieldTranslationResult, TranslationPreference, TranslationResult, ValueKind, ValueRepr,
+    VariableInfo, VariableMeta,
+};
 
 #[pyo3::pymodule]
 #[pyo3(name = "surfer")]
 pub fn surfer_pyo3_module(m: &pyo3::Bound<'_, pyo3::types::PyModule>) -> pyo3::PyResult<()> {
     m.add_class::<PythonBasicTranslator>().unwrap();
+    m.add_class::<PythonTranslator>().unwrap();
     m.add_class::<PythonValueKind>().unwrap();
+    m.add_class::<PythonValueRepr>().unwrap();
+    m.add_class::<PythonTranslationResult>().unwrap();
+    m.add_class::<PythonSubFieldTranslationResult>().unwrap();
+    m.add_class::<PythonVariableInfo>().unwrap();
+    m.add_class::<PythonTranslationPreference>().unwrap();
+    m.add_class::<PythonVariableMeta>().unwrap();
     Ok(())
 }
 
@@ -14,6 +26,12 @@ pub fn surfer_pyo3_module(m: &pyo3::Bound<'_, pyo3::types::PyModule>) -> pyo3::P
 struct PythonBasicTranslator {}
 // NOTE: No implementation for the PythonBasicTranslator here. Will be done later.
 
+/// Base class of translators producing hierarchical values. Subclasses implement the
+/// static methods `translate(variable, value)`, where `value` is the value as a bit string,
+/// and `variable_info(variable)`. `translates(variable)` is optional.
+#[pyo3::pyclass(name = "Translator", subclass)]
+struct PythonTranslator {}
+
 #[derive(Clone)]
 #[pyo3::pyclass(name = "ValueKind")]
 pub enum PythonValueKind {
@@ -41,3 +59,160 @@ impl From<PythonValueKind> for ValueKind {
         }
     }
 }
+
+/// See [`ValueRepr`]
+#[derive(Clone)]
+#[pyo3::pyclass(name = "ValueRepr")]
+pub enum PythonValueRepr {
+    Bit { value: char },
+    Bits { num_bits: u64, bits: String },
+    String { value: String },
+    Tuple {},
+    Struct {},
+    Enum { idx: usize, name: String },
+    Array {},
+    NotPresent {},
+}
+
+impl From<PythonValueRepr> for ValueRepr {
+    fn from(value: PythonValueRepr) -> Self {
+        match value {
+            PythonValueRepr::Bit { value } => ValueRepr::Bit(value),
+            PythonValueRepr::Bits { num_bits, bits } => ValueRepr::Bits(num_bits, bits),
+            PythonValueRepr::String { value } => ValueRepr::String(value),
+            PythonValueRepr::Tuple {} => ValueRepr::Tuple,
+            PythonValueRepr::Struct {} => ValueRepr::Struct,
+            PythonValueRepr::Enum { idx, name } => ValueRepr::Enum { idx, name },
+            PythonValueRepr::Array {} => ValueRepr::Array,
+            PythonValueRepr::NotPresent {} => ValueRepr::NotPresent,
+        }
+    }
+}
+
+#[derive(Clone)]
+#[pyo3::pyclass(name = "TranslationResult", get_all, set_all)]
+pub struct PythonTranslationResult {
+    pub val: PythonValueRepr,
+    pub subfields: Vec<PythonSubFieldTranslationResult>,
+    pub kind: PythonValueKind,
+}
+
+#[pyo3::pymethods]
+impl PythonTranslationResult {
+    #[new]
+    #[pyo3(signature = (val, kind, subfields = vec![]))]
+    fn new(
+        val: PythonValueRepr,
+        kind: PythonValueKind,
+        subfields: Vec<PythonSubFieldTranslationResult>,
+    ) -> Self {
+        Self {
+            val,
+            subfields,
+            kind,
+        }
+    }
+}
+
+impl From<PythonTranslationResult> for TranslationResult {
+    fn from(value: PythonTranslationResult) -> Self {
+        TranslationResult {
+            val: value.val.into(),
+            subfields: value
+                .subfields
+                .into_iter()
+                .map(|s| SubFieldTranslationResult::new(s.name, s.result.into()))
+                .collect(),
+            kind: value.kind.into(),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[pyo3::pyclass(name = "SubFieldTranslationResult", get_all, set_all)]
+pub struct PythonSubFieldTranslationResult {
+    pub name: String,
+    pub result: PythonTranslationResult,
+}
+
+#[pyo3::pymethods]
+impl PythonSubFieldTranslationResult {
+    #[new]
+    fn new(name: String, result: PythonTranslationResult) -> Self {
+        Self { name, result }
+    }
+}
+
+/// See [`VariableInfo`]
+#[derive(Clone)]
+#[pyo3::pyclass(name = "VariableInfo")]
+pub enum PythonVariableInfo {
+    Compound {
+        subfields: Vec<(String, PythonVariableInfo)>,
+    },
+    Bits {},
+    Bool {},
+    Clock {},
+    String {},
+    Real {},
+}
+
+impl From<PythonVariableInfo> for VariableInfo {
+    fn from(value: PythonVariableInfo) -> Self {
+        match value {
+            PythonVariableInfo::Compound { subfields } => VariableInfo::Compound {
+                subfields: subfields
+                    .into_iter()
+                    .map(|(name, info)| (name, info.into()))
+                    .collect(),
+            },
+            PythonVariableInfo::Bits {} => VariableInfo::Bits,
+            PythonVariableInfo::Bool {} => VariableInfo::Bool,
+            PythonVariableInfo::Clock {} => VariableInfo::Clock,
+            PythonVariableInfo::String {} => VariableInfo::String,
+            PythonVariableInfo::Real {} => VariableInfo::Real,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+#[pyo3::pyclass(name = "TranslationPreference")]
+pub enum PythonTranslationPreference {
+    Prefer,
+    Yes,
+    No,
+}
+
+impl From<PythonTranslationPreference> for TranslationPreference {
+    fn from(value: PythonTranslationPreference) -> Self {
+        match value {
+            PythonTranslationPreference::Prefer => TranslationPreference::Prefer,
+            PythonTranslationPreference::Yes => TranslationPreference::Yes,
+            PythonTranslationPreference::No => TranslationPreference::No,
+        }
+    }
+}
+
+/// The parts of [`VariableMeta`] that are visible to Python translators
+#[derive(Clone)]
+#[pyo3::pyclass(name = "VariableMeta", get_all)]
+pub struct PythonVariableMeta {
+    pub name: String,
+    /// Names of the scopes containing the variable
+    pub path: Vec<String>,
+    pub num_bits: Option<u32>,
+    pub variable_type: Option<String>,
+    pub enum_map: HashMap<String, String>,
+}
+
+impl<VarId, ScopeId> From<&VariableMeta<VarId, ScopeId>> for PythonVariableMeta {
+    fn from(value: &VariableMeta<VarId, ScopeId>) -> Self {
+        Self {
+            name: value.var.name.clone(),
+            path: value.var.path.strs.clone(),
+            num_bits: value.num_bits,
+            variable_type: value.variable_type.map(|t| t.to_string()),
+            enum_map: value.enum_map.clone(),
+        }
+    }
+}
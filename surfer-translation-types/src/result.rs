@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::ValueKind;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TranslationResult {
     pub val: ValueRepr,
     pub subfields: Vec<SubFieldTranslationResult>,
@@ -9,7 +11,7 @@ pub struct TranslationResult {
 
 /// The representation of the value, compound values can be
 /// be represented by the repr of their subfields
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum ValueRepr {
     Bit(char),
     /// The value is `.0` raw bits, and can be translated by further translators
@@ -59,7 +61,7 @@ impl HierFormatResult {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SubFieldTranslationResult {
     pub name: String,
     pub result: TranslationResult,
@@ -4,6 +4,7 @@ use color_eyre::Result;
 use num::BigUint;
 use std::sync::mpsc::Sender;
 
+use crate::arguments::{TranslatorArgument, TranslatorArguments};
 use crate::result::TranslationResult;
 use crate::{
     TranslationPreference, ValueKind, VariableEncoding, VariableInfo, VariableMeta, VariableValue,
@@ -28,6 +29,23 @@ pub trait Translator<VarId, ScopeId, Message>: Send + Sync {
     /// do by defining this method.
     /// Long running translators should run the reloading in the background using `perform_work`
     fn reload(&self, _sender: Sender<Message>) {}
+
+    /// Arguments which can be set per variable to configure the translation.
+    /// Translators without arguments do not have to implement this.
+    fn arguments(&self) -> Vec<TranslatorArgument> {
+        vec![]
+    }
+
+    /// Translate the value using the arguments set for the variable. Arguments which
+    /// are not set should use the default from [`Translator::arguments`]
+    fn translate_with_arguments(
+        &self,
+        variable: &VariableMeta<VarId, ScopeId>,
+        value: &VariableValue,
+        _arguments: &TranslatorArguments,
+    ) -> Result<TranslationResult> {
+        self.translate(variable, value)
+    }
 }
 
 /// A translator that only produces non-hierarchical values
@@ -43,6 +61,21 @@ pub trait BasicTranslator<VarId, ScopeId>: Send + Sync {
     fn variable_info(&self, _variable: &VariableMeta<VarId, ScopeId>) -> Result<VariableInfo> {
         Ok(VariableInfo::Bits)
     }
+
+    /// See [`Translator::arguments`]
+    fn arguments(&self) -> Vec<TranslatorArgument> {
+        vec![]
+    }
+
+    /// See [`Translator::translate_with_arguments`]
+    fn basic_translate_with_arguments(
+        &self,
+        num_bits: u64,
+        value: &VariableValue,
+        _arguments: &TranslatorArguments,
+    ) -> (String, ValueKind) {
+        self.basic_translate(num_bits, value)
+    }
 }
 
 enum NumberParseResult {
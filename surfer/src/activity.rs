@@ -0,0 +1,141 @@
+//! Density of value changes over the full time range, shown as a heatmap in the overview so
+//! that busy regions are easy to find.
+use num::BigInt;
+
+use crate::displayed_item::DisplayedItem;
+use crate::message::Message;
+use crate::wasm_util::perform_work;
+use crate::wave_container::VariableRef;
+use crate::wave_data::WaveData;
+use crate::State;
+
+/// Number of time ranges the changes are counted in
+pub const ACTIVITY_BINS: usize = 512;
+
+/// The variables and time range the activity was counted for
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivityKey {
+    variables: Vec<VariableRef>,
+    num_timestamps: BigInt,
+}
+
+#[derive(Debug, Clone)]
+pub struct Activity {
+    pub key: ActivityKey,
+    /// Number of changes in each of the [`ACTIVITY_BINS`] bins. Empty while being counted.
+    pub counts: Vec<u64>,
+}
+
+/// Counts the changes in each bin. `bin_starts` is the position in the time table at which
+/// each bin starts, and the last bin includes everything after its start. The first entry of
+/// each variable is its initial value, which is not counted as a change.
+fn count_changes(changes: &[Vec<u32>], bin_starts: &[usize]) -> Vec<u64> {
+    let mut counts = vec![0; bin_starts.len()];
+    for idx in changes.iter().flat_map(|indices| indices.iter().skip(1)) {
+        let bin = bin_starts.partition_point(|start| *start <= *idx as usize);
+        if let Some(count) = bin.checked_sub(1).and_then(|bin| counts.get_mut(bin)) {
+            *count += 1;
+        }
+    }
+    counts
+}
+
+impl WaveData {
+    /// The activity is shown for the variables of the focused item, or for the variables of
+    /// all displayed items if no variable or derived signal is focused
+    pub fn activity_key(&self) -> ActivityKey {
+        let item_variables = |item: &DisplayedItem| match item {
+            DisplayedItem::Variable(variable) => vec![variable.variable_ref.clone()],
+            DisplayedItem::Derived(derived) => {
+                derived.expr.variables().into_iter().cloned().collect()
+            }
+            _ => vec![],
+        };
+        let focused = self
+            .focused_item
+            .and_then(|idx| self.displayed_items_order.get(idx.0))
+            .and_then(|id| self.displayed_items.get(id))
+            .map(item_variables)
+            .filter(|variables| !variables.is_empty());
+        let all_variables = || {
+            self.displayed_items_order
+                .iter()
+                .filter_map(|id| self.displayed_items.get(id))
+                .flat_map(item_variables)
+                .collect()
+        };
+
+        let mut variables: Vec<VariableRef> = vec![];
+        for variable in focused.unwrap_or_else(all_variables) {
+            if !variables.contains(&variable) {
+                variables.push(variable);
+            }
+        }
+        ActivityKey {
+            variables,
+            num_timestamps: self.num_timestamps(),
+        }
+    }
+}
+
+impl State {
+    /// Starts counting the activity of the current [`ActivityKey`] in the background. The
+    /// result is delivered by [`Message::ActivityCounted`].
+    pub fn count_activity(&mut self) {
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        let key = waves.activity_key();
+        waves.activity = Some(Activity {
+            key: key.clone(),
+            counts: vec![],
+        });
+        let Some(wave_container) = waves.inner.as_waves() else {
+            return;
+        };
+
+        let num_timestamps = key.num_timestamps.to_biguint().unwrap_or_default();
+        let Some(bin_starts) = (0..ACTIVITY_BINS)
+            .map(|bin| wave_container.time_table_position(&(&num_timestamps * bin / ACTIVITY_BINS)))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+        let changes = key
+            .variables
+            .iter()
+            .filter_map(|variable| wave_container.change_time_indices(variable))
+            .collect::<Vec<_>>();
+        if changes.is_empty() {
+            return;
+        }
+
+        let sender = self.sys.channels.msg_sender.clone();
+        perform_work(move || {
+            let counts = count_changes(&changes, &bin_starts);
+            sender.send(Message::ActivityCounted(key, counts)).unwrap();
+        });
+    }
+
+    pub fn on_activity_counted(&mut self, key: ActivityKey, counts: Vec<u64>) {
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        // the displayed variables may have changed while counting
+        if let Some(activity) = waves.activity.as_mut().filter(|a| a.key == key) {
+            activity.counts = counts;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changes_are_counted_in_their_bins() {
+        let changes = vec![vec![0, 1, 2, 7, 9], vec![0, 3, 4, 8]];
+        let counts = count_changes(&changes, &[0, 3, 3, 6]);
+        assert_eq!(counts, vec![2, 0, 2, 3]);
+    }
+}
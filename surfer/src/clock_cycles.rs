@@ -0,0 +1,332 @@
+//! Counting time in cycles of a clock. When a cycle clock is set, the time axis, the cursor
+//! and marker times show the cycle a time is in rather than the time itself.
+//!
+//! Cycle `N` starts at the `N`th rising edge of the clock, so everything before the first
+//! rising edge is cycle 0.
+//!
+//! The rising edges are found on a worker thread, and are found again when the values have
+//! changed, e.g. while following a file that is still being written. They are shared with
+//! the protocol decoders and exports that sample on the same clock.
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use num::{BigInt, BigUint, ToBigInt, Zero};
+use serde::{Deserialize, Serialize};
+use surfer_translation_types::VariableValue;
+
+use crate::config::SurferConfig;
+use crate::displayed_item::DisplayedItem;
+use crate::message::Message;
+use crate::time::{time_string, TimeFormat, TimeUnit};
+use crate::viewport::Viewport;
+use crate::wave_container::{VariableRef, WaveContainer};
+use crate::wave_data::WaveData;
+use crate::State;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClockCycles {
+    pub clock: VariableRef,
+}
+
+impl ClockCycles {
+    pub fn new(clock: VariableRef) -> Self {
+        Self { clock }
+    }
+}
+
+/// The rising edges of a clock, see [`WaveData::found_clock_edges`]
+#[derive(Debug, Default)]
+pub struct ClockEdges {
+    /// Times of the rising edges. They are kept while they are found again after the values
+    /// have changed.
+    pub edges: Arc<Vec<BigUint>>,
+    /// The [`WaveData::values_version`] the edges were found for
+    found_version: Option<u64>,
+    /// The edges are being found on a worker thread
+    searching: bool,
+    /// Messages that are handled once the edges have been found for the current values
+    waiting: Vec<Message>,
+}
+
+impl ClockEdges {
+    /// The cycle that `time` is in
+    pub fn cycle_at(&self, time: &BigUint) -> usize {
+        self.edges.partition_point(|edge| edge <= time)
+    }
+
+    /// The time at which `cycle` starts
+    pub fn cycle_time(&self, cycle: usize) -> Option<BigUint> {
+        match cycle {
+            0 => Some(BigUint::zero()),
+            _ => self.edges.get(cycle - 1).cloned(),
+        }
+    }
+}
+
+/// The times at which `clock` goes high, `None` if the clock is not loaded. Walks all
+/// changes of the clock, so it is only called on worker threads.
+pub fn rising_edges(waves: &WaveContainer, clock: &VariableRef) -> Option<Vec<BigUint>> {
+    let is_high = |value: &VariableValue| match value {
+        VariableValue::BigUint(v) => !v.is_zero(),
+        VariableValue::String(s) => s == "1",
+    };
+    let mut edges = vec![];
+    let mut result = waves.query_variable(clock, &BigUint::zero()).ok()??;
+    let mut high = result.current.as_ref().is_some_and(|(_, v)| is_high(v));
+    while let Some(time) = result.next {
+        result = waves.query_variable(clock, &time).ok()??;
+        let now_high = result.current.as_ref().is_some_and(|(_, v)| is_high(v));
+        if now_high && !high {
+            edges.push(time);
+        }
+        high = now_high;
+    }
+    Some(edges)
+}
+
+impl WaveData {
+    /// The clocks whose rising edges are needed, by the cycle clock, the protocol decoders
+    /// or waiting exports
+    fn sampling_clocks(&self) -> HashSet<&VariableRef> {
+        let decoder_clocks = self.displayed_items.values().filter_map(|item| match item {
+            DisplayedItem::Decoder(decoder) => decoder.decoder.sampling_clock(),
+            _ => None,
+        });
+        let waiting_clocks = self
+            .clock_edges
+            .iter()
+            .filter(|(_, edges)| !edges.waiting.is_empty())
+            .map(|(clock, _)| clock);
+        self.cycle_clock
+            .iter()
+            .map(|cycles| &cycles.clock)
+            .chain(decoder_clocks)
+            .chain(waiting_clocks)
+            .collect()
+    }
+
+    /// The loaded clocks whose edges have not been found for the current values, and are not
+    /// being found
+    fn outdated_clocks(&self) -> Vec<VariableRef> {
+        let Some(waves) = self.inner.as_waves() else {
+            return vec![];
+        };
+        self.sampling_clocks()
+            .into_iter()
+            .filter(|clock| {
+                !self.clock_edges.get(*clock).is_some_and(|edges| {
+                    edges.searching || edges.found_version == Some(self.values_version)
+                }) && waves.is_loaded(clock)
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn clock_edges_outdated(&self) -> bool {
+        !self.outdated_clocks().is_empty()
+    }
+
+    /// The rising edges of `clock`, `None` until they have been found. The edges may be
+    /// those of older values while they are found again.
+    pub fn found_clock_edges(&self, clock: &VariableRef) -> Option<&ClockEdges> {
+        self.clock_edges
+            .get(clock)
+            .filter(|edges| edges.found_version.is_some())
+    }
+
+    /// The rising edges of `clock` if they have been found for the current values
+    pub fn current_clock_edges(&self, clock: &VariableRef) -> Option<Arc<Vec<BigUint>>> {
+        self.clock_edges
+            .get(clock)
+            .filter(|edges| edges.found_version == Some(self.values_version))
+            .map(|edges| edges.edges.clone())
+    }
+
+    /// The rising edges of the cycle clock, `None` if it is not set or has no edges
+    pub fn cycle_clock_edges(&self) -> Option<&ClockEdges> {
+        let cycles = self.cycle_clock.as_ref()?;
+        self.found_clock_edges(&cycles.clock)
+            .filter(|edges| !edges.edges.is_empty())
+    }
+
+    /// Formats `time`, as the cycle it is in if a cycle clock is set
+    pub fn time_string(
+        &self,
+        time: &BigInt,
+        wanted_timeunit: &TimeUnit,
+        time_format: &TimeFormat,
+    ) -> String {
+        match self.cycle_clock_edges() {
+            Some(cycles) => format!(
+                "cycle {}",
+                cycles.cycle_at(&time.to_biguint().unwrap_or_default())
+            ),
+            None => time_string(
+                time,
+                &self.inner.metadata().timescale,
+                wanted_timeunit,
+                time_format,
+            ),
+        }
+    }
+
+    /// Formats the time from `from` to `to`, as a number of cycles if a cycle clock is set
+    pub fn time_delta_string(
+        &self,
+        from: &BigInt,
+        to: &BigInt,
+        wanted_timeunit: &TimeUnit,
+        time_format: &TimeFormat,
+    ) -> String {
+        match self.cycle_clock_edges() {
+            Some(cycles) => {
+                let cycle = |time: &BigInt| {
+                    BigInt::from(cycles.cycle_at(&time.to_biguint().unwrap_or_default()))
+                };
+                format!("{} cycles", cycle(to) - cycle(from))
+            }
+            None => time_string(
+                &(to - from),
+                &self.inner.metadata().timescale,
+                wanted_timeunit,
+                time_format,
+            ),
+        }
+    }
+
+    /// Ticks at cycle boundaries, with steps of 1, 2 or 5 times a power of ten cycles
+    pub fn get_cycle_ticks(
+        &self,
+        viewport: &Viewport,
+        frame_width: f32,
+        text_size: f32,
+        config: &SurferConfig,
+    ) -> Option<Vec<(String, f32)>> {
+        let cycles = self.cycle_clock_edges()?;
+        let num_timestamps = self.num_timestamps();
+        let cycle_at_x = |x: f32| {
+            let time = viewport.as_time_bigint(x, frame_width, &num_timestamps);
+            cycles.cycle_at(&time.to_biguint().unwrap_or_default())
+        };
+        let first = cycle_at_x(0.);
+        let last = cycle_at_x(frame_width);
+
+        let char_width = text_size * (20. / 31.);
+        let label_width = (last.to_string().len() + 2) as f32 * char_width;
+        let max_labels =
+            ((frame_width * config.theme.ticks.density) / label_width).max(1.) as usize;
+        let step = (0..)
+            .flat_map(|exponent| [1, 2, 5].map(|m| m * 10usize.pow(exponent)))
+            .find(|step| (last - first) / step < max_labels)?;
+
+        Some(
+            (first.div_ceil(step) * step..=last)
+                .step_by(step)
+                .filter_map(|cycle| {
+                    let time = cycles.cycle_time(cycle)?.to_bigint()?;
+                    Some((
+                        cycle.to_string(),
+                        viewport.pixel_from_time(&time, frame_width, &num_timestamps),
+                    ))
+                })
+                .collect(),
+        )
+    }
+}
+
+impl State {
+    /// Starts finding the rising edges of the clocks whose values have changed. The edges are
+    /// delivered by [`Message::ClockEdgesFound`].
+    pub fn find_clock_edges(&mut self) {
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        let version = waves.values_version;
+        let clocks = waves.outdated_clocks();
+        for clock in &clocks {
+            waves
+                .clock_edges
+                .entry(clock.clone())
+                .or_default()
+                .searching = true;
+        }
+        for clock in clocks {
+            self.perform_work_on_waves(move |waves| {
+                let edges = rising_edges(waves, &clock);
+                Message::ClockEdgesFound(clock, version, edges)
+            });
+        }
+    }
+
+    pub fn on_clock_edges_found(
+        &mut self,
+        clock: VariableRef,
+        version: u64,
+        edges: Option<Vec<BigUint>>,
+    ) {
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        let is_current = version == waves.values_version;
+        let clock_edges = waves.clock_edges.entry(clock).or_default();
+        clock_edges.searching = false;
+        let mut waiting = vec![];
+        if let Some(edges) = edges {
+            clock_edges.edges = Arc::new(edges);
+            clock_edges.found_version = Some(version);
+            if is_current {
+                waiting = std::mem::take(&mut clock_edges.waiting);
+            }
+        }
+        for message in waiting {
+            self.sys.channels.msg_sender.send(message).unwrap();
+        }
+        self.invalidate_draw_commands();
+        // the values may have changed again while the edges were found
+        self.find_clock_edges();
+        self.decode_transactions();
+    }
+
+    /// The rising edges of `clock` for the current values. If they have to be found first,
+    /// `None` is returned and `retry` is handled once they have been found.
+    pub fn current_clock_edges_or_retry(
+        &mut self,
+        clock: &VariableRef,
+        retry: Message,
+    ) -> Option<Arc<Vec<BigUint>>> {
+        let waves = self.waves.as_mut()?;
+        if let Some(edges) = waves.current_clock_edges(clock) {
+            return Some(edges);
+        }
+        waves
+            .clock_edges
+            .entry(clock.clone())
+            .or_default()
+            .waiting
+            .push(retry);
+        self.find_clock_edges();
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cycles_start_at_rising_edges() {
+        let cycles = ClockEdges {
+            edges: Arc::new([5u32, 15, 25].map(BigUint::from).to_vec()),
+            ..Default::default()
+        };
+        let cycle_at = |time: u32| cycles.cycle_at(&BigUint::from(time));
+        assert_eq!(cycle_at(0), 0);
+        assert_eq!(cycle_at(4), 0);
+        assert_eq!(cycle_at(5), 1);
+        assert_eq!(cycle_at(14), 1);
+        assert_eq!(cycle_at(30), 3);
+        assert_eq!(cycles.cycle_time(0), Some(BigUint::zero()));
+        assert_eq!(cycles.cycle_time(2), Some(BigUint::from(15u8)));
+        assert_eq!(cycles.cycle_time(4), None);
+    }
+}
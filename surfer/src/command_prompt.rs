@@ -4,11 +4,14 @@ use std::iter::zip;
 use std::{fs, str::FromStr};
 
 use crate::config::{ArrowKeyBindings, HierarchyStyle};
-use crate::displayed_item::DisplayedItemIndex;
+use crate::displayed_item::{DisplayedFieldRef, DisplayedItemIndex};
 use crate::transaction_container::StreamScopeRef;
+use crate::value_export::parse_export_arguments;
 use crate::wave_container::{ScopeRef, ScopeRefExt, VariableRef, VariableRefExt};
 use crate::wave_data::ScopeType;
+use crate::wave_diff::parse_compare_arguments;
 use crate::wave_source::LoadOptions;
+use crate::wavedrom::parse_wavedrom_arguments;
 use crate::{
     clock_highlighting::ClockHighlightType,
     displayed_item::DisplayedItem,
@@ -25,6 +28,7 @@ use epaint::{FontFamily, FontId};
 use fzcmd::{expand_command, parse_command, Command, FuzzyOutput, ParamGreed, ParseError};
 use itertools::Itertools;
 use log::warn;
+use surfer_translation_types::TranslatorArgumentKind;
 
 type RestCommand = Box<dyn Fn(&str) -> Option<Command<Message>>>;
 
@@ -105,6 +109,34 @@ pub fn get_parser(state: &State) -> Command<Message> {
         })
         .unwrap_or_default();
 
+    // Arguments of the translator used by the focused variable
+    let focused_translator_arguments = state.waves.as_ref().and_then(|waves| {
+        let id = waves.displayed_items_order.get(waves.focused_item?.0)?;
+        match waves.displayed_items.get(id)? {
+            DisplayedItem::Variable(_) => {
+                let field = DisplayedFieldRef::from(*id);
+                let arguments = waves
+                    .variable_translator(&field, &state.sys.translators)
+                    .arguments();
+                Some((field, arguments))
+            }
+            _ => None,
+        }
+    });
+
+    let focused_variable_path = state.waves.as_ref().and_then(|waves| {
+        let id = waves.displayed_items_order.get(waves.focused_item?.0)?;
+        match waves.displayed_items.get(id)? {
+            DisplayedItem::Variable(variable) => Some(variable.variable_ref.full_path_string()),
+            _ => None,
+        }
+    });
+
+    let added_files: Vec<String> = match &state.waves {
+        Some(v) => v.added_files.iter().map(|file| file.name.clone()).collect(),
+        None => vec![],
+    };
+
     let color_names = state.config.theme.colors.keys().cloned().collect_vec();
 
     let active_scope = state.waves.as_ref().and_then(|w| w.active_scope.clone());
@@ -133,6 +165,10 @@ pub fn get_parser(state: &State) -> Command<Message> {
         files_with_ext(is_wave_file_extension)
     }
 
+    fn all_value_mapping_files() -> Vec<String> {
+        files_with_ext(|ext| ext == "toml" || ext == "csv")
+    }
+
     let markers = if let Some(waves) = &state.waves {
         waves
             .displayed_items_order
@@ -148,10 +184,22 @@ pub fn get_parser(state: &State) -> Command<Message> {
     };
 
     let keep_during_reload = state.config.behavior.keep_during_reload;
+    let (follow, follow_pinned) = (state.follow, state.follow_pinned);
     let mut commands = if state.waves.is_some() {
         vec![
             "load_file",
             "switch_file",
+            "file_add",
+            "file_add_url",
+            "file_set_time_offset",
+            "file_remove",
+            "compare",
+            "compare_file",
+            "compare_clear",
+            "difference_next",
+            "difference_previous",
+            "show_differences",
+            "show_protocol_decoder",
             "variable_add",
             "generator_add",
             "item_focus",
@@ -159,6 +207,7 @@ pub fn get_parser(state: &State) -> Command<Message> {
             "item_set_background_color",
             "item_unset_color",
             "item_unset_background_color",
+            "item_set_height",
             "item_unfocus",
             "item_rename",
             "zoom_fit",
@@ -181,23 +230,50 @@ pub fn get_parser(state: &State) -> Command<Message> {
             "scroll_to_end",
             "goto_start",
             "goto_end",
+            "delta_expand",
+            "delta_collapse",
+            "delta_next",
+            "delta_previous",
+            "value_search",
+            "value_search_next",
+            "value_search_previous",
+            "value_search_clear",
+            "show_value_search",
+            "show_measurement",
+            "cycle_clock_set",
+            "cycle_clock_clear",
+            "goto_cycle",
             "zoom_in",
             "zoom_out",
             "toggle_menu",
             "toggle_side_panel",
             "toggle_fullscreen",
             "toggle_tick_lines",
+            "toggle_follow",
+            "toggle_follow_pinned",
             "variable_add_from_scope",
             "generator_add_from_stream",
             "variable_set_name_type",
             "variable_force_name_type",
+            "variable_set_format_argument",
+            "variable_set_value_mapping",
+            "value_mapping_add",
+            "value_mapping_clear",
             "preference_set_clock_highlight",
             "preference_set_hierarchy_style",
             "preference_set_arrow_key_bindings",
             "goto_marker",
             "save_state",
             "save_state_as",
+            "export_values",
+            "export_wavedrom",
+            "gtkw_load",
+            "gtkw_save",
             "timeline_add",
+            "derived_add",
+            "bus_compose",
+            "bus_split",
+            "decoder_add",
             "show_marker_window",
             "viewport_add",
             "viewport_remove",
@@ -276,6 +352,67 @@ pub fn get_parser(state: &State) -> Command<Message> {
                         )))
                     }),
                 )),
+                "file_add" => single_word_delayed_suggestions(
+                    Box::new(all_wave_files),
+                    Box::new(|word| Some(Command::Terminal(Message::AddFile(word.into())))),
+                ),
+                "file_add_url" => Some(Command::NonTerminal(
+                    ParamGreed::Rest,
+                    vec![],
+                    Box::new(|query, _| {
+                        Some(Command::Terminal(Message::AddWaveformFileFromUrl(
+                            query.to_string(),
+                        )))
+                    }),
+                )),
+                "file_set_time_offset" => Some(Command::NonTerminal(
+                    ParamGreed::Word,
+                    added_files.clone(),
+                    Box::new(|name, _| {
+                        let name = name.to_string();
+                        single_word(
+                            vec![],
+                            Box::new(move |word| {
+                                Some(Command::Terminal(Message::SetFileTimeOffset {
+                                    name: name.clone(),
+                                    offset: word.trim().parse().ok()?,
+                                }))
+                            }),
+                        )
+                    }),
+                )),
+                "file_remove" => single_word(
+                    added_files.clone(),
+                    Box::new(|word| Some(Command::Terminal(Message::RemoveFile(word.to_string())))),
+                ),
+                "compare" => single_word(
+                    added_files.clone(),
+                    Box::new(|query| {
+                        let (file, rename_rules) = parse_compare_arguments(query)?;
+                        Some(Command::Terminal(Message::Compare { file, rename_rules }))
+                    }),
+                ),
+                "compare_file" => single_word_delayed_suggestions(
+                    Box::new(all_wave_files),
+                    Box::new(|query| {
+                        let (file, rename_rules) = parse_compare_arguments(query)?;
+                        Some(Command::Terminal(Message::CompareWithFile(
+                            file.into(),
+                            rename_rules,
+                        )))
+                    }),
+                ),
+                "compare_clear" => Some(Command::Terminal(Message::ClearComparison)),
+                "difference_next" => {
+                    Some(Command::Terminal(Message::MoveToDifference { next: true }))
+                }
+                "difference_previous" => {
+                    Some(Command::Terminal(Message::MoveToDifference { next: false }))
+                }
+                "show_differences" => Some(Command::Terminal(Message::SetDifferencesVisible(true))),
+                "show_protocol_decoder" => {
+                    Some(Command::Terminal(Message::SetDecoderWindowVisible(true)))
+                }
                 "config_reload" => Some(Command::Terminal(Message::ReloadConfig)),
                 "theme_select" => single_word(
                     theme_names.clone(),
@@ -291,6 +428,49 @@ pub fn get_parser(state: &State) -> Command<Message> {
                 "scroll_to_end" | "goto_end" => {
                     Some(Command::Terminal(Message::GoToEnd { viewport_idx: 0 }))
                 }
+                "delta_expand" => Some(Command::Terminal(Message::ExpandDeltaCycles(None))),
+                "delta_collapse" => Some(Command::Terminal(Message::CollapseDeltaCycles)),
+                "delta_next" => Some(Command::Terminal(Message::MoveDeltaCursor { next: true })),
+                "delta_previous" => {
+                    Some(Command::Terminal(Message::MoveDeltaCursor { next: false }))
+                }
+                "value_search" => Some(Command::NonTerminal(
+                    ParamGreed::Rest,
+                    vec![],
+                    Box::new(|query, _| {
+                        Some(Command::Terminal(Message::Batch(vec![
+                            Message::SetValueSearch(None, query.to_string()),
+                            Message::MoveToValueMatch { next: true },
+                        ])))
+                    }),
+                )),
+                "value_search_next" => {
+                    Some(Command::Terminal(Message::MoveToValueMatch { next: true }))
+                }
+                "value_search_previous" => {
+                    Some(Command::Terminal(Message::MoveToValueMatch { next: false }))
+                }
+                "value_search_clear" => Some(Command::Terminal(Message::ClearValueSearch)),
+                "show_value_search" => {
+                    Some(Command::Terminal(Message::SetValueSearchVisible(true)))
+                }
+                "show_measurement" => Some(Command::Terminal(Message::SetMeasurementVisible(true))),
+                "cycle_clock_set" => single_word(
+                    variables.clone(),
+                    Box::new(|word| {
+                        Some(Command::Terminal(Message::SetCycleClock(Some(
+                            VariableRef::from_hierarchy_string(word),
+                        ))))
+                    }),
+                ),
+                "cycle_clock_clear" => Some(Command::Terminal(Message::SetCycleClock(None))),
+                "goto_cycle" => single_word(
+                    vec![],
+                    Box::new(|word| {
+                        let cycle = word.trim().trim_start_matches("cycle").trim();
+                        Some(Command::Terminal(Message::GoToCycle(cycle.parse().ok()?)))
+                    }),
+                ),
                 "zoom_in" => Some(Command::Terminal(Message::CanvasZoom {
                     mouse_ptr: None,
                     delta: 0.5,
@@ -306,6 +486,10 @@ pub fn get_parser(state: &State) -> Command<Message> {
                 "toggle_side_panel" => Some(Command::Terminal(Message::ToggleSidePanel)),
                 "toggle_fullscreen" => Some(Command::Terminal(Message::ToggleFullscreen)),
                 "toggle_tick_lines" => Some(Command::Terminal(Message::ToggleTickLines)),
+                "toggle_follow" => Some(Command::Terminal(Message::SetFollow(!follow))),
+                "toggle_follow_pinned" => {
+                    Some(Command::Terminal(Message::SetFollowPinned(!follow_pinned)))
+                }
                 // scope commands
                 "scope_add" | "module_add" | "stream_add" | "scope_add_recursive" => {
                     let recursive = query == "scope_add_recursive";
@@ -425,6 +609,25 @@ pub fn get_parser(state: &State) -> Command<Message> {
                 "item_unset_background_color" => Some(Command::Terminal(
                     Message::ItemBackgroundColorChange(None, None),
                 )),
+                "item_set_height" => single_word(
+                    vec![
+                        "1".to_string(),
+                        "2".to_string(),
+                        "3".to_string(),
+                        "4".to_string(),
+                    ],
+                    Box::new(|word| {
+                        word.parse::<f32>()
+                            .ok()
+                            .filter(|factor| factor.is_finite())
+                            .map(|factor| {
+                                Command::Terminal(Message::Batch(vec![
+                                    Message::ItemHeightChangeStarted,
+                                    Message::ItemHeightScalingFactorChange(None, factor),
+                                ]))
+                            })
+                    }),
+                ),
                 "item_rename" => Some(Command::Terminal(Message::RenameItem(None))),
                 "variable_set_name_type" => single_word(
                     vec![
@@ -451,6 +654,61 @@ pub fn get_parser(state: &State) -> Command<Message> {
                         )))
                     }),
                 ),
+                "variable_set_format_argument" => {
+                    let (field, arguments) = focused_translator_arguments.clone()?;
+                    Some(Command::NonTerminal(
+                        ParamGreed::Word,
+                        arguments.iter().map(|a| a.name.clone()).collect(),
+                        Box::new(move |name, _| {
+                            let argument = arguments.iter().find(|a| a.name == name)?;
+                            let suggestions = match &argument.kind {
+                                TranslatorArgumentKind::Choice(choices) => choices.clone(),
+                                _ => vec![],
+                            };
+                            let field = field.clone();
+                            let name = name.to_string();
+                            single_word(
+                                suggestions,
+                                Box::new(move |value| {
+                                    Some(Command::Terminal(Message::VariableFormatArgumentChange(
+                                        field.clone(),
+                                        name.clone(),
+                                        value.to_string(),
+                                    )))
+                                }),
+                            )
+                        }),
+                    ))
+                }
+                "variable_set_value_mapping" => {
+                    let pattern = focused_variable_path.clone()?;
+                    single_word_delayed_suggestions(
+                        Box::new(all_value_mapping_files),
+                        Box::new(move |word| {
+                            Some(Command::Terminal(Message::LoadValueMapping {
+                                pattern: pattern.clone(),
+                                file: word.into(),
+                            }))
+                        }),
+                    )
+                }
+                "value_mapping_add" => Some(Command::NonTerminal(
+                    ParamGreed::Word,
+                    variables.clone(),
+                    Box::new(|pattern, _| {
+                        let pattern = pattern.to_string();
+                        single_word_delayed_suggestions(
+                            Box::new(all_value_mapping_files),
+                            Box::new(move |word| {
+                                Some(Command::Terminal(Message::LoadValueMapping {
+                                    pattern: pattern.clone(),
+                                    file: word.into(),
+                                }))
+                            }),
+                        )
+                    }),
+                )),
+                "value_mapping_clear" => Some(Command::Terminal(Message::ClearValueMappings)),
                 "item_focus" => single_word(
                     displayed_items.clone(),
                     Box::new(|word| {
@@ -546,6 +804,40 @@ pub fn get_parser(state: &State) -> Command<Message> {
                     }),
                 ),
                 "timeline_add" => Some(Command::Terminal(Message::AddTimeLine(None))),
+                "decoder_add" => Some(Command::NonTerminal(
+                    ParamGreed::Rest,
+                    vec![],
+                    Box::new(|query, _| {
+                        Some(Command::Terminal(Message::AddDecoder(query.to_string())))
+                    }),
+                )),
+                "derived_add" => Some(Command::NonTerminal(
+                    ParamGreed::Rest,
+                    vec![],
+                    Box::new(|query, _| {
+                        Some(Command::Terminal(Message::AddDerivedSignal(
+                            query.to_string(),
+                        )))
+                    }),
+                )),
+                "bus_compose" => optional_single_word(
+                    vec![],
+                    Box::new(|word| {
+                        let name = (!word.is_empty()).then(|| word.to_string());
+                        Some(Command::Terminal(Message::ComposeBus(name)))
+                    }),
+                ),
+                "bus_split" => optional_single_word(
+                    vec![],
+                    Box::new(|word| {
+                        let slice_width = if word.is_empty() {
+                            1
+                        } else {
+                            word.parse().ok()?
+                        };
+                        Some(Command::Terminal(Message::SplitVariable(None, slice_width)))
+                    }),
+                ),
                 "goto_marker" => single_word(
                     markers.keys().cloned().collect(),
                     Box::new(move |name| {
@@ -588,6 +880,45 @@ pub fn get_parser(state: &State) -> Command<Message> {
                         ))))
                     }),
                 ),
+                "export_values" => single_word(
+                    vec![],
+                    Box::new(|query| {
+                        let (path, clock, start, end) = parse_export_arguments(query)?;
+                        Some(Command::Terminal(Message::ExportValues {
+                            path: Some(path),
+                            clock,
+                            start,
+                            end,
+                        }))
+                    }),
+                ),
+                "gtkw_load" => single_word(
+                    vec![],
+                    Box::new(|word| {
+                        Some(Command::Terminal(Message::LoadGtkwFile(Some(
+                            std::path::Path::new(word).into(),
+                        ))))
+                    }),
+                ),
+                "gtkw_save" => single_word(
+                    vec![],
+                    Box::new(|word| {
+                        Some(Command::Terminal(Message::SaveGtkwFile(Some(
+                            std::path::Path::new(word).into(),
+                        ))))
+                    }),
+                ),
+                "export_wavedrom" => single_word(
+                    vec![],
+                    Box::new(|query| {
+                        let (path, markers, clock) = parse_wavedrom_arguments(query)?;
+                        Some(Command::Terminal(Message::ExportWaveDrom {
+                            path: Some(path),
+                            markers,
+                            clock,
+                        }))
+                    }),
+                ),
                 "load_state" => single_word(
                     vec![],
                     Box::new(|word| {
@@ -567,7 +567,7 @@ impl Default for SurferConfig {
     }
 }
 
-fn hex_string_to_color32(mut str: String) -> Result<Color32> {
+pub(crate) fn hex_string_to_color32(mut str: String) -> Result<Color32> {
     let mut hex_str = String::new();
     if str.len() == 3 {
         for c in str.chars() {
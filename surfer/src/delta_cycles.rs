@@ -0,0 +1,199 @@
+//! Expansion of a timestamp into its delta cycles. Event-driven simulators can change a
+//! variable several times without advancing time. The regular view only shows the last value
+//! of each timestamp, so such zero-time glitches are only visible when expanded.
+use emath::{Align2, Pos2, Rect, Vec2};
+use epaint::{FontId, Rounding, Stroke};
+use num::{BigUint, ToBigInt};
+use surfer_translation_types::TranslatedValue;
+
+use crate::displayed_item::{DisplayedFieldRef, DisplayedItem};
+use crate::translation::ValueKindExt;
+use crate::view::{DrawingContext, ItemDrawingInfo};
+use crate::viewport::Viewport;
+use crate::wave_data::WaveData;
+use crate::State;
+
+/// Width of each delta cycle on the canvas in pixels
+const DELTA_WIDTH: f32 = 80.;
+
+#[derive(Debug, Clone)]
+pub struct DeltaExpansion {
+    pub time: BigUint,
+    /// The largest number of values any displayed variable takes at `time`
+    pub num_deltas: usize,
+    /// The delta cycle shown in the value column
+    pub cursor: usize,
+}
+
+impl WaveData {
+    pub fn expand_delta_cycles(&mut self, time: BigUint) {
+        let Some(waves) = self.inner.as_waves() else {
+            return;
+        };
+        let num_deltas = self
+            .displayed_items
+            .values()
+            .filter_map(|item| match item {
+                DisplayedItem::Variable(variable) => waves
+                    .query_variable_deltas(&variable.variable_ref, &time)
+                    .ok(),
+                _ => None,
+            })
+            .map(|values| values.len())
+            .max()
+            .unwrap_or_default()
+            .max(1);
+        self.delta_expansion = Some(DeltaExpansion {
+            time,
+            num_deltas,
+            cursor: 0,
+        });
+    }
+
+    pub fn move_delta_cursor(&mut self, next: bool) {
+        if let Some(expansion) = &mut self.delta_expansion {
+            expansion.cursor = if next {
+                (expansion.cursor + 1).min(expansion.num_deltas - 1)
+            } else {
+                expansion.cursor.saturating_sub(1)
+            };
+        }
+    }
+}
+
+impl State {
+    /// The values of a field in each delta cycle of the expanded timestamp.
+    ///
+    /// The waveform does not record in which delta cycle a change happened, only the order of
+    /// the changes of each variable. Variables are therefore drawn from the first delta cycle
+    /// and keep their last value once they run out of changes.
+    pub fn delta_values(
+        &self,
+        waves: &WaveData,
+        displayed_field_ref: &DisplayedFieldRef,
+    ) -> Vec<Option<TranslatedValue>> {
+        let Some(expansion) = &waves.delta_expansion else {
+            return vec![];
+        };
+        let Some(DisplayedItem::Variable(variable)) =
+            waves.displayed_items.get(&displayed_field_ref.item)
+        else {
+            return vec![];
+        };
+        let mut values = waves
+            .inner
+            .as_waves()
+            .unwrap()
+            .query_variable_deltas(&variable.variable_ref, &expansion.time)
+            .unwrap_or_default()
+            .iter()
+            .map(|value| {
+                self.translate_field_value(waves, displayed_field_ref, value)
+                    .flatten()
+            })
+            .collect::<Vec<_>>();
+        if let Some(last) = values.last().cloned() {
+            values.resize(expansion.num_deltas, last);
+        }
+        values
+    }
+
+    pub fn draw_delta_expansion(
+        &self,
+        waves: &WaveData,
+        ctx: &mut DrawingContext,
+        size: Vec2,
+        viewport: &Viewport,
+        y_zero: f32,
+    ) {
+        let Some(expansion) = &waves.delta_expansion else {
+            return;
+        };
+        let theme = ctx.theme;
+        let left = viewport.pixel_from_time(
+            &expansion.time.to_bigint().unwrap(),
+            size.x,
+            &waves.num_timestamps(),
+        );
+        let column = |delta: usize, top: f32, bottom: f32| {
+            Rect::from_min_max(
+                (ctx.to_screen)(left + delta as f32 * DELTA_WIDTH, top),
+                (ctx.to_screen)(left + (delta + 1) as f32 * DELTA_WIDTH, bottom),
+            )
+        };
+
+        ctx.painter.rect(
+            Rect::from_min_max(
+                column(0, 0., size.y).min,
+                column(expansion.num_deltas - 1, 0., size.y).max,
+            ),
+            Rounding::ZERO,
+            theme.canvas_colors.background,
+            Stroke::new(theme.linewidth, theme.border_color),
+        );
+        ctx.painter.rect_filled(
+            column(expansion.cursor, 0., size.y),
+            Rounding::ZERO,
+            theme.canvas_colors.alt_background,
+        );
+
+        let text_size = ctx.cfg.text_size;
+        let char_width = text_size * (20. / 31.);
+        let num_chars = ((DELTA_WIDTH - 8.) / char_width).floor() as usize;
+        for drawing_info in &waves.drawing_infos {
+            let ItemDrawingInfo::Variable(variable_info) = drawing_info else {
+                continue;
+            };
+            let top = drawing_info.top() - y_zero;
+            let bottom = drawing_info.bottom() - y_zero;
+            let user_color = waves
+                .displayed_items
+                .get(&variable_info.displayed_field_ref.item)
+                .and_then(DisplayedItem::color)
+                .and_then(|color| theme.get_color(&color))
+                .unwrap_or(&theme.variable_default);
+
+            for (delta, value) in self
+                .delta_values(waves, &variable_info.displayed_field_ref)
+                .iter()
+                .enumerate()
+            {
+                let Some(value) = value else { continue };
+                let rect = column(delta, top, bottom).shrink2(Vec2::new(2., 1.));
+                ctx.painter.rect_stroke(
+                    rect,
+                    Rounding::same(2.),
+                    Stroke::new(theme.linewidth, value.kind.color(*user_color, theme)),
+                );
+                let content = if value.value.chars().count() > num_chars {
+                    value
+                        .value
+                        .chars()
+                        .take(num_chars.saturating_sub(1))
+                        .chain(['…'])
+                        .collect()
+                } else {
+                    value.value.clone()
+                };
+                ctx.painter.text(
+                    rect.left_center() + Vec2::new(3., 0.),
+                    Align2::LEFT_CENTER,
+                    content,
+                    FontId::monospace(text_size),
+                    theme.foreground,
+                );
+            }
+        }
+
+        for delta in 0..expansion.num_deltas {
+            let rect = column(delta, 0., size.y);
+            ctx.painter.text(
+                Pos2::new(rect.center().x, rect.bottom() - 2.),
+                Align2::CENTER_BOTTOM,
+                format!("δ{delta}"),
+                FontId::proportional(text_size),
+                theme.alt_text_color,
+            );
+        }
+    }
+}
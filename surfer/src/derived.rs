@@ -0,0 +1,954 @@
+//! Derived signals, computed from expressions over other variables like `valid && ready` or
+//! `wr_ptr - rd_ptr`.
+//!
+//! Values are unsigned bit vectors and the widths follow Verilog: comparisons and logical
+//! operators give a single bit, other operators give the width of their widest operand and
+//! wrap around on overflow. Bits that are not `0` or `1` make the result undefined.
+//!
+//! Buses are composed with concatenations like `{a, b, c}`, where `a` is the most significant
+//! part, and split with slices like `data[7:4]` or `data[3]`.
+use std::collections::HashMap;
+
+use color_eyre::eyre::{anyhow, bail, Context};
+use color_eyre::Result;
+use num::{BigUint, One, ToPrimitive, Zero};
+use serde::{Deserialize, Serialize};
+use surfer_translation_types::{
+    TranslatedValue, TranslatorArguments, VariableEncoding, VariableValue,
+};
+
+use crate::displayed_item::{
+    AnalogSettings, DisplayedDerived, DisplayedItem, DisplayedItemIndex, DisplayedVariable,
+    FieldFormat,
+};
+use crate::translation::{DynTranslator, TranslationResultExt, TranslatorList};
+use crate::wave_container::{
+    QueryResult, ScopeRef, ScopeRefExt, VariableMeta, VariableRef, VariableRefExt, WaveContainer,
+};
+use crate::wave_data::{ScopeType, WaveData};
+use crate::wellen::LoadSignalsCmd;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    /// `!`
+    Not,
+    /// `~`
+    BitNot,
+    /// `-`
+    Neg,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+/// Binary operators from the lowest to the highest precedence
+const PRECEDENCE: &[&[(&str, BinaryOp)]] = &[
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("|", BinaryOp::BitOr)],
+    &[("^", BinaryOp::BitXor)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+    ],
+    &[("<<", BinaryOp::Shl), (">>", BinaryOp::Shr)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+/// Operators in the order they are tokenized, longest first
+const OPERATORS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>", "|", "^", "&", "<", ">", "+", "-", "*", "/",
+    "%", "!", "~", "(", ")", "{", "}", ",",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Expr {
+    Variable {
+        variable: VariableRef,
+        width: u32,
+    },
+    Constant(BigUint),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// The operands concatenated with the first one as the most significant part
+    Concat(Vec<Expr>),
+    /// Bits `lsb` to `msb` of the operand, inclusive
+    Slice {
+        operand: Box<Expr>,
+        msb: u32,
+        lsb: u32,
+    },
+}
+
+/// The value of an expression, where `bits` is `None` if the value is undefined
+struct Value {
+    bits: Option<BigUint>,
+    width: u32,
+}
+
+fn mask(value: BigUint, width: u32) -> BigUint {
+    value & ((BigUint::one() << width) - 1u8)
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number(BigUint),
+    Operator(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Identifier(name) => write!(f, "'{name}'"),
+            Token::Number(value) => write!(f, "'{value}'"),
+            Token::Operator(op) => write!(f, "'{op}'"),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let is_identifier_char =
+        |c: char| c.is_alphanumeric() || matches!(c, '_' | '.' | '$' | '[' | ']' | ':');
+    let mut tokens = vec![];
+    let mut rest = expression.trim_start();
+    while !rest.is_empty() {
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Operator(op));
+            rest = &rest[op.len()..];
+        } else {
+            let len = rest
+                .find(|c: char| !is_identifier_char(c))
+                .unwrap_or(rest.len());
+            if len == 0 {
+                bail!("Unexpected '{}'", rest.chars().next().unwrap());
+            }
+            let (word, tail) = rest.split_at(len);
+            if word.starts_with(|c: char| c.is_ascii_digit()) {
+                tokens.push(Token::Number(parse_number(word)?));
+            } else {
+                tokens.push(Token::Identifier(word.to_string()));
+            }
+            rest = tail;
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Splits a trailing slice like `[7:4]` or `[3]` from `word`. Returns the rest of the word
+/// and the most and least significant bit of the slice.
+fn split_slice(word: &str) -> Option<(&str, u32, u32)> {
+    let (rest, slice) = word.strip_suffix(']')?.rsplit_once('[')?;
+    let (msb, lsb) = match slice.split_once(':') {
+        Some((msb, lsb)) => (msb.trim().parse().ok()?, lsb.trim().parse().ok()?),
+        None => {
+            let bit = slice.trim().parse().ok()?;
+            (bit, bit)
+        }
+    };
+    Some((rest, msb, lsb))
+}
+
+fn slice_suffix(msb: u32, lsb: u32) -> String {
+    if msb == lsb {
+        format!("[{msb}]")
+    } else {
+        format!("[{msb}:{lsb}]")
+    }
+}
+
+fn parse_number(word: &str) -> Result<BigUint> {
+    let digits = word.replace('_', "");
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x" | "0X") => (16, &digits[2..]),
+        Some("0b" | "0B") => (2, &digits[2..]),
+        _ => (10, digits.as_str()),
+    };
+    BigUint::parse_bytes(digits.as_bytes(), radix).ok_or_else(|| anyhow!("Invalid number {word}"))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Operator(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        if self.peek_operator() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            bail!("Expected '{expected}'")
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        let Some(operators) = PRECEDENCE.get(level) else {
+            return self.unary();
+        };
+        let mut lhs = self.binary(level + 1)?;
+        while let Some((_, op)) = self
+            .peek_operator()
+            .and_then(|token| operators.iter().find(|(name, _)| *name == token))
+        {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        let op = match self.peek_operator() {
+            Some("!") => UnaryOp::Not,
+            Some("~") => UnaryOp::BitNot,
+            Some("-") => UnaryOp::Neg,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        let expr = match token {
+            Some(Token::Number(value)) => Expr::Constant(value.clone()),
+            Some(Token::Identifier(name)) => match split_slice(name) {
+                Some((name, msb, lsb)) if !name.is_empty() => slice(variable(name), msb, lsb)?,
+                _ if name.starts_with('[') => bail!("Unexpected {}", self.tokens[self.pos - 1]),
+                _ => variable(name),
+            },
+            Some(Token::Operator("(")) => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                expr
+            }
+            Some(Token::Operator("{")) => {
+                let mut operands = vec![self.binary(0)?];
+                while self.peek_operator() == Some(",") {
+                    self.pos += 1;
+                    operands.push(self.binary(0)?);
+                }
+                self.expect("}")?;
+                Expr::Concat(operands)
+            }
+            Some(Token::Operator(op)) => bail!("Unexpected '{op}'"),
+            None => bail!("Unexpected end of expression"),
+        };
+        // slices of parenthesized expressions and concatenations are separate tokens
+        match self.tokens.get(self.pos) {
+            Some(Token::Identifier(word)) if word.starts_with('[') => {
+                let Some(("", msb, lsb)) = split_slice(word) else {
+                    bail!("Invalid slice {word}");
+                };
+                self.pos += 1;
+                slice(expr, msb, lsb)
+            }
+            _ => Ok(expr),
+        }
+    }
+}
+
+fn variable(name: &str) -> Expr {
+    Expr::Variable {
+        variable: VariableRef::from_hierarchy_string(name),
+        width: 0,
+    }
+}
+
+fn slice(operand: Expr, msb: u32, lsb: u32) -> Result<Expr> {
+    if msb < lsb {
+        bail!("Slice [{msb}:{lsb}] must have the most significant bit first");
+    }
+    Ok(Expr::Slice {
+        operand: Box::new(operand),
+        msb,
+        lsb,
+    })
+}
+
+impl Expr {
+    /// Parses an expression. Variables are not yet resolved, see [`Expr::resolve`]
+    pub fn parse(expression: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            pos: 0,
+        };
+        let expr = parser.binary(0)?;
+        if parser.pos < parser.tokens.len() {
+            bail!("Unexpected {} after expression", parser.tokens[parser.pos]);
+        }
+        Ok(expr)
+    }
+
+    /// Looks up the variables in the expression, either by their full path or relative to
+    /// `scope`, and updates their references and widths.
+    pub fn resolve(&mut self, waves: &WaveContainer, scope: Option<&ScopeRef>) -> Result<()> {
+        let lookup = |variable: &VariableRef| {
+            let relative = scope.map(|scope| {
+                VariableRef::new(
+                    ScopeRef::from_strs(&[scope.strs(), variable.path.strs()].concat()),
+                    variable.name.clone(),
+                )
+            });
+            waves
+                .update_variable_ref(variable)
+                .or_else(|| relative.and_then(|r| waves.update_variable_ref(&r)))
+        };
+        match self {
+            Expr::Variable { variable, width } => {
+                let resolved = lookup(variable)
+                    .ok_or_else(|| anyhow!("No variable {}", variable.full_path_string()))?;
+                *width = waves.variable_meta(&resolved)?.num_bits.unwrap_or(1);
+                *variable = resolved;
+            }
+            Expr::Constant(_) => {}
+            Expr::Unary(_, operand) => operand.resolve(waves, scope)?,
+            Expr::Binary(_, lhs, rhs) => {
+                lhs.resolve(waves, scope)?;
+                rhs.resolve(waves, scope)?;
+            }
+            Expr::Concat(operands) => {
+                for operand in operands {
+                    operand.resolve(waves, scope)?;
+                }
+            }
+            Expr::Slice { operand, msb, lsb } => {
+                // names like `data[3]` are also used for variables of their own
+                let whole = match operand.as_ref() {
+                    Expr::Variable { variable, .. } => Some(VariableRef::new(
+                        variable.path.clone(),
+                        format!("{}{}", variable.name, slice_suffix(*msb, *lsb)),
+                    ))
+                    .filter(|whole| lookup(whole).is_some()),
+                    _ => None,
+                };
+                if let Some(whole) = whole {
+                    *self = Expr::Variable {
+                        variable: whole,
+                        width: 0,
+                    };
+                    return self.resolve(waves, scope);
+                }
+                operand.resolve(waves, scope)?;
+                let width = operand.width();
+                if *msb >= width {
+                    bail!("Bit {msb} is out of range for a {width} bit value");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn variables(&self) -> Vec<&VariableRef> {
+        match self {
+            Expr::Variable { variable, .. } => vec![variable],
+            Expr::Constant(_) => vec![],
+            Expr::Unary(_, operand) => operand.variables(),
+            Expr::Binary(_, lhs, rhs) => [lhs.variables(), rhs.variables()].concat(),
+            Expr::Concat(operands) => operands.iter().flat_map(Expr::variables).collect(),
+            Expr::Slice { operand, .. } => operand.variables(),
+        }
+    }
+
+    fn variables_mut(&mut self) -> Vec<(&mut VariableRef, &mut u32)> {
+        match self {
+            Expr::Variable { variable, width } => vec![(variable, width)],
+            Expr::Constant(_) => vec![],
+            Expr::Unary(_, operand) => operand.variables_mut(),
+            Expr::Binary(_, lhs, rhs) => {
+                let mut variables = lhs.variables_mut();
+                variables.extend(rhs.variables_mut());
+                variables
+            }
+            Expr::Concat(operands) => operands.iter_mut().flat_map(Expr::variables_mut).collect(),
+            Expr::Slice { operand, .. } => operand.variables_mut(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.eval(&|_| None).width
+    }
+
+    fn eval(&self, values: &impl Fn(&VariableRef) -> Option<BigUint>) -> Value {
+        match self {
+            Expr::Variable { variable, width } => Value {
+                bits: values(variable).map(|v| mask(v, *width)),
+                width: *width,
+            },
+            Expr::Constant(value) => Value {
+                bits: Some(value.clone()),
+                width: value.bits().max(1) as u32,
+            },
+            Expr::Unary(op, operand) => {
+                let Value { bits, width } = operand.eval(values);
+                match op {
+                    UnaryOp::Not => Value {
+                        bits: bits.map(|v| BigUint::from(v.is_zero())),
+                        width: 1,
+                    },
+                    UnaryOp::BitNot => Value {
+                        bits: bits.map(|v| mask(v ^ ((BigUint::one() << width) - 1u8), width)),
+                        width,
+                    },
+                    UnaryOp::Neg => Value {
+                        bits: bits.map(|v| mask((BigUint::one() << width) - v, width)),
+                        width,
+                    },
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(values);
+                let rhs = rhs.eval(values);
+                let width = match op {
+                    _ if self.is_boolean() => 1,
+                    BinaryOp::Shl | BinaryOp::Shr => lhs.width,
+                    _ => lhs.width.max(rhs.width),
+                };
+                let (Some(a), Some(b)) = (lhs.bits, rhs.bits) else {
+                    return Value { bits: None, width };
+                };
+                let bits = match op {
+                    BinaryOp::Or => BigUint::from(!a.is_zero() || !b.is_zero()),
+                    BinaryOp::And => BigUint::from(!a.is_zero() && !b.is_zero()),
+                    BinaryOp::Eq => BigUint::from(a == b),
+                    BinaryOp::Ne => BigUint::from(a != b),
+                    BinaryOp::Lt => BigUint::from(a < b),
+                    BinaryOp::Le => BigUint::from(a <= b),
+                    BinaryOp::Gt => BigUint::from(a > b),
+                    BinaryOp::Ge => BigUint::from(a >= b),
+                    BinaryOp::BitOr => a | b,
+                    BinaryOp::BitXor => a ^ b,
+                    BinaryOp::BitAnd => a & b,
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Sub => (BigUint::one() << width) + a - b,
+                    BinaryOp::Mul => a * b,
+                    BinaryOp::Div | BinaryOp::Rem if b.is_zero() => {
+                        return Value { bits: None, width };
+                    }
+                    BinaryOp::Div => a / b,
+                    BinaryOp::Rem => a % b,
+                    // shifting by the width or more shifts out all bits
+                    BinaryOp::Shl | BinaryOp::Shr => match b.to_u32().filter(|b| *b < width) {
+                        Some(shift) if *op == BinaryOp::Shl => a << shift,
+                        Some(shift) => a >> shift,
+                        None => BigUint::zero(),
+                    },
+                };
+                Value {
+                    bits: Some(mask(bits, width)),
+                    width,
+                }
+            }
+            Expr::Concat(operands) => {
+                let values = operands.iter().map(|o| o.eval(values)).collect::<Vec<_>>();
+                let width = values.iter().map(|v| v.width).sum();
+                let bits = values.into_iter().try_fold(BigUint::zero(), |acc, v| {
+                    v.bits.map(|bits| (acc << v.width) | bits)
+                });
+                Value { bits, width }
+            }
+            Expr::Slice { operand, msb, lsb } => {
+                let width = msb - lsb + 1;
+                Value {
+                    bits: operand.eval(values).bits.map(|v| mask(v >> *lsb, width)),
+                    width,
+                }
+            }
+        }
+    }
+
+    fn is_boolean(&self) -> bool {
+        matches!(
+            self,
+            Expr::Unary(UnaryOp::Not, _)
+                | Expr::Binary(
+                    BinaryOp::Or
+                        | BinaryOp::And
+                        | BinaryOp::Eq
+                        | BinaryOp::Ne
+                        | BinaryOp::Lt
+                        | BinaryOp::Le
+                        | BinaryOp::Gt
+                        | BinaryOp::Ge,
+                    _,
+                    _
+                )
+        )
+    }
+}
+
+/// The bits of a variable value, or `None` if some bit is not `0` or `1`
+fn value_bits(value: &VariableValue) -> Option<BigUint> {
+    match value {
+        VariableValue::BigUint(v) => Some(v.clone()),
+        VariableValue::String(s) => BigUint::parse_bytes(s.as_bytes(), 2),
+    }
+}
+
+impl DisplayedDerived {
+    pub fn new(expression: &str, waves: &WaveContainer, scope: Option<&ScopeRef>) -> Result<Self> {
+        let expr =
+            Expr::parse(expression).with_context(|| format!("Invalid expression {expression}"))?;
+        Self::from_expr(expression.trim().to_string(), expr, waves, scope)
+    }
+
+    /// A derived signal computing `expr`, which is shown as `expression`
+    pub fn from_expr(
+        expression: String,
+        mut expr: Expr,
+        waves: &WaveContainer,
+        scope: Option<&ScopeRef>,
+    ) -> Result<Self> {
+        expr.resolve(waves, scope)?;
+        Ok(DisplayedDerived {
+            expression,
+            expr,
+            color: None,
+            background_color: None,
+            manual_name: None,
+            format: None,
+            format_arguments: TranslatorArguments::default(),
+            analog: None,
+            height_scaling_factor: None,
+        })
+    }
+
+    /// Updates the variable references after a new waveform has been loaded.
+    pub fn update(&self, waves: &WaveContainer, keep_unavailable: bool) -> Option<DisplayedItem> {
+        let mut result = self.clone();
+        match result.expr.resolve(waves, None) {
+            Ok(()) => Some(DisplayedItem::Derived(result)),
+            Err(_) if keep_unavailable => {
+                // the variables are looked up by name again on the next query, which fails
+                // until they are available
+                let mut result = self.clone();
+                for (variable, _) in result.expr.variables_mut() {
+                    variable.clear_id();
+                }
+                Some(DisplayedItem::Derived(result))
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Meta data describing the derived signal to translators
+    pub fn meta(&self) -> VariableMeta {
+        VariableMeta {
+            var: VariableRef::new(ScopeRef::empty(), self.expression.clone()),
+            num_bits: Some(self.expr.width()),
+            variable_type: None,
+            index: None,
+            direction: None,
+            enum_map: HashMap::new(),
+            encoding: VariableEncoding::BitVector,
+        }
+    }
+
+    /// Evaluates the expression at `time`. The value changes whenever one of the variables
+    /// changes, so the result holds from the latest change of any variable until the
+    /// earliest next one.
+    pub fn query(&self, waves: &WaveContainer, time: &BigUint) -> Result<Option<QueryResult>> {
+        let mut values = HashMap::new();
+        let mut change_time = BigUint::zero();
+        let mut next: Option<BigUint> = None;
+        let mut complete = true;
+        for variable in self.expr.variables() {
+            // variables still being loaded make the whole expression unavailable
+            let Some(result) = waves.query_variable(variable, time)? else {
+                return Ok(None);
+            };
+            if let Some(variable_next) = result.next {
+                next = Some(next.map_or(variable_next.clone(), |n| n.min(variable_next)));
+            }
+            match result.current {
+                Some((variable_time, value)) => {
+                    change_time = change_time.max(variable_time);
+                    values.insert(variable.clone(), value_bits(&value));
+                }
+                None => complete = false,
+            }
+        }
+        let current = complete.then(|| {
+            let value = self
+                .expr
+                .eval(&|variable| values.get(variable).cloned().flatten());
+            let value = match value.bits {
+                Some(bits) => VariableValue::BigUint(bits),
+                None => VariableValue::String("x".repeat(value.width as usize)),
+            };
+            (change_time, value)
+        });
+        Ok(Some(QueryResult { current, next }))
+    }
+}
+
+impl WaveData {
+    /// Adds a derived signal computed from `expression` and loads the variables it uses
+    pub fn add_derived(
+        &mut self,
+        expression: &str,
+        name: Option<String>,
+        vidx: Option<DisplayedItemIndex>,
+    ) -> Result<Option<LoadSignalsCmd>> {
+        let expr =
+            Expr::parse(expression).with_context(|| format!("Invalid expression {expression}"))?;
+        self.add_derived_expr(expression.trim().to_string(), expr, name, vidx)
+    }
+
+    /// Adds a derived signal computing `expr`, which is shown as `expression`. Expressions
+    /// built from variables are not parsed from text since the parser does not accept all
+    /// variable names.
+    fn add_derived_expr(
+        &mut self,
+        expression: String,
+        expr: Expr,
+        name: Option<String>,
+        vidx: Option<DisplayedItemIndex>,
+    ) -> Result<Option<LoadSignalsCmd>> {
+        let scope = match &self.active_scope {
+            Some(ScopeType::WaveScope(scope)) => Some(scope),
+            _ => None,
+        };
+        let waves = self
+            .inner
+            .as_waves_mut()
+            .ok_or_else(|| anyhow!("Derived signals require a waveform"))?;
+        let mut derived = DisplayedDerived::from_expr(expression, expr, waves, scope)?;
+        derived.manual_name = name;
+        let cmd = waves.load_variables(derived.expr.variables().into_iter())?;
+        self.insert_item(DisplayedItem::Derived(derived), vidx);
+        Ok(cmd)
+    }
+
+    /// Concatenates the selected variables into a bus, with the topmost variable as the
+    /// most significant part. The bus is inserted after the last of them.
+    pub fn compose_bus(&mut self, name: Option<String>) -> Result<Option<LoadSignalsCmd>> {
+        let selected = self
+            .displayed_items_order
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| self.selected_items.contains(id))
+            .collect::<Vec<_>>();
+        if selected.len() < 2 {
+            bail!("Select at least two variables to compose a bus");
+        }
+        let (names, operands): (Vec<_>, Vec<_>) = selected
+            .iter()
+            .map(|(_, id)| match &self.displayed_items[id] {
+                DisplayedItem::Variable(variable) => Ok((
+                    variable.variable_ref.full_path_string(),
+                    Expr::Variable {
+                        variable: variable.variable_ref.clone(),
+                        width: 0,
+                    },
+                )),
+                DisplayedItem::Derived(derived) => {
+                    Ok((format!("({})", derived.expression), derived.expr.clone()))
+                }
+                item => bail!("Cannot add {} to a bus", item.name()),
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let vidx = selected.last().map(|(idx, _)| DisplayedItemIndex(*idx));
+        self.add_derived_expr(
+            format!("{{{}}}", names.join(", ")),
+            Expr::Concat(operands),
+            name,
+            vidx,
+        )
+    }
+
+    /// Splits a variable into slices of `slice_width` bits, from the most significant one.
+    /// The slices are inserted after the variable.
+    pub fn split_variable(
+        &mut self,
+        vidx: DisplayedItemIndex,
+        slice_width: u32,
+    ) -> Result<Option<LoadSignalsCmd>> {
+        let Some(DisplayedItem::Variable(variable)) = self
+            .displayed_items_order
+            .get(vidx.0)
+            .and_then(|id| self.displayed_items.get(id))
+        else {
+            bail!("Only variables can be split");
+        };
+        let variable_ref = variable.variable_ref.clone();
+        let path = variable_ref.full_path_string();
+        let display_name = variable.display_name.clone();
+        let width = self
+            .inner
+            .as_waves()
+            .ok_or_else(|| anyhow!("Splitting variables requires a waveform"))?
+            .variable_meta(&variable_ref)?
+            .num_bits
+            .unwrap_or(1);
+        if slice_width == 0 || slice_width >= width {
+            bail!("Cannot split {display_name} with {width} bits into slices of {slice_width}");
+        }
+
+        let mut cmd = None;
+        for (offset, msb) in (0..width).rev().step_by(slice_width as usize).enumerate() {
+            let lsb = msb.saturating_sub(slice_width - 1);
+            let slice = slice_suffix(msb, lsb);
+            let expr = Expr::Slice {
+                operand: Box::new(Expr::Variable {
+                    variable: variable_ref.clone(),
+                    width: 0,
+                }),
+                msb,
+                lsb,
+            };
+            cmd = self
+                .add_derived_expr(
+                    format!("{path}{slice}"),
+                    expr,
+                    Some(format!("{display_name}{slice}")),
+                    Some(DisplayedItemIndex(vidx.0 + offset)),
+                )?
+                .or(cmd);
+        }
+        Ok(cmd)
+    }
+}
+
+/// An item whose values are drawn as a waveform, either a variable or a derived signal
+#[derive(Clone, Copy)]
+pub enum Signal<'a> {
+    Variable(&'a DisplayedVariable),
+    Derived(&'a DisplayedDerived),
+}
+
+impl<'a> Signal<'a> {
+    pub fn from_item(item: &'a DisplayedItem) -> Option<Self> {
+        match item {
+            DisplayedItem::Variable(variable) => Some(Signal::Variable(variable)),
+            DisplayedItem::Derived(derived) => Some(Signal::Derived(derived)),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Signal::Variable(variable) => variable.variable_ref.full_path_string(),
+            Signal::Derived(derived) => derived.expression.clone(),
+        }
+    }
+
+    pub fn meta(&self, waves: &WaveContainer) -> Result<VariableMeta> {
+        match self {
+            Signal::Variable(variable) => waves.variable_meta(&variable.variable_ref),
+            Signal::Derived(derived) => Ok(derived.meta()),
+        }
+    }
+
+    pub fn query(&self, waves: &WaveContainer, time: &BigUint) -> Result<Option<QueryResult>> {
+        match self {
+            Signal::Variable(variable) => waves.query_variable(&variable.variable_ref, time),
+            Signal::Derived(derived) => derived.query(waves, time),
+        }
+    }
+
+    pub fn format(&self) -> &'a Option<String> {
+        match self {
+            Signal::Variable(variable) => &variable.format,
+            Signal::Derived(derived) => &derived.format,
+        }
+    }
+
+    pub fn format_arguments(&self) -> &'a TranslatorArguments {
+        match self {
+            Signal::Variable(variable) => &variable.format_arguments,
+            Signal::Derived(derived) => &derived.format_arguments,
+        }
+    }
+
+    /// Derived signals are translated as a whole, so only variables have field formats
+    pub fn field_formats(&self) -> &'a [FieldFormat] {
+        match self {
+            Signal::Variable(variable) => &variable.field_formats,
+            Signal::Derived(_) => &[],
+        }
+    }
+
+    pub fn analog(&self) -> Option<AnalogSettings> {
+        match self {
+            Signal::Variable(variable) => variable.analog,
+            Signal::Derived(derived) => derived.analog,
+        }
+    }
+
+    /// Translates a value with `translator` and returns the part shown for `field`. Returns
+    /// `None` if the value could not be translated and `Some(None)` if the field has no
+    /// value.
+    pub fn translate_field_value(
+        &self,
+        waves: &WaveContainer,
+        field: &[String],
+        translator: &DynTranslator,
+        translators: &TranslatorList,
+        value: &VariableValue,
+    ) -> Option<Option<TranslatedValue>> {
+        let meta = self.meta(waves).ok()?;
+        let result = translator
+            .translate_with_arguments(&meta, value, self.format_arguments())
+            .ok()?;
+
+        let fields = result.format_flat(
+            self.format(),
+            self.format_arguments(),
+            self.field_formats(),
+            translators,
+        );
+        Some(
+            fields
+                .into_iter()
+                .find(|res| res.names == field)
+                .and_then(|res| res.value),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn eval(expression: &str, values: &[(&str, u32, u64)]) -> Option<u64> {
+        let mut expr = Expr::parse(expression).unwrap();
+        set_widths(&mut expr, values);
+        let values = values
+            .iter()
+            .map(|(name, _, value)| (name.to_string(), BigUint::from(*value)))
+            .collect::<HashMap<_, _>>();
+        expr.eval(&|variable| values.get(&variable.full_path_string()).cloned())
+            .bits
+            .map(|bits| bits.to_u64().unwrap())
+    }
+
+    fn set_widths(expr: &mut Expr, values: &[(&str, u32, u64)]) {
+        for (variable, width) in expr.variables_mut() {
+            *width = values
+                .iter()
+                .find(|(name, _, _)| *name == variable.full_path_string())
+                .map(|(_, width, _)| *width)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn logical_operators_give_single_bits() {
+        let values = [("top.valid", 1, 1), ("top.ready", 1, 0)];
+        assert_eq!(eval("top.valid && top.ready", &values), Some(0));
+        assert_eq!(eval("top.valid || top.ready", &values), Some(1));
+        assert_eq!(eval("!top.ready", &values), Some(1));
+    }
+
+    #[test]
+    fn arithmetic_wraps_at_operand_width() {
+        let values = [("wr_ptr", 4, 2), ("rd_ptr", 4, 5)];
+        assert_eq!(eval("wr_ptr - rd_ptr", &values), Some(13));
+        assert_eq!(eval("rd_ptr - wr_ptr", &values), Some(3));
+        assert_eq!(eval("wr_ptr + 15", &values), Some(1));
+        assert_eq!(eval("-wr_ptr", &values), Some(14));
+        assert_eq!(eval("~wr_ptr", &values), Some(13));
+    }
+
+    #[test]
+    fn precedence_and_parentheses() {
+        let values = [("a", 8, 3), ("b", 8, 4)];
+        assert_eq!(eval("a + b * 2", &values), Some(11));
+        assert_eq!(eval("(a + b) * 2", &values), Some(14));
+        assert_eq!(eval("a + b > 6 && a < b", &values), Some(1));
+        assert_eq!(eval("a << 1 | 1", &values), Some(7));
+        assert_eq!(eval("b % 0x3 == 0b1", &values), Some(1));
+    }
+
+    #[test]
+    fn division_by_zero_is_undefined() {
+        let values = [("a", 8, 3), ("b", 8, 0)];
+        assert_eq!(eval("a / b", &values), None);
+        assert_eq!(eval("a / b + 1", &values), None);
+    }
+
+    #[test]
+    fn widths_follow_operators() {
+        let values = [("count", 8, 0), ("flag", 1, 0)];
+        let width = |expression: &str| {
+            let mut expr = Expr::parse(expression).unwrap();
+            set_widths(&mut expr, &values);
+            expr.width()
+        };
+        assert_eq!(width("count > 10"), 1);
+        assert_eq!(width("count + flag"), 8);
+        assert_eq!(width("flag << 3"), 1);
+        assert_eq!(width("0x1ff & count"), 9);
+    }
+
+    #[test]
+    fn concatenations_put_first_operand_on_top() {
+        let values = [("a", 1, 1), ("b", 1, 0), ("c", 2, 0b11)];
+        assert_eq!(eval("{a, b, c}", &values), Some(0b1011));
+        assert_eq!(eval("{c, a}", &values), Some(0b111));
+        assert_eq!(eval("{a, b} + 1", &values), Some(0b11));
+    }
+
+    #[test]
+    fn slices_select_bits() {
+        let values = [("data", 16, 0xabcd), ("a", 1, 1)];
+        assert_eq!(eval("data[7:4]", &values), Some(0xc));
+        assert_eq!(eval("data[0]", &values), Some(1));
+        assert_eq!(eval("data[15:8] == 0xab", &values), Some(1));
+        assert_eq!(eval("{a, data[3:0]}[4:1]", &values), Some(0b1110));
+        assert_eq!(eval("(data >> 8)[3:0]", &values), Some(0xb));
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        assert!(Expr::parse("").is_err());
+        assert!(Expr::parse("a &&").is_err());
+        assert!(Expr::parse("(a + b").is_err());
+        assert!(Expr::parse("a b").is_err());
+        assert!(Expr::parse("a # b").is_err());
+        assert!(Expr::parse("{a, b").is_err());
+        assert!(Expr::parse("data[3:7]").is_err());
+        assert!(Expr::parse("[3:0]").is_err());
+    }
+}
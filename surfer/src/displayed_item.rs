@@ -4,9 +4,11 @@ use egui::{Context, FontSelection, Key, RichText, Style, WidgetText, Window};
 use emath::Align;
 use epaint::text::LayoutJob;
 use serde::{Deserialize, Serialize};
-use surfer_translation_types::VariableInfo;
+use surfer_translation_types::{TranslatorArguments, VariableInfo};
 
 use crate::config::SurferConfig;
+use crate::derived::Expr;
+use crate::protocol_decoder::{DecodedTransaction, ProtocolDecoder};
 use crate::transaction_container::TransactionStreamRef;
 use crate::wave_container::{VariableRef, VariableRefExt, WaveContainer};
 use crate::{
@@ -15,6 +17,10 @@ use crate::{
 };
 
 const DEFAULT_DIVIDER_NAME: &str = "";
+pub const MIN_HEIGHT_SCALING_FACTOR: f32 = 1.;
+pub const MAX_HEIGHT_SCALING_FACTOR: f32 = 20.;
+/// Height given to variables at the default height when they are switched to analog display
+pub const ANALOG_HEIGHT_SCALING_FACTOR: f32 = 3.;
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen::prelude::wasm_bindgen)]
@@ -67,12 +73,16 @@ pub enum DisplayedItem {
     TimeLine(DisplayedTimeLine),
     Placeholder(DisplayedPlaceholder),
     Stream(DisplayedStream),
+    Derived(DisplayedDerived),
+    Decoder(DisplayedDecoder),
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct FieldFormat {
     pub field: Vec<String>,
     pub format: String,
+    #[serde(default)]
+    pub arguments: TranslatorArguments,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -86,7 +96,49 @@ pub struct DisplayedVariable {
     pub display_name_type: VariableNameType,
     pub manual_name: Option<String>,
     pub format: Option<String>,
+    /// Arguments for the translator in `format`
+    #[serde(default)]
+    pub format_arguments: TranslatorArguments,
     pub field_formats: Vec<FieldFormat>,
+    /// Draw the value as an analog waveform rather than as a bus
+    #[serde(default)]
+    pub analog: Option<AnalogSettings>,
+    /// Height of the item relative to the default row height
+    #[serde(default)]
+    pub height_scaling_factor: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum AnalogStyle {
+    /// Hold each value until the next change
+    Step,
+    /// Connect consecutive values by straight lines
+    Interpolated,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum AnalogRange {
+    /// Scale to the smallest and largest value currently in view
+    Auto,
+    Manual {
+        min: f64,
+        max: f64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct AnalogSettings {
+    pub style: AnalogStyle,
+    pub range: AnalogRange,
+}
+
+impl Default for AnalogSettings {
+    fn default() -> Self {
+        Self {
+            style: AnalogStyle::Step,
+            range: AnalogRange::Auto,
+        }
+    }
 }
 
 impl DisplayedVariable {
@@ -101,6 +153,17 @@ impl DisplayedVariable {
         }
     }
 
+    pub fn get_format_arguments(&self, field: &[String]) -> Option<&TranslatorArguments> {
+        if field.is_empty() {
+            Some(&self.format_arguments)
+        } else {
+            self.field_formats
+                .iter()
+                .find(|ff| ff.field == field)
+                .map(|ff| &ff.arguments)
+        }
+    }
+
     /// Updates the variable after a new waveform has been loaded.
     pub fn update(
         &self,
@@ -131,7 +194,10 @@ impl DisplayedVariable {
             display_name_type: self.display_name_type,
             manual_name: self.manual_name,
             format: self.format,
+            format_arguments: self.format_arguments,
             field_formats: self.field_formats,
+            analog: self.analog,
+            height_scaling_factor: self.height_scaling_factor,
         }
     }
 }
@@ -141,6 +207,9 @@ pub struct DisplayedDivider {
     pub color: Option<String>,
     pub background_color: Option<String>,
     pub name: Option<String>,
+    /// Height of the item relative to the default row height
+    #[serde(default)]
+    pub height_scaling_factor: Option<f32>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -149,6 +218,9 @@ pub struct DisplayedMarker {
     pub background_color: Option<String>,
     pub name: Option<String>,
     pub idx: u8,
+    /// Height of the item relative to the default row height
+    #[serde(default)]
+    pub height_scaling_factor: Option<f32>,
 }
 
 impl DisplayedMarker {
@@ -181,6 +253,9 @@ pub struct DisplayedTimeLine {
     pub color: Option<String>,
     pub background_color: Option<String>,
     pub name: Option<String>,
+    /// Height of the item relative to the default row height
+    #[serde(default)]
+    pub height_scaling_factor: Option<f32>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -192,7 +267,15 @@ pub struct DisplayedPlaceholder {
     pub display_name_type: VariableNameType,
     pub manual_name: Option<String>,
     pub format: Option<String>,
+    /// Arguments for the translator in `format`
+    #[serde(default)]
+    pub format_arguments: TranslatorArguments,
     pub field_formats: Vec<FieldFormat>,
+    #[serde(default)]
+    pub analog: Option<AnalogSettings>,
+    /// Height of the item relative to the default row height
+    #[serde(default)]
+    pub height_scaling_factor: Option<f32>,
 }
 
 impl DisplayedPlaceholder {
@@ -210,7 +293,10 @@ impl DisplayedPlaceholder {
             display_name_type: self.display_name_type,
             manual_name: self.manual_name,
             format: self.format,
+            format_arguments: self.format_arguments,
             field_formats: self.field_formats,
+            analog: self.analog,
+            height_scaling_factor: self.height_scaling_factor,
         }
     }
 }
@@ -223,6 +309,46 @@ pub struct DisplayedStream {
     pub display_name: String,
     pub manual_name: Option<String>,
     pub rows: usize,
+    /// Height of the item relative to the default row height
+    #[serde(default)]
+    pub height_scaling_factor: Option<f32>,
+}
+
+/// Transactions decoded from bit-level variables, see [`crate::protocol_decoder`]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DisplayedDecoder {
+    pub decoder: ProtocolDecoder,
+    /// Decoded once the variables are loaded
+    #[serde(skip)]
+    pub transactions: Option<Vec<DecodedTransaction>>,
+    /// The [`crate::wave_data::WaveData::values_version`] the transactions were decoded for
+    #[serde(skip)]
+    pub decoded_version: Option<u64>,
+    pub color: Option<String>,
+    pub background_color: Option<String>,
+    pub manual_name: Option<String>,
+    /// Height of the item relative to the default row height
+    #[serde(default)]
+    pub height_scaling_factor: Option<f32>,
+}
+
+/// A signal computed from an expression over other variables, see [`crate::derived`]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DisplayedDerived {
+    pub expression: String,
+    pub expr: Expr,
+    pub color: Option<String>,
+    pub background_color: Option<String>,
+    pub manual_name: Option<String>,
+    pub format: Option<String>,
+    /// Arguments for the translator in `format`
+    #[serde(default)]
+    pub format_arguments: TranslatorArguments,
+    #[serde(default)]
+    pub analog: Option<AnalogSettings>,
+    /// Height of the item relative to the default row height
+    #[serde(default)]
+    pub height_scaling_factor: Option<f32>,
 }
 
 impl DisplayedItem {
@@ -234,6 +360,8 @@ impl DisplayedItem {
             DisplayedItem::TimeLine(timeline) => timeline.color.clone(),
             DisplayedItem::Placeholder(_) => None,
             DisplayedItem::Stream(stream) => stream.color.clone(),
+            DisplayedItem::Derived(derived) => derived.color.clone(),
+            DisplayedItem::Decoder(decoder) => decoder.color.clone(),
         }
     }
 
@@ -245,6 +373,8 @@ impl DisplayedItem {
             DisplayedItem::TimeLine(timeline) => timeline.color.clone_from(&color_name),
             DisplayedItem::Placeholder(placeholder) => placeholder.color.clone_from(&color_name),
             DisplayedItem::Stream(stream) => stream.color.clone_from(&color_name),
+            DisplayedItem::Derived(derived) => derived.color.clone_from(&color_name),
+            DisplayedItem::Decoder(decoder) => decoder.color.clone_from(&color_name),
         }
     }
 
@@ -276,6 +406,15 @@ impl DisplayedItem {
                 .as_ref()
                 .unwrap_or(&stream.display_name)
                 .clone(),
+            DisplayedItem::Derived(derived) => derived
+                .manual_name
+                .as_ref()
+                .unwrap_or(&derived.expression)
+                .clone(),
+            DisplayedItem::Decoder(decoder) => decoder
+                .manual_name
+                .clone()
+                .unwrap_or_else(|| decoder.decoder.name()),
         }
     }
 
@@ -288,7 +427,7 @@ impl DisplayedItem {
         config: &SurferConfig,
     ) {
         match self {
-            DisplayedItem::Variable(_) => {
+            DisplayedItem::Variable(_) | DisplayedItem::Derived(_) | DisplayedItem::Decoder(_) => {
                 RichText::new(self.name())
                     .color(*color)
                     .line_height(Some(config.layout.waveforms_line_height))
@@ -342,6 +481,45 @@ impl DisplayedItem {
             DisplayedItem::Stream(stream) => {
                 stream.manual_name = name;
             }
+            DisplayedItem::Derived(derived) => {
+                derived.manual_name = name;
+            }
+            DisplayedItem::Decoder(decoder) => {
+                decoder.manual_name = name;
+            }
+        }
+    }
+
+    pub fn height_scaling_factor(&self) -> f32 {
+        let factor = match self {
+            DisplayedItem::Variable(variable) => variable.height_scaling_factor,
+            DisplayedItem::Divider(divider) => divider.height_scaling_factor,
+            DisplayedItem::Marker(marker) => marker.height_scaling_factor,
+            DisplayedItem::TimeLine(timeline) => timeline.height_scaling_factor,
+            DisplayedItem::Placeholder(placeholder) => placeholder.height_scaling_factor,
+            DisplayedItem::Stream(stream) => stream.height_scaling_factor,
+            DisplayedItem::Derived(derived) => derived.height_scaling_factor,
+            DisplayedItem::Decoder(decoder) => decoder.height_scaling_factor,
+        };
+        factor.unwrap_or(1.)
+    }
+
+    pub fn set_height_scaling_factor(&mut self, factor: f32) {
+        if !factor.is_finite() {
+            return;
+        }
+        let factor = Some(factor.clamp(MIN_HEIGHT_SCALING_FACTOR, MAX_HEIGHT_SCALING_FACTOR));
+        match self {
+            DisplayedItem::Variable(variable) => variable.height_scaling_factor = factor,
+            DisplayedItem::Divider(divider) => divider.height_scaling_factor = factor,
+            DisplayedItem::Marker(marker) => marker.height_scaling_factor = factor,
+            DisplayedItem::TimeLine(timeline) => timeline.height_scaling_factor = factor,
+            DisplayedItem::Placeholder(placeholder) => {
+                placeholder.height_scaling_factor = factor;
+            }
+            DisplayedItem::Stream(stream) => stream.height_scaling_factor = factor,
+            DisplayedItem::Derived(derived) => derived.height_scaling_factor = factor,
+            DisplayedItem::Decoder(decoder) => decoder.height_scaling_factor = factor,
         }
     }
 
@@ -353,6 +531,8 @@ impl DisplayedItem {
             DisplayedItem::TimeLine(timeline) => &timeline.background_color,
             DisplayedItem::Placeholder(_) => &None,
             DisplayedItem::Stream(stream) => &stream.background_color,
+            DisplayedItem::Derived(derived) => &derived.background_color,
+            DisplayedItem::Decoder(decoder) => &decoder.background_color,
         };
         background_color.clone()
     }
@@ -377,6 +557,12 @@ impl DisplayedItem {
             DisplayedItem::Stream(stream) => {
                 stream.background_color.clone_from(&color_name);
             }
+            DisplayedItem::Derived(derived) => {
+                derived.background_color.clone_from(&color_name);
+            }
+            DisplayedItem::Decoder(decoder) => {
+                decoder.background_color.clone_from(&color_name);
+            }
         }
     }
 }
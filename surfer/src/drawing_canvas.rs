@@ -8,26 +8,32 @@ use ftr_parser::types::{Transaction, TxGenerator};
 use itertools::Itertools;
 use log::{error, warn};
 use num::bigint::{ToBigInt, ToBigUint};
-use num::{BigInt, BigUint, ToPrimitive};
+use num::{BigInt, BigUint, One, ToPrimitive, Zero};
 use rayon::prelude::{IntoParallelRefIterator, ParallelBridge, ParallelIterator};
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::f64::consts::PI;
 use surfer_translation_types::{
     SubFieldFlatTranslationResult, TranslatedValue, ValueKind, VariableInfo, VariableType,
+    VariableValue,
 };
 
 use crate::clock_highlighting::draw_clock_edge;
 use crate::config::SurferTheme;
 use crate::data_container::DataContainer;
+use crate::derived::Signal;
 use crate::displayed_item::{
-    DisplayedFieldRef, DisplayedItemIndex, DisplayedItemRef, DisplayedVariable,
+    AnalogRange, AnalogSettings, AnalogStyle, DisplayedDecoder, DisplayedFieldRef,
+    DisplayedItemIndex, DisplayedItemRef,
 };
 use crate::transaction_container::{TransactionRef, TransactionStreamRef};
 use crate::translation::{TranslationResultExt, TranslatorList, ValueKindExt, VariableInfoExt};
-use crate::view::{DrawConfig, DrawingContext, ItemDrawingInfo};
+use crate::value_search::Number;
+use crate::view::{
+    DerivedDrawingInfo, DrawConfig, DrawingContext, ItemDrawingInfo, VariableDrawingInfo,
+};
 use crate::viewport::Viewport;
-use crate::wave_container::{QueryResult, VariableRefExt};
+use crate::wave_container::QueryResult;
 use crate::wave_data::WaveData;
 use crate::CachedDrawData::TransactionDrawData;
 use crate::{
@@ -41,6 +47,10 @@ pub struct DrawnRegion {
     /// between the previous and next pixels. Only used by the bool drawing logic to
     /// draw draw a vertical line and prevent apparent aliasing
     force_anti_alias: bool,
+    /// True if the value matches the active value search
+    search_match: bool,
+    /// The number plotted for the value in analog mode
+    analog_value: Option<f64>,
 }
 
 /// List of values to draw for a variable. It is an ordered list of values that should
@@ -48,6 +58,7 @@ pub struct DrawnRegion {
 pub struct DrawingCommands {
     is_bool: bool,
     is_clock: bool,
+    analog: Option<AnalogSettings>,
     values: Vec<(f32, DrawnRegion)>,
 }
 
@@ -57,6 +68,7 @@ impl DrawingCommands {
             values: vec![],
             is_bool: true,
             is_clock: false,
+            analog: None,
         }
     }
 
@@ -65,6 +77,7 @@ impl DrawingCommands {
             values: vec![],
             is_bool: true,
             is_clock: true,
+            analog: None,
         }
     }
 
@@ -73,6 +86,16 @@ impl DrawingCommands {
             values: vec![],
             is_bool: false,
             is_clock: false,
+            analog: None,
+        }
+    }
+
+    pub fn new_analog(settings: AnalogSettings) -> Self {
+        Self {
+            values: vec![],
+            is_bool: false,
+            is_clock: false,
+            analog: Some(settings),
         }
     }
 
@@ -95,7 +118,7 @@ struct VariableDrawCommands {
 }
 
 fn variable_draw_commands(
-    displayed_variable: &DisplayedVariable,
+    signal: Signal,
     display_id: DisplayedItemRef,
     timestamps: &[(f32, num::BigUint)],
     waves: &WaveData,
@@ -106,11 +129,8 @@ fn variable_draw_commands(
     let mut clock_edges = vec![];
     let mut local_msgs = vec![];
 
-    let meta = match waves
-        .inner
-        .as_waves()
-        .unwrap()
-        .variable_meta(&displayed_variable.variable_ref)
+    let meta = match signal
+        .meta(waves.inner.as_waves().unwrap())
         .context("failed to get variable meta")
     {
         Ok(meta) => meta,
@@ -129,6 +149,11 @@ fn variable_draw_commands(
 
     let mut prev_values = HashMap::new();
 
+    let value_search = waves
+        .value_search
+        .as_ref()
+        .filter(|search| search.item == display_id);
+
     // In order to insert a final draw command at the end of a trace,
     // we need to know if this is the last timestamp to draw
     let end_pixel = timestamps.iter().last().map(|t| t.0).unwrap_or_default();
@@ -146,11 +171,7 @@ fn variable_draw_commands(
             continue;
         }
 
-        let query_result = waves
-            .inner
-            .as_waves()
-            .unwrap()
-            .query_variable(&displayed_variable.variable_ref, time);
+        let query_result = signal.query(waves.inner.as_waves().unwrap(), time);
         next_change = match &query_result {
             Ok(Some(QueryResult {
                 next: Some(timestamp),
@@ -186,31 +207,34 @@ fn variable_draw_commands(
             continue;
         }
 
-        let translation_result = match translator.translate(&meta, &val) {
-            Ok(result) => result,
-            Err(e) => {
-                error!(
-                    "{translator_name} for {variable_name} failed. Disabling:",
-                    translator_name = translator.name(),
-                    variable_name = displayed_variable.variable_ref.full_path_string()
-                );
-                error!("{e:#}");
-                local_msgs.push(Message::ResetVariableFormat(displayed_field_ref));
-                return None;
-            }
-        };
+        let translation_result =
+            match translator.translate_with_arguments(&meta, &val, signal.format_arguments()) {
+                Ok(result) => result,
+                Err(e) => {
+                    error!(
+                        "{translator_name} for {variable_name} failed. Disabling:",
+                        translator_name = translator.name(),
+                        variable_name = signal.name()
+                    );
+                    error!("{e:#}");
+                    local_msgs.push(Message::ResetVariableFormat(displayed_field_ref));
+                    return None;
+                }
+            };
 
         let fields = translation_result.format_flat(
-            &displayed_variable.format,
-            &displayed_variable.field_formats,
+            signal.format(),
+            signal.format_arguments(),
+            signal.field_formats(),
             translators,
         );
 
         for SubFieldFlatTranslationResult { names, value } in fields {
             let entry = local_commands.entry(names.clone()).or_insert_with(|| {
-                match info.get_subinfo(&names) {
-                    VariableInfo::Bool => DrawingCommands::new_bool(),
-                    VariableInfo::Clock => DrawingCommands::new_clock(),
+                match (info.get_subinfo(&names), signal.analog()) {
+                    (VariableInfo::Bool, _) => DrawingCommands::new_bool(),
+                    (VariableInfo::Clock, _) => DrawingCommands::new_clock(),
+                    (_, Some(analog)) if names.is_empty() => DrawingCommands::new_analog(analog),
                     _ => DrawingCommands::new_wide(),
                 }
             });
@@ -246,11 +270,23 @@ fn variable_draw_commands(
                     }
                 }
 
+                let search_match = names.is_empty()
+                    && value_search.is_some_and(|search| {
+                        value
+                            .as_ref()
+                            .is_some_and(|value| search.matches(&value.value, &val))
+                    });
+                let analog_value = entry
+                    .analog
+                    .and(value.as_ref())
+                    .and_then(|value| analog_value(&value.value, &val));
                 entry.push((
                     *pixel,
                     DrawnRegion {
                         inner: value,
                         force_anti_alias: anti_alias && !new_value,
+                        search_match,
+                        analog_value,
                     },
                 ));
             }
@@ -271,6 +307,8 @@ impl State {
                 self.sys.draw_data.borrow_mut()[viewport] = None;
             }
         }
+        // the measurement depends on the same data as the drawing
+        self.sys.measurement.borrow_mut().take();
     }
 
     pub fn generate_draw_commands(
@@ -338,15 +376,12 @@ impl State {
             .displayed_items_order
             .par_iter()
             .map(|id| (*id, waves.displayed_items.get(id)))
-            .filter_map(|(id, item)| match item {
-                Some(DisplayedItem::Variable(variable_ref)) => Some((id, variable_ref)),
-                _ => None,
-            })
+            .filter_map(|(id, item)| Some((id, Signal::from_item(item?)?)))
             // Iterate over the variables, generating draw commands for all the
             // subfields
-            .filter_map(|(id, displayed_variable)| {
+            .filter_map(|(id, signal)| {
                 variable_draw_commands(
-                    displayed_variable,
+                    signal,
                     id,
                     &timestamps,
                     waves,
@@ -456,6 +491,8 @@ impl State {
                 );
             }
 
+            let line_height =
+                cfg.line_height * displayed_stream.height_scaling_factor.unwrap_or(1.);
             let mut last_times_on_row = vec![(BigUint::ZERO, BigUint::ZERO)];
             for gen in &generators {
                 for tx in &gen.transactions {
@@ -492,7 +529,7 @@ impl State {
                                 frame_width - 1.,
                                 &waves.num_timestamps(),
                             ),
-                            cfg.line_height * curr_row as f32 + 4.0,
+                            line_height * curr_row as f32 + 4.0,
                         );
                         let max = Pos2::new(
                             viewport.pixel_from_time(
@@ -500,7 +537,7 @@ impl State {
                                 frame_width - 1.,
                                 &waves.num_timestamps(),
                             ),
-                            cfg.line_height * (curr_row + 1) as f32 - 4.0,
+                            line_height * (curr_row + 1) as f32 - 4.0,
                         );
 
                         let tx_ref = TransactionRef { id: curr_tx_id };
@@ -702,6 +739,7 @@ impl State {
                         last_edge = *current_edge;
                     }
                 }
+                let decoded_transaction_offsets = waves.decoded_transaction_offsets();
                 let zero_y = to_screen.transform_pos(Pos2::ZERO).y;
                 for (idx, drawing_info) in waves.drawing_infos.iter().enumerate() {
                     // We draw in absolute coords, but the variable offset in the y
@@ -718,10 +756,15 @@ impl State {
                         .and_then(|color| self.config.theme.get_color(&color));
 
                     match drawing_info {
-                        ItemDrawingInfo::Variable(variable_info) => {
-                            if let Some(commands) =
-                                draw_commands.get(&variable_info.displayed_field_ref)
-                            {
+                        ItemDrawingInfo::Variable(VariableDrawingInfo {
+                            displayed_field_ref,
+                            ..
+                        })
+                        | ItemDrawingInfo::Derived(DerivedDrawingInfo {
+                            displayed_field_ref,
+                            ..
+                        }) => {
+                            if let Some(commands) = draw_commands.get(displayed_field_ref) {
                                 // Get background color and determine best text color
                                 let background_color = self.get_background_color(
                                     waves,
@@ -753,6 +796,45 @@ impl State {
                                         &self.config.theme.variable_default
                                     }
                                 });
+                                self.draw_value_search_matches(
+                                    commands,
+                                    y_offset,
+                                    drawing_info.bottom() - drawing_info.top(),
+                                    &mut ctx,
+                                );
+                                self.draw_differences(
+                                    waves,
+                                    drawing_info,
+                                    viewport_idx,
+                                    frame_width,
+                                    y_offset,
+                                    &mut ctx,
+                                );
+                                if let Some(analog) = &commands.analog {
+                                    self.draw_analog(
+                                        commands,
+                                        analog,
+                                        color,
+                                        y_offset,
+                                        drawing_info.bottom() - drawing_info.top(),
+                                        &mut ctx,
+                                        *text_color,
+                                    );
+                                    continue;
+                                }
+                                let row_cfg = DrawConfig::new(
+                                    cfg.canvas_height,
+                                    cfg.line_height
+                                        * displayed_item
+                                            .map_or(1., DisplayedItem::height_scaling_factor),
+                                    cfg.text_size,
+                                );
+                                let mut row_ctx = DrawingContext {
+                                    painter: &mut *ctx.painter,
+                                    cfg: &row_cfg,
+                                    to_screen: ctx.to_screen,
+                                    theme: ctx.theme,
+                                };
                                 for (old, new) in
                                     commands.values.iter().zip(commands.values.iter().skip(1))
                                 {
@@ -763,14 +845,14 @@ impl State {
                                             color,
                                             y_offset,
                                             commands.is_clock && draw_clock_rising_marker,
-                                            &mut ctx,
+                                            &mut row_ctx,
                                         );
                                     } else {
                                         self.draw_region(
                                             (old, new),
                                             color,
                                             y_offset,
-                                            &mut ctx,
+                                            &mut row_ctx,
                                             *text_color,
                                         );
                                     }
@@ -800,6 +882,29 @@ impl State {
                             );
                         }
                         ItemDrawingInfo::Stream(_) => {}
+                        ItemDrawingInfo::Decoder(_) => {
+                            let first_id = waves
+                                .displayed_items_order
+                                .get(drawing_info.item_list_idx())
+                                .and_then(|id| decoded_transaction_offsets.get(id));
+                            if let (Some(DisplayedItem::Decoder(decoder)), Some(first_id)) =
+                                (displayed_item, first_id)
+                            {
+                                self.draw_decoded_transactions(
+                                    decoder,
+                                    *first_id,
+                                    color,
+                                    y_offset,
+                                    drawing_info.bottom() - drawing_info.top(),
+                                    waves,
+                                    viewport_idx,
+                                    frame_width,
+                                    &mut ctx,
+                                    ui,
+                                    msgs,
+                                );
+                            }
+                        }
                     }
                 }
             }
@@ -808,7 +913,6 @@ impl State {
                 let stream_to_displayed_txs = &draw_data.stream_to_displayed_txs;
                 let inc_relation_tx_ids = &draw_data.inc_relation_tx_ids;
                 let out_relation_tx_ids = &draw_data.out_relation_tx_ids;
-                let highlight_rgba = Rgba::from(self.config.theme.transaction_highlight);
 
                 let mut inc_relation_starts = vec![];
                 let mut out_relation_starts = vec![];
@@ -879,45 +983,21 @@ impl State {
                                             focused_transaction_start = Some(start);
                                         }
 
-                                        let transaction_rect = Rect { min, max };
-                                        let mut response =
-                                            ui.allocate_rect(transaction_rect, Sense::click());
-
-                                        response = handle_transaction_tooltip(
+                                        let response = self.draw_transaction(
+                                            tx_ref,
+                                            Rect { min, max },
+                                            color,
+                                            waves,
+                                            &mut ctx,
+                                            ui,
+                                            msgs,
+                                        );
+                                        handle_transaction_tooltip(
                                             response,
                                             waves,
                                             &tx_draw_command.gen_ref,
                                             tx_ref,
                                         );
-
-                                        if response.clicked() {
-                                            msgs.push(Message::FocusTransaction(
-                                                Some(tx_ref.clone()),
-                                                None,
-                                            ));
-                                        }
-                                        let tx_fill_color = if is_transaction_focused {
-                                            Color32::from(
-                                                Rgba::from(
-                                                    color
-                                                        .unwrap_or(
-                                                            &self.config.theme.transaction_default,
-                                                        )
-                                                        .additive(),
-                                                ) + highlight_rgba,
-                                            )
-                                        } else {
-                                            color
-                                                .unwrap_or(&self.config.theme.transaction_default)
-                                                .gamma_multiply(0.6)
-                                        };
-                                        let stroke = Stroke::new(1.5, tx_fill_color.additive());
-                                        ctx.painter.rect(
-                                            transaction_rect,
-                                            Rounding::same(5.0),
-                                            tx_fill_color,
-                                            stroke,
-                                        );
                                     }
                                 }
                                 // Draws the surrounding border of the stream
@@ -960,6 +1040,8 @@ impl State {
                         ItemDrawingInfo::Variable(_) => {}
                         ItemDrawingInfo::Divider(_) => {}
                         ItemDrawingInfo::Marker(_) => {}
+                        ItemDrawingInfo::Derived(_) => {}
+                        ItemDrawingInfo::Decoder(_) => {}
                     }
                 }
 
@@ -980,6 +1062,14 @@ impl State {
         #[cfg(feature = "performance_plot")]
         self.sys.timing.borrow_mut().end("Wave drawing");
 
+        self.draw_delta_expansion(
+            waves,
+            &mut ctx,
+            response.rect.size(),
+            &waves.viewports[viewport_idx],
+            y_zero,
+        );
+
         waves.draw_graphics(
             &mut ctx,
             response.rect.size(),
@@ -1082,6 +1172,295 @@ impl State {
         }
     }
 
+    /// Draws a transaction as a box, highlighted if it is focused. Clicking it focuses it.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_transaction(
+        &self,
+        tx_ref: &TransactionRef,
+        rect: Rect,
+        user_color: Option<&Color32>,
+        waves: &WaveData,
+        ctx: &mut DrawingContext,
+        ui: &mut Ui,
+        msgs: &mut Vec<Message>,
+    ) -> Response {
+        let response = ui.allocate_rect(rect, Sense::click());
+        if response.clicked() {
+            msgs.push(Message::FocusTransaction(Some(tx_ref.clone()), None));
+        }
+        let color = user_color.unwrap_or(&self.config.theme.transaction_default);
+        let is_focused = waves
+            .focused_transaction
+            .0
+            .as_ref()
+            .is_some_and(|focused| focused == tx_ref);
+        let fill_color = if is_focused {
+            Color32::from(
+                Rgba::from(color.additive()) + Rgba::from(self.config.theme.transaction_highlight),
+            )
+        } else {
+            color.gamma_multiply(0.6)
+        };
+        draw_transaction_box(ctx, rect, fill_color);
+        response
+    }
+
+    /// Draws the transactions of a protocol decoder like those of transaction streams, with
+    /// their label inside if it fits. The transactions are focused as `first_id` and the
+    /// following ids.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_decoded_transactions(
+        &self,
+        decoder: &DisplayedDecoder,
+        first_id: usize,
+        user_color: Option<&Color32>,
+        offset: f32,
+        height: f32,
+        waves: &WaveData,
+        viewport_idx: usize,
+        frame_width: f32,
+        ctx: &mut DrawingContext,
+        ui: &mut Ui,
+        msgs: &mut Vec<Message>,
+    ) {
+        let Some(transactions) = &decoder.transactions else {
+            return;
+        };
+        let viewport = waves.viewports[viewport_idx];
+        let num_timestamps = waves.num_timestamps();
+        let left = viewport.left_edge_time(&num_timestamps);
+        let x = |time: &BigUint| {
+            viewport.pixel_from_time(
+                &BigInt::from(time.clone()),
+                frame_width - 1.,
+                &num_timestamps,
+            )
+        };
+        let time_string = |time: &BigUint| {
+            waves.time_string(
+                &BigInt::from(time.clone()),
+                &self.wanted_timeunit,
+                &self.get_time_format(),
+            )
+        };
+        let text_color = *self.config.theme.get_best_text_color(
+            &user_color
+                .unwrap_or(&self.config.theme.transaction_default)
+                .gamma_multiply(0.6),
+        );
+        let char_width = ctx.cfg.text_size * (20. / 31.);
+
+        let first = transactions
+            .partition_point(|transaction| BigInt::from(transaction.end.clone()) < left);
+        for (idx, transaction) in transactions.iter().enumerate().skip(first) {
+            let min_x = x(&transaction.start).max(0.);
+            if min_x > frame_width {
+                break;
+            }
+            let max_x = x(&transaction.end).min(frame_width - 1.).max(min_x + 1.);
+            // decoders have the height of a variable, which leaves little room around the label
+            let rect = Rect {
+                min: (ctx.to_screen)(min_x, offset + 2.),
+                max: (ctx.to_screen)(max_x, offset + height - 2.),
+            };
+            let response = self.draw_transaction(
+                &TransactionRef { id: first_id + idx },
+                rect,
+                user_color,
+                waves,
+                ctx,
+                ui,
+                msgs,
+            );
+
+            let num_chars = ((max_x - min_x - 4.) / char_width).floor() as usize;
+            if num_chars >= 1 {
+                let content = if transaction.label.chars().count() > num_chars {
+                    transaction
+                        .label
+                        .chars()
+                        .take(num_chars - 1)
+                        .chain(['…'])
+                        .collect::<String>()
+                } else {
+                    transaction.label.clone()
+                };
+                ctx.painter.text(
+                    rect.center(),
+                    Align2::CENTER_CENTER,
+                    content,
+                    FontId::monospace(ctx.cfg.text_size),
+                    text_color,
+                );
+            }
+
+            response
+                .on_hover_text(format!(
+                    "{}\n{} - {}",
+                    transaction.label,
+                    time_string(&transaction.start),
+                    time_string(&transaction.end)
+                ))
+                .on_hover_ui(|ui| transaction_tooltip_table(ui, &transaction.attributes));
+        }
+    }
+
+    /// Highlights the values of a row which match the value search
+    fn draw_value_search_matches(
+        &self,
+        commands: &DrawingCommands,
+        offset: f32,
+        height: f32,
+        ctx: &mut DrawingContext,
+    ) {
+        let color = self.config.theme.accent_info.background.gamma_multiply(0.5);
+        for ((old_x, old), (new_x, _)) in commands.values.iter().tuple_windows() {
+            if old.search_match {
+                ctx.painter.rect_filled(
+                    Rect::from_min_max(
+                        (ctx.to_screen)(*old_x, offset),
+                        (ctx.to_screen)(*new_x, offset + height),
+                    ),
+                    Rounding::ZERO,
+                    color,
+                );
+            }
+        }
+    }
+
+    /// Marks the time ranges in which the variable of a row differs from the variable it is
+    /// compared with
+    fn draw_differences(
+        &self,
+        waves: &WaveData,
+        drawing_info: &ItemDrawingInfo,
+        viewport_idx: usize,
+        frame_width: f32,
+        offset: f32,
+        ctx: &mut DrawingContext,
+    ) {
+        let ItemDrawingInfo::Variable(info) = drawing_info else {
+            return;
+        };
+        // only the root value is compared
+        if !info.field_ref.field.is_empty() {
+            return;
+        }
+        let Some(ranges) = waves.differences(&info.field_ref.root) else {
+            return;
+        };
+        let viewport = &waves.viewports[viewport_idx];
+        let num_timestamps = waves.num_timestamps();
+        let height = drawing_info.bottom() - drawing_info.top();
+        let color = self
+            .config
+            .theme
+            .accent_error
+            .background
+            .gamma_multiply(0.5);
+        for (start, end) in ranges {
+            let x = |time: &BigUint| {
+                viewport
+                    .pixel_from_time(&time.to_bigint().unwrap(), frame_width, &num_timestamps)
+                    .clamp(-1., frame_width + 1.)
+            };
+            let (start_x, end_x) = (x(start), x(end));
+            if end_x < 0. || start_x > frame_width {
+                continue;
+            }
+            // short differences should still be visible when zoomed out
+            ctx.painter.rect_filled(
+                Rect::from_min_max(
+                    (ctx.to_screen)(start_x, offset),
+                    (ctx.to_screen)(end_x.max(start_x + 1.), offset + height),
+                ),
+                Rounding::ZERO,
+                color,
+            );
+        }
+    }
+
+    /// Draws `commands` as a line graph filling a row of height `row_height`. Values which
+    /// are not numbers are drawn like regular wide variables.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_analog(
+        &self,
+        commands: &DrawingCommands,
+        settings: &AnalogSettings,
+        user_color: Color32,
+        offset: f32,
+        row_height: f32,
+        ctx: &mut DrawingContext,
+        text_color: Color32,
+    ) {
+        let values = commands
+            .values
+            .iter()
+            .map(|command| (command, command.1.analog_value))
+            .collect::<Vec<_>>();
+
+        let (min, max) = match settings.range {
+            AnalogRange::Manual { min, max } => (min, max),
+            AnalogRange::Auto => values
+                .iter()
+                .filter_map(|(_, value)| *value)
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+                    (min.min(v), max.max(v))
+                }),
+        };
+        // Keep the trace off the row borders
+        let margin = 2.;
+        let to_y = |value: f64| {
+            let fraction = if max > min {
+                ((value - min) / (max - min)).clamp(0., 1.) as f32
+            } else {
+                0.5
+            };
+            offset + margin + (1. - fraction) * (row_height - 2. * margin)
+        };
+
+        let stroke = Stroke {
+            color: user_color,
+            width: self.config.theme.linewidth,
+        };
+        let mut trace = vec![];
+        for window in values.windows(2) {
+            let [(old, old_value), (new, new_value)] = window else {
+                continue;
+            };
+            let (old_x, new_x) = (old.0, new.0);
+            match *old_value {
+                Some(old_value) => {
+                    if trace.is_empty() {
+                        trace.push((ctx.to_screen)(old_x, to_y(old_value)));
+                    }
+                    match (settings.style, *new_value) {
+                        (AnalogStyle::Interpolated, Some(new_value)) => {
+                            trace.push((ctx.to_screen)(new_x, to_y(new_value)));
+                        }
+                        (AnalogStyle::Step, Some(new_value)) => {
+                            trace.push((ctx.to_screen)(new_x, to_y(old_value)));
+                            trace.push((ctx.to_screen)(new_x, to_y(new_value)));
+                        }
+                        (_, None) => {
+                            trace.push((ctx.to_screen)(new_x, to_y(old_value)));
+                            ctx.painter
+                                .add(PathShape::line(std::mem::take(&mut trace), stroke));
+                        }
+                    }
+                }
+                None => {
+                    // Center the bus style region vertically in the row
+                    let region_offset = offset + (row_height - ctx.cfg.line_height) / 2.;
+                    self.draw_region((*old, *new), user_color, region_offset, ctx, text_color);
+                }
+            }
+        }
+        if !trace.is_empty() {
+            ctx.painter.add(PathShape::line(trace, stroke));
+        }
+    }
+
     fn draw_bool_transition(
         &self,
         ((old_x, prev_region), (new_x, new_region)): (&(f32, DrawnRegion), &(f32, DrawnRegion)),
@@ -1249,6 +1628,15 @@ impl State {
 
             if let Some(time) = snap_pos {
                 self.draw_line(&time, ctx, size, &waves.viewports[viewport_idx], waves);
+                if waves.delta_expansion.is_some() {
+                    if ui.button("Collapse delta cycles").clicked() {
+                        msgs.push(Message::CollapseDeltaCycles);
+                        ui.close_menu();
+                    }
+                } else if ui.button("Expand delta cycles").clicked() {
+                    msgs.push(Message::ExpandDeltaCycles(Some(time.clone())));
+                    ui.close_menu();
+                }
                 ui.menu_button("Set marker", |ui| {
                     macro_rules! close_menu {
                         () => {{
@@ -1296,12 +1684,9 @@ impl State {
         if let Some(utimestamp) = timestamp.to_biguint() {
             if let Some(vidx) = waves.get_item_at_y(pos.y) {
                 if let Some(id) = waves.displayed_items_order.get(vidx) {
-                    if let DisplayedItem::Variable(variable) = &waves.displayed_items[id] {
-                        if let Ok(Some(res)) = waves
-                            .inner
-                            .as_waves()
-                            .unwrap()
-                            .query_variable(&variable.variable_ref, &utimestamp)
+                    if let Some(signal) = Signal::from_item(&waves.displayed_items[id]) {
+                        if let Ok(Some(res)) =
+                            signal.query(waves.inner.as_waves().unwrap(), &utimestamp)
                         {
                             let prev_time = if let Some(v) = res.current {
                                 v.0.to_bigint().unwrap()
@@ -1360,6 +1745,58 @@ impl State {
 
 impl WaveData {}
 
+/// The number to plot for a value in analog mode, if it is finite. Values written in some
+/// radix, e.g. by the hexadecimal translator, are taken from the raw value.
+fn analog_value(translated: &str, raw: &VariableValue) -> Option<f64> {
+    Number::from_value(translated, raw)
+        .map(|number| number.to_f64())
+        .filter(|v| v.is_finite())
+}
+
+/// True if `signal` can be drawn in analog mode, i.e. if its translator turns its values into
+/// numbers. This is checked with the smallest and largest value of the variable.
+pub fn supports_analog(
+    signal: &Signal,
+    field_ref: &DisplayedFieldRef,
+    waves: &WaveData,
+    translators: &TranslatorList,
+) -> bool {
+    let Some(meta) = waves
+        .inner
+        .as_waves()
+        .and_then(|wave_container| signal.meta(wave_container).ok())
+    else {
+        return false;
+    };
+    let translator = waves.variable_translator(field_ref, translators);
+    match translator.variable_info(&meta) {
+        Ok(VariableInfo::Real) => true,
+        Ok(VariableInfo::Bits) => {
+            let max = (BigUint::one() << meta.num_bits.unwrap_or(0)) - 1u32;
+            [BigUint::zero(), max].into_iter().all(|raw| {
+                let raw = VariableValue::BigUint(raw);
+                translator
+                    .translate_with_arguments(&meta, &raw, signal.format_arguments())
+                    .ok()
+                    .and_then(|result| {
+                        result
+                            .format_flat(
+                                signal.format(),
+                                signal.format_arguments(),
+                                signal.field_formats(),
+                                translators,
+                            )
+                            .into_iter()
+                            .find(|field| field.names.is_empty())?
+                            .value
+                    })
+                    .is_some_and(|translated| analog_value(&translated.value, &raw).is_some())
+            })
+        }
+        _ => false,
+    }
+}
+
 trait VariableExt {
     fn bool_drawing_spec(
         &self,
@@ -1420,8 +1857,13 @@ fn handle_transaction_tooltip(
         .find(|transaction| transaction.get_tx_id() == tx_ref.id)
         .unwrap();
 
+    let attributes = tx
+        .attributes
+        .iter()
+        .map(|attribute| (attribute.name.clone(), attribute.value().to_string()))
+        .collect::<Vec<_>>();
     let response = response.on_hover_text(transaction_tooltip_text(waves, tx));
-    response.on_hover_ui(|ui| transaction_tooltip_table(ui, tx))
+    response.on_hover_ui(|ui| transaction_tooltip_table(ui, &attributes))
 }
 
 fn transaction_tooltip_text(waves: &WaveData, tx: &Transaction) -> String {
@@ -1445,7 +1887,15 @@ fn transaction_tooltip_text(waves: &WaveData, tx: &Transaction) -> String {
     )
 }
 
-fn transaction_tooltip_table(ui: &mut Ui, tx: &Transaction) {
+/// Draws a transaction as a box with rounded corners
+fn draw_transaction_box(ctx: &mut DrawingContext, rect: Rect, fill_color: Color32) {
+    let stroke = Stroke::new(1.5, fill_color.additive());
+    ctx.painter
+        .rect(rect, Rounding::same(5.0), fill_color, stroke);
+}
+
+/// The names and values of the attributes of a transaction
+fn transaction_tooltip_table(ui: &mut Ui, attributes: &[(String, String)]) {
     TableBuilder::new(ui)
         .column(Column::exact(80.))
         .column(Column::exact(80.))
@@ -1458,15 +1908,13 @@ fn transaction_tooltip_table(ui: &mut Ui, tx: &Transaction) {
             });
         })
         .body(|body| {
-            let total_rows = tx.attributes.len();
-            let attributes = &tx.attributes;
-            body.rows(15., total_rows, |mut row| {
-                let attribute = attributes.get(row.index()).unwrap();
+            body.rows(15., attributes.len(), |mut row| {
+                let (name, value) = &attributes[row.index()];
                 row.col(|ui| {
-                    ui.label(attribute.name.clone());
+                    ui.label(name);
                 });
                 row.col(|ui| {
-                    ui.label(attribute.value());
+                    ui.label(value);
                 });
             });
         });
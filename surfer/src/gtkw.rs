@@ -0,0 +1,586 @@
+//! Reading and writing GTKWave save files (`.gtkw`). The signals, comment traces, markers,
+//! zoom and data formats are mapped to and from the items, markers, viewport and translators
+//! of Surfer. Other GTKWave settings are ignored when loading and not written when saving.
+//!
+//! Times in save files are in the timescale of the dump file, so they are scaled by the
+//! multiplier of the timescale.
+use std::path::PathBuf;
+
+#[cfg(not(target_arch = "wasm32"))]
+use camino::Utf8Path;
+#[cfg(not(target_arch = "wasm32"))]
+use color_eyre::{eyre::Context, Result};
+use log::{info, warn};
+use num::{BigInt, FromPrimitive, ToPrimitive};
+use rfd::AsyncFileDialog;
+
+use crate::displayed_item::DisplayedItem;
+use crate::message::Message;
+use crate::translation::TranslatorList;
+use crate::value_export::save_export;
+use crate::wasm_util::perform_async_work;
+use crate::wave_container::{VariableRef, VariableRefExt};
+use crate::wave_data::WaveData;
+use crate::wave_source::{string_to_wavesource, LoadOptions, WaveSource};
+use crate::wellen::LoadSignalsCmd;
+use crate::State;
+
+// Trace flags of GTKWave, written in hex on `@` lines before the traces they apply to
+const TR_HEX: u32 = 0x2;
+const TR_DEC: u32 = 0x4;
+const TR_BIN: u32 = 0x8;
+const TR_OCT: u32 = 0x10;
+const TR_RJUSTIFY: u32 = 0x20;
+const TR_BLANK: u32 = 0x200;
+const TR_SIGNED: u32 = 0x400;
+const TR_ASCII: u32 = 0x800;
+const TR_GRP_END: u32 = 0x1000000;
+
+/// GTKWave has 26 named markers, `A` to `Z`
+const NUM_NAMED_MARKERS: u8 = 26;
+/// Width of the waves in pixels that is assumed when the save file does not tell
+const DEFAULT_WAVE_WIDTH: f64 = 1000.;
+const SIGNALS_WIDTH: f64 = 200.;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GtkwTrace {
+    Signal {
+        name: String,
+        alias: Option<String>,
+        translator: Option<String>,
+    },
+    /// A comment trace or the start of a group, both of which become dividers
+    Comment(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GtkwSave {
+    pub dumpfile: Option<String>,
+    /// Time at the left edge of the waves
+    pub timestart: Option<BigInt>,
+    /// Base 2 logarithm of the number of pixels per time unit
+    pub zoom: Option<f64>,
+    /// Width of the waves in pixels
+    pub wave_width: Option<f64>,
+    /// The primary marker of GTKWave, which is the cursor of Surfer
+    pub cursor: Option<BigInt>,
+    pub markers: Vec<(u8, BigInt)>,
+    pub traces: Vec<GtkwTrace>,
+}
+
+fn translator_for_flags(flags: u32) -> Option<&'static str> {
+    if flags & TR_ASCII != 0 {
+        Some("ASCII")
+    } else if flags & TR_SIGNED != 0 {
+        Some("Signed")
+    } else if flags & TR_HEX != 0 {
+        Some("Hexadecimal")
+    } else if flags & TR_DEC != 0 {
+        Some("Unsigned")
+    } else if flags & TR_BIN != 0 {
+        Some("Binary")
+    } else if flags & TR_OCT != 0 {
+        Some("Octal")
+    } else {
+        None
+    }
+}
+
+fn flags_for_translator(translator: Option<&str>) -> u32 {
+    TR_RJUSTIFY
+        | match translator {
+            Some("ASCII") => TR_ASCII,
+            Some("Signed") => TR_SIGNED,
+            Some("Unsigned") => TR_DEC,
+            Some("Binary") => TR_BIN,
+            Some("Octal") => TR_OCT,
+            _ => TR_HEX,
+        }
+}
+
+/// Parses a time of a marker, where -1 means that the marker is not set
+fn marker_time(word: &str) -> Option<BigInt> {
+    word.parse::<BigInt>()
+        .ok()
+        .filter(|time| *time >= BigInt::from(0))
+}
+
+impl GtkwSave {
+    pub fn parse(content: &str) -> Self {
+        let mut save = GtkwSave::default();
+        let mut flags = TR_RJUSTIFY | TR_HEX;
+        let (mut size_width, mut signals_width, mut sst_width, mut sst_expanded) =
+            (None, 0., 0., false);
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with("[*]") {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix('[') {
+                let Some((key, value)) = rest.split_once(']') else {
+                    continue;
+                };
+                let value = value.trim();
+                let number = || value.parse::<f64>().ok();
+                match key {
+                    "dumpfile" => save.dumpfile = Some(value.trim_matches('"').to_string()),
+                    "timestart" => save.timestart = value.parse().ok(),
+                    "size" => {
+                        size_width = value
+                            .split_whitespace()
+                            .next()
+                            .and_then(|w| w.parse::<f64>().ok())
+                    }
+                    "signals_width" => signals_width = number().unwrap_or_default(),
+                    "sst_width" => sst_width = number().unwrap_or_default(),
+                    "sst_expanded" => sst_expanded = value == "1",
+                    _ => {}
+                }
+            } else if let Some(rest) = line.strip_prefix('*') {
+                let mut words = rest.split_whitespace();
+                save.zoom = words.next().and_then(|zoom| zoom.parse().ok());
+                save.cursor = words.next().and_then(marker_time);
+                save.markers = words
+                    .take(NUM_NAMED_MARKERS as usize)
+                    .enumerate()
+                    .filter_map(|(idx, word)| Some((idx as u8, marker_time(word)?)))
+                    .collect();
+            } else if let Some(hex) = line.strip_prefix('@') {
+                flags = u32::from_str_radix(hex, 16).unwrap_or(flags);
+            } else if let Some(comment) = line.strip_prefix('-') {
+                if flags & TR_GRP_END == 0 {
+                    save.traces.push(GtkwTrace::Comment(comment.to_string()));
+                }
+            } else if line.starts_with(['#', '^', '%']) {
+                warn!("Ignoring unsupported trace {line} in GTKWave save file");
+            } else {
+                let (alias, name) = match line
+                    .strip_prefix("+{")
+                    .and_then(|rest| rest.split_once('}'))
+                {
+                    Some((alias, name)) => (Some(alias.to_string()), name.trim()),
+                    None => (None, line),
+                };
+                if flags & TR_BLANK == 0 {
+                    save.traces.push(GtkwTrace::Signal {
+                        name: name.to_string(),
+                        alias,
+                        translator: translator_for_flags(flags).map(str::to_string),
+                    });
+                }
+            }
+        }
+        save.wave_width = size_width
+            .map(|width| width - signals_width - if sst_expanded { sst_width } else { 0. })
+            .filter(|width| *width > 0.);
+        save
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read(path: &Utf8Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read GTKWave save file {path}"))?;
+        let mut save = Self::parse(&content);
+        if let Some(dir) = path.parent() {
+            save.resolve_dumpfile(dir.as_std_path());
+        }
+        Ok(save)
+    }
+
+    /// Dump files are usually given relative to the save file in `dir`
+    #[cfg(not(target_arch = "wasm32"))]
+    fn resolve_dumpfile(&mut self, dir: &std::path::Path) {
+        if let Some(dumpfile) = &mut self.dumpfile {
+            if std::path::Path::new(dumpfile.as_str()).is_relative() && !dumpfile.contains("://") {
+                *dumpfile = dir.join(&*dumpfile).to_string_lossy().to_string();
+            }
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut result = vec![
+            "[*]".to_string(),
+            "[*] Surfer".to_string(),
+            "[*]".to_string(),
+        ];
+        if let Some(dumpfile) = &self.dumpfile {
+            result.push(format!("[dumpfile] \"{dumpfile}\""));
+        }
+        if let Some(timestart) = &self.timestart {
+            result.push(format!("[timestart] {timestart}"));
+        }
+        let wave_width = self.wave_width.unwrap_or(DEFAULT_WAVE_WIDTH);
+        result.push(format!(
+            "[size] {} 800",
+            (wave_width + SIGNALS_WIDTH).round()
+        ));
+        result.push(format!("[signals_width] {SIGNALS_WIDTH}"));
+        result.push("[sst_expanded] 0".to_string());
+
+        let time_or_unset =
+            |time: Option<&BigInt>| time.map_or("-1".to_string(), BigInt::to_string);
+        let mut zoom_line = format!(
+            "*{:.6} {}",
+            self.zoom.unwrap_or_default(),
+            time_or_unset(self.cursor.as_ref())
+        );
+        for idx in 0..NUM_NAMED_MARKERS {
+            let time = self
+                .markers
+                .iter()
+                .find(|(marker, _)| *marker == idx)
+                .map(|(_, time)| time);
+            zoom_line.push(' ');
+            zoom_line.push_str(&time_or_unset(time));
+        }
+        result.push(zoom_line);
+
+        let mut current_flags = None;
+        for trace in &self.traces {
+            let (flags, line) = match trace {
+                GtkwTrace::Signal {
+                    name,
+                    alias,
+                    translator,
+                } => (
+                    flags_for_translator(translator.as_deref()),
+                    match alias {
+                        Some(alias) => format!("+{{{alias}}} {name}"),
+                        None => name.clone(),
+                    },
+                ),
+                GtkwTrace::Comment(comment) => (TR_BLANK, format!("-{comment}")),
+            };
+            if current_flags != Some(flags) {
+                result.push(format!("@{flags:x}"));
+                current_flags = Some(flags);
+            }
+            result.push(line);
+        }
+        result.push(String::new());
+        result.join("\n")
+    }
+}
+
+impl WaveData {
+    fn time_scale(&self) -> BigInt {
+        BigInt::from(self.inner.metadata().timescale.multiplier.unwrap_or(1))
+    }
+
+    /// Finds the variable of a signal of a save file, whose name may end with the bit range
+    fn gtkw_variable(&self, name: &str) -> Option<VariableRef> {
+        let waves = self.inner.as_waves()?;
+        let lookup =
+            |name: &str| waves.update_variable_ref(&VariableRef::from_hierarchy_string(name));
+        lookup(name).or_else(|| {
+            let (name, _range) = name.strip_suffix(']')?.rsplit_once('[')?;
+            lookup(name)
+        })
+    }
+
+    /// Replaces the items, markers and view by those of the save file
+    pub fn apply_gtkw(
+        &mut self,
+        save: &GtkwSave,
+        translators: &TranslatorList,
+    ) -> Vec<LoadSignalsCmd> {
+        for id in self.displayed_items_order.clone() {
+            self.remove_displayed_item(id);
+        }
+        self.markers.clear();
+        self.focused_item = None;
+
+        let mut cmds = vec![];
+        for trace in &save.traces {
+            match trace {
+                GtkwTrace::Comment(comment) => {
+                    let name = (!comment.is_empty()).then(|| comment.clone());
+                    self.add_divider(name, None);
+                }
+                GtkwTrace::Signal {
+                    name,
+                    alias,
+                    translator,
+                } => {
+                    let Some(variable) = self.gtkw_variable(name) else {
+                        warn!("Signal {name} from the GTKWave save file is not in the waves");
+                        continue;
+                    };
+                    // Formats of single bits are ignored to keep them drawn as bits
+                    let is_vector = self
+                        .inner
+                        .as_waves()
+                        .and_then(|waves| waves.variable_meta(&variable).ok())
+                        .and_then(|meta| meta.num_bits)
+                        .is_some_and(|bits| bits > 1);
+                    cmds.extend(self.add_variables(translators, vec![variable]));
+                    let Some(DisplayedItem::Variable(displayed)) = self
+                        .displayed_items_order
+                        .last()
+                        .and_then(|id| self.displayed_items.get_mut(id))
+                    else {
+                        continue;
+                    };
+                    if is_vector {
+                        displayed.format.clone_from(translator);
+                    }
+                    displayed.manual_name.clone_from(alias);
+                }
+            }
+        }
+
+        let scale = self.time_scale();
+        self.cursor = save.cursor.as_ref().map(|time| time / &scale);
+        for (idx, time) in &save.markers {
+            self.set_marker_position(*idx, &(time / &scale));
+        }
+        if let (Some(start), Some(zoom)) = (&save.timestart, save.zoom) {
+            let width = save.wave_width.unwrap_or(DEFAULT_WAVE_WIDTH);
+            if let Some(duration) = BigInt::from_f64(2f64.powf(-zoom) * width) {
+                let num_timestamps = self.num_timestamps();
+                self.viewports[0].zoom_to_range(
+                    &(start / &scale),
+                    &((start + duration) / &scale),
+                    &num_timestamps,
+                );
+            }
+        }
+        cmds
+    }
+
+    /// The items, markers and view as a GTKWave save file
+    pub fn gtkw_save(&self) -> GtkwSave {
+        let waves = self.inner.as_waves();
+        let traces = self
+            .displayed_items_order
+            .iter()
+            .filter_map(|id| self.displayed_items.get(id))
+            .filter_map(|item| match item {
+                DisplayedItem::Variable(variable) => {
+                    let meta = waves?.variable_meta(&variable.variable_ref).ok();
+                    let index = meta.as_ref().and_then(|meta| meta.index.clone());
+                    let translator = if meta.and_then(|meta| meta.num_bits) == Some(1) {
+                        Some("Binary".to_string())
+                    } else {
+                        variable.format.clone()
+                    };
+                    Some(GtkwTrace::Signal {
+                        name: variable.variable_ref.full_path_string() + &index.unwrap_or_default(),
+                        alias: variable.manual_name.clone(),
+                        translator,
+                    })
+                }
+                DisplayedItem::Divider(divider) => {
+                    Some(GtkwTrace::Comment(divider.name.clone().unwrap_or_default()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let scale = self.time_scale();
+        if self.markers.keys().any(|idx| *idx >= NUM_NAMED_MARKERS) {
+            warn!("Only the first {NUM_NAMED_MARKERS} markers are saved in GTKWave save files");
+        }
+        let markers = self
+            .markers
+            .iter()
+            .filter(|(idx, _)| **idx < NUM_NAMED_MARKERS)
+            .map(|(idx, time)| (*idx, time * &scale))
+            .collect();
+
+        let num_timestamps = self.num_timestamps();
+        let viewport = self.viewports[0];
+        let start = viewport.left_edge_time(&num_timestamps) * &scale;
+        let end = viewport.right_edge_time(&num_timestamps) * &scale;
+        let zoom = (&end - &start)
+            .to_f64()
+            .filter(|duration| *duration > 0.)
+            .map(|duration| -(duration / DEFAULT_WAVE_WIDTH).log2());
+
+        GtkwSave {
+            dumpfile: match &self.source {
+                WaveSource::File(file) => Some(file.to_string()),
+                WaveSource::Url(url) => Some(url.clone()),
+                _ => None,
+            },
+            timestart: Some(start),
+            zoom,
+            wave_width: Some(DEFAULT_WAVE_WIDTH),
+            cursor: self.cursor.as_ref().map(|time| time * &scale),
+            markers,
+            traces,
+        }
+    }
+}
+
+impl State {
+    /// Reads the save file at `path`, or a file picked by the user
+    pub fn load_gtkw_file(&mut self, path: Option<PathBuf>) {
+        let sender = self.sys.channels.msg_sender.clone();
+
+        perform_async_work(async move {
+            let source = if let Some(_path) = path {
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    Some(_path.into())
+                }
+                #[cfg(target_arch = "wasm32")]
+                {
+                    None
+                }
+            } else {
+                AsyncFileDialog::new()
+                    .set_title("Load GTKWave save file")
+                    .add_filter("GTKWave save files (*.gtkw)", &["gtkw"])
+                    .add_filter("All files", &["*"])
+                    .pick_file()
+                    .await
+            };
+            let Some(source) = source else {
+                return;
+            };
+            let bytes = source.read().await;
+            #[allow(unused_mut)]
+            let mut save = GtkwSave::parse(&String::from_utf8_lossy(&bytes));
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(dir) = source.path().parent() {
+                save.resolve_dumpfile(dir);
+            }
+            sender.send(Message::LoadGtkw(Box::new(save))).unwrap();
+        });
+    }
+
+    /// Applies a save file once the waves are loaded. If no waves are open, the dump file of
+    /// the save file is loaded first.
+    pub fn load_gtkw(&mut self, save: GtkwSave) {
+        if self.waves.is_none() {
+            match save.dumpfile.as_deref().map(string_to_wavesource) {
+                Some(WaveSource::File(file)) => {
+                    self.load_from_file(file, LoadOptions::clean()).ok();
+                }
+                Some(WaveSource::Url(url)) => self.load_wave_from_url(url, LoadOptions::clean()),
+                _ => {
+                    warn!("The GTKWave save file has no dump file to load");
+                    return;
+                }
+            }
+        }
+        // Applied before any other startup commands, which may refer to the items
+        self.sys
+            .batch_commands
+            .push_front(Message::ApplyGtkw(Box::new(save)));
+        self.sys.batch_commands_completed = false;
+    }
+
+    pub fn apply_gtkw(&mut self, save: &GtkwSave) {
+        self.save_current_canvas("Load GTKWave save file".to_string());
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        let cmds = waves.apply_gtkw(save, &self.sys.translators);
+        for cmd in cmds {
+            self.load_variables(cmd);
+        }
+        self.invalidate_draw_commands();
+        info!("Applied GTKWave save file");
+    }
+
+    /// Writes the current items and view to `path`, or a file picked by the user
+    pub fn save_gtkw_file(&mut self, path: Option<PathBuf>) {
+        let Some(waves) = &self.waves else {
+            return;
+        };
+        let save = waves.gtkw_save();
+        save_export(
+            path,
+            "Export GTKWave save file",
+            &[("GTKWave save files (*.gtkw)", &["gtkw"])],
+            move |_| Ok(save.encode()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SAVE_FILE: &str = r#"[*]
+[*] GTKWave Analyzer v3.3.104 (w)1999-2020 BSI
+[*] Mon Jan  1 00:00:00 2024
+[*]
+[dumpfile] "/tmp/counter.vcd"
+[timestart] 100
+[size] 1400 800
+[pos] -1 -1
+*-3.000000 150 -1 400 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1 -1
+[treeopen] tb.
+[sst_width] 250
+[signals_width] 150
+[sst_expanded] 1
+@28
+tb.clk
+@200
+-Counter
+@22
+tb.counter[7:0]
+@420
++{delta} tb.diff[3:0]
+@1000200
+-group_end
+"#;
+
+    #[test]
+    fn save_file_is_parsed() {
+        let save = GtkwSave::parse(SAVE_FILE);
+        assert_eq!(save.dumpfile.as_deref(), Some("/tmp/counter.vcd"));
+        assert_eq!(save.timestart, Some(BigInt::from(100)));
+        assert_eq!(save.zoom, Some(-3.));
+        assert_eq!(save.wave_width, Some(1000.));
+        assert_eq!(save.cursor, Some(BigInt::from(150)));
+        assert_eq!(save.markers, vec![(1, BigInt::from(400))]);
+        assert_eq!(
+            save.traces,
+            vec![
+                GtkwTrace::Signal {
+                    name: "tb.clk".to_string(),
+                    alias: None,
+                    translator: Some("Binary".to_string()),
+                },
+                GtkwTrace::Comment("Counter".to_string()),
+                GtkwTrace::Signal {
+                    name: "tb.counter[7:0]".to_string(),
+                    alias: None,
+                    translator: Some("Hexadecimal".to_string()),
+                },
+                GtkwTrace::Signal {
+                    name: "tb.diff[3:0]".to_string(),
+                    alias: Some("delta".to_string()),
+                    translator: Some("Signed".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn encoded_save_file_parses_to_the_same() {
+        let save = GtkwSave::parse(SAVE_FILE);
+        assert_eq!(GtkwSave::parse(&save.encode()), save);
+    }
+
+    #[test]
+    fn flags_round_trip_through_translators() {
+        for translator in [
+            "ASCII",
+            "Signed",
+            "Unsigned",
+            "Binary",
+            "Octal",
+            "Hexadecimal",
+        ] {
+            assert_eq!(
+                translator_for_flags(flags_for_translator(Some(translator))),
+                Some(translator)
+            );
+        }
+        assert_eq!(flags_for_translator(None), TR_RJUSTIFY | TR_HEX);
+    }
+}
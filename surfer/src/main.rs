@@ -1,7 +1,9 @@
 #![deny(unused_crate_dependencies)]
 
+mod activity;
 #[cfg(feature = "performance_plot")]
 mod benchmark;
+mod clock_cycles;
 mod clock_highlighting;
 mod command_prompt;
 mod config;
@@ -10,21 +12,29 @@ mod cxxrtl;
 #[cfg(not(target_arch = "wasm32"))]
 mod cxxrtl_container;
 mod data_container;
+mod delta_cycles;
+mod derived;
 mod dialog;
 mod displayed_item;
 mod drawing_canvas;
 mod file_watcher;
 mod graphics;
+mod gtkw;
 mod help;
 mod hierarchy;
 mod keys;
 mod logs;
 mod marker;
+mod measurement;
 mod menus;
 mod message;
 mod mousegestures;
+mod multi_wave_container;
 mod overview;
+mod protocol_decoder;
 mod remote;
+#[cfg(not(target_arch = "wasm32"))]
+mod render;
 mod state_util;
 mod statusbar;
 #[cfg(test)]
@@ -34,10 +44,13 @@ mod toolbar;
 mod transaction_container;
 mod translation;
 mod util;
+mod value_export;
+mod value_search;
 mod variable_direction;
 mod variable_name_filter;
 mod variable_name_type;
 mod variable_type;
+mod vcd_tail;
 mod view;
 mod viewport;
 mod wasm_api;
@@ -46,7 +59,9 @@ mod wasm_panic;
 mod wasm_util;
 mod wave_container;
 mod wave_data;
+mod wave_diff;
 mod wave_source;
+mod wavedrom;
 mod wellen;
 
 use std::cell::RefCell;
@@ -77,11 +92,12 @@ use log::{error, info, trace, warn};
 use num::BigInt;
 use ron::ser::PrettyConfig;
 use serde::{Deserialize, Serialize};
-use surfer_translation_types::Translator;
+use surfer_translation_types::{Translator, TranslatorArguments};
 use time::{TimeStringFormatting, TimeUnit};
 
 #[cfg(feature = "performance_plot")]
 use crate::benchmark::Timing;
+use crate::clock_cycles::ClockCycles;
 use crate::command_prompt::get_parser;
 use crate::config::{SurferConfig, SurferTheme};
 use crate::data_container::DataContainer;
@@ -89,20 +105,30 @@ use crate::data_container::DataContainer::Transactions;
 use crate::dialog::ReloadWaveformDialog;
 use crate::displayed_item::{
     DisplayedFieldRef, DisplayedItem, DisplayedItemIndex, DisplayedItemRef, FieldFormat,
+    ANALOG_HEIGHT_SCALING_FACTOR,
 };
 use crate::drawing_canvas::TxDrawingCommands;
 use crate::file_watcher::FileWatcher;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gtkw::GtkwSave;
+use crate::measurement::MeasurementCache;
 use crate::message::{HeaderResult, Message};
 use crate::transaction_container::{
     StreamScopeRef, TransactionContainer, TransactionRef, TransactionStreamRef,
 };
+use crate::translation::mapping_translator::{
+    ValueMapping, ValueMappingTranslator, VALUE_MAPPING_TRANSLATOR_NAME,
+};
+use crate::translation::rhdl::RhdlTranslator;
 #[cfg(feature = "spade")]
 use crate::translation::spade::SpadeTranslator;
 use crate::translation::{all_translators, AnyTranslator, TranslatorList};
+use crate::value_search::{ValueMatch, ValueSearch, MAX_SEARCHED_CHANGES};
 use crate::variable_name_filter::VariableNameFilterType;
+use crate::vcd_tail::Follower;
 use crate::viewport::Viewport;
 use crate::wasm_util::{perform_work, UrlArgs};
-use crate::wave_container::{ScopeRef, ScopeRefExt, VariableRef, WaveContainer};
+use crate::wave_container::{ScopeRef, ScopeRefExt, VariableRef, VariableRefExt, WaveContainer};
 use crate::wave_data::{ScopeType, WaveData};
 use crate::wave_source::{string_to_wavesource, LoadOptions, LoadProgress, WaveFormat, WaveSource};
 use crate::wellen::convert_format;
@@ -120,6 +146,10 @@ struct Args {
     spade_state: Option<Utf8PathBuf>,
     #[clap(long)]
     spade_top: Option<String>,
+    /// RHDL type description (RON) used to translate variables into structs, enums and arrays.
+    /// Defaults to `<wave file stem>.rhdl.ron` next to the waveform file if it exists.
+    #[clap(long)]
+    rhdl_types: Option<Utf8PathBuf>,
     /// Path to a file containing 'commands' to run after a waveform has been loaded.
     /// The commands are the same as those used in the command line interface inside the program.
     /// Commands are separated by lines or ;. Empty lines are ignored. Line comments starting with
@@ -135,6 +165,11 @@ struct Args {
     #[clap(long, short)]
     state_file: Option<Utf8PathBuf>,
 
+    /// Follow the VCD file while a simulator is still writing it, reading what is appended to
+    /// it instead of reloading it.
+    #[clap(long)]
+    follow: bool,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -168,11 +203,15 @@ enum Commands {
         #[arg(long)]
         file: String,
     },
+    #[cfg(not(target_arch = "wasm32"))]
+    /// renders the waves to an SVG or PNG image without opening a window
+    Render(render::RenderArgs),
 }
 
 struct StartupParams {
     pub spade_state: Option<Utf8PathBuf>,
     pub spade_top: Option<String>,
+    pub rhdl_types: Option<Utf8PathBuf>,
     pub waves: Option<WaveSource>,
     pub startup_commands: Vec<String>,
 }
@@ -183,6 +222,7 @@ impl StartupParams {
         Self {
             spade_state: None,
             spade_top: None,
+            rhdl_types: None,
             waves: None,
             startup_commands: vec![],
         }
@@ -193,6 +233,7 @@ impl StartupParams {
         Self {
             spade_state: None,
             spade_top: None,
+            rhdl_types: None,
             waves: url.load_url.map(WaveSource::Url),
             startup_commands: url.startup_commands.map(|c| vec![c]).unwrap_or_default(),
         }
@@ -200,29 +241,35 @@ impl StartupParams {
 
     #[allow(dead_code)] // NOTE: Only used in desktop version
     pub fn from_args(args: Args) -> Self {
-        let startup_commands = if let Some(cmd_file) = args.command_file() {
-            std::fs::read_to_string(cmd_file)
-                .map_err(|e| error!("Failed to read commands from {cmd_file}. {e:#?}"))
-                .ok()
-                .map(|file_content| {
-                    file_content
-                        .lines()
-                        .map(std::string::ToString::to_string)
-                        .collect()
-                })
-                .unwrap_or_default()
-        } else {
-            vec![]
-        };
+        let startup_commands = args
+            .command_file()
+            .as_ref()
+            .map(read_command_file)
+            .unwrap_or_default();
         Self {
             spade_state: args.spade_state,
             spade_top: args.spade_top,
+            rhdl_types: args.rhdl_types,
             waves: args.wave_file.map(|s| string_to_wavesource(&s)),
             startup_commands,
         }
     }
 }
 
+#[allow(dead_code)] // NOTE: Only used in desktop version
+fn read_command_file(cmd_file: &Utf8PathBuf) -> Vec<String> {
+    std::fs::read_to_string(cmd_file)
+        .map_err(|e| error!("Failed to read commands from {cmd_file}. {e:#?}"))
+        .ok()
+        .map(|file_content| {
+            file_content
+                .lines()
+                .map(std::string::ToString::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> Result<()> {
@@ -262,7 +309,12 @@ fn main() -> Result<()> {
         });
     });
 
+    if let Some(Commands::Render(render_args)) = args.command {
+        return render::render(render_args);
+    }
+
     let state_file = args.state_file.clone();
+    let follow = args.follow;
     let startup_params = StartupParams::from_args(args);
     let waves = startup_params.waves.clone();
 
@@ -284,6 +336,8 @@ fn main() -> Result<()> {
         None => State::new()?,
     }
     .with_params(startup_params);
+    // following starts once the body of the file has been loaded
+    state.follow |= follow;
 
     // install a file watcher that emits a `SuggestReloadWaveform` message
     // whenever the user-provided file changes.
@@ -481,6 +535,11 @@ pub struct SystemState {
     last_canvas_rect: RefCell<Option<Rect>>,
     variable_name_filter: RefCell<String>,
     item_renaming_string: RefCell<String>,
+    value_search_string: RefCell<String>,
+    decoder_query: RefCell<String>,
+    /// The markers to measure between, where `None` is the cursor
+    measurement_range: RefCell<(Option<u8>, Option<u8>)>,
+    measurement: MeasurementCache,
 
     /// These items should be expanded into subfields in the next frame. Cleared after each
     /// frame
@@ -500,6 +559,9 @@ pub struct SystemState {
     // Undo and Redo stacks
     undo_stack: Vec<CanvasState>,
     redo_stack: Vec<CanvasState>,
+
+    /// Reads what is appended to the file of the waves while it is followed
+    follower: Option<Follower>,
 }
 
 impl Default for SystemState {
@@ -537,6 +599,10 @@ impl SystemState {
             last_canvas_rect: RefCell::new(None),
             variable_name_filter: RefCell::new(String::new()),
             item_renaming_string: RefCell::new(String::new()),
+            value_search_string: RefCell::new(String::new()),
+            decoder_query: RefCell::new(String::new()),
+            measurement_range: RefCell::new((None, None)),
+            measurement: MeasurementCache::default(),
 
             items_to_expand: RefCell::new(vec![]),
             char_to_add_to_prompt: RefCell::new(None),
@@ -548,6 +614,7 @@ impl SystemState {
             timing: RefCell::new(Timing::new()),
             undo_stack: vec![],
             redo_stack: vec![],
+            follower: None,
         }
     }
 }
@@ -584,6 +651,10 @@ pub struct State {
     // Vector of translators which have failed at the `translates` function for a variable.
     blacklisted_translators: HashSet<(VariableRef, String)>,
 
+    /// User-defined value to label mappings, applied by the value mapping translator
+    #[serde(default)]
+    value_mappings: Vec<ValueMapping>,
+
     show_about: bool,
     show_keys: bool,
     show_gestures: bool,
@@ -592,6 +663,14 @@ pub struct State {
     show_performance: bool,
     show_logs: bool,
     show_cursor_window: bool,
+    #[serde(default)]
+    show_value_search: bool,
+    #[serde(default)]
+    show_measurement: bool,
+    #[serde(default)]
+    show_differences: bool,
+    #[serde(default)]
+    show_protocol_decoder: bool,
     wanted_timeunit: TimeUnit,
     time_string_format: Option<TimeStringFormatting>,
     show_url_entry: bool,
@@ -599,6 +678,12 @@ pub struct State {
     /// that surfer should reload changed files from disk.
     #[serde(skip, default)]
     show_reload_suggestion: Option<ReloadWaveformDialog>,
+    /// Read what is appended to the file of the waves instead of reloading it
+    #[serde(default)]
+    follow: bool,
+    /// Keep the end of the waves in view while following the file
+    #[serde(default)]
+    follow_pinned: bool,
     variable_name_filter_focused: bool,
     variable_name_filter_type: VariableNameFilterType,
     variable_name_filter_case_insensitive: bool,
@@ -649,6 +734,7 @@ impl State {
             previous_waves: None,
             count: None,
             blacklisted_translators: HashSet::new(),
+            value_mappings: vec![],
             show_about: false,
             show_keys: false,
             show_gestures: false,
@@ -656,11 +742,17 @@ impl State {
             show_license: false,
             show_logs: false,
             show_cursor_window: false,
+            show_value_search: false,
+            show_measurement: false,
+            show_differences: false,
+            show_protocol_decoder: false,
             wanted_timeunit: TimeUnit::None,
             time_string_format: None,
             show_url_entry: false,
             show_quick_start: false,
             show_reload_suggestion: None,
+            follow: false,
+            follow_pinned: false,
             rename_target: None,
             variable_name_filter_focused: false,
             variable_name_filter_type: VariableNameFilterType::Fuzzy,
@@ -694,12 +786,10 @@ impl State {
 
         // Long running translators which we load in a thread
         {
-            #[cfg(feature = "spade")]
             let sender = self.sys.channels.msg_sender.clone();
-            #[cfg(not(feature = "spade"))]
-            let _ = self.sys.channels.msg_sender.clone();
             let waves = args.waves.clone();
             perform_work(move || {
+                RhdlTranslator::load(&waves, &args.rhdl_types, sender.clone());
                 #[cfg(feature = "spade")]
                 SpadeTranslator::load(&waves, &args.spade_top, &args.spade_state, sender);
                 #[cfg(not(feature = "spade"))]
@@ -719,6 +809,13 @@ impl State {
                     LoadOptions::clean(),
                 ));
             }
+            #[cfg(not(target_arch = "wasm32"))]
+            Some(WaveSource::File(file)) if file.extension() == Some("gtkw") => {
+                match GtkwSave::read(&file) {
+                    Ok(save) => self.add_startup_message(Message::LoadGtkw(Box::new(save))),
+                    Err(e) => error!("{e:#}"),
+                }
+            }
             Some(WaveSource::File(file)) => {
                 self.add_startup_message(Message::LoadFile(file, LoadOptions::clean()));
             }
@@ -802,6 +899,75 @@ impl State {
                     waves.add_divider(name, vidx);
                 }
             }
+            Message::AddDerivedSignal(expression) => {
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                let undo_state =
+                    State::current_canvas_state(waves, format!("Add derived signal {expression}"));
+                match waves.add_derived(&expression, None, None) {
+                    Ok(cmd) => {
+                        self.push_undo_state(undo_state);
+                        if let Some(cmd) = cmd {
+                            self.load_variables(cmd);
+                        }
+                        self.invalidate_draw_commands();
+                    }
+                    Err(e) => warn!("{e:#}"),
+                }
+            }
+            Message::AddDecoder(query) => {
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                let undo_state = State::current_canvas_state(waves, format!("Add decoder {query}"));
+                match waves.add_decoder(&query, None) {
+                    Ok(cmd) => {
+                        self.push_undo_state(undo_state);
+                        if let Some(cmd) = cmd {
+                            self.load_variables(cmd);
+                        }
+                        self.decode_transactions();
+                        self.show_protocol_decoder = false;
+                        self.invalidate_draw_commands();
+                    }
+                    Err(e) => warn!("{e:#}"),
+                }
+            }
+            Message::ComposeBus(name) => {
+                self.save_current_canvas("Compose bus".into());
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                match waves.compose_bus(name) {
+                    Ok(cmd) => {
+                        if let Some(cmd) = cmd {
+                            self.load_variables(cmd);
+                        }
+                        self.invalidate_draw_commands();
+                    }
+                    Err(e) => warn!("{e:#}"),
+                }
+            }
+            Message::SplitVariable(vidx, slice_width) => {
+                self.save_current_canvas("Split variable".into());
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                let Some(vidx) = vidx.or(waves.focused_item) else {
+                    warn!("Splitting a variable requires a focused item");
+                    return;
+                };
+                match waves.split_variable(vidx, slice_width) {
+                    Ok(cmd) => {
+                        if let Some(cmd) = cmd {
+                            self.load_variables(cmd);
+                        }
+                        self.invalidate_draw_commands();
+                    }
+                    Err(e) => warn!("{e:#}"),
+                }
+            }
             Message::AddTimeLine(vidx) => {
                 self.save_current_canvas("Add timeline".into());
                 if let Some(waves) = self.waves.as_mut() {
@@ -1046,6 +1212,32 @@ impl State {
             }
             Message::SetLogsVisible(visibility) => self.show_logs = visibility,
             Message::SetCursorWindowVisible(visibility) => self.show_cursor_window = visibility,
+            Message::SetMeasurementVisible(visibility) => self.show_measurement = visibility,
+            Message::Measure(key) => self.start_measurement(key),
+            Message::Measured(key, measurement) => self.on_measured(key, measurement),
+            Message::SetDifferencesVisible(visibility) => self.show_differences = visibility,
+            Message::SetDecoderWindowVisible(visibility) => {
+                self.show_protocol_decoder = visibility;
+            }
+            Message::CountActivity => self.count_activity(),
+            Message::ActivityCounted(key, counts) => self.on_activity_counted(key, counts),
+            Message::FindClockEdges => self.find_clock_edges(),
+            Message::ClockEdgesFound(clock, version, edges) => {
+                self.on_clock_edges_found(clock, version, edges);
+            }
+            Message::DecodeTransactions => self.decode_transactions(),
+            Message::TransactionsDecoded(id, version, transactions) => {
+                self.on_transactions_decoded(id, version, transactions);
+            }
+            Message::SetValueSearchVisible(visibility) => {
+                self.show_value_search = visibility;
+                if let Some(search) = self.waves.as_ref().and_then(|w| w.value_search.as_ref()) {
+                    self.sys
+                        .value_search_string
+                        .borrow_mut()
+                        .clone_from(&search.query);
+                }
+            }
             Message::VerticalScroll(direction, count) => {
                 let Some(waves) = self.waves.as_mut() else {
                     return;
@@ -1199,6 +1391,57 @@ impl State {
                     }
                 };
             }
+            Message::GoToCycle(cycle) => {
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                let Some(time) = waves
+                    .cycle_clock
+                    .as_ref()
+                    .and_then(|cycles| cycles.cycle_time(cycle))
+                else {
+                    warn!("No cycle {cycle}, the cycle clock is not set or too short");
+                    return;
+                };
+                let time = BigInt::from(time);
+                let num_timestamps = waves.num_timestamps();
+                waves.viewports[0].go_to_time(&time, &num_timestamps);
+                waves.cursor = Some(time);
+                self.invalidate_draw_commands();
+            }
+            Message::SetCycleClock(clock) => {
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                waves.cycle_clock = None;
+                if let Some(clock) = clock {
+                    let Some(inner) = waves.inner.as_waves_mut() else {
+                        return;
+                    };
+                    let Some(clock) = inner.update_variable_ref(&clock) else {
+                        warn!("No variable {}", clock.full_path_string());
+                        return;
+                    };
+                    if inner
+                        .variable_meta(&clock)
+                        .map_or(true, |meta| meta.num_bits != Some(1))
+                    {
+                        warn!("{} is not a clock", clock.full_path_string());
+                        return;
+                    }
+                    let load_command = inner
+                        .load_variables(std::iter::once(&clock))
+                        .map_err(|e| warn!("{e:#}"))
+                        .ok()
+                        .flatten();
+                    waves.cycle_clock = Some(ClockCycles::new(clock));
+                    if let Some(cmd) = load_command {
+                        self.load_variables(cmd);
+                    }
+                    self.find_clock_edges();
+                }
+                self.invalidate_draw_commands();
+            }
             Message::SetTimeUnit(timeunit) => {
                 self.wanted_timeunit = timeunit;
                 self.invalidate_draw_commands();
@@ -1232,6 +1475,15 @@ impl State {
                     return;
                 }
 
+                if let Some(DisplayedItem::Derived(derived)) =
+                    waves.displayed_items.get_mut(&displayed_field_ref.item)
+                {
+                    derived.format = Some(format);
+                    derived.format_arguments = TranslatorArguments::default();
+                    self.invalidate_draw_commands();
+                    return;
+                }
+
                 let Some(DisplayedItem::Variable(displayed_variable)) =
                     waves.displayed_items.get_mut(&displayed_field_ref.item)
                 else {
@@ -1252,6 +1504,7 @@ impl State {
                     let new_info = translator.variable_info(&meta).unwrap();
 
                     displayed_variable.format = Some(format);
+                    displayed_variable.format_arguments = TranslatorArguments::default();
                     displayed_variable.info = new_info;
                 } else {
                     displayed_variable
@@ -1260,6 +1513,59 @@ impl State {
                     displayed_variable.field_formats.push(FieldFormat {
                         field: displayed_field_ref.field,
                         format,
+                        arguments: TranslatorArguments::default(),
+                    });
+                }
+                self.invalidate_draw_commands();
+            }
+            Message::VariableFormatArgumentChange(displayed_field_ref, name, value) => {
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                if !matches!(
+                    waves.displayed_items.get(&displayed_field_ref.item),
+                    Some(DisplayedItem::Variable(_))
+                ) {
+                    return;
+                }
+                let translator =
+                    waves.variable_translator(&displayed_field_ref, &self.sys.translators);
+                let translator_name = translator.name();
+                let Some(argument) = translator.arguments().into_iter().find(|a| a.name == name)
+                else {
+                    warn!("{translator_name} has no argument {name}");
+                    return;
+                };
+                let value = match argument.parse_value(&value) {
+                    Ok(value) => value,
+                    Err(e) => {
+                        warn!("{e:#}");
+                        return;
+                    }
+                };
+
+                let Some(DisplayedItem::Variable(displayed_variable)) =
+                    waves.displayed_items.get_mut(&displayed_field_ref.item)
+                else {
+                    return;
+                };
+                if displayed_field_ref.field.is_empty() {
+                    displayed_variable.format_arguments.set(&name, value);
+                } else if let Some(field_format) = displayed_variable
+                    .field_formats
+                    .iter_mut()
+                    .find(|ff| ff.field == displayed_field_ref.field)
+                {
+                    field_format.arguments.set(&name, value);
+                } else {
+                    // The field uses the default translator, make it explicit so the
+                    // arguments are stored together with it
+                    let mut arguments = TranslatorArguments::default();
+                    arguments.set(&name, value);
+                    displayed_variable.field_formats.push(FieldFormat {
+                        field: displayed_field_ref.field,
+                        format: translator_name,
+                        arguments,
                     });
                 }
                 self.invalidate_draw_commands();
@@ -1309,6 +1615,30 @@ impl State {
                     }
                 };
             }
+            Message::ItemHeightChangeStarted => {
+                self.save_current_canvas("Change item height".to_string());
+            }
+            Message::ItemHeightScalingFactorChange(vidx, factor) => {
+                if let Some(waves) = self.waves.as_mut() {
+                    if let Some(DisplayedItemIndex(idx)) = vidx.or(waves.focused_item) {
+                        waves.displayed_items_order.get(idx).map(|id| {
+                            waves
+                                .displayed_items
+                                .entry(*id)
+                                .and_modify(|item| item.set_height_scaling_factor(factor))
+                        });
+                    }
+                    if vidx.is_none() {
+                        for idx in waves.selected_items.iter() {
+                            waves
+                                .displayed_items
+                                .entry(*idx)
+                                .and_modify(|item| item.set_height_scaling_factor(factor));
+                        }
+                    }
+                    self.invalidate_draw_commands();
+                };
+            }
             Message::ItemBackgroundColorChange(vidx, color_name) => {
                 self.save_current_canvas(format!(
                     "Change item background color to {}",
@@ -1367,7 +1697,7 @@ impl State {
                 };
                 self.save_current_canvas(undo_msg.to_string());
                 if let Some(waves) = &mut self.waves {
-                    if let Some(inner) = waves.inner.as_transactions() {
+                    let transactions = if let Some(inner) = waves.inner.as_transactions() {
                         let mut transactions = waves
                             .displayed_items_order
                             .iter()
@@ -1388,8 +1718,13 @@ impl State {
                             })
                             .flatten()
                             .collect_vec();
-
                         transactions.sort();
+                        Some(transactions)
+                    } else {
+                        // the transactions of protocol decoders
+                        Some(waves.decoded_transaction_ids()).filter(|ids| !ids.is_empty())
+                    };
+                    if let Some(transactions) = transactions {
                         let tx = if let Some(focused_tx) = &waves.focused_transaction.0 {
                             let next_id = transactions
                                 .iter()
@@ -1427,18 +1762,103 @@ impl State {
                 }
             }
             Message::ResetVariableFormat(displayed_field_ref) => {
-                if let Some(DisplayedItem::Variable(displayed_variable)) = self
+                match self
                     .waves
                     .as_mut()
                     .and_then(|waves| waves.displayed_items.get_mut(&displayed_field_ref.item))
                 {
-                    if displayed_field_ref.field.is_empty() {
-                        displayed_variable.format = None;
-                    } else {
-                        displayed_variable
-                            .field_formats
-                            .retain(|ff| ff.field != displayed_field_ref.field);
+                    Some(DisplayedItem::Variable(displayed_variable)) => {
+                        if displayed_field_ref.field.is_empty() {
+                            displayed_variable.format = None;
+                            displayed_variable.format_arguments = TranslatorArguments::default();
+                        } else {
+                            displayed_variable
+                                .field_formats
+                                .retain(|ff| ff.field != displayed_field_ref.field);
+                        }
+                    }
+                    Some(DisplayedItem::Derived(derived)) => {
+                        derived.format = None;
+                        derived.format_arguments = TranslatorArguments::default();
+                    }
+                    _ => return,
+                }
+                self.invalidate_draw_commands();
+            }
+            Message::ExpandDeltaCycles(time) => {
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                let Some(time) = time.or_else(|| waves.cursor.clone()) else {
+                    warn!("Expanding delta cycles requires a time or a cursor");
+                    return;
+                };
+                let Some(utime) = time.to_biguint() else {
+                    return;
+                };
+                // the value column shows the values at the cursor, so move it to the deltas
+                waves.cursor = Some(time);
+                waves.expand_delta_cycles(utime);
+            }
+            Message::CollapseDeltaCycles => {
+                if let Some(waves) = self.waves.as_mut() {
+                    waves.delta_expansion = None;
+                }
+            }
+            Message::MoveDeltaCursor { next } => {
+                if let Some(waves) = self.waves.as_mut() {
+                    waves.move_delta_cursor(next);
+                }
+            }
+            Message::SetValueSearch(vidx, query) => {
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                let Some(item) = vidx
+                    .or(waves.focused_item)
+                    .and_then(|idx| waves.displayed_items_order.get(idx.0))
+                else {
+                    warn!("Value search requires a focused variable");
+                    return;
+                };
+                match ValueSearch::new(*item, &query) {
+                    Ok(search) => {
+                        waves.value_search = Some(search);
+                        self.invalidate_draw_commands();
                     }
+                    Err(e) => warn!("Invalid value search '{query}': {e}"),
+                }
+            }
+            Message::ClearValueSearch => {
+                if let Some(waves) = self.waves.as_mut() {
+                    waves.value_search = None;
+                    self.invalidate_draw_commands();
+                }
+            }
+            Message::MoveToValueMatch { next } => {
+                let Some(waves) = self.waves.as_ref() else {
+                    return;
+                };
+                let Some(start) = waves.search_start(next) else {
+                    return;
+                };
+                let time = match self.find_value_match(waves, &start, next, MAX_SEARCHED_CHANGES) {
+                    Some(ValueMatch::Found(time)) => time,
+                    Some(ValueMatch::Stopped(time)) => {
+                        info!(
+                            "No match in {MAX_SEARCHED_CHANGES} changes, search again to continue \
+                            from {time}"
+                        );
+                        time
+                    }
+                    None => {
+                        info!("No more matches for the value search");
+                        return;
+                    }
+                };
+                let waves = self.waves.as_mut().unwrap();
+                waves.cursor = Some(time.into());
+                if waves.go_to_cursor_if_not_in_view() {
                     self.invalidate_draw_commands();
                 }
             }
@@ -1456,6 +1876,24 @@ impl State {
             Message::LoadFromData(data, load_options) => {
                 self.load_from_data(data, load_options).ok();
             }
+            Message::AddFile(filename) => self.add_wave_file(WaveSource::File(filename)),
+            Message::AddWaveformFileFromUrl(url) => self.add_wave_file(WaveSource::Url(url)),
+            Message::SetFileTimeOffset { name, offset } => {
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                waves.set_file_time_offset(&name, offset);
+                self.find_clock_edges();
+                self.decode_transactions();
+                self.update_comparison();
+            }
+            Message::RemoveFile(name) => {
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                waves.remove_file(&name);
+                self.invalidate_draw_commands();
+            }
             #[cfg(feature = "python")]
             Message::LoadPythonTranslator(filename) => {
                 try_log_error!(
@@ -1463,6 +1901,44 @@ impl State {
                     "Error loading Python translator",
                 )
             }
+            Message::LoadValueMapping { pattern, file } => {
+                match ValueMapping::from_file(&file, pattern) {
+                    Ok(mapping) => {
+                        // a new mapping for the same pattern replaces the old one, and
+                        // takes precedence over mappings with other matching patterns
+                        self.value_mappings.retain(|m| m.pattern != mapping.pattern);
+                        self.value_mappings.insert(0, mapping.clone());
+                        self.update_value_mapping_translator();
+                        if let Some(waves) = &mut self.waves {
+                            for item in waves.displayed_items.values_mut() {
+                                if let DisplayedItem::Variable(variable) = item {
+                                    if mapping.matches(&variable.variable_ref.full_path_string()) {
+                                        variable.format =
+                                            Some(VALUE_MAPPING_TRANSLATOR_NAME.to_string());
+                                        variable.format_arguments = TranslatorArguments::default();
+                                    }
+                                }
+                            }
+                        }
+                        self.invalidate_draw_commands();
+                    }
+                    Err(e) => error!("Error loading value mapping: {e:#}"),
+                }
+            }
+            Message::ClearValueMappings => {
+                self.value_mappings.clear();
+                self.update_value_mapping_translator();
+                if let Some(waves) = &mut self.waves {
+                    for item in waves.displayed_items.values_mut() {
+                        if let DisplayedItem::Variable(variable) = item {
+                            if variable.format.as_deref() == Some(VALUE_MAPPING_TRANSLATOR_NAME) {
+                                variable.format = None;
+                            }
+                        }
+                    }
+                }
+                self.invalidate_draw_commands();
+            }
             Message::LoadSpadeTranslator { top, state } => {
                 #[cfg(feature = "spade")]
                 {
@@ -1501,12 +1977,16 @@ impl State {
                         let shared_hierarchy = Arc::new(header.hierarchy);
                         let new_waves =
                             Box::new(WaveContainer::new_waveform(shared_hierarchy.clone()));
-                        self.on_waves_loaded(
-                            source.clone(),
-                            convert_format(header.file_format),
-                            new_waves,
-                            load_options,
-                        );
+                        if self.is_pending_file(&source) {
+                            self.on_added_waves_loaded(&source, new_waves);
+                        } else {
+                            self.on_waves_loaded(
+                                source.clone(),
+                                convert_format(header.file_format),
+                                new_waves,
+                                load_options,
+                            );
+                        }
                         // start parsing of the body
                         self.load_wave_body(source, header.body, header.body_len, shared_hierarchy);
                     }
@@ -1516,12 +1996,16 @@ impl State {
                             server.clone(),
                             hierarchy.clone(),
                         ));
-                        self.on_waves_loaded(
-                            source.clone(),
-                            convert_format(file_format),
-                            new_waves,
-                            load_options,
-                        );
+                        if self.is_pending_file(&source) {
+                            self.on_added_waves_loaded(&source, new_waves);
+                        } else {
+                            self.on_waves_loaded(
+                                source.clone(),
+                                convert_format(file_format),
+                                new_waves,
+                                load_options,
+                            );
+                        }
                         // body is already being parsed on the server, we need to request the time table though
                         Self::get_time_table_from_server(
                             self.sys.channels.msg_sender.clone(),
@@ -1538,26 +2022,21 @@ impl State {
                     .waves
                     .as_mut()
                     .expect("Waves should be loaded at this point!");
+                // the body may belong to the first file or to an added file
+                let Some(body_waves) = waves.waves_from_source_mut(&source) else {
+                    error!("Loaded the body of {source}, which is no longer open");
+                    return;
+                };
                 // add source and time table
-                let maybe_cmd = waves
-                    .inner
-                    .as_waves_mut()
-                    .unwrap()
-                    .wellen_add_body(body)
-                    .unwrap_or_else(|err| {
-                        error!("While getting commands to lazy-load signals: {err:?}");
-                        None
-                    });
+                let maybe_cmd = body_waves.wellen_add_body(body).unwrap_or_else(|err| {
+                    error!("While getting commands to lazy-load signals: {err:?}");
+                    None
+                });
                 // Pre-load parameters
-                let param_cmd = waves
-                    .inner
-                    .as_waves_mut()
-                    .unwrap()
-                    .load_parameters()
-                    .unwrap_or_else(|err| {
-                        error!("While getting commands to lazy-load parameters: {err:?}");
-                        None
-                    });
+                let param_cmd = body_waves.load_parameters().unwrap_or_else(|err| {
+                    error!("While getting commands to lazy-load parameters: {err:?}");
+                    None
+                });
                 // update viewports, now that we have the time table
                 waves.update_viewports();
                 // make sure we redraw
@@ -1570,6 +2049,39 @@ impl State {
                 if let Some(cmd) = maybe_cmd {
                     self.load_variables(cmd);
                 }
+                // the compared files may be complete now
+                self.update_comparison();
+                if self.follow && self.waves.as_ref().is_some_and(|w| w.source == source) {
+                    self.start_following();
+                }
+            }
+            Message::Compare { file, rename_rules } => self.compare(file, rename_rules),
+            Message::CompareWithFile(filename, rename_rules) => {
+                self.compare_with_file(WaveSource::File(filename), rename_rules);
+            }
+            Message::UpdateComparison => self.update_comparison(),
+            Message::ClearComparison => {
+                if let Some(waves) = self.waves.as_mut() {
+                    waves.comparison = None;
+                    self.invalidate_draw_commands();
+                }
+            }
+            Message::MoveToDifference { next } => {
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                let Some(start) = waves.search_start(next) else {
+                    return;
+                };
+                match waves.find_difference(&start, next) {
+                    Some(time) => {
+                        waves.cursor = Some(time.into());
+                        if waves.go_to_cursor_if_not_in_view() {
+                            self.invalidate_draw_commands();
+                        }
+                    }
+                    None => info!("No more differences"),
+                }
             }
             Message::SignalsLoaded(start, res) => {
                 info!("Loaded {} variables in {:?}", res.len(), start.elapsed());
@@ -1583,6 +2095,14 @@ impl State {
                     Ok(Some(cmd)) => self.load_variables(cmd),
                     _ => {}
                 }
+                if let Some(waves) = self.waves.as_mut() {
+                    // count the activity again with the newly loaded variables
+                    waves.activity = None;
+                    waves.values_changed();
+                }
+                self.find_clock_edges();
+                self.decode_transactions();
+                self.update_comparison();
                 // make sure we redraw since now more variable data is available
                 self.invalidate_draw_commands();
             }
@@ -1770,6 +2290,8 @@ impl State {
                     translator.reload(self.sys.channels.msg_sender.clone());
                 }
             }
+            // what is appended is read instead while the file is followed
+            Message::SuggestReloadWaveform if self.sys.follower.is_some() => {}
             Message::SuggestReloadWaveform => match self.config.autoreload_files {
                 Some(true) => {
                     self.update(Message::ReloadWaveform(true));
@@ -1794,6 +2316,10 @@ impl State {
             Message::UpdateReloadWaveformDialog(dialog) => {
                 self.show_reload_suggestion = Some(dialog);
             }
+            Message::SetFollow(follow) => self.set_follow(follow),
+            Message::SetFollowPinned(pinned) => self.follow_pinned = pinned,
+            Message::FollowedFileChanged => self.read_followed_file(),
+            Message::FollowedFileRead(chunk) => self.add_followed_changes(*chunk),
             Message::RemovePlaceholders => {
                 if let Some(waves) = self.waves.as_mut() {
                     waves.remove_placeholders();
@@ -1844,6 +2370,36 @@ impl State {
                     }
                 }
             }
+            Message::VariableAnalogChange(vidx, analog) => {
+                self.save_current_canvas("Change analog display".to_string());
+                let Some(waves) = self.waves.as_mut() else {
+                    return;
+                };
+                let mut ids: Vec<DisplayedItemRef> = vec![];
+                if let Some(DisplayedItemIndex(idx)) = vidx.or(waves.focused_item) {
+                    ids.extend(waves.displayed_items_order.get(idx));
+                }
+                if vidx.is_none() {
+                    ids.extend(waves.selected_items.iter());
+                }
+                for id in ids {
+                    let (item_analog, height_scaling_factor) =
+                        match waves.displayed_items.get_mut(&id) {
+                            Some(DisplayedItem::Variable(variable)) => {
+                                (&mut variable.analog, &mut variable.height_scaling_factor)
+                            }
+                            Some(DisplayedItem::Derived(derived)) => {
+                                (&mut derived.analog, &mut derived.height_scaling_factor)
+                            }
+                            _ => continue,
+                        };
+                    *item_analog = analog;
+                    if analog.is_some() && height_scaling_factor.is_none() {
+                        *height_scaling_factor = Some(ANALOG_HEIGHT_SCALING_FACTOR);
+                    }
+                }
+                self.invalidate_draw_commands();
+            }
             Message::ForceVariableNameTypes(name_type) => {
                 if let Some(waves) = self.waves.as_mut() {
                     waves.force_variable_name_type(name_type);
@@ -1885,10 +2441,16 @@ impl State {
             Message::OpenFileDialog(mode) => {
                 self.open_file_dialog(mode);
             }
+            #[cfg(not(target_arch = "wasm32"))]
+            Message::OpenAddFileDialog => self.open_add_file_dialog(),
             #[cfg(feature = "python")]
             Message::OpenPythonPluginDialog => {
                 self.open_python_file_dialog();
             }
+            #[cfg(feature = "wasm_plugins")]
+            Message::OpenWasmTranslatorDialog => {
+                self.open_wasm_translator_dialog();
+            }
             #[cfg(feature = "python")]
             Message::ReloadPythonPlugin => {
                 try_log_error!(
@@ -1898,6 +2460,10 @@ impl State {
                 self.invalidate_draw_commands();
             }
             Message::SaveStateFile(path) => self.save_state_file(path),
+            Message::LoadGtkwFile(path) => self.load_gtkw_file(path),
+            Message::LoadGtkw(save) => self.load_gtkw(*save),
+            Message::ApplyGtkw(save) => self.apply_gtkw(&save),
+            Message::SaveGtkwFile(path) => self.save_gtkw_file(path),
             Message::LoadStateFile(path) => self.load_state_file(path),
             Message::LoadState(state, path) => self.load_state(state, path),
             Message::SetStateFile(path) => {
@@ -2053,6 +2619,17 @@ impl State {
                 self.drag_source_idx = None;
                 self.drag_target_idx = None;
             }
+            Message::ExportValues {
+                path,
+                clock,
+                start,
+                end,
+            } => self.export_values(path, clock, start, end),
+            Message::ExportWaveDrom {
+                path,
+                markers,
+                clock,
+            } => self.export_wavedrom(path, markers, clock),
             Message::VariableValueToClipbord(vidx) => {
                 if let Some(waves) = &self.waves {
                     if let Some(DisplayedItemIndex(vidx)) = vidx.or(waves.focused_item) {
@@ -2267,6 +2844,15 @@ impl State {
                     display_item_ref_counter: 0,
                     old_num_timestamps: None,
                     graphics: HashMap::new(),
+                    delta_expansion: None,
+                    value_search: None,
+                    cycle_clock: None,
+                    clock_edges: HashMap::new(),
+                    values_version: 0,
+                    decoding: HashSet::new(),
+                    activity: None,
+                    comparison: None,
+                    added_files: vec![],
                 },
                 None,
             )
@@ -2316,6 +2902,15 @@ impl State {
             display_item_ref_counter: 0,
             old_num_timestamps: None,
             graphics: HashMap::new(),
+            delta_expansion: None,
+            value_search: None,
+            cycle_clock: None,
+            clock_edges: HashMap::new(),
+            values_version: 0,
+            decoding: HashSet::new(),
+            activity: None,
+            comparison: None,
+            added_files: vec![],
         };
 
         self.invalidate_draw_commands();
@@ -2432,6 +3027,9 @@ impl State {
         mem::swap(&mut self.sys, &mut loaded_state.sys);
         // the config is also not exported and instance specific, swap back
         mem::swap(&mut self.config, &mut loaded_state.config);
+        // the value mapping translator is part of the system state, rebuild it from the
+        // loaded mappings before the variables are translated
+        self.update_value_mapping_translator();
 
         // swap back waves for inner, source, format since we want to keep the file
         // fix up all wave references from paths if a wave is loaded
@@ -2440,6 +3038,12 @@ impl State {
             (&mut self.waves, &mut loaded_state.waves)
         {
             mem::swap(&mut waves.active_scope, &mut new_waves.active_scope);
+            // the cycle clock is loaded along with the items
+            waves.cycle_clock = new_waves
+                .cycle_clock
+                .take()
+                .and_then(|cycles| waves.inner.as_waves()?.update_variable_ref(&cycles.clock))
+                .map(ClockCycles::new);
             let items = std::mem::take(&mut new_waves.displayed_items);
             let items_order = std::mem::take(&mut new_waves.displayed_items_order);
             let load_commands = waves.update_with_items(&items, items_order, &self.sys.translators);
@@ -2457,6 +3061,10 @@ impl State {
         if let Some(load_commands) = load_commands {
             self.load_variables(load_commands);
         };
+        // open the added files of the state, their items are placeholders until then
+        if let Some(new_waves) = &mut loaded_state.waves {
+            self.restore_added_files(mem::take(&mut new_waves.added_files));
+        }
 
         // reset drag to avoid confusion
         self.drag_started = false;
@@ -2477,6 +3085,14 @@ impl State {
         }
     }
 
+    fn update_value_mapping_translator(&mut self) {
+        self.sys
+            .translators
+            .add_or_replace(AnyTranslator::Full(Box::new(ValueMappingTranslator::new(
+                &self.value_mappings,
+            ))));
+    }
+
     /// Returns true if the waveform and all requested signals have been loaded.
     /// Used for testing to make sure the GUI is at its final state before taking a
     /// snapshot.
@@ -2543,18 +3159,44 @@ impl State {
         }
     }
 
+    /// Runs `work` on a snapshot of the waves on a worker thread and handles the message it
+    /// returns. The waves of a running simulation cannot be copied, and are worked on right
+    /// away.
+    pub fn perform_work_on_waves<F>(&self, work: F)
+    where
+        F: FnOnce(&WaveContainer) -> Message + Send + 'static,
+    {
+        let Some(waves) = self.waves.as_ref().and_then(|waves| waves.inner.as_waves()) else {
+            return;
+        };
+        let sender = self.sys.channels.msg_sender.clone();
+        match waves.snapshot() {
+            Some(snapshot) => perform_work(move || {
+                sender.send(work(&snapshot)).unwrap();
+                if let Some(ctx) = EGUI_CONTEXT.read().unwrap().as_ref() {
+                    ctx.request_repaint();
+                }
+            }),
+            None => sender.send(work(waves)).unwrap(),
+        }
+    }
+
     /// Push the current canvas state to the undo stack
     fn save_current_canvas(&mut self, message: String) {
         if let Some(waves) = &self.waves {
-            self.sys
-                .undo_stack
-                .push(State::current_canvas_state(waves, message));
+            self.push_undo_state(State::current_canvas_state(waves, message));
+        }
+    }
 
-            if self.sys.undo_stack.len() > self.config.undo_stack_size {
-                self.sys.undo_stack.remove(0);
-            }
-            self.sys.redo_stack.clear();
+    /// Push a canvas state that was taken before a change to the undo stack, once the change
+    /// has succeeded
+    fn push_undo_state(&mut self, state: CanvasState) {
+        self.sys.undo_stack.push(state);
+
+        if self.sys.undo_stack.len() > self.config.undo_stack_size {
+            self.sys.undo_stack.remove(0);
         }
+        self.sys.redo_stack.clear();
     }
 }
 
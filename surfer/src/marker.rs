@@ -9,7 +9,6 @@ use crate::{
     config::SurferTheme,
     displayed_item::{DisplayedItem, DisplayedMarker},
     message::Message,
-    time::time_string,
     view::{DrawingContext, ItemDrawingInfo},
     viewport::Viewport,
     wave_data::WaveData,
@@ -107,6 +106,7 @@ impl WaveData {
                     background_color: None,
                     name: None,
                     idx,
+                    height_scaling_factor: None,
                 }),
                 None,
             );
@@ -249,9 +249,9 @@ impl State {
                                         });
                                 }
                                 for (_, col_marker_time, _) in &markers {
-                                    ui.label(time_string(
-                                        &(*row_marker_time - *col_marker_time),
-                                        &waves.inner.metadata().timescale,
+                                    ui.label(waves.time_delta_string(
+                                        col_marker_time,
+                                        row_marker_time,
                                         &self.wanted_timeunit,
                                         &self.get_time_format(),
                                     ));
@@ -307,12 +307,11 @@ impl State {
             let x = waves.numbered_marker_location(drawing_info.idx, viewport, view_width);
 
             // Time string
-            let time = time_string(
+            let time = waves.time_string(
                 waves
                     .markers
                     .get(&drawing_info.idx)
                     .unwrap_or(&BigInt::from(0)),
-                &waves.inner.metadata().timescale,
                 &self.wanted_timeunit,
                 &self.get_time_format(),
             );
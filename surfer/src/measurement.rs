@@ -0,0 +1,448 @@
+//! Measurements of the focused variable between two markers, or between a marker and the
+//! cursor.
+//!
+//! Measurements are made on a worker thread. The last measurement is shown until the one for
+//! the current range arrives.
+use egui::{ComboBox, Context, Grid, Ui, Window};
+use num::{BigInt, BigUint, ToPrimitive, Zero};
+use surfer_translation_types::{TranslatorArguments, VariableValue};
+
+use crate::derived::Signal;
+use crate::displayed_item::{DisplayedFieldRef, DisplayedItem, DisplayedItemRef};
+use crate::message::Message;
+use crate::time::{frequency_string, time_string};
+use crate::translation::{DynTranslator, TranslatorList};
+use crate::value_search::Number;
+use crate::wave_container::WaveContainer;
+use crate::wave_data::WaveData;
+use crate::State;
+
+/// Measuring stops after this many value changes to bound the time and memory it takes
+const MAX_MEASURED_CHANGES: usize = 1_000_000;
+
+/// The item, range and values a measurement was made for, used to cache it between frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasurementKey {
+    item: DisplayedItemRef,
+    start: BigUint,
+    end: BigUint,
+    translator: String,
+    format_arguments: TranslatorArguments,
+    /// The [`WaveData::values_version`] of the measured values
+    values_version: u64,
+}
+
+/// The last measurement and the one that is being made, kept between frames
+#[derive(Default)]
+pub struct MeasurementCache {
+    /// The last measurement that was made, `None` if the variable has no value in the range
+    measured: Option<(MeasurementKey, Option<Measurement>)>,
+    /// The measurement that is being made on a worker thread
+    measuring: Option<MeasurementKey>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Measurement {
+    /// Number of value changes after the start of the range
+    pub transitions: usize,
+    /// Time spent high and low. Only measured for single bit variables
+    pub high_low: Option<(BigUint, BigUint)>,
+    /// Average time between rising edges, if there are at least two of them
+    pub period: Option<BigUint>,
+    /// Minimum, maximum and time weighted mean of the numeric values
+    pub numeric: Option<(f64, f64, f64)>,
+    /// The range has more than [`MAX_MEASURED_CHANGES`] changes and only its beginning was
+    /// measured
+    pub truncated: bool,
+}
+
+/// A value of the measured variable, which holds from `time` until the next sample
+struct Sample {
+    time: BigUint,
+    bit: Option<bool>,
+    number: Option<f64>,
+}
+
+impl Measurement {
+    fn new(samples: &[Sample], start: &BigUint, end: &BigUint, single_bit: bool) -> Self {
+        let mut high = BigUint::zero();
+        let mut low = BigUint::zero();
+        let mut rising_edges = vec![];
+        let mut min_max: Option<(f64, f64)> = None;
+        let (mut weighted_sum, mut total_weight) = (0., 0.);
+
+        for (i, sample) in samples.iter().enumerate() {
+            let until = samples.get(i + 1).map_or(end, |next| &next.time);
+            let duration = until - &sample.time;
+            match sample.bit {
+                Some(true) => high += &duration,
+                Some(false) => low += &duration,
+                None => {}
+            }
+            if i > 0 && sample.bit == Some(true) && samples[i - 1].bit == Some(false) {
+                rising_edges.push(&sample.time);
+            }
+            if let Some(number) = sample.number {
+                min_max = Some(min_max.map_or((number, number), |(min, max)| {
+                    (min.min(number), max.max(number))
+                }));
+                let weight = duration.to_f64().unwrap_or(f64::MAX);
+                weighted_sum += number * weight;
+                total_weight += weight;
+            }
+        }
+
+        let period = match rising_edges.as_slice() {
+            [first, .., last] => Some((*last - *first) / (rising_edges.len() - 1)),
+            _ => None,
+        };
+        Measurement {
+            transitions: samples.iter().filter(|s| &s.time > start).count(),
+            high_low: single_bit.then_some((high, low)),
+            period,
+            numeric: min_max.map(|(min, max)| {
+                // a range of zero length has a single value
+                let mean = if total_weight > 0. {
+                    weighted_sum / total_weight
+                } else {
+                    min
+                };
+                (min, max, mean)
+            }),
+            truncated: false,
+        }
+    }
+}
+
+/// The time of a numbered marker, or of the cursor if `marker` is `None`
+fn measurement_time(waves: &WaveData, marker: Option<u8>) -> Option<&BigInt> {
+    match marker {
+        Some(idx) => waves.markers.get(&idx),
+        None => waves.cursor.as_ref(),
+    }
+}
+
+fn measurement_end_combo(ui: &mut Ui, waves: &WaveData, id: &str, selected: &mut Option<u8>) {
+    let mut ends = vec![(None, "Cursor".to_string())];
+    ends.extend(
+        waves
+            .displayed_items
+            .values()
+            .filter_map(|item| match item {
+                DisplayedItem::Marker(marker) => Some((Some(marker.idx), item.name())),
+                _ => None,
+            }),
+    );
+    ends.sort_by_key(|(idx, _)| *idx);
+    let selected_text = ends
+        .iter()
+        .find(|(idx, _)| idx == selected)
+        .map(|(_, name)| name.clone())
+        .unwrap_or_default();
+    ComboBox::from_id_source(id)
+        .selected_text(selected_text)
+        .show_ui(ui, |ui| {
+            for (idx, name) in ends {
+                ui.selectable_value(selected, idx, name);
+            }
+        });
+}
+
+/// Measures the root value of `signal` from `start` to `end`, translating the values with
+/// `translator`
+fn measure(
+    signal: Signal,
+    waves: &WaveContainer,
+    translator: &DynTranslator,
+    translators: &TranslatorList,
+    start: &BigUint,
+    end: &BigUint,
+) -> Option<Measurement> {
+    let single_bit = signal.meta(waves).ok()?.num_bits == Some(1);
+    let sample = |time: BigUint, value: &VariableValue| Sample {
+        time,
+        bit: match value {
+            _ if !single_bit => None,
+            VariableValue::BigUint(v) => Some(!v.is_zero()),
+            VariableValue::String(s) => match s.as_str() {
+                "1" => Some(true),
+                "0" => Some(false),
+                _ => None,
+            },
+        },
+        // the numeric value of single bits is given by the high and low times
+        number: (!single_bit)
+            .then(|| signal.translate_field_value(waves, &[], translator, translators, value))
+            .flatten()
+            .flatten()
+            .and_then(|translated| Number::from_value(&translated.value, value))
+            .map(|number| number.to_f64()),
+    };
+    let query = |time: &BigUint| signal.query(waves, time).ok().flatten();
+
+    let mut result = query(start)?;
+    let mut previous = result.current.as_ref().map(|(_, value)| value.clone());
+    let mut samples = previous
+        .iter()
+        .map(|value| sample(start.clone(), value))
+        .collect::<Vec<_>>();
+    let mut truncated = false;
+    while let Some(time) = result.next.clone().filter(|time| time <= end) {
+        if samples.len() > MAX_MEASURED_CHANGES {
+            truncated = true;
+            break;
+        }
+        result = query(&time)?;
+        let Some((_, value)) = &result.current else {
+            break;
+        };
+        // some waveforms repeat the value without changing it
+        if previous.as_ref() != Some(value) {
+            samples.push(sample(time, value));
+            previous = Some(value.clone());
+        }
+    }
+
+    let end = match samples.last() {
+        Some(last) if truncated => last.time.clone(),
+        _ => end.clone(),
+    };
+    Some(Measurement {
+        truncated,
+        ..Measurement::new(&samples, start, &end, single_bit)
+    })
+}
+
+impl State {
+    /// Starts making the measurement for `key` on a worker thread. The result is delivered by
+    /// [`Message::Measured`].
+    pub fn start_measurement(&mut self, key: MeasurementKey) {
+        let Some(item) = self
+            .waves
+            .as_ref()
+            .and_then(|waves| waves.displayed_items.get(&key.item))
+            .cloned()
+        else {
+            return;
+        };
+        self.sys.measurement.measuring = Some(key.clone());
+        let translators = self.sys.translators.clone();
+        self.perform_work_on_waves(move |waves| {
+            let measurement = Signal::from_item(&item).and_then(|signal| {
+                let translator = translators.get_translator(&key.translator);
+                measure(
+                    signal,
+                    waves,
+                    translator,
+                    &translators,
+                    &key.start,
+                    &key.end,
+                )
+            });
+            Message::Measured(key, measurement)
+        });
+    }
+
+    pub fn on_measured(&mut self, key: MeasurementKey, measurement: Option<Measurement>) {
+        let cache = &mut self.sys.measurement;
+        if cache.measuring.as_ref() == Some(&key) {
+            cache.measuring = None;
+        }
+        cache.measured = Some((key, measurement));
+    }
+
+    pub fn draw_measurement_window(&self, ctx: &Context, msgs: &mut Vec<Message>) {
+        let Some(waves) = &self.waves else { return };
+        let mut open = true;
+        Window::new("Measurement")
+            .open(&mut open)
+            .collapsible(true)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let focused = waves
+                    .focused_item
+                    .and_then(|idx| waves.displayed_items_order.get(idx.0))
+                    .filter(|id| {
+                        waves
+                            .displayed_items
+                            .get(id)
+                            .and_then(Signal::from_item)
+                            .is_some()
+                    });
+                let Some(item) = focused.copied() else {
+                    ui.label("Focus a variable to measure it");
+                    return;
+                };
+                ui.label(format!("Measuring {}", waves.displayed_items[&item].name()));
+
+                let mut range = self.sys.measurement_range.borrow_mut();
+                ui.horizontal(|ui| {
+                    ui.label("From");
+                    measurement_end_combo(ui, waves, "measurement_start", &mut range.0);
+                    ui.label("to");
+                    measurement_end_combo(ui, waves, "measurement_end", &mut range.1);
+                });
+                let (Some(a), Some(b)) = (
+                    measurement_time(waves, range.0),
+                    measurement_time(waves, range.1),
+                ) else {
+                    ui.label("Place the cursor and markers to measure between");
+                    return;
+                };
+                let start = a.min(b).to_biguint().unwrap_or_default();
+                let end = a.max(b).to_biguint().unwrap_or_default();
+
+                let key = MeasurementKey {
+                    item,
+                    start,
+                    end,
+                    translator: waves
+                        .variable_translator(&DisplayedFieldRef::from(item), &self.sys.translators)
+                        .name(),
+                    format_arguments: waves
+                        .displayed_items
+                        .get(&item)
+                        .and_then(Signal::from_item)
+                        .map(|signal| signal.format_arguments().clone())
+                        .unwrap_or_default(),
+                    values_version: waves.values_version,
+                };
+                let cache = &self.sys.measurement;
+                let up_to_date = cache
+                    .measured
+                    .as_ref()
+                    .is_some_and(|(measured, _)| *measured == key);
+                if !up_to_date && cache.measuring.as_ref() != Some(&key) {
+                    msgs.push(Message::Measure(key));
+                }
+                let Some((measured, measurement)) = &cache.measured else {
+                    ui.label("Measuring...");
+                    return;
+                };
+                if !up_to_date {
+                    ui.label("Measuring...");
+                }
+                let Some(measurement) = measurement else {
+                    ui.label("The variable has no value in the range");
+                    return;
+                };
+
+                let timescale = &waves.inner.metadata().timescale;
+                let time = |time: &BigUint| {
+                    time_string(
+                        &BigInt::from(time.clone()),
+                        timescale,
+                        &self.wanted_timeunit,
+                        &self.get_time_format(),
+                    )
+                };
+                Grid::new("measurement")
+                    .num_columns(2)
+                    .striped(true)
+                    .spacing([10., 5.])
+                    .show(ui, |ui| {
+                        let mut row = |name: &str, value: String| {
+                            ui.label(name);
+                            ui.label(value);
+                            ui.end_row();
+                        };
+                        row("Duration", time(&(&measured.end - &measured.start)));
+                        row(
+                            "Transitions",
+                            format!(
+                                "{}{}",
+                                measurement.transitions,
+                                if measurement.truncated { "+" } else { "" }
+                            ),
+                        );
+                        if let Some((high, low)) = &measurement.high_low {
+                            row("High time", time(high));
+                            row("Low time", time(low));
+                            let total = high + low;
+                            if !total.is_zero() {
+                                let duty_cycle = high.to_f64().unwrap_or_default()
+                                    / total.to_f64().unwrap_or(f64::MAX);
+                                row("Duty cycle", format!("{:.1} %", duty_cycle * 100.));
+                            }
+                        }
+                        if let Some(period) = &measurement.period {
+                            row("Period", time(period));
+                            if let Some(frequency) = frequency_string(period, timescale) {
+                                row("Frequency", frequency);
+                            }
+                        }
+                        if let Some((min, max, mean)) = measurement.numeric {
+                            row("Minimum", min.to_string());
+                            row("Maximum", max.to_string());
+                            row("Mean", format!("{mean:.3}"));
+                        }
+                    });
+                if measurement.truncated {
+                    ui.label(format!(
+                        "Only the first {MAX_MEASURED_CHANGES} changes were measured"
+                    ));
+                }
+            });
+        if !open {
+            msgs.push(Message::SetMeasurementVisible(false));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn samples(values: &[(u32, Option<bool>, Option<f64>)]) -> Vec<Sample> {
+        values
+            .iter()
+            .map(|(time, bit, number)| Sample {
+                time: BigUint::from(*time),
+                bit: *bit,
+                number: *number,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clocks_give_duty_cycle_and_period() {
+        let clock = samples(&[
+            (0, Some(false), None),
+            (5, Some(true), None),
+            (8, Some(false), None),
+            (15, Some(true), None),
+            (18, Some(false), None),
+            (25, Some(true), None),
+        ]);
+        let measurement = Measurement::new(&clock, &0u8.into(), &30u8.into(), true);
+        assert_eq!(measurement.transitions, 5);
+        assert_eq!(
+            measurement.high_low,
+            Some((BigUint::from(11u8), BigUint::from(19u8)))
+        );
+        assert_eq!(measurement.period, Some(BigUint::from(10u8)));
+        assert_eq!(measurement.numeric, None);
+    }
+
+    #[test]
+    fn numeric_mean_is_time_weighted() {
+        let counter = samples(&[
+            (10, None, Some(1.)),
+            (12, None, Some(4.)),
+            (18, None, Some(-3.)),
+        ]);
+        let measurement = Measurement::new(&counter, &10u8.into(), &20u8.into(), false);
+        assert_eq!(measurement.transitions, 2);
+        assert_eq!(measurement.high_low, None);
+        assert_eq!(measurement.period, None);
+        assert_eq!(measurement.numeric, Some((-3., 4., 2.)));
+    }
+
+    #[test]
+    fn empty_range_has_single_value() {
+        let value = samples(&[(7, None, Some(3.))]);
+        let measurement = Measurement::new(&value, &7u8.into(), &7u8.into(), false);
+        assert_eq!(measurement.transitions, 0);
+        assert_eq!(measurement.numeric, Some((3., 3., 3.)));
+    }
+}
@@ -1,14 +1,23 @@
 //! Menu handling.
 use color_eyre::eyre::WrapErr;
-use egui::{menu, Button, Context, TextWrapMode, TopBottomPanel, Ui};
+use egui::{menu, Button, Context, DragValue, TextWrapMode, TopBottomPanel, Ui};
 use itertools::Itertools;
-use surfer_translation_types::{TranslationPreference, Translator};
+use surfer_translation_types::{
+    TranslationPreference, Translator, TranslatorArgument, TranslatorArgumentKind,
+    TranslatorArgumentValue, TranslatorArguments, VariableInfo,
+};
 
 use crate::wave_container::{FieldRef, VariableRefExt};
 use crate::{
     clock_highlighting::clock_highlight_type_menu,
     config::{ArrowKeyBindings, HierarchyStyle},
-    displayed_item::{DisplayedFieldRef, DisplayedItem, DisplayedItemIndex},
+    derived::Signal,
+    displayed_item::{
+        AnalogRange, AnalogSettings, AnalogStyle, DisplayedDerived, DisplayedFieldRef,
+        DisplayedItem, DisplayedItemIndex, DisplayedItemRef, MAX_HEIGHT_SCALING_FACTOR,
+        MIN_HEIGHT_SCALING_FACTOR,
+    },
+    drawing_canvas::supports_analog,
     message::Message,
     time::{timeformat_menu, timeunit_menu},
     variable_name_filter::variable_name_filter_type_menu,
@@ -91,12 +100,26 @@ impl State {
             b("Open file...", Message::OpenFileDialog(OpenMode::Open)).add_closing_menu(msgs, ui);
             b("Switch file...", Message::OpenFileDialog(OpenMode::Switch))
                 .add_closing_menu(msgs, ui);
+            #[cfg(not(target_arch = "wasm32"))]
+            b("Add file...", Message::OpenAddFileDialog)
+                .enabled(waves_loaded)
+                .add_closing_menu(msgs, ui);
             b(
                 "Reload",
                 Message::ReloadWaveform(self.config.behavior.keep_during_reload),
             )
             .shortcut("r")
             .add_closing_menu(msgs, ui);
+            ui.radio(self.follow, "Follow file").clicked().then(|| {
+                ui.close_menu();
+                msgs.push(Message::SetFollow(!self.follow));
+            });
+            ui.radio(self.follow_pinned, "Keep end in view when following")
+                .clicked()
+                .then(|| {
+                    ui.close_menu();
+                    msgs.push(Message::SetFollowPinned(!self.follow_pinned));
+                });
 
             b("Load state...", Message::LoadStateFile(None)).add_closing_menu(msgs, ui);
             #[cfg(not(target_arch = "wasm32"))]
@@ -110,16 +133,61 @@ impl State {
                     .add_closing_menu(msgs, ui);
             }
             b("Save state as...", Message::SaveStateFile(None)).add_closing_menu(msgs, ui);
+            let (start, end) = self
+                .waves
+                .as_ref()
+                .map(|waves| waves.visible_range())
+                .unwrap_or_default();
+            b(
+                "Export visible values...",
+                Message::ExportValues {
+                    path: None,
+                    clock: None,
+                    start,
+                    end,
+                },
+            )
+            .enabled(waves_loaded)
+            .add_closing_menu(msgs, ui);
+            let wavedrom_markers = self
+                .waves
+                .as_ref()
+                .and_then(|waves| waves.first_two_markers());
+            b(
+                "Export WaveDrom between markers...",
+                Message::ExportWaveDrom {
+                    path: None,
+                    markers: wavedrom_markers.unwrap_or_default(),
+                    // Sample on the cycle clock if there is one
+                    clock: self
+                        .waves
+                        .as_ref()
+                        .and_then(|waves| waves.cycle_clock.as_ref())
+                        .map(|cycles| cycles.clock.clone()),
+                },
+            )
+            .enabled(wavedrom_markers.is_some())
+            .add_closing_menu(msgs, ui);
+            b("Load GTKWave save file...", Message::LoadGtkwFile(None)).add_closing_menu(msgs, ui);
+            b("Export GTKWave save file...", Message::SaveGtkwFile(None))
+                .enabled(waves_loaded)
+                .add_closing_menu(msgs, ui);
             b("Open URL...", Message::SetUrlEntryVisible(true)).add_closing_menu(msgs, ui);
             #[cfg(feature = "python")]
             {
                 b("Add Python translator", Message::OpenPythonPluginDialog)
                     .add_closing_menu(msgs, ui);
-                b("Reload Python translator", Message::ReloadPythonPlugin)
+                b("Reload Python translators", Message::ReloadPythonPlugin)
                     .enabled(self.sys.translators.has_python_translator())
                     .add_closing_menu(msgs, ui);
                 b("Exit", Message::Exit).add_closing_menu(msgs, ui);
             }
+            #[cfg(feature = "wasm_plugins")]
+            b(
+                "Add WebAssembly translator...",
+                Message::OpenWasmTranslatorDialog,
+            )
+            .add_closing_menu(msgs, ui);
         });
         ui.menu_button("View", |ui: &mut Ui| {
             ui.style_mut().wrap_mode = Some(TextWrapMode::Extend);
@@ -160,6 +228,13 @@ impl State {
                 b("Add viewport", Message::AddViewport).add_closing_menu(msgs, ui);
                 b("Remove viewport", Message::RemoveViewport).add_closing_menu(msgs, ui);
                 ui.separator();
+                b("Differences", Message::SetDifferencesVisible(true)).add_closing_menu(msgs, ui);
+                b(
+                    "Protocol decoder...",
+                    Message::SetDecoderWindowVisible(true),
+                )
+                .add_closing_menu(msgs, ui);
+                ui.separator();
             }
 
             b("Toggle side panel", Message::ToggleSidePanel)
@@ -352,6 +427,10 @@ impl State {
             self.add_format_menu(&dfr, displayed_item, path, msgs, ui);
         }
 
+        if let DisplayedItem::Derived(derived) = displayed_item {
+            self.add_derived_menus(derived, displayed_item_id, vidx, msgs, ui);
+        }
+
         ui.menu_button("Color", |ui| {
             let selected_color = &displayed_item
                 .color()
@@ -422,13 +501,129 @@ impl State {
                         });
                 }
             });
+
+            if path.is_some_and(|path| path.field.is_empty())
+                && (variable.analog.is_some()
+                    || supports_analog(
+                        &Signal::Variable(variable),
+                        &displayed_item_id.into(),
+                        waves,
+                        &self.sys.translators,
+                    ))
+            {
+                ui.menu_button("Analog", |ui| {
+                    if let Some(analog) = analog_menu(ui, variable.analog) {
+                        msgs.push(Message::VariableAnalogChange(
+                            if waves.selected_items.contains(&displayed_item_id) {
+                                None
+                            } else {
+                                Some(vidx)
+                            },
+                            analog,
+                        ));
+                    }
+                });
+            }
         }
 
+        ui.menu_button("Height", |ui| {
+            let mut factor = displayed_item.height_scaling_factor();
+            let response = ui.add(
+                DragValue::new(&mut factor)
+                    .range(MIN_HEIGHT_SCALING_FACTOR..=MAX_HEIGHT_SCALING_FACTOR)
+                    .speed(0.05)
+                    .suffix(" rows"),
+            );
+            if response.drag_started() || response.gained_focus() {
+                msgs.push(Message::ItemHeightChangeStarted);
+            }
+            response.changed().then(|| {
+                msgs.push(Message::ItemHeightScalingFactorChange(
+                    if waves.selected_items.contains(&displayed_item_id) {
+                        None
+                    } else {
+                        Some(vidx)
+                    },
+                    factor,
+                ));
+            });
+        });
+
         if ui.button("Rename").clicked() {
             ui.close_menu();
             msgs.push(Message::RenameItem(Some(vidx)));
         }
 
+        if let DisplayedItem::Variable(_) = displayed_item {
+            if ui.button("Search values").clicked() {
+                ui.close_menu();
+                msgs.push(Message::FocusItem(vidx));
+                msgs.push(Message::SetValueSearchVisible(true));
+            }
+        }
+
+        if matches!(
+            displayed_item,
+            DisplayedItem::Variable(_) | DisplayedItem::Derived(_)
+        ) && ui.button("Measure").clicked()
+        {
+            ui.close_menu();
+            msgs.push(Message::FocusItem(vidx));
+            msgs.push(Message::SetMeasurementVisible(true));
+        }
+
+        if let DisplayedItem::Variable(variable) = displayed_item {
+            if matches!(variable.info, VariableInfo::Bool | VariableInfo::Clock) {
+                let is_cycle_clock = waves
+                    .cycle_clock
+                    .as_ref()
+                    .is_some_and(|cycles| cycles.clock == variable.variable_ref);
+                if is_cycle_clock && ui.button("Show time instead of cycles").clicked() {
+                    ui.close_menu();
+                    msgs.push(Message::SetCycleClock(None));
+                }
+                if !is_cycle_clock && ui.button("Count cycles of this clock").clicked() {
+                    ui.close_menu();
+                    msgs.push(Message::SetCycleClock(Some(variable.variable_ref.clone())));
+                }
+            }
+        }
+
+        if let DisplayedItem::Variable(variable) = displayed_item {
+            if !matches!(
+                variable.info,
+                VariableInfo::Bool
+                    | VariableInfo::Clock
+                    | VariableInfo::String
+                    | VariableInfo::Real
+            ) {
+                ui.menu_button("Split", |ui| {
+                    for (label, slice_width) in [("Bits", 1), ("Nibbles", 4), ("Bytes", 8)] {
+                        if ui.button(label).clicked() {
+                            ui.close_menu();
+                            msgs.push(Message::SplitVariable(Some(vidx), slice_width));
+                        }
+                    }
+                });
+            }
+        }
+
+        if waves.selected_items.len() > 1
+            && waves.selected_items.contains(&displayed_item_id)
+            && ui.button("Compose bus").clicked()
+        {
+            ui.close_menu();
+            msgs.push(Message::ComposeBus(None));
+        }
+
+        if let DisplayedItem::Variable(_) = displayed_item {
+            if ui.button("Decode protocol...").clicked() {
+                ui.close_menu();
+                msgs.push(Message::FocusItem(vidx));
+                msgs.push(Message::SetDecoderWindowVisible(true));
+            }
+        }
+
         if ui.button("Remove").clicked() {
             msgs.push(if waves.selected_items.contains(&displayed_item_id) {
                 Message::Batch(vec![
@@ -524,11 +719,27 @@ impl State {
         preferred_translators.sort_by(|a, b| numeric_sort::cmp(a, b));
         bad_translators.sort_by(|a, b| numeric_sort::cmp(a, b));
 
-        let selected_translator = match displayed_item {
+        let displayed_variable = match displayed_item {
             DisplayedItem::Variable(var) => Some(var),
             _ => None,
-        }
-        .and_then(|displayed_variable| displayed_variable.get_format(&displayed_field_ref.field));
+        };
+        let selected_translator = displayed_variable.and_then(|displayed_variable| {
+            displayed_variable.get_format(&displayed_field_ref.field)
+        });
+        let arguments = displayed_variable
+            .map(|_| {
+                waves
+                    .variable_translator(displayed_field_ref, &self.sys.translators)
+                    .arguments()
+            })
+            .unwrap_or_default();
+        let no_arguments = TranslatorArguments::default();
+        let current_arguments = displayed_variable
+            .and_then(|displayed_variable| {
+                displayed_variable.get_format_arguments(&displayed_field_ref.field)
+            })
+            .unwrap_or(&no_arguments);
+        let mut argument_msgs = vec![];
 
         let mut menu_entry = |ui: &mut Ui, name: &str| {
             ui.radio(selected_translator.is_some_and(|st| st == name), name)
@@ -554,6 +765,180 @@ impl State {
                     }
                 });
             }
+            if !arguments.is_empty() {
+                ui.separator();
+                ui.menu_button("Arguments", |ui| {
+                    for argument in &arguments {
+                        let current = current_arguments
+                            .get(&argument.name)
+                            .unwrap_or(&argument.default);
+                        if let Some(value) = translator_argument_entry(ui, argument, current) {
+                            argument_msgs.push(Message::VariableFormatArgumentChange(
+                                displayed_field_ref.clone(),
+                                argument.name.clone(),
+                                value,
+                            ));
+                        }
+                    }
+                });
+            }
         });
+        msgs.append(&mut argument_msgs);
     }
+
+    fn add_derived_menus(
+        &self,
+        derived: &DisplayedDerived,
+        displayed_item_id: DisplayedItemRef,
+        vidx: DisplayedItemIndex,
+        msgs: &mut Vec<Message>,
+        ui: &mut Ui,
+    ) {
+        let Some(waves) = &self.waves else { return };
+        let meta = derived.meta();
+        let mut translators = self
+            .sys
+            .translators
+            .all_translator_names()
+            .into_iter()
+            .filter(|name| {
+                matches!(
+                    self.sys.translators.get_translator(name).translates(&meta),
+                    Ok(TranslationPreference::Yes | TranslationPreference::Prefer)
+                )
+            })
+            .collect_vec();
+        translators.sort_by(|a, b| numeric_sort::cmp(a, b));
+
+        ui.menu_button("Format", |ui| {
+            for name in translators {
+                ui.radio(derived.format.as_deref() == Some(name), name)
+                    .clicked()
+                    .then(|| {
+                        ui.close_menu();
+                        msgs.push(Message::VariableFormatChange(
+                            DisplayedFieldRef::from(displayed_item_id),
+                            name.to_string(),
+                        ));
+                    });
+            }
+        });
+
+        if derived.analog.is_some()
+            || supports_analog(
+                &Signal::Derived(derived),
+                &displayed_item_id.into(),
+                waves,
+                &self.sys.translators,
+            )
+        {
+            ui.menu_button("Analog", |ui| {
+                if let Some(analog) = analog_menu(ui, derived.analog) {
+                    msgs.push(Message::VariableAnalogChange(
+                        if waves.selected_items.contains(&displayed_item_id) {
+                            None
+                        } else {
+                            Some(vidx)
+                        },
+                        analog,
+                    ));
+                }
+            });
+        }
+    }
+}
+
+/// Widgets for the analog display settings of a variable. Returns the new settings if they
+/// were changed
+fn analog_menu(ui: &mut Ui, current: Option<AnalogSettings>) -> Option<Option<AnalogSettings>> {
+    let mut changed = false;
+    let mut enabled = current.is_some();
+    let mut settings = current.unwrap_or_default();
+
+    changed |= ui.checkbox(&mut enabled, "Draw as analog").changed();
+    ui.add_enabled_ui(enabled, |ui| {
+        ui.separator();
+        changed |= ui
+            .radio_value(&mut settings.style, AnalogStyle::Step, "Step")
+            .changed();
+        changed |= ui
+            .radio_value(
+                &mut settings.style,
+                AnalogStyle::Interpolated,
+                "Interpolated",
+            )
+            .changed();
+        ui.separator();
+        let mut manual = matches!(settings.range, AnalogRange::Manual { .. });
+        changed |= ui
+            .radio_value(&mut manual, false, "Automatic range")
+            .changed();
+        changed |= ui.radio_value(&mut manual, true, "Manual range").changed();
+        let (mut min, mut max) = match settings.range {
+            AnalogRange::Manual { min, max } => (min, max),
+            AnalogRange::Auto => (0., 1.),
+        };
+        ui.add_enabled_ui(manual, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Min");
+                changed |= ui.add(DragValue::new(&mut min)).changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label("Max");
+                changed |= ui.add(DragValue::new(&mut max)).changed();
+            });
+        });
+        settings.range = if manual {
+            AnalogRange::Manual { min, max }
+        } else {
+            AnalogRange::Auto
+        };
+    });
+
+    changed.then(|| enabled.then_some(settings))
+}
+
+/// Widget for editing one translator argument. Returns the new value if it was changed
+fn translator_argument_entry(
+    ui: &mut Ui,
+    argument: &TranslatorArgument,
+    current: &TranslatorArgumentValue,
+) -> Option<String> {
+    ui.horizontal(|ui| {
+        ui.label(&argument.name)
+            .on_hover_text(&argument.description);
+        match (&argument.kind, current) {
+            (
+                TranslatorArgumentKind::Integer { min, max },
+                TranslatorArgumentValue::Integer(value),
+            ) => {
+                let mut value = *value;
+                ui.add(DragValue::new(&mut value).range(*min..=*max))
+                    .changed()
+                    .then(|| value.to_string())
+            }
+            (TranslatorArgumentKind::Float, _) => {
+                let mut value = match current {
+                    TranslatorArgumentValue::Float(value) => *value,
+                    TranslatorArgumentValue::Integer(value) => *value as f64,
+                    TranslatorArgumentValue::String(_) => 0.,
+                };
+                ui.add(DragValue::new(&mut value).speed(0.01))
+                    .changed()
+                    .then(|| value.to_string())
+            }
+            (TranslatorArgumentKind::Choice(choices), _) => {
+                let current = current.to_string();
+                let mut selected = None;
+                for choice in choices {
+                    if ui.radio(*choice == current, choice).clicked() {
+                        selected = Some(choice.clone());
+                    }
+                }
+                selected
+            }
+            _ => None,
+        }
+    })
+    .inner
 }
@@ -4,22 +4,28 @@ use derivative::Derivative;
 use egui::DroppedFile;
 use emath::{Pos2, Vec2};
 use ftr_parser::types::Transaction;
-use num::BigInt;
+use num::{BigInt, BigUint};
 use serde::Deserialize;
 use std::path::PathBuf;
 use surver::Status;
 
+use crate::activity::ActivityKey;
 use crate::graphics::{Graphic, GraphicId};
+use crate::gtkw::GtkwSave;
+use crate::measurement::{Measurement, MeasurementKey};
+use crate::protocol_decoder::DecodedTransaction;
 use crate::transaction_container::{
     StreamScopeRef, TransactionContainer, TransactionRef, TransactionStreamRef,
 };
 use crate::translation::DynTranslator;
+use crate::vcd_tail::TailChunk;
 use crate::viewport::ViewportStrategy;
 use crate::wave_data::ScopeType;
+use crate::wave_diff::RenameRule;
 use crate::{
     clock_highlighting::ClockHighlightType,
     config::ArrowKeyBindings,
-    displayed_item::{DisplayedFieldRef, DisplayedItemIndex, DisplayedItemRef},
+    displayed_item::{AnalogSettings, DisplayedFieldRef, DisplayedItemIndex, DisplayedItemRef},
     time::{TimeStringFormatting, TimeUnit},
     variable_name_type::VariableNameType,
     wave_container::{ScopeRef, VariableRef, WaveContainer},
@@ -79,11 +85,23 @@ pub enum Message {
     ScrollToItem(usize),
     SetScrollOffset(f32),
     VariableFormatChange(DisplayedFieldRef, String),
+    /// Set the translator argument named `.1` to `.2`. The value is parsed
+    /// according to the arguments of the current translator of the field
+    VariableFormatArgumentChange(DisplayedFieldRef, String, String),
     ItemSelectionClear,
     ItemColorChange(Option<DisplayedItemIndex>, Option<String>),
     ItemBackgroundColorChange(Option<DisplayedItemIndex>, Option<String>),
     ItemNameChange(Option<DisplayedItemIndex>, Option<String>),
+    /// Set the height of an item relative to the default row height. Applies to the
+    /// selected items if no index is given. Does not save an undo step, as dragging
+    /// changes the height every frame; send [`Message::ItemHeightChangeStarted`] first
+    ItemHeightScalingFactorChange(Option<DisplayedItemIndex>, f32),
+    /// Save the item heights as one undo step before they are changed
+    ItemHeightChangeStarted,
     ChangeVariableNameType(Option<DisplayedItemIndex>, VariableNameType),
+    /// Draw the variable as an analog waveform, or as a regular one if `None`.
+    /// Applies to the selected items if no index is given
+    VariableAnalogChange(Option<DisplayedItemIndex>, Option<AnalogSettings>),
     ForceVariableNameTypes(VariableNameType),
     SetNameAlignRight(bool),
     SetClockHighlightType(ClockHighlightType),
@@ -105,13 +123,59 @@ pub enum Message {
         viewport_idx: usize,
     },
     CursorSet(BigInt),
+    /// Show the delta cycles at the given time, or at the cursor if no time is given
+    ExpandDeltaCycles(Option<BigInt>),
+    CollapseDeltaCycles,
+    MoveDeltaCursor {
+        next: bool,
+    },
+    /// Search the values of a variable for a condition like `== FETCH` or `in 0x80..0x8F`
+    SetValueSearch(Option<DisplayedItemIndex>, String),
+    ClearValueSearch,
+    /// Move the cursor to the next or previous time the searched variable matches
+    MoveToValueMatch {
+        next: bool,
+    },
     #[serde(skip)]
     SurferServerStatus(web_time::Instant, String, Status),
     LoadFile(Utf8PathBuf, LoadOptions),
     LoadWaveformFileFromUrl(String, LoadOptions),
     LoadFromData(Vec<u8>, LoadOptions),
+    /// Open a waveform file next to the loaded waves
+    AddFile(Utf8PathBuf),
+    AddWaveformFileFromUrl(String),
+    /// Shift the times of an added file by `offset` to align it with the first file
+    SetFileTimeOffset {
+        name: String,
+        offset: BigInt,
+    },
+    /// Close an added file and remove the items showing its variables
+    RemoveFile(String),
+    /// Compare the first file with an added file, pairing variables by their full paths after
+    /// applying the rename rules
+    Compare {
+        file: String,
+        rename_rules: Vec<RenameRule>,
+    },
+    /// Add a file and compare the first file with it
+    CompareWithFile(Utf8PathBuf, Vec<RenameRule>),
+    ClearComparison,
+    /// Compare the displayed variables after they have changed
+    UpdateComparison,
+    /// Move the cursor to the start of the next or previous difference between the compared
+    /// files
+    MoveToDifference {
+        next: bool,
+    },
     #[cfg(feature = "python")]
     LoadPythonTranslator(Utf8PathBuf),
+    /// Load a value to label mapping from a toml or csv file and apply it to all variables
+    /// whose full path matches `pattern`
+    LoadValueMapping {
+        pattern: String,
+        file: Utf8PathBuf,
+    },
+    ClearValueMappings,
     /// Load a spade translator using the specified top and the specified state encoded as ron.
     LoadSpadeTranslator {
         top: String,
@@ -182,6 +246,14 @@ pub enum Message {
     /// Update the waveform dialog UI with the provided dialog model.
     #[serde(skip)]
     UpdateReloadWaveformDialog(ReloadWaveformDialog),
+    /// Read what a simulator appends to the VCD file of the waves instead of reloading it
+    SetFollow(bool),
+    /// Keep the end of the waves in view while following the file
+    SetFollowPinned(bool),
+    #[serde(skip)]
+    FollowedFileChanged,
+    #[serde(skip)]
+    FollowedFileRead(#[derivative(Debug = "ignore")] Box<TailChunk>),
     RemovePlaceholders,
     ZoomToFit {
         viewport_idx: usize,
@@ -193,6 +265,10 @@ pub enum Message {
         viewport_idx: usize,
     },
     GoToTime(Option<BigInt>, usize),
+    /// Move the cursor and the first viewport to the start of a cycle of the cycle clock
+    GoToCycle(usize),
+    /// Count time in cycles of a clock instead of in time units, or stop doing so if `None`
+    SetCycleClock(Option<VariableRef>),
     ToggleMenu,
     ToggleToolbar,
     ToggleOverview,
@@ -211,11 +287,25 @@ pub enum Message {
     SelectPrevCommand,
     SelectNextCommand,
     OpenFileDialog(OpenMode),
+    #[cfg(not(target_arch = "wasm32"))]
+    OpenAddFileDialog,
     #[cfg(feature = "python")]
     OpenPythonPluginDialog,
+    #[cfg(feature = "wasm_plugins")]
+    OpenWasmTranslatorDialog,
     #[cfg(feature = "python")]
     ReloadPythonPlugin,
     SaveStateFile(Option<PathBuf>),
+    /// Load a GTKWave save file, or pick one if the path is `None`
+    LoadGtkwFile(Option<PathBuf>),
+    #[serde(skip)]
+    LoadGtkw(Box<GtkwSave>),
+    /// Replace the items, markers and view by those of a GTKWave save file
+    #[serde(skip)]
+    ApplyGtkw(Box<GtkwSave>),
+    /// Export the items, markers and view to a GTKWave save file, or pick one if the path is
+    /// `None`
+    SaveGtkwFile(Option<PathBuf>),
     LoadStateFile(Option<PathBuf>),
     LoadState(crate::State, Option<PathBuf>),
     SetStateFile(PathBuf),
@@ -235,6 +325,30 @@ pub enum Message {
     SetPerformanceVisible(bool),
     SetContinuousRedraw(bool),
     SetCursorWindowVisible(bool),
+    SetValueSearchVisible(bool),
+    /// Show the measurements of the focused variable between markers
+    SetMeasurementVisible(bool),
+    /// Make a measurement on a worker thread
+    #[serde(skip)]
+    Measure(MeasurementKey),
+    #[serde(skip)]
+    Measured(MeasurementKey, Option<Measurement>),
+    SetDifferencesVisible(bool),
+    SetDecoderWindowVisible(bool),
+    /// Count the value changes shown in the overview for the current variables
+    CountActivity,
+    #[serde(skip)]
+    ActivityCounted(ActivityKey, Vec<u64>),
+    /// Find the rising edges of the clocks whose values have changed
+    FindClockEdges,
+    /// The rising edges of a clock for a [`crate::wave_data::WaveData::values_version`],
+    /// `None` if the clock is not loaded
+    #[serde(skip)]
+    ClockEdgesFound(VariableRef, u64, Option<Vec<BigUint>>),
+    /// Decode the transactions of the decoders whose variables have changed
+    DecodeTransactions,
+    #[serde(skip)]
+    TransactionsDecoded(DisplayedItemRef, u64, Option<Vec<DecodedTransaction>>),
     ToggleFullscreen,
     SetHierarchyStyle(HierarchyStyle),
     SetArrowKeyBindings(ArrowKeyBindings),
@@ -244,6 +358,19 @@ pub enum Message {
     // Argument is position to insert after, None inserts after focused item,
     // or last if no focused item
     AddTimeLine(Option<DisplayedItemIndex>),
+    /// Add a derived signal computed from an expression over variables, e.g. `a.valid && a.ready`.
+    /// It is inserted after the focused item, or last if no item is focused
+    AddDerivedSignal(String),
+    /// Concatenate the selected items into a bus with an optional name. The topmost item is
+    /// the most significant part
+    ComposeBus(Option<String>),
+    /// Add a decoder turning bit-level variables into transactions, e.g.
+    /// `uart rx=tb.tx baud=115200`. It is inserted after the focused item, or last if no
+    /// item is focused
+    AddDecoder(String),
+    /// Split a variable into slices with the given number of bits. Splits the focused item
+    /// if no index is given
+    SplitVariable(Option<DisplayedItemIndex>, u32),
     ToggleTickLines,
     ToggleVariableTooltip,
     /// Set a marker at a specific position. If it doesn't exist, it will be created
@@ -262,6 +389,22 @@ pub enum Message {
         next: bool,
     },
     VariableValueToClipbord(Option<DisplayedItemIndex>),
+    /// Write the displayed items between the two markers as WaveDrom WaveJSON, sampled at the
+    /// rising edges of `clock` or at every change. Opens a file dialog if `path` is `None`.
+    ExportWaveDrom {
+        path: Option<PathBuf>,
+        markers: (u8, u8),
+        clock: Option<VariableRef>,
+    },
+    /// Write the values of the displayed items from `start` to `end` to a CSV or JSON file,
+    /// at every change or at the rising edges of `clock`. Opens a file dialog if `path` is
+    /// `None`.
+    ExportValues {
+        path: Option<PathBuf>,
+        clock: Option<VariableRef>,
+        start: Option<BigUint>,
+        end: Option<BigUint>,
+    },
     InvalidateDrawCommands,
     AddGraphic(GraphicId, Graphic),
     RemoveGraphic(GraphicId),
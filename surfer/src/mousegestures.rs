@@ -4,7 +4,6 @@ use emath::{Align2, Pos2, Rect, RectTransform, Vec2};
 use epaint::{FontId, Stroke};
 
 use crate::config::SurferTheme;
-use crate::time::time_string;
 use crate::view::DrawingContext;
 use crate::{wave_data::WaveData, Message, State};
 
@@ -215,23 +214,21 @@ impl State {
             (ctx.to_screen)(current_location.x, current_location.y),
             format!(
                 "Zoom in: {} to {}",
-                time_string(
+                waves.time_string(
                     &(waves.viewports[viewport_idx].as_time_bigint(
                         minx,
                         width,
                         &waves.num_timestamps()
                     )),
-                    &waves.inner.metadata().timescale,
                     &self.wanted_timeunit,
                     &self.get_time_format()
                 ),
-                time_string(
+                waves.time_string(
                     &(waves.viewports[viewport_idx].as_time_bigint(
                         maxx,
                         width,
                         &waves.num_timestamps()
                     )),
-                    &waves.inner.metadata().timescale,
                     &self.wanted_timeunit,
                     &self.get_time_format()
                 ),
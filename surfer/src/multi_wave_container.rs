@@ -0,0 +1,810 @@
+//! Several waveform files opened side by side in one session. The scopes of the file that
+//! was opened first are shown as usual, and every added file gets a root scope named after
+//! it, under which its own hierarchy is shown. Variables of added files are referred to by
+//! paths starting with that name, which is how saved states remember the file an item came
+//! from.
+use color_eyre::Result;
+use log::{info, warn};
+use num::{BigInt, BigUint, ToBigInt, Zero};
+use serde::{Deserialize, Serialize};
+use surfer_translation_types::VariableValue;
+
+use crate::data_container::DataContainer;
+use crate::displayed_item::DisplayedItem;
+use crate::translation::TranslatorList;
+use crate::wave_container::{
+    MetaData, QueryResult, ScopeRef, ScopeRefExt, SimulationStatus, VariableMeta, VariableRef,
+    WaveContainer,
+};
+use crate::wave_data::WaveData;
+use crate::wave_source::{LoadOptions, WaveSource};
+use crate::wellen::{LoadSignalsCmd, LoadSignalsResult};
+use crate::State;
+
+/// A file added to the session. Saved with the state so that the file can be opened again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddedFile {
+    /// Name of the root scope of the file
+    pub name: String,
+    pub source: WaveSource,
+    /// Added to the times in the file to align it with the first file
+    pub time_offset: BigInt,
+}
+
+pub struct AddedWaves {
+    pub name: String,
+    pub time_offset: BigInt,
+    pub waves: WaveContainer,
+}
+
+impl AddedWaves {
+    /// Time in the file for a time in the session. `None` before the start of the file.
+    fn local_time(&self, time: &BigUint) -> Option<BigUint> {
+        (time.to_bigint()? - &self.time_offset).to_biguint()
+    }
+
+    /// Time in the session for a time in the file
+    fn session_time(&self, time: BigUint) -> BigUint {
+        (BigInt::from(time) + &self.time_offset)
+            .to_biguint()
+            .unwrap_or_default()
+    }
+
+    fn to_session_scope(&self, scope: ScopeRef) -> ScopeRef {
+        ScopeRef {
+            strs: std::iter::once(self.name.clone())
+                .chain(scope.strs)
+                .collect(),
+            id: scope.id,
+        }
+    }
+
+    pub fn to_session_variable(&self, variable: VariableRef) -> VariableRef {
+        VariableRef {
+            path: self.to_session_scope(variable.path),
+            name: variable.name,
+            id: variable.id,
+        }
+    }
+}
+
+pub struct MultiWaveContainer {
+    /// The file that was opened first
+    pub primary: WaveContainer,
+    pub added: Vec<AddedWaves>,
+}
+
+impl MultiWaveContainer {
+    /// The added file a scope is in, and the scope within that file. `None` for scopes of the
+    /// primary file.
+    fn split_scope(&self, scope: &ScopeRef) -> Option<(&AddedWaves, ScopeRef)> {
+        let (first, rest) = scope.strs.split_first()?;
+        let added = self.added.iter().find(|added| &added.name == first)?;
+        let local = ScopeRef {
+            strs: rest.to_vec(),
+            id: scope.id.clone(),
+        };
+        Some((added, local))
+    }
+
+    fn split_variable(&self, variable: &VariableRef) -> Option<(&AddedWaves, VariableRef)> {
+        let (added, path) = self.split_scope(&variable.path)?;
+        let local = VariableRef {
+            path,
+            name: variable.name.clone(),
+            id: variable.id.clone(),
+        };
+        Some((added, local))
+    }
+
+    pub fn snapshot(&self) -> Option<MultiWaveContainer> {
+        Some(MultiWaveContainer {
+            primary: self.primary.snapshot()?,
+            added: self
+                .added
+                .iter()
+                .map(|added| {
+                    Some(AddedWaves {
+                        name: added.name.clone(),
+                        time_offset: added.time_offset.clone(),
+                        waves: added.waves.snapshot()?,
+                    })
+                })
+                .collect::<Option<_>>()?,
+        })
+    }
+
+    fn all_waves(&self) -> impl Iterator<Item = &WaveContainer> {
+        std::iter::once(&self.primary).chain(self.added.iter().map(|added| &added.waves))
+    }
+
+    pub fn wants_anti_aliasing(&self) -> bool {
+        self.primary.wants_anti_aliasing()
+    }
+
+    pub fn is_fully_loaded(&self) -> bool {
+        self.all_waves().all(WaveContainer::is_fully_loaded)
+    }
+
+    pub fn body_loaded(&self) -> bool {
+        self.all_waves().all(WaveContainer::body_loaded)
+    }
+
+    pub fn variable_names(&self) -> Vec<String> {
+        let added = self.added.iter().flat_map(|added| {
+            added
+                .waves
+                .variable_names()
+                .into_iter()
+                .map(|name| format!("{}.{name}", added.name))
+        });
+        self.primary
+            .variable_names()
+            .into_iter()
+            .chain(added)
+            .collect()
+    }
+
+    pub fn variables(&self) -> Vec<VariableRef> {
+        let added = self.added.iter().flat_map(|added| {
+            added
+                .waves
+                .variables()
+                .into_iter()
+                .map(|variable| added.to_session_variable(variable))
+        });
+        self.primary.variables().into_iter().chain(added).collect()
+    }
+
+    pub fn variables_in_scope(&self, scope: &ScopeRef) -> Vec<VariableRef> {
+        match self.split_scope(scope) {
+            Some((added, local)) => added
+                .waves
+                .variables_in_scope(&local)
+                .into_iter()
+                .map(|variable| added.to_session_variable(variable))
+                .collect(),
+            None => self.primary.variables_in_scope(scope),
+        }
+    }
+
+    pub fn no_variables_in_scope(&self, scope: &ScopeRef) -> bool {
+        match self.split_scope(scope) {
+            Some((added, local)) => added.waves.no_variables_in_scope(&local),
+            None => self.primary.no_variables_in_scope(scope),
+        }
+    }
+
+    pub fn load_variables<S: AsRef<VariableRef>, T: Iterator<Item = S>>(
+        &mut self,
+        variables: T,
+    ) -> Result<Option<LoadSignalsCmd>> {
+        let mut primary = vec![];
+        let mut added = vec![vec![]; self.added.len()];
+        for variable in variables {
+            let variable = variable.as_ref();
+            match self.split_variable(variable) {
+                Some((file, local)) => {
+                    let idx = self.added.iter().position(|a| a.name == file.name).unwrap();
+                    added[idx].push(local);
+                }
+                None => primary.push(variable.clone()),
+            }
+        }
+
+        let mut cmds = vec![self.primary.load_variables(primary.iter())?];
+        for (file, variables) in self.added.iter_mut().zip(added) {
+            if !variables.is_empty() {
+                cmds.push(file.waves.load_variables(variables.iter())?);
+            }
+        }
+        Ok(LoadSignalsCmd::merge(cmds.into_iter().flatten()))
+    }
+
+    pub fn on_signals_loaded(&mut self, res: LoadSignalsResult) -> Result<Option<LoadSignalsCmd>> {
+        let requested_by = |waves: &WaveContainer| match waves {
+            WaveContainer::Wellen(f) => f.requested(&res),
+            _ => false,
+        };
+        match self
+            .added
+            .iter_mut()
+            .find(|added| requested_by(&added.waves))
+        {
+            Some(added) => added.waves.on_signals_loaded(res),
+            None => self.primary.on_signals_loaded(res),
+        }
+    }
+
+    pub fn variable_meta(&self, variable: &VariableRef) -> Result<VariableMeta> {
+        match self.split_variable(variable) {
+            Some((added, local)) => {
+                let mut meta = added.waves.variable_meta(&local)?;
+                meta.var = variable.clone();
+                Ok(meta)
+            }
+            None => self.primary.variable_meta(variable),
+        }
+    }
+
+    pub fn query_variable(
+        &self,
+        variable: &VariableRef,
+        time: &BigUint,
+    ) -> Result<Option<QueryResult>> {
+        let Some((added, local)) = self.split_variable(variable) else {
+            return self.primary.query_variable(variable, time);
+        };
+        let Some(local_time) = added.local_time(time) else {
+            // the file has not started yet, so its first value is the next change
+            let first = added.waves.query_variable(&local, &BigUint::zero())?;
+            return Ok(first.map(|res| QueryResult {
+                current: None,
+                next: res
+                    .current
+                    .map(|(time, _)| time)
+                    .or(res.next)
+                    .map(|time| added.session_time(time)),
+            }));
+        };
+        let res = added.waves.query_variable(&local, &local_time)?;
+        Ok(res.map(|res| QueryResult {
+            current: res
+                .current
+                .map(|(time, value)| (added.session_time(time), value)),
+            next: res.next.map(|time| added.session_time(time)),
+        }))
+    }
+
+    pub fn query_variable_deltas(
+        &self,
+        variable: &VariableRef,
+        time: &BigUint,
+    ) -> Result<Vec<VariableValue>> {
+        match self.split_variable(variable) {
+            Some((added, local)) => match added.local_time(time) {
+                Some(local_time) => added.waves.query_variable_deltas(&local, &local_time),
+                None => Ok(vec![]),
+            },
+            None => self.primary.query_variable_deltas(variable, time),
+        }
+    }
+
+    pub fn update_variable_ref(&self, variable: &VariableRef) -> Option<VariableRef> {
+        match self.split_variable(variable) {
+            Some((added, local)) => added
+                .waves
+                .update_variable_ref(&local)
+                .map(|variable| added.to_session_variable(variable)),
+            None => self.primary.update_variable_ref(variable),
+        }
+    }
+
+    pub fn scope_names(&self) -> Vec<String> {
+        let added = self.added.iter().flat_map(|added| {
+            std::iter::once(added.name.clone()).chain(
+                added
+                    .waves
+                    .scope_names()
+                    .into_iter()
+                    .map(|name| format!("{}.{name}", added.name)),
+            )
+        });
+        self.primary
+            .scope_names()
+            .into_iter()
+            .chain(added)
+            .collect()
+    }
+
+    pub fn metadata(&self) -> MetaData {
+        self.primary.metadata()
+    }
+
+    pub fn root_scopes(&self) -> Vec<ScopeRef> {
+        let added = self
+            .added
+            .iter()
+            .map(|added| ScopeRef::from_strs(&[&added.name]));
+        self.primary
+            .root_scopes()
+            .into_iter()
+            .chain(added)
+            .collect()
+    }
+
+    pub fn child_scopes(&self, scope: &ScopeRef) -> Result<Vec<ScopeRef>> {
+        let Some((added, local)) = self.split_scope(scope) else {
+            return self.primary.child_scopes(scope);
+        };
+        let children = if local.strs.is_empty() {
+            added.waves.root_scopes()
+        } else {
+            added.waves.child_scopes(&local)?
+        };
+        Ok(children
+            .into_iter()
+            .map(|child| added.to_session_scope(child))
+            .collect())
+    }
+
+    /// Only the primary file is included, since the indices of the files refer to different
+    /// lists of timestamps
+    pub fn change_time_indices(&self, variable: &VariableRef) -> Option<Vec<u32>> {
+        match self.split_variable(variable) {
+            Some(_) => None,
+            None => self.primary.change_time_indices(variable),
+        }
+    }
+
+    pub fn time_table_position(&self, time: &BigUint) -> Option<usize> {
+        self.primary.time_table_position(time)
+    }
+
+    pub fn max_timestamp(&self) -> Option<BigUint> {
+        let added = self.added.iter().filter_map(|added| {
+            added
+                .waves
+                .max_timestamp()
+                .map(|time| added.session_time(time))
+        });
+        self.primary.max_timestamp().into_iter().chain(added).max()
+    }
+
+    pub fn scope_exists(&self, scope: &ScopeRef) -> bool {
+        match self.split_scope(scope) {
+            Some((_, local)) if local.strs.is_empty() => true,
+            Some((added, local)) => added.waves.scope_exists(&local),
+            None => self.primary.scope_exists(scope),
+        }
+    }
+
+    pub fn get_scope_tooltip_data(&self, scope: &ScopeRef) -> String {
+        match self.split_scope(scope) {
+            Some((added, local)) if local.strs.is_empty() => {
+                format!("Added file\nTime offset: {}", added.time_offset)
+            }
+            Some((added, local)) => added.waves.get_scope_tooltip_data(&local),
+            None => self.primary.get_scope_tooltip_data(scope),
+        }
+    }
+
+    pub fn simulation_status(&self) -> Option<SimulationStatus> {
+        self.primary.simulation_status()
+    }
+
+    pub fn unpause_simulation(&self) {
+        self.primary.unpause_simulation();
+    }
+
+    pub fn pause_simulation(&self) {
+        self.primary.pause_simulation();
+    }
+}
+
+/// Name of the root scope of a file, based on its file name
+fn file_name(source: &WaveSource) -> String {
+    let path = match source {
+        WaveSource::File(path) | WaveSource::DragAndDrop(Some(path)) => path.as_str(),
+        WaveSource::Url(url) => url.as_str(),
+        _ => "",
+    };
+    let name = path.rsplit(['/', '\\']).next().unwrap_or_default();
+    let stem = name.split('.').next().unwrap_or_default();
+    if stem.is_empty() {
+        "file".to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+impl WaveData {
+    fn multi_waves_mut(&mut self) -> Option<&mut MultiWaveContainer> {
+        match self.inner.as_waves_mut() {
+            Some(WaveContainer::Multi(multi)) => Some(multi),
+            _ => None,
+        }
+    }
+
+    /// A name for the root scope of `source` that no other root scope has
+    fn unique_file_name(&self, source: &WaveSource) -> String {
+        let taken = self
+            .inner
+            .root_scopes()
+            .into_iter()
+            .map(|scope| scope.to_string())
+            .chain(self.added_files.iter().map(|file| file.name.clone()))
+            .collect::<Vec<_>>();
+        let name = file_name(source);
+        (1..)
+            .map(|n| match n {
+                1 => name.clone(),
+                _ => format!("{name}_{n}"),
+            })
+            .find(|candidate| !taken.contains(candidate))
+            .unwrap()
+    }
+
+    /// True if `source` has been added but not loaded yet
+    pub fn is_pending_file(&self, source: &WaveSource) -> bool {
+        let Some(file) = self.added_files.iter().find(|file| &file.source == source) else {
+            return false;
+        };
+        match self.inner.as_waves() {
+            Some(WaveContainer::Multi(multi)) => {
+                !multi.added.iter().any(|added| added.name == file.name)
+            }
+            _ => true,
+        }
+    }
+
+    /// The wave container that `source` was loaded into
+    pub fn waves_from_source_mut(&mut self, source: &WaveSource) -> Option<&mut WaveContainer> {
+        let name = self
+            .added_files
+            .iter()
+            .find(|file| &file.source == source)
+            .map(|file| file.name.clone());
+        match (self.inner.as_waves_mut()?, name) {
+            (WaveContainer::Multi(multi), Some(name)) => multi
+                .added
+                .iter_mut()
+                .find(|added| added.name == name)
+                .map(|added| &mut added.waves),
+            (WaveContainer::Multi(multi), None) => Some(&mut multi.primary),
+            (waves, None) => Some(waves),
+            (_, Some(_)) => None,
+        }
+    }
+
+    /// Adds the waves of a pending file, and turns the placeholders of its variables into
+    /// variables
+    pub fn on_added_waves_loaded(
+        &mut self,
+        source: &WaveSource,
+        waves: WaveContainer,
+        translators: &TranslatorList,
+    ) -> Option<LoadSignalsCmd> {
+        let file = self
+            .added_files
+            .iter()
+            .find(|file| &file.source == source)?
+            .clone();
+        if !self.inner.is_waves() {
+            return None;
+        }
+        self.old_num_timestamps = Some(self.num_timestamps());
+        if self.multi_waves_mut().is_none() {
+            let DataContainer::Waves(primary) =
+                std::mem::replace(&mut self.inner, DataContainer::Empty)
+            else {
+                return None;
+            };
+            self.inner = DataContainer::Waves(WaveContainer::Multi(Box::new(MultiWaveContainer {
+                primary,
+                added: vec![],
+            })));
+        }
+        self.multi_waves_mut()?.added.push(AddedWaves {
+            name: file.name,
+            time_offset: file.time_offset,
+            waves,
+        });
+        self.activity = None;
+
+        let items = self.displayed_items.clone();
+        let order = self.displayed_items_order.clone();
+        self.update_with_items(&items, order, translators)
+    }
+
+    pub fn set_file_time_offset(&mut self, name: &str, offset: BigInt) {
+        let Some(file) = self.added_files.iter_mut().find(|file| file.name == name) else {
+            warn!("No added file named {name}");
+            return;
+        };
+        file.time_offset = offset.clone();
+        self.old_num_timestamps = Some(self.num_timestamps());
+        if let Some(added) = self
+            .multi_waves_mut()
+            .and_then(|multi| multi.added.iter_mut().find(|added| added.name == name))
+        {
+            added.time_offset = offset;
+        }
+        self.update_viewports();
+        self.values_changed();
+        self.delta_expansion = None;
+        self.activity = None;
+        if let Some(comparison) = self.comparison.as_mut().filter(|c| c.file == name) {
+            comparison.invalidate();
+        }
+    }
+
+    /// Closes an added file and removes the items showing its variables
+    pub fn remove_file(&mut self, name: &str) {
+        let Some(idx) = self.added_files.iter().position(|file| file.name == name) else {
+            warn!("No added file named {name}");
+            return;
+        };
+        self.added_files.remove(idx);
+
+        let in_file =
+            |variable: &VariableRef| variable.path.strs.first().map(String::as_str) == Some(name);
+        let removed = self
+            .displayed_items
+            .iter()
+            .filter(|(_, item)| match item {
+                DisplayedItem::Variable(variable) => in_file(&variable.variable_ref),
+                DisplayedItem::Placeholder(placeholder) => in_file(&placeholder.variable_ref),
+                DisplayedItem::Derived(derived) => {
+                    derived.expr.variables().into_iter().any(in_file)
+                }
+                DisplayedItem::Decoder(decoder) => {
+                    decoder.decoder.variables().into_iter().any(in_file)
+                }
+                _ => false,
+            })
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in removed {
+            self.remove_displayed_item(id);
+        }
+        if self
+            .cycle_clock
+            .as_ref()
+            .is_some_and(|cycles| in_file(&cycles.clock))
+        {
+            self.cycle_clock = None;
+        }
+        if self.comparison.as_ref().is_some_and(|c| c.file == name) {
+            self.comparison = None;
+        }
+
+        self.old_num_timestamps = Some(self.num_timestamps());
+        if let Some(multi) = self.multi_waves_mut() {
+            multi.added.retain(|added| added.name != name);
+            if multi.added.is_empty() {
+                if let DataContainer::Waves(WaveContainer::Multi(multi)) =
+                    std::mem::replace(&mut self.inner, DataContainer::Empty)
+                {
+                    self.inner = DataContainer::Waves(multi.primary);
+                }
+            }
+        }
+        self.update_viewports();
+        self.delta_expansion = None;
+        self.activity = None;
+    }
+}
+
+impl State {
+    /// Opens another file next to the currently loaded waves. Opens it as usual if no waves
+    /// are loaded.
+    pub fn add_wave_file(&mut self, source: WaveSource) {
+        let Some(waves) = self.waves.as_mut().filter(|waves| waves.inner.is_waves()) else {
+            self.load_added_file(source);
+            return;
+        };
+        if waves.source == source || waves.added_files.iter().any(|f| f.source == source) {
+            warn!("{source} is already open");
+            return;
+        }
+        let name = waves.unique_file_name(&source);
+        info!("Adding {source} as {name}");
+        waves.added_files.push(AddedFile {
+            name,
+            source: source.clone(),
+            time_offset: BigInt::zero(),
+        });
+        self.load_added_file(source);
+    }
+
+    pub fn is_pending_file(&self, source: &WaveSource) -> bool {
+        self.waves
+            .as_ref()
+            .is_some_and(|waves| waves.is_pending_file(source))
+    }
+
+    /// Starts loading an added file. The file is added to the session once its hierarchy has
+    /// been loaded, see [`State::on_added_waves_loaded`].
+    pub fn load_added_file(&mut self, source: WaveSource) {
+        match source {
+            WaveSource::File(path) => {
+                self.load_wave_from_file(path, LoadOptions::clean()).ok();
+            }
+            WaveSource::Url(url) => self.load_wave_from_url(url, LoadOptions::clean()),
+            _ => warn!("Only files and URLs can be added to a session"),
+        }
+    }
+
+    pub fn on_added_waves_loaded(&mut self, source: &WaveSource, waves: Box<WaveContainer>) {
+        info!("{source} added");
+        let Some(wave_data) = self.waves.as_mut() else {
+            return;
+        };
+        if let Some(cmd) = wave_data.on_added_waves_loaded(source, *waves, &self.sys.translators) {
+            self.load_variables(cmd);
+        }
+        self.invalidate_draw_commands();
+    }
+
+    /// Starts loading the added files of a saved state that are not open yet, and applies
+    /// the saved time offsets of those that are
+    pub fn restore_added_files(&mut self, files: Vec<AddedFile>) {
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        let mut to_load = vec![];
+        for file in files {
+            match waves.added_files.iter().find(|f| f.source == file.source) {
+                Some(open) if open.name == file.name => {
+                    let name = open.name.clone();
+                    waves.set_file_time_offset(&name, file.time_offset);
+                }
+                Some(open) => warn!(
+                    "{} is open as {}, not as {} like in the saved state",
+                    file.source, open.name, file.name
+                ),
+                None => {
+                    to_load.push(file.source.clone());
+                    waves.added_files.push(file);
+                }
+            }
+        }
+        for source in to_load {
+            self.load_added_file(source);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use camino::Utf8PathBuf;
+    use project_root::get_project_root;
+
+    use super::*;
+    use crate::tests::snapshot::{
+        background_runtime, handle_messages_until, wait_for_waves_fully_loaded,
+    };
+    use crate::{Message, StartupParams};
+
+    #[test]
+    fn file_names_are_file_stems() {
+        assert_eq!(
+            file_name(&WaveSource::File("a/b/golden.vcd".into())),
+            "golden"
+        );
+        assert_eq!(
+            file_name(&WaveSource::Url("https://x.org/runs/fail.fst".to_string())),
+            "fail"
+        );
+        assert_eq!(file_name(&WaveSource::Data), "file");
+    }
+
+    #[test]
+    fn added_file_times_are_offset() {
+        let added = AddedWaves {
+            name: "fail".to_string(),
+            time_offset: BigInt::from(-5),
+            waves: WaveContainer::Empty,
+        };
+        assert_eq!(added.session_time(BigUint::from(7u32)), BigUint::from(2u32));
+        assert_eq!(added.session_time(BigUint::from(3u32)), BigUint::zero());
+        assert_eq!(
+            added.local_time(&BigUint::from(2u32)),
+            Some(BigUint::from(7u32))
+        );
+
+        let added = AddedWaves {
+            time_offset: BigInt::from(10),
+            ..added
+        };
+        assert_eq!(added.local_time(&BigUint::from(4u32)), None);
+        assert_eq!(
+            added.session_time(BigUint::from(4u32)),
+            BigUint::from(14u32)
+        );
+    }
+
+    #[test]
+    fn added_files_are_offset_and_restored_from_a_state_file() {
+        let runtime = background_runtime();
+        let _enter = runtime.enter();
+        let example = |name: &str| -> Utf8PathBuf {
+            get_project_root()
+                .unwrap()
+                .join("examples")
+                .join(name)
+                .try_into()
+                .unwrap()
+        };
+        let open_counter = || {
+            let mut state = State::new_default_config()
+                .unwrap()
+                .with_params(StartupParams {
+                    waves: Some(WaveSource::File(example("counter.vcd"))),
+                    spade_top: None,
+                    rhdl_types: None,
+                    spade_state: None,
+                    startup_commands: vec![],
+                });
+            wait_for_waves_fully_loaded(&mut state, 10);
+            state
+        };
+        let wait_for_added_file = |state: &mut State| {
+            handle_messages_until(
+                state,
+                |msg| matches!(&msg, Message::WaveHeaderLoaded(..)),
+                10,
+            );
+            wait_for_waves_fully_loaded(state, 10);
+        };
+        let counter = VariableRef::from_hierarchy_string("counter2.tb.dut.counter");
+        let query = |state: &State, time: u32| {
+            let res = state
+                .waves
+                .as_ref()
+                .unwrap()
+                .inner
+                .as_waves()
+                .unwrap()
+                .query_variable(&counter, &BigUint::from(time))
+                .unwrap()
+                .unwrap();
+            (res.current, res.next)
+        };
+        let value = |time: u32, value: u32| {
+            Some((
+                BigUint::from(time),
+                VariableValue::BigUint(BigUint::from(value)),
+            ))
+        };
+
+        let mut state = open_counter();
+        state.update(Message::AddFile(example("counter2.vcd")));
+        wait_for_added_file(&mut state);
+        state.update(Message::AddVariables(vec![counter.clone()]));
+        wait_for_waves_fully_loaded(&mut state, 10);
+        assert_eq!(
+            query(&state, 120),
+            (value(110, 1), Some(BigUint::from(130u32)))
+        );
+
+        state.update(Message::SetFileTimeOffset {
+            name: "counter2".to_string(),
+            offset: BigInt::from(1000),
+        });
+        assert_eq!(query(&state, 500), (None, Some(BigUint::from(1000u32))));
+        assert_eq!(
+            query(&state, 1120),
+            (value(1110, 1), Some(BigUint::from(1130u32)))
+        );
+
+        let mut save_file = tempfile::NamedTempFile::new().unwrap();
+        write!(save_file, "{}", state.encode_state().unwrap()).unwrap();
+        save_file.flush().unwrap();
+
+        let mut state = open_counter();
+        state.update(Message::LoadStateFile(Some(save_file.path().to_path_buf())));
+        handle_messages_until(&mut state, |msg| matches!(&msg, Message::LoadState(..)), 10);
+        wait_for_added_file(&mut state);
+        let waves = state.waves.as_ref().unwrap();
+        assert_eq!(
+            waves.added_files,
+            vec![AddedFile {
+                name: "counter2".to_string(),
+                source: WaveSource::File(example("counter2.vcd")),
+                time_offset: BigInt::from(1000),
+            }]
+        );
+        assert!(matches!(
+            waves.displayed_items.values().next(),
+            Some(DisplayedItem::Variable(variable)) if variable.variable_ref == counter
+        ));
+        assert_eq!(
+            query(&state, 1120),
+            (value(1110, 1), Some(BigUint::from(1130u32)))
+        );
+    }
+}
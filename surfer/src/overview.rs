@@ -39,6 +39,13 @@ impl State {
             theme: &self.config.theme,
         };
 
+        match &waves.activity {
+            Some(activity) if activity.key == waves.activity_key() => {
+                self.draw_activity(&activity.counts, &mut ctx, frame_width, frame_height);
+            }
+            _ => msgs.push(Message::CountActivity),
+        }
+
         let viewport_all = waves.viewport_all();
         for vidx in 0..waves.viewports.len() {
             let minx = viewport_all.pixel_from_absolute_time(
@@ -121,4 +128,33 @@ impl State {
             }
         });
     }
+
+    /// Draws the number of value changes in each bin of the full time range as a heatmap
+    fn draw_activity(
+        &self,
+        counts: &[u64],
+        ctx: &mut DrawingContext,
+        frame_width: f32,
+        frame_height: f32,
+    ) {
+        let Some(max_count) = counts.iter().max().copied().filter(|max| *max > 0) else {
+            return;
+        };
+        let bin_width = frame_width / counts.len() as f32;
+        for (bin, count) in counts.iter().enumerate().filter(|(_, count)| **count > 0) {
+            // the square root keeps quiet regions visible next to very busy ones
+            let intensity = (*count as f32 / max_count as f32).sqrt();
+            let min = (ctx.to_screen)(bin as f32 * bin_width, 0.);
+            let max = (ctx.to_screen)((bin + 1) as f32 * bin_width, frame_height);
+            ctx.painter.rect_filled(
+                Rect { min, max },
+                Rounding::ZERO,
+                self.config
+                    .theme
+                    .accent_info
+                    .background
+                    .gamma_multiply(intensity),
+            );
+        }
+    }
 }
@@ -0,0 +1,1139 @@
+//! Protocol decoders that turn bit-level variables into transactions, like the bytes sent
+//! over a UART or the transfers on an I2C bus. The transactions are drawn, focused and moved
+//! between like those of the transaction streams of FTR files, alongside the variables they
+//! are decoded from.
+//!
+//! Decoders are described by the protocol followed by its variables and parameters as
+//! `key=value` words, e.g. `uart rx=tb.tx baud=115200` or
+//! `spi sclk=tb.sck mosi=tb.mosi cs=tb.cs cpol=0 cpha=1`.
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use color_eyre::eyre::{anyhow, bail};
+use color_eyre::Result;
+use egui::{Context, Key, TextEdit, Window};
+use num::{BigUint, FromPrimitive, ToPrimitive, Zero};
+use serde::{Deserialize, Serialize};
+use surfer_translation_types::VariableValue;
+
+use crate::displayed_item::{
+    DisplayedDecoder, DisplayedItem, DisplayedItemIndex, DisplayedItemRef,
+};
+use crate::message::Message;
+use crate::time::{TimeScale, TimeUnit};
+use crate::wave_container::{VariableRef, VariableRefExt, WaveContainer};
+use crate::wave_data::WaveData;
+use crate::wellen::LoadSignalsCmd;
+use crate::State;
+
+/// The protocols with their variables, where optional variables end with `?`, and their
+/// default parameters
+const PROTOCOLS: [(&str, &[&str], &str); 4] = [
+    ("uart", &["rx"], "baud=115200"),
+    ("spi", &["sclk", "mosi", "miso?", "cs?"], "cpol=0 cpha=0"),
+    ("i2c", &["scl", "sda"], "address_bits=7"),
+    ("valid_ready", &["clock", "valid", "ready", "data?"], ""),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ProtocolDecoder {
+    /// 8 data bits sent LSB first between a start and a stop bit, without parity. The line
+    /// idles high.
+    Uart { rx: VariableRef, baud_rate: u32 },
+    /// 8 bit words sent MSB first. The chip select is active low, words that are cut short by
+    /// releasing it are dropped.
+    Spi {
+        sclk: VariableRef,
+        mosi: VariableRef,
+        miso: Option<VariableRef>,
+        cs: Option<VariableRef>,
+        cpol: bool,
+        cpha: bool,
+    },
+    /// One transaction per transfer from a start condition to the next stop or repeated
+    /// start. Addresses have 7 or 10 bits.
+    I2c {
+        scl: VariableRef,
+        sda: VariableRef,
+        address_bits: u8,
+    },
+    /// One transaction per handshake, i.e. per rising clock edge at which both valid and
+    /// ready are high
+    ValidReady {
+        clock: VariableRef,
+        valid: VariableRef,
+        ready: VariableRef,
+        data: Option<VariableRef>,
+    },
+}
+
+/// The `key=value` words of a decoder, which are removed as they are used
+struct Parameters<'a>(HashMap<&'a str, &'a str>);
+
+impl Parameters<'_> {
+    fn optional_variable(&mut self, key: &str) -> Option<VariableRef> {
+        self.0
+            .remove(key)
+            .filter(|value| !value.is_empty())
+            .map(VariableRef::from_hierarchy_string)
+    }
+
+    fn variable(&mut self, key: &str) -> Result<VariableRef> {
+        self.optional_variable(key)
+            .ok_or_else(|| anyhow!("Missing variable {key}="))
+    }
+
+    fn optional_number<T: FromStr>(&mut self, key: &str) -> Result<Option<T>> {
+        self.0
+            .remove(key)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| anyhow!("{key}={value} is not a number"))
+            })
+            .transpose()
+    }
+
+    fn flag(&mut self, key: &str) -> Result<bool> {
+        match self.0.remove(key) {
+            None | Some("0") => Ok(false),
+            Some("1") => Ok(true),
+            Some(value) => bail!("{key} is 0 or 1, not {value}"),
+        }
+    }
+}
+
+impl ProtocolDecoder {
+    /// Parses the protocol followed by its variables and parameters. The variables are
+    /// looked up when the decoder is added.
+    pub fn parse(query: &str) -> Result<Self> {
+        let mut words = query.split_whitespace();
+        let protocol = words.next().ok_or_else(|| anyhow!("No protocol given"))?;
+        let mut parameters = Parameters(HashMap::new());
+        for word in words {
+            let (key, value) = word
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected key=value, got {word}"))?;
+            parameters.0.insert(key, value);
+        }
+
+        let decoder = match protocol {
+            "uart" => {
+                let rx = parameters.variable("rx")?;
+                let baud_rate = parameters
+                    .optional_number("baud")?
+                    .ok_or_else(|| anyhow!("Missing baud="))?;
+                if baud_rate == 0 {
+                    bail!("The baud rate cannot be 0");
+                }
+                ProtocolDecoder::Uart { rx, baud_rate }
+            }
+            "spi" => ProtocolDecoder::Spi {
+                sclk: parameters.variable("sclk")?,
+                mosi: parameters.variable("mosi")?,
+                miso: parameters.optional_variable("miso"),
+                cs: parameters.optional_variable("cs"),
+                cpol: parameters.flag("cpol")?,
+                cpha: parameters.flag("cpha")?,
+            },
+            "i2c" => {
+                let scl = parameters.variable("scl")?;
+                let sda = parameters.variable("sda")?;
+                let address_bits = parameters.optional_number("address_bits")?.unwrap_or(7);
+                if address_bits != 7 && address_bits != 10 {
+                    bail!("I2C addresses have 7 or 10 bits, not {address_bits}");
+                }
+                ProtocolDecoder::I2c {
+                    scl,
+                    sda,
+                    address_bits,
+                }
+            }
+            "valid_ready" => ProtocolDecoder::ValidReady {
+                clock: parameters.variable("clock")?,
+                valid: parameters.variable("valid")?,
+                ready: parameters.variable("ready")?,
+                data: parameters.optional_variable("data"),
+            },
+            _ => bail!(
+                "Unknown protocol {protocol}, expected one of {}",
+                PROTOCOLS.map(|(protocol, _, _)| protocol).join(", ")
+            ),
+        };
+        if let Some(key) = parameters.0.keys().next() {
+            bail!("Unknown parameter {key} for {protocol}");
+        }
+        Ok(decoder)
+    }
+
+    pub fn variables(&self) -> Vec<&VariableRef> {
+        match self {
+            ProtocolDecoder::Uart { rx, .. } => vec![rx],
+            ProtocolDecoder::Spi {
+                sclk,
+                mosi,
+                miso,
+                cs,
+                ..
+            } => [Some(sclk), Some(mosi), miso.as_ref(), cs.as_ref()]
+                .into_iter()
+                .flatten()
+                .collect(),
+            ProtocolDecoder::I2c { scl, sda, .. } => vec![scl, sda],
+            ProtocolDecoder::ValidReady {
+                clock,
+                valid,
+                ready,
+                data,
+            } => [Some(clock), Some(valid), Some(ready), data.as_ref()]
+                .into_iter()
+                .flatten()
+                .collect(),
+        }
+    }
+
+    fn variables_mut(&mut self) -> Vec<&mut VariableRef> {
+        match self {
+            ProtocolDecoder::Uart { rx, .. } => vec![rx],
+            ProtocolDecoder::Spi {
+                sclk,
+                mosi,
+                miso,
+                cs,
+                ..
+            } => [Some(sclk), Some(mosi), miso.as_mut(), cs.as_mut()]
+                .into_iter()
+                .flatten()
+                .collect(),
+            ProtocolDecoder::I2c { scl, sda, .. } => vec![scl, sda],
+            ProtocolDecoder::ValidReady {
+                clock,
+                valid,
+                ready,
+                data,
+            } => [Some(clock), Some(valid), Some(ready), data.as_mut()]
+                .into_iter()
+                .flatten()
+                .collect(),
+        }
+    }
+
+    /// The protocol and the variable carrying the data, e.g. `UART tb.tx`
+    pub fn name(&self) -> String {
+        let (protocol, variable) = match self {
+            ProtocolDecoder::Uart { rx, .. } => ("UART", rx),
+            ProtocolDecoder::Spi { mosi, .. } => ("SPI", mosi),
+            ProtocolDecoder::I2c { sda, .. } => ("I2C", sda),
+            ProtocolDecoder::ValidReady { valid, .. } => ("valid/ready", valid),
+        };
+        format!("{protocol} {}", variable.full_path_string())
+    }
+
+    /// The clock on whose rising edges the variables are sampled
+    pub fn sampling_clock(&self) -> Option<&VariableRef> {
+        match self {
+            ProtocolDecoder::ValidReady { clock, .. } => Some(clock),
+            _ => None,
+        }
+    }
+
+    /// Decodes the transactions, `None` until all variables are loaded. `clock_edges` are the
+    /// rising edges of the [`Self::sampling_clock`].
+    pub fn decode(
+        &self,
+        waves: &WaveContainer,
+        clock_edges: Option<&[BigUint]>,
+    ) -> Option<Vec<DecodedTransaction>> {
+        match self {
+            ProtocolDecoder::Uart { rx, baud_rate } => {
+                let period = bit_period(*baud_rate, &waves.metadata().timescale)?;
+                Some(decode_uart(&bit_changes(waves, rx)?, period))
+            }
+            ProtocolDecoder::Spi {
+                sclk,
+                mosi,
+                miso,
+                cs,
+                cpol,
+                cpha,
+            } => {
+                let optional = |variable: &Option<VariableRef>| match variable {
+                    Some(variable) => bit_changes(waves, variable).map(Some),
+                    None => Some(None),
+                };
+                Some(decode_spi(
+                    &bit_changes(waves, sclk)?,
+                    &bit_changes(waves, mosi)?,
+                    optional(miso)?.as_deref(),
+                    optional(cs)?.as_deref(),
+                    *cpol,
+                    *cpha,
+                ))
+            }
+            ProtocolDecoder::I2c {
+                scl,
+                sda,
+                address_bits,
+            } => Some(decode_i2c(
+                &bit_changes(waves, scl)?,
+                &bit_changes(waves, sda)?,
+                *address_bits,
+            )),
+            ProtocolDecoder::ValidReady {
+                valid, ready, data, ..
+            } => {
+                let valid = bit_changes(waves, valid)?;
+                let ready = bit_changes(waves, ready)?;
+                let mut cycles = vec![];
+                for edge in clock_edges? {
+                    let sampled = sample_time(edge);
+                    let data = match data {
+                        Some(data) => waves
+                            .query_variable(data, &sampled)
+                            .ok()??
+                            .current
+                            .map(|(_, value)| value_string(&value)),
+                        None => None,
+                    };
+                    cycles.push(HandshakeCycle {
+                        valid: level_at(&valid, &sampled) == Some(true),
+                        ready: level_at(&ready, &sampled) == Some(true),
+                        edge: edge.clone(),
+                        data,
+                    });
+                }
+                Some(decode_valid_ready(&cycles))
+            }
+        }
+    }
+}
+
+/// A decoder for `protocol` with the given variables filled in, in the order the protocol
+/// lists them. Required variables that are not given are left empty.
+pub fn decoder_template(protocol: &str, variables: &[String]) -> String {
+    let Some((_, keys, defaults)) = PROTOCOLS.iter().find(|(name, _, _)| *name == protocol) else {
+        return protocol.to_string();
+    };
+    let mut variables = variables.iter();
+    let mut words = vec![protocol.to_string()];
+    for key in keys.iter() {
+        let (key, optional) = match key.strip_suffix('?') {
+            Some(key) => (key, true),
+            None => (*key, false),
+        };
+        match variables.next() {
+            Some(variable) => words.push(format!("{key}={variable}")),
+            None if !optional => words.push(format!("{key}=")),
+            None => {}
+        }
+    }
+    if !defaults.is_empty() {
+        words.push(defaults.to_string());
+    }
+    words.join(" ")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedTransaction {
+    pub start: BigUint,
+    pub end: BigUint,
+    /// Short summary drawn inside the transaction
+    pub label: String,
+    /// Names and values shown when hovering the transaction
+    pub attributes: Vec<(String, String)>,
+}
+
+/// The value of a single bit, `None` for anything but 0 and 1
+fn bit_value(value: &VariableValue) -> Option<bool> {
+    match value {
+        VariableValue::BigUint(v) => Some(!v.is_zero()),
+        VariableValue::String(s) => match s.as_str() {
+            "1" | "h" | "H" => Some(true),
+            "0" | "l" | "L" => Some(false),
+            _ => None,
+        },
+    }
+}
+
+fn value_string(value: &VariableValue) -> String {
+    match value {
+        VariableValue::BigUint(v) => format!("{v:x}"),
+        VariableValue::String(s) => s.clone(),
+    }
+}
+
+/// The times at which a single bit variable changes and the values it changes to, `None`
+/// if the variable is not loaded
+fn bit_changes(
+    waves: &WaveContainer,
+    variable: &VariableRef,
+) -> Option<Vec<(BigUint, Option<bool>)>> {
+    let mut changes = vec![];
+    let mut result = waves.query_variable(variable, &BigUint::zero()).ok()??;
+    if let Some((time, value)) = &result.current {
+        changes.push((time.clone(), bit_value(value)));
+    }
+    while let Some(time) = result.next {
+        result = waves.query_variable(variable, &time).ok()??;
+        if let Some((_, value)) = &result.current {
+            changes.push((time, bit_value(value)));
+        }
+    }
+    Some(changes)
+}
+
+/// The time at which the values are sampled at a clock edge. Registers see the values from
+/// just before the edge.
+fn sample_time(edge: &BigUint) -> BigUint {
+    if edge.is_zero() {
+        edge.clone()
+    } else {
+        edge - 1u32
+    }
+}
+
+/// The value of a bit at `time`, given the times at which it changes
+fn level_at(changes: &[(BigUint, Option<bool>)], time: &BigUint) -> Option<bool> {
+    let idx = changes.partition_point(|(change, _)| change <= time);
+    changes.get(idx.checked_sub(1)?)?.1
+}
+
+/// The byte made up of 8 bits, `None` if any of them is undefined
+fn byte_value(bits: &[Option<bool>], msb_first: bool) -> Option<u8> {
+    bits.iter().enumerate().try_fold(0u8, |byte, (idx, bit)| {
+        let shift = if msb_first { bits.len() - 1 - idx } else { idx };
+        Some(byte | (u8::from((*bit)?) << shift))
+    })
+}
+
+fn byte_string(byte: Option<u8>) -> String {
+    byte.map_or_else(|| "xx".to_string(), |byte| format!("{byte:02x}"))
+}
+
+/// The number of time steps per bit, `None` if the waveform has no time unit
+fn bit_period(baud_rate: u32, timescale: &TimeScale) -> Option<f64> {
+    if matches!(timescale.unit, TimeUnit::None | TimeUnit::Auto) || baud_rate == 0 {
+        return None;
+    }
+    let steps_per_second = 10f64.powi((-timescale.unit.exponent()).into())
+        / f64::from(timescale.multiplier.unwrap_or(1));
+    Some(steps_per_second / f64::from(baud_rate))
+}
+
+/// Decodes the bytes on a UART line with `period` time steps per bit. The bits are
+/// sampled in their middle, starting from the falling edge of the start bit.
+pub fn decode_uart(rx: &[(BigUint, Option<bool>)], period: f64) -> Vec<DecodedTransaction> {
+    let mut transactions = vec![];
+    let mut previous = None;
+    let mut busy_until = 0.;
+    for (time, level) in rx {
+        let falling = previous == Some(true) && *level == Some(false);
+        previous = *level;
+        let Some(start) = time
+            .to_f64()
+            .filter(|start| falling && *start >= busy_until)
+        else {
+            continue;
+        };
+        let time_after = |bits: f64| BigUint::from_f64((start + bits * period).round());
+        let sample =
+            |bit: u8| time_after(f64::from(bit) + 0.5).and_then(|time| level_at(rx, &time));
+        // glitches shorter than half a bit are not start bits
+        if sample(0) != Some(false) {
+            continue;
+        }
+        let byte = byte_value(&(1..=8).map(sample).collect::<Vec<_>>(), false);
+        let mut attributes = vec![("data".to_string(), byte_string(byte))];
+        if let Some(c) = byte.map(char::from).filter(char::is_ascii_graphic) {
+            attributes.push(("ascii".to_string(), c.to_string()));
+        }
+        if sample(9) != Some(true) {
+            attributes.push(("error".to_string(), "missing stop bit".to_string()));
+        }
+        transactions.push(DecodedTransaction {
+            start: time.clone(),
+            end: time_after(10.).unwrap_or_else(|| time.clone()),
+            label: byte_string(byte),
+            attributes,
+        });
+        // the next start bit can begin right after the middle of the stop bit
+        busy_until = start + 9.5 * period;
+    }
+    transactions
+}
+
+/// Decodes the 8 bit words on an SPI bus. The bits are sampled on the rising edges of the
+/// clock if `cpol == cpha` and on the falling edges otherwise, seeing the values from just
+/// before the edge. Without a chip select, all clock edges belong to one frame.
+pub fn decode_spi(
+    sclk: &[(BigUint, Option<bool>)],
+    mosi: &[(BigUint, Option<bool>)],
+    miso: Option<&[(BigUint, Option<bool>)]>,
+    cs: Option<&[(BigUint, Option<bool>)]>,
+    cpol: bool,
+    cpha: bool,
+) -> Vec<DecodedTransaction> {
+    let sample_level = cpol == cpha;
+    // words may not span a change of the chip select
+    let frame = |time: &BigUint| cs.map(|cs| cs.partition_point(|(change, _)| change <= time));
+    let selected = |time: &BigUint| cs.map_or(true, |cs| level_at(cs, time) == Some(false));
+
+    let mut transactions = vec![];
+    let mut word: Vec<(&BigUint, Option<bool>, Option<bool>)> = vec![];
+    let mut previous = None;
+    for (idx, (time, level)) in sclk.iter().enumerate() {
+        let sampling_edge =
+            previous.is_some() && previous != *level && *level == Some(sample_level);
+        previous = *level;
+        if !sampling_edge {
+            continue;
+        }
+        if !selected(time)
+            || word
+                .first()
+                .is_some_and(|(start, _, _)| frame(*start) != frame(time))
+        {
+            word.clear();
+        }
+        if !selected(time) {
+            continue;
+        }
+        let sampled = sample_time(time);
+        word.push((
+            time,
+            level_at(mosi, &sampled),
+            miso.and_then(|miso| level_at(miso, &sampled)),
+        ));
+        if word.len() < 8 {
+            continue;
+        }
+
+        let mosi_byte = byte_value(
+            &word.iter().map(|(_, bit, _)| *bit).collect::<Vec<_>>(),
+            true,
+        );
+        let mut label = byte_string(mosi_byte);
+        let mut attributes = vec![("mosi".to_string(), byte_string(mosi_byte))];
+        if miso.is_some() {
+            let miso_byte = byte_value(
+                &word.iter().map(|(_, _, bit)| *bit).collect::<Vec<_>>(),
+                true,
+            );
+            label = format!("{label}/{}", byte_string(miso_byte));
+            attributes.push(("miso".to_string(), byte_string(miso_byte)));
+        }
+        // the word ends with the clock edge following its last bit
+        let end = sclk.get(idx + 1).map_or(time, |(next, _)| next);
+        transactions.push(DecodedTransaction {
+            start: word[0].0.clone(),
+            end: end.clone(),
+            label,
+            attributes,
+        });
+        word.clear();
+    }
+    transactions
+}
+
+/// The bits of an I2C transfer, sampled on the rising edges of the clock
+struct I2cTransfer {
+    start: BigUint,
+    bits: Vec<Option<bool>>,
+}
+
+impl I2cTransfer {
+    /// The transaction of the transfer, where reads with a 10 bit address use the address
+    /// of the previous transfer since they only send its 2 most significant bits
+    fn finish(
+        self,
+        end: &BigUint,
+        address_bits: u8,
+        last_address: &mut Option<u16>,
+    ) -> Option<DecodedTransaction> {
+        // every byte is followed by an acknowledge bit, which is low if the byte was received
+        let mut bytes = self
+            .bits
+            .chunks_exact(9)
+            .map(|bits| (byte_value(&bits[..8], true), bits[8] == Some(false)));
+        let (first, first_ack) = bytes.next()?;
+        let first = first?;
+        let read = first & 1 == 1;
+        let mut acks = vec![first_ack];
+        let address = if address_bits == 10 && first >> 3 == 0b11110 {
+            let high = u16::from((first >> 1) & 0b11) << 8;
+            if read {
+                last_address
+                    .filter(|address| address & 0x300 == high)
+                    .unwrap_or(high)
+            } else {
+                let (low, ack) = bytes.next()?;
+                acks.push(ack);
+                high | u16::from(low?)
+            }
+        } else {
+            u16::from(first >> 1)
+        };
+        *last_address = Some(address);
+
+        let data = bytes
+            .map(|(byte, ack)| {
+                acks.push(ack);
+                byte_string(byte)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let direction = if read { "R" } else { "W" };
+        let mut label = format!("{direction} {address:#x}");
+        if !first_ack {
+            label.push_str(" NAK");
+        } else if !data.is_empty() {
+            label = format!("{label}: {data}");
+        }
+        Some(DecodedTransaction {
+            start: self.start,
+            end: end.clone(),
+            label,
+            attributes: vec![
+                ("address".to_string(), format!("{address:#x}")),
+                (
+                    "direction".to_string(),
+                    if read { "read" } else { "write" }.to_string(),
+                ),
+                ("data".to_string(), data),
+                (
+                    "acks".to_string(),
+                    acks.iter()
+                        .map(|ack| if *ack { "A" } else { "N" })
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+            ],
+        })
+    }
+}
+
+/// Decodes the transfers on an I2C bus. A transfer starts when SDA falls while SCL is high
+/// and ends when SDA rises while SCL is high or at a repeated start.
+pub fn decode_i2c(
+    scl: &[(BigUint, Option<bool>)],
+    sda: &[(BigUint, Option<bool>)],
+    address_bits: u8,
+) -> Vec<DecodedTransaction> {
+    let mut times = scl
+        .iter()
+        .chain(sda)
+        .map(|(time, _)| time)
+        .collect::<Vec<_>>();
+    times.sort();
+    times.dedup();
+
+    let mut transactions = vec![];
+    let mut transfer: Option<I2cTransfer> = None;
+    let mut last_address = None;
+    let (mut scl_level, mut sda_level) = (None, None);
+    for time in times {
+        let (new_scl, new_sda) = (level_at(scl, time), level_at(sda, time));
+        let clock_high = scl_level == Some(true) && new_scl == Some(true);
+        match (sda_level, new_sda) {
+            (Some(true), Some(false)) if clock_high => {
+                if let Some(transfer) = transfer.take() {
+                    transactions.extend(transfer.finish(time, address_bits, &mut last_address));
+                }
+                transfer = Some(I2cTransfer {
+                    start: time.clone(),
+                    bits: vec![],
+                });
+            }
+            (Some(false), Some(true)) if clock_high => {
+                if let Some(transfer) = transfer.take() {
+                    transactions.extend(transfer.finish(time, address_bits, &mut last_address));
+                }
+            }
+            _ => {
+                if scl_level == Some(false) && new_scl == Some(true) {
+                    if let Some(transfer) = &mut transfer {
+                        transfer.bits.push(new_sda);
+                    }
+                }
+            }
+        }
+        scl_level = new_scl;
+        sda_level = new_sda;
+    }
+    transactions
+}
+
+/// The values of a valid/ready interface at a rising clock edge
+#[derive(Debug, Clone)]
+pub struct HandshakeCycle {
+    pub edge: BigUint,
+    pub valid: bool,
+    pub ready: bool,
+    pub data: Option<String>,
+}
+
+/// One transaction per handshake. It starts at the clock edge before valid was first seen
+/// and ends at the edge at which both valid and ready are high.
+pub fn decode_valid_ready(cycles: &[HandshakeCycle]) -> Vec<DecodedTransaction> {
+    let mut transactions = vec![];
+    // the start of the transfer that is waiting for ready and the cycles it has waited
+    let mut waiting: Option<(BigUint, usize)> = None;
+    let mut previous_edge = BigUint::zero();
+    for cycle in cycles {
+        if cycle.valid {
+            let (start, waited) = waiting.take().unwrap_or((previous_edge.clone(), 0));
+            if cycle.ready {
+                let mut attributes = vec![("wait cycles".to_string(), waited.to_string())];
+                if let Some(data) = &cycle.data {
+                    attributes.insert(0, ("data".to_string(), data.clone()));
+                }
+                transactions.push(DecodedTransaction {
+                    start,
+                    end: cycle.edge.clone(),
+                    label: cycle.data.clone().unwrap_or_else(|| "transfer".to_string()),
+                    attributes,
+                });
+            } else {
+                waiting = Some((start, waited + 1));
+            }
+        } else {
+            waiting = None;
+        }
+        previous_edge = cycle.edge.clone();
+    }
+    transactions
+}
+
+impl DisplayedDecoder {
+    pub fn new(decoder: ProtocolDecoder) -> Self {
+        DisplayedDecoder {
+            decoder,
+            transactions: None,
+            decoded_version: None,
+            color: None,
+            background_color: None,
+            manual_name: None,
+            height_scaling_factor: None,
+        }
+    }
+
+    /// Updates the variable references after a new waveform has been loaded. The
+    /// transactions are decoded again once the variables are loaded.
+    pub fn update(&self, waves: &WaveContainer, keep_unavailable: bool) -> Option<DisplayedItem> {
+        let mut result = self.clone();
+        result.transactions = None;
+        result.decoded_version = None;
+        for variable in result.decoder.variables_mut() {
+            match waves.update_variable_ref(variable) {
+                Some(new_variable) => *variable = new_variable,
+                None if keep_unavailable => variable.clear_id(),
+                None => return None,
+            }
+        }
+        Some(DisplayedItem::Decoder(result))
+    }
+
+    /// The transaction that is in progress at `time`
+    pub fn transaction_at(&self, time: &BigUint) -> Option<&DecodedTransaction> {
+        let transactions = self.transactions.as_ref()?;
+        let idx = transactions.partition_point(|transaction| transaction.start <= *time);
+        transactions
+            .get(idx.checked_sub(1)?)
+            .filter(|transaction| transaction.end >= *time)
+    }
+}
+
+impl WaveData {
+    /// Adds a decoder, see [`ProtocolDecoder::parse`], and loads the variables it uses
+    pub fn add_decoder(
+        &mut self,
+        query: &str,
+        vidx: Option<DisplayedItemIndex>,
+    ) -> Result<Option<LoadSignalsCmd>> {
+        let mut decoder = ProtocolDecoder::parse(query)?;
+        let waves = self
+            .inner
+            .as_waves_mut()
+            .ok_or_else(|| anyhow!("Protocol decoders require a waveform"))?;
+        for variable in decoder.variables_mut() {
+            *variable = waves
+                .update_variable_ref(variable)
+                .ok_or_else(|| anyhow!("No variable {}", variable.full_path_string()))?;
+        }
+        if let ProtocolDecoder::Uart { baud_rate, .. } = &decoder {
+            if bit_period(*baud_rate, &waves.metadata().timescale).is_none() {
+                bail!("Decoding a UART requires a waveform with a time unit");
+            }
+        }
+        let cmd = waves.load_variables(decoder.variables().into_iter())?;
+        self.insert_item(DisplayedItem::Decoder(DisplayedDecoder::new(decoder)), vidx);
+        Ok(cmd)
+    }
+
+    /// The id of the first transaction of each decoder. Decoded transactions are focused
+    /// through the [`TransactionRef`](crate::transaction_container::TransactionRef)s of
+    /// transaction streams, numbered in the order of the decoders in the item list.
+    pub fn decoded_transaction_offsets(&self) -> HashMap<DisplayedItemRef, usize> {
+        let mut offsets = HashMap::new();
+        let mut offset = 0;
+        for id in &self.displayed_items_order {
+            if let Some(DisplayedItem::Decoder(decoder)) = self.displayed_items.get(id) {
+                offsets.insert(*id, offset);
+                offset += decoder.transactions.as_ref().map_or(0, Vec::len);
+            }
+        }
+        offsets
+    }
+
+    /// The ids of the transactions of all decoders, see
+    /// [`WaveData::decoded_transaction_offsets`]
+    pub fn decoded_transaction_ids(&self) -> Vec<usize> {
+        let num_transactions = self
+            .displayed_items
+            .values()
+            .filter_map(|item| match item {
+                DisplayedItem::Decoder(decoder) => decoder.transactions.as_ref().map(Vec::len),
+                _ => None,
+            })
+            .sum();
+        (0..num_transactions).collect()
+    }
+
+    /// The decoders that are not being decoded and whose transactions were decoded before the
+    /// values last changed. Decoders that sample on a clock are decoded once its edges have
+    /// been found for the current values.
+    fn outdated_decoders(&self) -> Vec<(DisplayedItemRef, ProtocolDecoder)> {
+        self.displayed_items
+            .iter()
+            .filter_map(|(id, item)| match item {
+                DisplayedItem::Decoder(decoder)
+                    if !self.decoding.contains(id)
+                        && decoder.decoded_version != Some(self.values_version)
+                        && decoder
+                            .decoder
+                            .sampling_clock()
+                            .map_or(true, |clock| self.current_clock_edges(clock).is_some()) =>
+                {
+                    Some((*id, decoder.decoder.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn decoders_outdated(&self) -> bool {
+        !self.outdated_decoders().is_empty()
+    }
+
+    /// The full names of the selected variables from top to bottom, or of the focused
+    /// variable if none are selected
+    fn selected_variable_names(&self) -> Vec<String> {
+        let focused = self
+            .focused_item
+            .and_then(|idx| self.displayed_items_order.get(idx.0));
+        self.displayed_items_order
+            .iter()
+            .filter(|id| {
+                self.selected_items.contains(*id)
+                    || (self.selected_items.is_empty() && Some(*id) == focused)
+            })
+            .filter_map(|id| match self.displayed_items.get(id) {
+                Some(DisplayedItem::Variable(variable)) => {
+                    Some(variable.variable_ref.full_path_string())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl State {
+    /// Decodes the transactions of the outdated decoders on worker threads. The transactions
+    /// decoded before are shown until the new ones arrive.
+    pub fn decode_transactions(&mut self) {
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        let version = waves.values_version;
+        let decoders = waves
+            .outdated_decoders()
+            .into_iter()
+            .map(|(id, decoder)| {
+                let clock_edges = decoder
+                    .sampling_clock()
+                    .and_then(|clock| waves.current_clock_edges(clock));
+                (id, decoder, clock_edges)
+            })
+            .collect::<Vec<_>>();
+        waves.decoding.extend(decoders.iter().map(|(id, _, _)| *id));
+        for (id, decoder, clock_edges) in decoders {
+            self.perform_work_on_waves(move |waves| {
+                let transactions = decoder.decode(waves, clock_edges.as_deref().map(Vec::as_slice));
+                Message::TransactionsDecoded(id, version, transactions)
+            });
+        }
+    }
+
+    pub fn on_transactions_decoded(
+        &mut self,
+        id: DisplayedItemRef,
+        version: u64,
+        transactions: Option<Vec<DecodedTransaction>>,
+    ) {
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        waves.decoding.remove(&id);
+        if let Some(DisplayedItem::Decoder(decoder)) = waves.displayed_items.get_mut(&id) {
+            decoder.transactions = transactions;
+            decoder.decoded_version = Some(version);
+        }
+        self.invalidate_draw_commands();
+        // the values may have changed while decoding
+        self.decode_transactions();
+    }
+
+    pub fn draw_protocol_decoder_window(&self, ctx: &Context, msgs: &mut Vec<Message>) {
+        let Some(waves) = &self.waves else { return };
+        let mut open = true;
+        let mut query = self.sys.decoder_query.borrow_mut();
+        Window::new("Protocol decoder")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label("Pick a protocol to fill in the selected variables from top to bottom");
+                ui.horizontal(|ui| {
+                    for (protocol, _, _) in PROTOCOLS {
+                        if ui.button(protocol).clicked() {
+                            *query = decoder_template(protocol, &waves.selected_variable_names());
+                        }
+                    }
+                });
+                let response = ui.add(
+                    TextEdit::singleline(&mut *query)
+                        .hint_text("uart rx=tb.tx baud=115200")
+                        .desired_width(400.),
+                );
+                if let Err(e) = ProtocolDecoder::parse(&query) {
+                    if !query.trim().is_empty() {
+                        ui.colored_label(self.config.theme.accent_error.background, e.to_string());
+                    }
+                }
+                if ui.button("Add").clicked()
+                    || (response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)))
+                {
+                    msgs.push(Message::AddDecoder(query.clone()));
+                }
+            });
+        if !open {
+            msgs.push(Message::SetDecoderWindowVisible(false));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Changes of a bit given as the time steps at which it toggles, starting from `initial`
+    fn toggles(initial: bool, times: &[u32]) -> Vec<(BigUint, Option<bool>)> {
+        std::iter::once((BigUint::zero(), Some(initial)))
+            .chain(
+                times
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, time)| (BigUint::from(*time), Some(initial == (idx % 2 == 1)))),
+            )
+            .collect()
+    }
+
+    /// Changes of a bit that holds `bits[n]` from `n * period` on
+    fn levels(bits: &[bool], period: u32) -> Vec<(BigUint, Option<bool>)> {
+        bits.iter()
+            .enumerate()
+            .map(|(idx, bit)| (BigUint::from(idx as u32 * period), Some(*bit)))
+            .collect()
+    }
+
+    #[test]
+    fn decoders_are_parsed() {
+        assert_eq!(
+            ProtocolDecoder::parse("uart rx=tb.tx baud=9600").unwrap(),
+            ProtocolDecoder::Uart {
+                rx: VariableRef::from_hierarchy_string("tb.tx"),
+                baud_rate: 9600
+            }
+        );
+        assert_eq!(
+            ProtocolDecoder::parse("spi sclk=tb.sck mosi=tb.mosi cpha=1").unwrap(),
+            ProtocolDecoder::Spi {
+                sclk: VariableRef::from_hierarchy_string("tb.sck"),
+                mosi: VariableRef::from_hierarchy_string("tb.mosi"),
+                miso: None,
+                cs: None,
+                cpol: false,
+                cpha: true
+            }
+        );
+        assert!(ProtocolDecoder::parse("uart rx=tb.tx").is_err());
+        assert!(ProtocolDecoder::parse("i2c scl=a sda=b address_bits=8").is_err());
+        assert!(ProtocolDecoder::parse("i2c scl=a sda=b clock=c").is_err());
+        assert!(ProtocolDecoder::parse("can rx=a").is_err());
+    }
+
+    #[test]
+    fn templates_fill_in_variables_in_order() {
+        let variables = ["tb.sck".to_string(), "tb.mosi".to_string()];
+        assert_eq!(
+            decoder_template("spi", &variables),
+            "spi sclk=tb.sck mosi=tb.mosi cpol=0 cpha=0"
+        );
+        assert_eq!(
+            decoder_template("valid_ready", &variables),
+            "valid_ready clock=tb.sck valid=tb.mosi ready="
+        );
+    }
+
+    #[test]
+    fn uart_bytes_are_decoded() {
+        // idle, start bit, 0x41 LSB first, stop bit, then 0x0a without a stop bit
+        let mut bits = vec![
+            true, false, true, false, false, false, false, false, true, false,
+        ];
+        bits.extend([
+            true, false, false, true, false, true, false, false, false, false,
+        ]);
+        bits.extend([false, false, true]);
+        let rx = levels(&bits, 10);
+
+        let transactions = decode_uart(&rx, 10.);
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].start, BigUint::from(10u32));
+        assert_eq!(transactions[0].end, BigUint::from(110u32));
+        assert_eq!(transactions[0].label, "41");
+        assert!(transactions[0]
+            .attributes
+            .contains(&("ascii".to_string(), "A".to_string())));
+        assert_eq!(transactions[1].start, BigUint::from(110u32));
+        assert_eq!(transactions[1].label, "0a");
+        assert!(transactions[1]
+            .attributes
+            .contains(&("error".to_string(), "missing stop bit".to_string())));
+    }
+
+    #[test]
+    fn spi_words_follow_the_clock_mode() {
+        // 0xa5 MSB first, changing on the falling edges at 0, 10, ... and the clock rising
+        // at 5, 15, ...
+        let mosi = levels(&[true, false, true, false, false, true, false, true], 10);
+        let sclk = toggles(
+            false,
+            &(0..8)
+                .flat_map(|bit| [bit * 10 + 5, bit * 10 + 10])
+                .collect::<Vec<_>>(),
+        );
+        let transactions = decode_spi(&sclk, &mosi, None, None, false, false);
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].label, "a5");
+        assert_eq!(transactions[0].start, BigUint::from(5u32));
+        assert_eq!(transactions[0].end, BigUint::from(80u32));
+
+        // sampling on the falling edges sees every bit just before it changes
+        let transactions = decode_spi(&sclk, &mosi, None, None, false, true);
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].label, "a5");
+        assert_eq!(transactions[0].start, BigUint::from(10u32));
+
+        // releasing the chip select drops the partial word
+        let cs = toggles(false, &[30, 32]);
+        assert!(decode_spi(&sclk, &mosi, None, Some(&cs), false, false).is_empty());
+    }
+
+    /// The SCL and SDA changes for a transfer of `bytes` with the acknowledge bits `acks`,
+    /// framed by a start and a stop condition. Every bit takes 10 time steps.
+    fn i2c_transfer(
+        bytes: &[u8],
+        acks: &[bool],
+    ) -> (Vec<(BigUint, Option<bool>)>, Vec<(BigUint, Option<bool>)>) {
+        let mut sda_bits = vec![true];
+        for (byte, ack) in bytes.iter().zip(acks) {
+            sda_bits.extend((0..8).rev().map(|bit| (byte >> bit) & 1 == 1));
+            sda_bits.push(!ack);
+        }
+        let num_bits = sda_bits.len() as u32 - 1;
+        // start condition at 5, the bits are set at 10n and sampled at 10n + 5
+        let mut sda = vec![
+            (BigUint::zero(), Some(true)),
+            (BigUint::from(5u32), Some(false)),
+        ];
+        sda.extend(
+            sda_bits[1..]
+                .iter()
+                .enumerate()
+                .map(|(idx, bit)| (BigUint::from(10 * (idx as u32 + 1)), Some(*bit))),
+        );
+        let end = 10 * (num_bits + 1);
+        sda.push((BigUint::from(end), Some(false)));
+        sda.push((BigUint::from(end + 5), Some(true)));
+
+        let mut scl = vec![
+            (BigUint::zero(), Some(true)),
+            (BigUint::from(8u32), Some(false)),
+        ];
+        for bit in 1..=num_bits {
+            scl.push((BigUint::from(10 * bit + 5), Some(true)));
+            scl.push((BigUint::from(10 * bit + 8), Some(false)));
+        }
+        scl.push((BigUint::from(end + 2), Some(true)));
+        (scl, sda)
+    }
+
+    #[test]
+    fn i2c_transfers_are_decoded() {
+        let (scl, sda) = i2c_transfer(&[0x50 << 1, 0x12, 0x34], &[true, true, false]);
+        let transactions = decode_i2c(&scl, &sda, 7);
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].label, "W 0x50: 12 34");
+        assert_eq!(transactions[0].start, BigUint::from(5u32));
+        assert!(transactions[0]
+            .attributes
+            .contains(&("acks".to_string(), "A A N".to_string())));
+
+        let (scl, sda) = i2c_transfer(&[(0x50 << 1) | 1], &[false]);
+        assert_eq!(decode_i2c(&scl, &sda, 7)[0].label, "R 0x50 NAK");
+
+        let (scl, sda) = i2c_transfer(&[0b1111_0110, 0x34], &[true, true]);
+        assert_eq!(decode_i2c(&scl, &sda, 10)[0].label, "W 0x334");
+    }
+
+    #[test]
+    fn handshakes_span_their_wait_cycles() {
+        let cycle = |edge: u32, valid, ready, data: &str| HandshakeCycle {
+            edge: BigUint::from(edge),
+            valid,
+            ready,
+            data: Some(data.to_string()),
+        };
+        let cycles = [
+            cycle(10, false, true, "0"),
+            cycle(20, true, true, "1"),
+            cycle(30, true, false, "2"),
+            cycle(40, true, false, "2"),
+            cycle(50, true, true, "2"),
+            cycle(60, true, false, "3"),
+            cycle(70, false, true, "3"),
+        ];
+        let transactions = decode_valid_ready(&cycles);
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(
+            (&transactions[0].start, &transactions[0].end),
+            (&BigUint::from(10u32), &BigUint::from(20u32))
+        );
+        assert_eq!(transactions[1].label, "2");
+        assert_eq!(transactions[1].start, BigUint::from(20u32));
+        assert!(transactions[1]
+            .attributes
+            .contains(&("wait cycles".to_string(), "2".to_string())));
+    }
+}
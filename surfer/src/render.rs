@@ -0,0 +1,514 @@
+//! Rendering waves to an image without opening a window, for the `render` subcommand.
+//!
+//! PNG images are rasterized by skia like the snapshot tests do, which requires the `render`
+//! feature. SVG images are written from the shapes that egui paints, so that lines stay sharp
+//! and text can be searched.
+use std::fmt::Write as _;
+use std::time::Instant;
+
+use camino::Utf8PathBuf;
+use color_eyre::eyre::{anyhow, bail, Context as _};
+use color_eyre::Result;
+use ecolor::Color32;
+use egui::{FontFamily, RawInput};
+use emath::{Pos2, Rect, Vec2};
+use epaint::{ClippedShape, ColorMode, PathStroke, Shape, Stroke};
+use log::info;
+use num::BigInt;
+
+use crate::config::SurferTheme;
+use crate::message::Message;
+use crate::wave_source::string_to_wavesource;
+use crate::{read_command_file, setup_custom_font, StartupParams, State};
+
+/// Number of frames drawn before the image is taken, to let the layout settle
+const FRAMES_BEFORE_RENDER: usize = 5;
+
+#[derive(clap::Args)]
+pub struct RenderArgs {
+    /// Waveform file in VCD, FST, or GHW format. Defaults to the file of the state file.
+    wave_file: Option<String>,
+    /// Image to write. The format, SVG or PNG, is chosen by the extension.
+    #[clap(long, short)]
+    output: Utf8PathBuf,
+    /// State file to apply to the waves, e.g. saved from the GUI
+    #[clap(long, short)]
+    state_file: Option<Utf8PathBuf>,
+    /// File containing commands to run after the waves have been loaded, like for the GUI
+    #[clap(long, short)]
+    command_file: Option<Utf8PathBuf>,
+    /// First timestamp to render, in the timescale of the waveform file
+    #[clap(long)]
+    start: Option<BigInt>,
+    /// Last timestamp to render, in the timescale of the waveform file
+    #[clap(long)]
+    end: Option<BigInt>,
+    /// Width of the image in pixels
+    #[clap(long, default_value_t = 1280)]
+    width: u32,
+    /// Height of the image in pixels
+    #[clap(long, default_value_t = 720)]
+    height: u32,
+    /// Theme to render with instead of the configured one
+    #[clap(long)]
+    theme: Option<String>,
+    /// Viewport to render if the state has several
+    #[clap(long, default_value_t = 0)]
+    viewport: usize,
+    /// Also render the menu, toolbar, hierarchy, overview and statusbar
+    #[clap(long)]
+    full_ui: bool,
+    /// Seconds to wait for the waves and the variables to load
+    #[clap(long, default_value_t = 60)]
+    timeout: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImageFormat {
+    Svg,
+    Png,
+}
+
+impl ImageFormat {
+    fn from_path(path: &Utf8PathBuf) -> Result<Self> {
+        match path.extension().map(str::to_ascii_lowercase).as_deref() {
+            Some("svg") => Ok(ImageFormat::Svg),
+            Some("png") => Ok(ImageFormat::Png),
+            _ => bail!("Cannot tell the image format of {path}, use a .svg or .png extension"),
+        }
+    }
+}
+
+fn wait_until_loaded(state: &mut State, timeout_s: u64) -> Result<()> {
+    let start = Instant::now();
+    while !(state.waves_fully_loaded() && state.batch_commands_completed()) {
+        state.handle_async_messages();
+        state.handle_batch_commands();
+        if start.elapsed().as_secs() > timeout_s {
+            bail!("The waves were not loaded within {timeout_s}s");
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    Ok(())
+}
+
+/// Loads the waves, state and commands given by `args` and writes the image. Has to be called
+/// from within a tokio runtime, since the waves are loaded asynchronously.
+pub fn render(args: RenderArgs) -> Result<()> {
+    let format = ImageFormat::from_path(&args.output)?;
+    let state = match &args.state_file {
+        Some(file) => std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read state from {file}"))
+            .and_then(|content| {
+                ron::from_str::<State>(&content)
+                    .with_context(|| format!("Failed to decode state from {file}"))
+            })?,
+        None => State::new()?,
+    };
+    let waves = match &args.wave_file {
+        Some(file) => string_to_wavesource(file),
+        None => state
+            .waves
+            .as_ref()
+            .map(|waves| waves.source.clone())
+            .ok_or_else(|| anyhow!("No waveform file to render"))?,
+    };
+    let mut state = state.with_params(StartupParams {
+        spade_state: None,
+        spade_top: None,
+        rhdl_types: None,
+        waves: Some(waves),
+        startup_commands: args
+            .command_file
+            .as_ref()
+            .map(read_command_file)
+            .unwrap_or_default(),
+    });
+    wait_until_loaded(&mut state, args.timeout)?;
+
+    if let Some(theme) = args.theme {
+        state.config.theme = SurferTheme::new(Some(theme.clone()))
+            .with_context(|| format!("Failed to load theme {theme}"))?;
+    }
+    if !args.full_ui {
+        state.show_menu = Some(false);
+        state.show_toolbar = Some(false);
+        state.show_hierarchy = Some(false);
+        state.show_overview = Some(false);
+        state.show_statusbar = Some(false);
+    }
+    let waves = state
+        .waves
+        .as_mut()
+        .ok_or_else(|| anyhow!("No waves were loaded"))?;
+    let Some(viewport) = waves.viewports.get(args.viewport).copied() else {
+        bail!(
+            "There is no viewport {}, the state has {}",
+            args.viewport,
+            waves.viewports.len()
+        );
+    };
+    waves.viewports = vec![viewport];
+    *state.sys.draw_data.borrow_mut() = vec![None];
+    if args.start.is_some() || args.end.is_some() {
+        let num_timestamps = waves.num_timestamps();
+        state.update(Message::ZoomToRange {
+            start: args.start.unwrap_or_default(),
+            end: args.end.unwrap_or(num_timestamps),
+            viewport_idx: 0,
+        });
+    }
+    state.invalidate_draw_commands();
+    // commands may have added variables which are loaded now
+    wait_until_loaded(&mut state, args.timeout)?;
+
+    let size = Vec2::new(args.width as f32, args.height as f32);
+    let image = match format {
+        ImageFormat::Svg => render_svg(&mut state, size),
+        ImageFormat::Png => render_png(&mut state, size)?,
+    };
+    std::fs::write(&args.output, image)
+        .with_context(|| format!("Failed to write {}", args.output))?;
+    info!("Rendered {}", args.output);
+    Ok(())
+}
+
+#[cfg(feature = "render")]
+fn render_png(state: &mut State, size: Vec2) -> Result<Vec<u8>> {
+    let (width, height) = (size.x as i32, size.y as i32);
+    let mut surface = skia_safe::surfaces::raster_n32_premul((width, height))
+        .ok_or_else(|| anyhow!("Failed to create a {width}x{height} image"))?;
+    egui_skia_renderer::draw_onto_surface(
+        &mut surface,
+        |ctx| {
+            ctx.set_visuals(state.get_visuals());
+            setup_custom_font(ctx);
+            state.draw(ctx, Some(size));
+        },
+        Some(egui_skia_renderer::RasterizeOptions {
+            frames_before_screenshot: FRAMES_BEFORE_RENDER,
+            ..Default::default()
+        }),
+    );
+    let data = surface
+        .image_snapshot()
+        .encode(None, skia_safe::EncodedImageFormat::PNG, None)
+        .ok_or_else(|| anyhow!("Failed to encode the image"))?;
+    Ok(data.as_bytes().to_vec())
+}
+
+#[cfg(not(feature = "render"))]
+fn render_png(_state: &mut State, _size: Vec2) -> Result<Vec<u8>> {
+    bail!("Surfer is not compiled with the `render` feature, which PNG images require")
+}
+
+fn render_svg(state: &mut State, size: Vec2) -> Vec<u8> {
+    let ctx = egui::Context::default();
+    ctx.set_visuals(state.get_visuals());
+    setup_custom_font(&ctx);
+    let input = RawInput {
+        screen_rect: Some(Rect::from_min_size(Pos2::ZERO, size)),
+        ..Default::default()
+    };
+    let mut shapes = vec![];
+    for _ in 0..FRAMES_BEFORE_RENDER {
+        shapes = ctx
+            .run(input.clone(), |ctx| {
+                state.draw(ctx, Some(size));
+            })
+            .shapes;
+    }
+    to_svg(&shapes, size).into_bytes()
+}
+
+/// Color and opacity attributes of a fill or a stroke
+fn paint(attribute: &str, color: Color32) -> String {
+    if color.a() == 0 {
+        return format!(r#"{attribute}="none""#);
+    }
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    if a == 255 {
+        format!(r##"{attribute}="#{r:02x}{g:02x}{b:02x}""##)
+    } else {
+        format!(
+            r##"{attribute}="#{r:02x}{g:02x}{b:02x}" {attribute}-opacity="{:.3}""##,
+            a as f32 / 255.
+        )
+    }
+}
+
+fn stroke(stroke: &Stroke) -> String {
+    if stroke.width <= 0. {
+        return paint("stroke", Color32::TRANSPARENT);
+    }
+    format!(
+        r#"{} stroke-width="{}""#,
+        paint("stroke", stroke.color),
+        stroke.width
+    )
+}
+
+fn path_stroke(path_stroke: &PathStroke) -> String {
+    match path_stroke.color {
+        ColorMode::Solid(color) => stroke(&Stroke::new(path_stroke.width, color)),
+        // gradients are not used for waves
+        _ => paint("stroke", Color32::TRANSPARENT),
+    }
+}
+
+fn points(points: &[Pos2]) -> String {
+    points
+        .iter()
+        .map(|p| format!("{},{}", p.x, p.y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn write_shape(svg: &mut String, shape: &Shape) {
+    match shape {
+        Shape::Vec(shapes) => {
+            for shape in shapes {
+                write_shape(svg, shape);
+            }
+        }
+        Shape::Circle(circle) => {
+            let _ = writeln!(
+                svg,
+                r#"<circle cx="{}" cy="{}" r="{}" {} {}/>"#,
+                circle.center.x,
+                circle.center.y,
+                circle.radius,
+                paint("fill", circle.fill),
+                stroke(&circle.stroke)
+            );
+        }
+        Shape::LineSegment {
+            points: [a, b],
+            stroke,
+        } => {
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" {}/>"#,
+                a.x,
+                a.y,
+                b.x,
+                b.y,
+                path_stroke(stroke)
+            );
+        }
+        Shape::Path(path) => {
+            let element = if path.closed { "polygon" } else { "polyline" };
+            let _ = writeln!(
+                svg,
+                r#"<{element} points="{}" {} {}/>"#,
+                points(&path.points),
+                paint("fill", path.fill),
+                path_stroke(&path.stroke)
+            );
+        }
+        Shape::Rect(rect) => {
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}" {} {}/>"#,
+                rect.rect.min.x,
+                rect.rect.min.y,
+                rect.rect.width(),
+                rect.rect.height(),
+                rect.rounding.nw,
+                paint("fill", rect.fill),
+                stroke(&rect.stroke)
+            );
+        }
+        Shape::Text(text) => {
+            let sections = &text.galley.job.sections;
+            for row in &text.galley.rows {
+                let Some(first) = row.glyphs.first() else {
+                    continue;
+                };
+                let Some(section) = sections.get(first.section_index as usize) else {
+                    continue;
+                };
+                let format = &section.format;
+                let color =
+                    text.override_text_color
+                        .unwrap_or(if format.color == Color32::PLACEHOLDER {
+                            text.fallback_color
+                        } else {
+                            format.color
+                        });
+                let family = match format.font_id.family {
+                    FontFamily::Monospace => "monospace",
+                    _ => "sans-serif",
+                };
+                let content = row.glyphs.iter().map(|glyph| glyph.chr).collect::<String>();
+                let _ = writeln!(
+                    svg,
+                    r#"<text x="{}" y="{}" font-family="{family}" font-size="{}" {} xml:space="preserve">{}</text>"#,
+                    text.pos.x + first.pos.x,
+                    text.pos.y + first.pos.y,
+                    format.font_id.size,
+                    paint("fill", color),
+                    escape(&content)
+                );
+            }
+        }
+        Shape::Mesh(mesh) => {
+            // textures are not supported, so each triangle is filled with one color
+            for triangle in mesh.indices.chunks_exact(3) {
+                let vertices = triangle
+                    .iter()
+                    .map(|i| mesh.vertices[*i as usize])
+                    .collect::<Vec<_>>();
+                let _ = writeln!(
+                    svg,
+                    r#"<polygon points="{}" {}/>"#,
+                    points(&vertices.iter().map(|v| v.pos).collect::<Vec<_>>()),
+                    paint("fill", vertices[0].color)
+                );
+            }
+        }
+        Shape::QuadraticBezier(bezier) => {
+            let [a, b, c] = bezier.points;
+            let _ = writeln!(
+                svg,
+                r#"<path d="M {},{} Q {},{} {},{}{}" {} {}/>"#,
+                a.x,
+                a.y,
+                b.x,
+                b.y,
+                c.x,
+                c.y,
+                if bezier.closed { " Z" } else { "" },
+                paint("fill", bezier.fill),
+                path_stroke(&bezier.stroke)
+            );
+        }
+        Shape::CubicBezier(bezier) => {
+            let [a, b, c, d] = bezier.points;
+            let _ = writeln!(
+                svg,
+                r#"<path d="M {},{} C {},{} {},{} {},{}{}" {} {}/>"#,
+                a.x,
+                a.y,
+                b.x,
+                b.y,
+                c.x,
+                c.y,
+                d.x,
+                d.y,
+                if bezier.closed { " Z" } else { "" },
+                paint("fill", bezier.fill),
+                path_stroke(&bezier.stroke)
+            );
+        }
+        // callbacks paint with the GPU, and nothing else is used by Surfer
+        _ => {}
+    }
+}
+
+/// Writes the shapes painted by egui as an SVG image. Consecutive shapes with the same clip
+/// rectangle are grouped and clipped together.
+fn to_svg(shapes: &[ClippedShape], size: Vec2) -> String {
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = size.x,
+        h = size.y
+    );
+    svg.push('\n');
+    let mut clip_rect = None;
+    let mut clip_paths = 0;
+    for ClippedShape {
+        clip_rect: clip,
+        shape,
+    } in shapes
+    {
+        if clip_rect != Some(*clip) {
+            if clip_rect.is_some() {
+                svg.push_str("</g>\n");
+            }
+            clip_paths += 1;
+            let _ = writeln!(
+                svg,
+                r#"<clipPath id="clip{clip_paths}"><rect x="{}" y="{}" width="{}" height="{}"/></clipPath>"#,
+                clip.min.x,
+                clip.min.y,
+                clip.width(),
+                clip.height()
+            );
+            let _ = writeln!(svg, r#"<g clip-path="url(#clip{clip_paths})">"#);
+            clip_rect = Some(*clip);
+        }
+        write_shape(&mut svg, shape);
+    }
+    if clip_rect.is_some() {
+        svg.push_str("</g>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod test {
+    use epaint::{PathShape, RectShape, Rounding};
+
+    use super::*;
+
+    #[test]
+    fn image_format_is_chosen_by_extension() {
+        assert_eq!(
+            ImageFormat::from_path(&"waves.SVG".into()).unwrap(),
+            ImageFormat::Svg
+        );
+        assert_eq!(
+            ImageFormat::from_path(&"out/waves.png".into()).unwrap(),
+            ImageFormat::Png
+        );
+        assert!(ImageFormat::from_path(&"waves.jpg".into()).is_err());
+        assert!(ImageFormat::from_path(&"waves".into()).is_err());
+    }
+
+    #[test]
+    fn shapes_are_written_as_clipped_svg_elements() {
+        let clip = Rect::from_min_size(Pos2::ZERO, Vec2::new(100., 50.));
+        let shapes = [
+            ClippedShape {
+                clip_rect: clip,
+                shape: Shape::Rect(RectShape::filled(
+                    Rect::from_min_size(Pos2::new(1., 2.), Vec2::new(10., 20.)),
+                    Rounding::ZERO,
+                    Color32::from_rgb(0xff, 0x80, 0),
+                )),
+            },
+            ClippedShape {
+                clip_rect: clip,
+                shape: Shape::Path(PathShape::line(
+                    vec![Pos2::new(0., 0.), Pos2::new(5., 5.)],
+                    Stroke::new(1., Color32::WHITE),
+                )),
+            },
+        ];
+        let svg = to_svg(&shapes, Vec2::new(100., 50.));
+        assert_eq!(svg.matches("<clipPath").count(), 1);
+        assert!(svg.contains(
+            r##"<rect x="1" y="2" width="10" height="20" rx="0" fill="#ff8000" stroke="none"/>"##
+        ));
+        assert!(svg.contains(
+            r##"<polyline points="0,0 5,5" fill="none" stroke="#ffffff" stroke-width="1"/>"##
+        ));
+    }
+
+    #[test]
+    fn translucent_colors_have_an_opacity() {
+        assert_eq!(paint("fill", Color32::TRANSPARENT), r#"fill="none""#);
+        assert_eq!(
+            paint("fill", Color32::from_rgba_unmultiplied(255, 0, 0, 51)),
+            r##"fill="#ff0000" fill-opacity="0.200""##
+        );
+    }
+}
@@ -2,7 +2,7 @@ use egui::{Context, Frame, Layout, Margin, TopBottomPanel, Ui};
 use emath::Align;
 use web_time::{Duration, Instant};
 
-use crate::time::{time_string, timeunit_menu};
+use crate::time::timeunit_menu;
 use crate::wave_source::draw_progress_information;
 use crate::{message::Message, wave_data::WaveData, State};
 
@@ -54,9 +54,8 @@ impl State {
             if let Some(waves) = waves {
                 ui.with_layout(Layout::right_to_left(Align::RIGHT), |ui| {
                     if let Some(time) = &waves.cursor {
-                        ui.label(time_string(
+                        ui.label(waves.time_string(
                             time,
-                            &waves.inner.metadata().timescale,
                             &self.wanted_timeunit,
                             &self.get_time_format(),
                         ))
@@ -1,4 +1,5 @@
 use std::{
+    collections::{BTreeSet, HashSet},
     fs::File,
     io::IsTerminal,
     path::{Path, PathBuf},
@@ -9,18 +10,22 @@ use egui_skia_renderer::draw_onto_surface;
 use emath::Vec2;
 use image::{DynamicImage, ImageFormat};
 use log::info;
-use num::{bigint::ToBigInt, BigInt};
+use num::{bigint::ToBigInt, BigInt, BigUint};
 use project_root::get_project_root;
 use skia_safe::EncodedImageFormat;
+use surfer_translation_types::VariableValue;
 use test_log::test;
 
 use crate::wave_data::ScopeType;
 use crate::{
+    clock_cycles::rising_edges,
     clock_highlighting::ClockHighlightType,
     config::{HierarchyStyle, SurferConfig},
-    displayed_item::{DisplayedFieldRef, DisplayedItemIndex, DisplayedItemRef},
+    derived::{Expr, Signal},
+    displayed_item::{DisplayedFieldRef, DisplayedItem, DisplayedItemIndex, DisplayedItemRef},
     message::AsyncJob,
     setup_custom_font, transaction_container,
+    value_search::ValueMatch,
     variable_name_filter::VariableNameFilterType,
     wave_container::{ScopeRef, VariableRef},
     wave_source::LoadOptions,
@@ -48,26 +53,18 @@ fn print_image(img: &DynamicImage) {
     }
 }
 
-pub(crate) fn render_and_compare_inner(
-    filename: &Path,
-    state: impl Fn() -> State,
-    size: Vec2,
-    feathering: bool,
-    threshold_score: f64,
-) {
-    info!("test up and running");
-
-    // https://tokio.rs/tokio/topics/bridging
-    // We want to run the gui in the main thread, but some long running tasks like
-    // laoading VCDs should be done asynchronously. We can't just use std::thread to
-    // do that due to wasm support, so we'll start a tokio runtime
+// https://tokio.rs/tokio/topics/bridging
+// We want to run the gui in the main thread, but some long running tasks like
+// laoading VCDs should be done asynchronously. We can't just use std::thread to
+// do that due to wasm support, so we'll start a tokio runtime on a thread of its own,
+// which has to be entered before the state is created
+pub(crate) fn background_runtime() -> tokio::runtime::Handle {
     let runtime = tokio::runtime::Builder::new_current_thread()
         .worker_threads(1)
         .enable_all()
         .build()
         .unwrap();
-
-    let _enter = runtime.enter();
+    let handle = runtime.handle().clone();
 
     std::thread::spawn(move || {
         runtime.block_on(async {
@@ -76,6 +73,20 @@ pub(crate) fn render_and_compare_inner(
             }
         });
     });
+    handle
+}
+
+pub(crate) fn render_and_compare_inner(
+    filename: &Path,
+    state: impl Fn() -> State,
+    size: Vec2,
+    feathering: bool,
+    threshold_score: f64,
+) {
+    info!("test up and running");
+
+    let runtime = background_runtime();
+    let _enter = runtime.enter();
 
     let mut state = state();
     state.config.layout.show_statusbar = false;
@@ -240,6 +251,7 @@ macro_rules! snapshot_ui_with_file_spade_and_msgs {
                         get_project_root().unwrap().join($file).try_into().unwrap(),
                     )),
                     spade_top: spade_top.clone(),
+                    rhdl_types: None,
                     spade_state,
                     startup_commands: vec![],
                 });
@@ -313,6 +325,7 @@ fn render_readme_screenshot() {
                             .unwrap(),
                     )),
                     spade_top: None,
+                    rhdl_types: None,
                     spade_state: None,
                     startup_commands: vec![],
                 });
@@ -441,6 +454,7 @@ snapshot_ui!(overview_can_be_hidden, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -474,6 +488,7 @@ snapshot_ui!(statusbar_can_be_hidden, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -499,6 +514,7 @@ snapshot_ui! {example_vcd_renders, || {
     let mut state = State::new_default_config().unwrap().with_params(StartupParams {
         waves: Some(WaveSource::File(get_project_root().unwrap().join("examples/counter.vcd").try_into().unwrap())),
         spade_top: None,
+        rhdl_types: None,
         spade_state: None,
         startup_commands: vec![]
     });
@@ -550,6 +566,7 @@ snapshot_ui! {resizing_the_canvas_redraws, || {
     let mut state = State::new_default_config().unwrap().with_params(StartupParams {
         waves: Some(WaveSource::File(get_project_root().unwrap().join("examples/counter.vcd").try_into().unwrap())),
         spade_top: None,
+        rhdl_types: None,
         spade_state: None,
         startup_commands: vec![]
     });
@@ -987,6 +1004,7 @@ snapshot_ui!(regex_error_indication, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1034,6 +1052,7 @@ snapshot_ui!(fuzzy_signal_filter_works, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1079,6 +1098,7 @@ snapshot_ui!(contain_signal_filter_works, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1124,6 +1144,7 @@ snapshot_ui!(regex_signal_filter_works, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1173,6 +1194,7 @@ snapshot_ui!(start_signal_filter_works, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1218,6 +1240,7 @@ snapshot_ui!(case_sensitive_signal_filter_works, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1265,6 +1288,7 @@ snapshot_ui!(load_keep_all_works, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1328,6 +1352,7 @@ snapshot_ui!(load_keep_signal_remove_unavailable_works, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1525,6 +1550,7 @@ snapshot_ui!(signals_can_be_added_after_file_switch, || {
         .with_params(StartupParams {
             waves: Some(WaveSource::File(project_root.join("examples/counter.vcd"))),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1619,6 +1645,7 @@ snapshot_ui!(rising_clock_markers, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1653,7 +1680,11 @@ snapshot_ui!(rising_clock_markers, || {
     state
 });
 
-fn handle_messages_until(state: &mut State, matcher: impl Fn(&Message) -> bool, timeout_s: u64) {
+pub(crate) fn handle_messages_until(
+    state: &mut State,
+    matcher: impl Fn(&Message) -> bool,
+    timeout_s: u64,
+) {
     let load_start = std::time::Instant::now();
     loop {
         if load_start.elapsed().as_secs() > timeout_s {
@@ -1697,6 +1728,7 @@ snapshot_ui!(save_and_start_with_state, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1753,6 +1785,7 @@ snapshot_ui!(save_and_start_with_state, || {
         .with_params(StartupParams {
             spade_state: None,
             spade_top: None,
+            rhdl_types: None,
             waves: Some(WaveSource::File(
                 get_project_root()
                     .unwrap()
@@ -1770,6 +1803,353 @@ snapshot_ui!(save_and_start_with_state, || {
     state
 });
 
+#[test]
+fn cycle_clock_is_restored_from_state() {
+    let runtime = background_runtime();
+    let _enter = runtime.enter();
+    let open_counter = || {
+        let mut state = State::new_default_config()
+            .unwrap()
+            .with_params(StartupParams {
+                waves: Some(WaveSource::File(
+                    get_project_root()
+                        .unwrap()
+                        .join("examples/counter.vcd")
+                        .try_into()
+                        .unwrap(),
+                )),
+                spade_top: None,
+                rhdl_types: None,
+                spade_state: None,
+                startup_commands: vec![],
+            });
+        wait_for_waves_fully_loaded(&mut state, 10);
+        state
+    };
+    let clock = VariableRef::from_hierarchy_string("tb.dut.clk");
+
+    let mut state = open_counter();
+    state.update(Message::SetCycleClock(Some(clock.clone())));
+    let encoded = state.encode_state().unwrap();
+
+    let mut state = open_counter();
+    state.load_state(ron::from_str(&encoded).unwrap(), None);
+    handle_messages_until(
+        &mut state,
+        |msg| matches!(&msg, Message::ClockEdgesFound(_, _, Some(_))),
+        10,
+    );
+    let waves = state.waves.as_ref().unwrap();
+    let cycles = waves
+        .cycle_clock
+        .as_ref()
+        .expect("The cycle clock should be restored");
+    assert_eq!(cycles.clock, clock);
+    assert!(waves.cycle_clock_edges().is_some());
+}
+
+#[test]
+fn valid_ready_decoder_samples_before_the_clock_edge() {
+    let runtime = background_runtime();
+    let _enter = runtime.enter();
+    let mut state = State::new_default_config()
+        .unwrap()
+        .with_params(StartupParams {
+            waves: Some(WaveSource::File(
+                get_project_root()
+                    .unwrap()
+                    .join("examples/valid_ready.vcd")
+                    .try_into()
+                    .unwrap(),
+            )),
+            spade_top: None,
+            rhdl_types: None,
+            spade_state: None,
+            startup_commands: vec![],
+        });
+    wait_for_waves_fully_loaded(&mut state, 10);
+
+    state.update(Message::AddDecoder(
+        "valid_ready clock=tb.clk valid=tb.valid ready=tb.ready data=tb.data".to_string(),
+    ));
+    // the decoder is decoded once before its variables are loaded
+    handle_messages_until(
+        &mut state,
+        |msg| matches!(&msg, Message::TransactionsDecoded(_, _, Some(_))),
+        10,
+    );
+    let transactions = state
+        .waves
+        .as_ref()
+        .and_then(|waves| {
+            waves.displayed_items.values().find_map(|item| match item {
+                DisplayedItem::Decoder(decoder) => decoder.transactions.clone(),
+                _ => None,
+            })
+        })
+        .expect("The transactions should be decoded");
+    // valid and data change at the edge at 35, which still sees the second transfer
+    assert_eq!(
+        transactions
+            .iter()
+            .map(|t| (t.start.clone(), t.end.clone(), t.label.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (BigUint::from(5u32), BigUint::from(25u32), "11"),
+            (BigUint::from(25u32), BigUint::from(35u32), "22"),
+        ]
+    );
+    assert!(transactions[0]
+        .attributes
+        .contains(&("wait cycles".to_string(), "1".to_string())));
+
+    // decoded transactions are moved between like those of transaction streams
+    let focused = |state: &State| state.waves.as_ref().unwrap().focused_transaction.0.clone();
+    state.update(Message::MoveTransaction { next: true });
+    assert_eq!(
+        focused(&state),
+        Some(transaction_container::TransactionRef { id: 0 })
+    );
+    state.update(Message::MoveTransaction { next: true });
+    assert_eq!(
+        focused(&state),
+        Some(transaction_container::TransactionRef { id: 1 })
+    );
+}
+
+#[test]
+fn values_are_exported_at_changes_and_clock_edges() {
+    let runtime = background_runtime();
+    let _enter = runtime.enter();
+    let mut state = State::new_default_config()
+        .unwrap()
+        .with_params(StartupParams {
+            waves: Some(WaveSource::File(
+                get_project_root()
+                    .unwrap()
+                    .join("examples/valid_ready.vcd")
+                    .try_into()
+                    .unwrap(),
+            )),
+            spade_top: None,
+            rhdl_types: None,
+            spade_state: None,
+            startup_commands: vec![],
+        });
+    wait_for_waves_fully_loaded(&mut state, 10);
+    state.update(Message::AddVariables(vec![
+        VariableRef::from_hierarchy_string("tb.valid"),
+        VariableRef::from_hierarchy_string("tb.data"),
+    ]));
+    wait_for_waves_fully_loaded(&mut state, 10);
+
+    let waves = state.waves.as_ref().unwrap();
+    let time = |t: u32| BigUint::from(t);
+    assert_eq!(
+        waves.change_times(&time(0), &time(50), 10),
+        BTreeSet::from([0, 10, 30, 35].map(time))
+    );
+    assert_eq!(
+        waves.change_times(&time(0), &time(50), 2),
+        BTreeSet::from([0, 10].map(time))
+    );
+
+    let row = |t: u32, valid: &str, data: &str| {
+        (
+            time(t),
+            vec![Some(valid.to_string()), Some(data.to_string())],
+        )
+    };
+    let table = state.value_table(waves, None, &time(0), &time(50)).unwrap();
+    assert_eq!(table.columns, vec!["tb.valid", "tb.data"]);
+    assert_eq!(
+        table.rows,
+        vec![
+            row(0, "0", "00"),
+            row(10, "1", "11"),
+            row(30, "1", "22"),
+            row(35, "0", "33"),
+        ]
+    );
+
+    let clock = VariableRef::from_hierarchy_string("tb.clk");
+    if let Some(cmd) = state
+        .waves
+        .as_mut()
+        .and_then(|waves| waves.inner.as_waves_mut())
+        .and_then(|inner| inner.load_variables(std::iter::once(&clock)).unwrap())
+    {
+        state.load_variables(cmd);
+    }
+    wait_for_waves_fully_loaded(&mut state, 10);
+    let waves = state.waves.as_ref().unwrap();
+    let edges = rising_edges(waves.inner.as_waves().unwrap(), &clock).unwrap();
+    let table = state
+        .value_table(waves, Some(&edges), &time(10), &time(30))
+        .unwrap();
+    assert_eq!(table.rows, vec![row(15, "1", "11"), row(25, "1", "11")]);
+}
+
+#[test]
+fn split_variable_is_composed_into_a_bus_again() {
+    let runtime = background_runtime();
+    let _enter = runtime.enter();
+    let mut state = State::new_default_config()
+        .unwrap()
+        .with_params(StartupParams {
+            waves: Some(WaveSource::File(
+                get_project_root()
+                    .unwrap()
+                    .join("examples/counter.vcd")
+                    .try_into()
+                    .unwrap(),
+            )),
+            spade_top: None,
+            rhdl_types: None,
+            spade_state: None,
+            startup_commands: vec![],
+        });
+    wait_for_waves_fully_loaded(&mut state, 10);
+    state.update(Message::AddVariables(vec![
+        VariableRef::from_hierarchy_string("tb.dut.counter"),
+    ]));
+    wait_for_waves_fully_loaded(&mut state, 10);
+
+    state.update(Message::SplitVariable(Some(DisplayedItemIndex(0)), 2));
+    let waves = state.waves.as_mut().unwrap();
+    assert_eq!(waves.displayed_items_order.len(), 3);
+    let slices = waves.displayed_items_order[1..].to_vec();
+    let slice_expr = |item: &DisplayedItem| match item {
+        DisplayedItem::Derived(derived) => match &derived.expr {
+            Expr::Slice { operand, msb, lsb } => match operand.as_ref() {
+                Expr::Variable { variable, width } => (
+                    derived.expression.clone(),
+                    variable.full_path_string(),
+                    *width,
+                    *msb,
+                    *lsb,
+                ),
+                expr => panic!("Expected a variable, got {expr:?}"),
+            },
+            expr => panic!("Expected a slice, got {expr:?}"),
+        },
+        _ => panic!("Expected a derived signal"),
+    };
+    assert_eq!(
+        slices
+            .iter()
+            .map(|id| slice_expr(&waves.displayed_items[id]))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                "tb.dut.counter[3:2]".to_string(),
+                "tb.dut.counter".to_string(),
+                4,
+                3,
+                2
+            ),
+            (
+                "tb.dut.counter[1:0]".to_string(),
+                "tb.dut.counter".to_string(),
+                4,
+                1,
+                0
+            ),
+        ]
+    );
+
+    waves.selected_items = HashSet::from_iter(slices.iter().copied());
+    state.update(Message::ComposeBus(Some("bus".to_string())));
+    let waves = state.waves.as_ref().unwrap();
+    assert_eq!(waves.displayed_items_order.len(), 4);
+    let bus = waves.displayed_items_order[3];
+    let DisplayedItem::Derived(derived) = &waves.displayed_items[&bus] else {
+        panic!("Expected the bus to be a derived signal");
+    };
+    assert_eq!(
+        derived.expression,
+        "{(tb.dut.counter[3:2]), (tb.dut.counter[1:0])}"
+    );
+    assert_eq!(derived.manual_name, Some("bus".to_string()));
+    assert!(matches!(
+        &derived.expr,
+        Expr::Concat(operands) if matches!(
+            operands.as_slice(),
+            [Expr::Slice { msb: 3, lsb: 2, .. }, Expr::Slice { msb: 1, lsb: 0, .. }]
+        )
+    ));
+
+    let value = |id: &DisplayedItemRef, time: u32| {
+        let signal = Signal::from_item(&waves.displayed_items[id]).unwrap();
+        match signal
+            .query(waves.inner.as_waves().unwrap(), &BigUint::from(time))
+            .unwrap()
+            .and_then(|result| result.current)
+        {
+            Some((_, VariableValue::BigUint(value))) => value.to_u32_digits(),
+            other => panic!("Expected a value, got {other:?}"),
+        }
+    };
+    // the counter is 5 at 190 and 14 at 370
+    assert_eq!(value(&slices[0], 190), vec![1]);
+    assert_eq!(value(&slices[1], 190), vec![1]);
+    assert_eq!(value(&bus, 190), vec![5]);
+    assert_eq!(value(&slices[0], 370), vec![3]);
+    assert_eq!(value(&slices[1], 370), vec![2]);
+    assert_eq!(value(&bus, 370), vec![14]);
+}
+
+#[test]
+fn value_search_checks_a_bounded_number_of_changes() {
+    let runtime = background_runtime();
+    let _enter = runtime.enter();
+    let mut state = State::new_default_config()
+        .unwrap()
+        .with_params(StartupParams {
+            waves: Some(WaveSource::File(
+                get_project_root()
+                    .unwrap()
+                    .join("examples/counter.vcd")
+                    .try_into()
+                    .unwrap(),
+            )),
+            spade_top: None,
+            rhdl_types: None,
+            spade_state: None,
+            startup_commands: vec![],
+        });
+    wait_for_waves_fully_loaded(&mut state, 10);
+    state.update(Message::AddVariables(vec![
+        VariableRef::from_hierarchy_string("tb.dut.counter"),
+    ]));
+    wait_for_waves_fully_loaded(&mut state, 10);
+    state.update(Message::SetValueSearch(
+        Some(DisplayedItemIndex(0)),
+        "== 5".to_string(),
+    ));
+
+    // the counter counts up from 1 at 110, 130, 150, ...
+    let waves = state.waves.as_ref().unwrap();
+    let time = |t: u32| BigUint::from(t);
+    assert_eq!(
+        state.find_value_match(waves, &time(0), true, 10),
+        Some(ValueMatch::Found(time(190)))
+    );
+    assert_eq!(
+        state.find_value_match(waves, &time(0), true, 3),
+        Some(ValueMatch::Stopped(time(150)))
+    );
+    assert_eq!(
+        state.find_value_match(waves, &time(150), true, 3),
+        Some(ValueMatch::Found(time(190)))
+    );
+    assert_eq!(
+        state.find_value_match(waves, &time(190), false, 2),
+        Some(ValueMatch::Stopped(time(150)))
+    );
+    assert_eq!(state.find_value_match(waves, &time(100), false, 10), None);
+}
+
 snapshot_ui!(switch, || {
     // check that variables are kept, not available ones as well
     let mut state = State::new_default_config()
@@ -1783,6 +2163,7 @@ snapshot_ui!(switch, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1858,6 +2239,7 @@ snapshot_ui!(switch_and_switch_back, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1946,6 +2328,7 @@ snapshot_ui!(save_and_load, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -1989,6 +2372,7 @@ snapshot_ui!(save_and_load, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -2073,6 +2457,7 @@ snapshot_ui!(arrow_drawing, || {
                     .unwrap(),
             )),
             spade_top: None,
+            rhdl_types: None,
             spade_state: None,
             startup_commands: vec![],
         });
@@ -9,7 +9,7 @@ use enum_iterator::Sequence;
 use epaint::{FontId, Stroke};
 use ftr_parser::types::Timescale;
 use itertools::Itertools;
-use num::{BigInt, BigRational, ToPrimitive};
+use num::{BigInt, BigRational, BigUint, ToPrimitive, Zero};
 use pure_rust_locales::{locale_match, Locale};
 use serde::{Deserialize, Serialize};
 use sys_locale::get_locale;
@@ -85,7 +85,7 @@ impl From<ftr_parser::types::Timescale> for TimeUnit {
 }
 
 impl TimeUnit {
-    fn exponent(&self) -> i8 {
+    pub fn exponent(&self) -> i8 {
         match self {
             TimeUnit::FemtoSeconds => -15,
             TimeUnit::PicoSeconds => -12,
@@ -280,6 +280,25 @@ fn split_and_format_number(time: String, format: &TimeStringFormatting) -> Strin
     }
 }
 
+/// Formats the frequency of a periodic signal, e.g. `100 MHz`. Returns `None` if the
+/// waveform has no time unit.
+pub fn frequency_string(period: &BigUint, timescale: &TimeScale) -> Option<String> {
+    if matches!(timescale.unit, TimeUnit::None | TimeUnit::Auto) || period.is_zero() {
+        return None;
+    }
+    let frequency = 10f64.powi((-timescale.unit.exponent()).into())
+        / (period.to_f64()? * f64::from(timescale.multiplier.unwrap_or(1)));
+    let (value, unit) = [(1e12, "THz"), (1e9, "GHz"), (1e6, "MHz"), (1e3, "kHz")]
+        .into_iter()
+        .find(|(scale, _)| frequency >= *scale)
+        .map_or((frequency, "Hz"), |(scale, unit)| (frequency / scale, unit));
+    let value = format!("{value:.3}");
+    Some(format!(
+        "{} {unit}",
+        value.trim_end_matches('0').trim_end_matches('.')
+    ))
+}
+
 /// Heuristically find a suitable time unit for the given time.
 fn find_auto_scale(time: &BigInt, timescale: &TimeScale) -> TimeUnit {
     // In case of seconds, nothing to do as it is the largest supported unit
@@ -359,6 +378,9 @@ impl WaveData {
         time_format: &TimeFormat,
         config: &SurferConfig,
     ) -> Vec<(String, f32)> {
+        if let Some(ticks) = self.get_cycle_ticks(viewport, frame_width, text_size, config) {
+            return ticks;
+        }
         let char_width = text_size * (20. / 31.);
         let rightexp = viewport
             .curr_right
@@ -463,9 +485,11 @@ impl State {
 
 #[cfg(test)]
 mod test {
-    use num::BigInt;
+    use num::{BigInt, BigUint};
 
-    use crate::time::{time_string, TimeFormat, TimeScale, TimeStringFormatting, TimeUnit};
+    use crate::time::{
+        frequency_string, time_string, TimeFormat, TimeScale, TimeStringFormatting, TimeUnit,
+    };
 
     #[test]
     fn print_time_standard() {
@@ -735,4 +759,28 @@ mod test {
             "220"
         );
     }
+
+    #[test]
+    fn print_frequency() {
+        let timescale = |unit, multiplier| TimeScale {
+            unit,
+            multiplier: Some(multiplier),
+        };
+        assert_eq!(
+            frequency_string(&BigUint::from(10u8), &timescale(TimeUnit::NanoSeconds, 1)),
+            Some("100 MHz".to_string())
+        );
+        assert_eq!(
+            frequency_string(&BigUint::from(3u8), &timescale(TimeUnit::PicoSeconds, 10)),
+            Some("33.333 GHz".to_string())
+        );
+        assert_eq!(
+            frequency_string(&BigUint::from(4u8), &timescale(TimeUnit::Seconds, 1)),
+            Some("0.25 Hz".to_string())
+        );
+        assert_eq!(
+            frequency_string(&BigUint::from(4u8), &timescale(TimeUnit::None, 1)),
+            None
+        );
+    }
 }
@@ -0,0 +1,312 @@
+//! User-defined tables mapping values of variables to labels.
+//!
+//! A mapping is read from a toml file
+//!
+//! ```toml
+//! [[entries]]
+//! value = 0
+//! label = "IDLE"
+//!
+//! [[entries]]
+//! value = "0x3"
+//! label = "ERROR"
+//! color = "#ff0000"
+//! ```
+//!
+//! or from a csv file with `value,label[,color]` on each line, and is applied to all
+//! variables whose full path matches a pattern where `*` matches any sequence of characters.
+use std::collections::HashMap;
+
+use camino::Utf8Path;
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use ecolor::Color32;
+use num::BigUint;
+use serde::{Deserialize, Serialize};
+use surfer_translation_types::{
+    BasicTranslator, TranslationResult, Translator, ValueRepr, VariableValue,
+};
+
+use crate::config::hex_string_to_color32;
+use crate::message::Message;
+use crate::wave_container::{ScopeId, VarId, VariableMeta, VariableRefExt};
+
+use super::register_translators::parse_label_value;
+use super::{HexTranslator, TranslationPreference, ValueKind, VariableInfo};
+
+pub const VALUE_MAPPING_TRANSLATOR_NAME: &str = "Value mapping";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValueMappingEntry {
+    pub value: BigUint,
+    pub label: String,
+    pub color: Option<Color32>,
+}
+
+/// A value to label table and the variables it applies to. The entries are stored rather
+/// than the file they were read from so that saved states do not depend on the file.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValueMapping {
+    pub pattern: String,
+    pub entries: Vec<ValueMappingEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingToml {
+    entries: Vec<MappingEntryToml>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingEntryToml {
+    value: MappingValue,
+    label: String,
+    color: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MappingValue {
+    Integer(u64),
+    String(String),
+}
+
+fn parse_color(color: &str) -> Result<Color32> {
+    hex_string_to_color32(color.trim().trim_start_matches('#').to_string())
+}
+
+impl ValueMapping {
+    /// Read a mapping from a `.toml` or `.csv` file
+    pub fn from_file(path: &Utf8Path, pattern: String) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+        let entries = match path.extension() {
+            Some("toml") => Self::parse_toml(&text),
+            Some("csv") => Self::parse_csv(&text),
+            _ => bail!("Value mappings must be .toml or .csv files, got {path}"),
+        }
+        .with_context(|| format!("Failed to parse {path}"))?;
+        Ok(Self { pattern, entries })
+    }
+
+    fn parse_toml(text: &str) -> Result<Vec<ValueMappingEntry>> {
+        let mapping: MappingToml = toml::from_str(text)?;
+        mapping
+            .entries
+            .into_iter()
+            .map(|entry| {
+                Ok(ValueMappingEntry {
+                    value: match entry.value {
+                        MappingValue::Integer(v) => BigUint::from(v),
+                        MappingValue::String(v) => parse_label_value(&v)?,
+                    },
+                    label: entry.label,
+                    color: entry.color.as_deref().map(parse_color).transpose()?,
+                })
+            })
+            .collect()
+    }
+
+    /// Parse `value,label[,color]` lines. Empty lines, lines starting with `#` and a
+    /// `value,label` header are skipped.
+    fn parse_csv(text: &str) -> Result<Vec<ValueMappingEntry>> {
+        text.lines()
+            .enumerate()
+            .map(|(no, line)| (no, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .filter(|(_, line)| !line.to_lowercase().starts_with("value,"))
+            .map(|(no, line)| {
+                let columns = line.split(',').map(str::trim).collect::<Vec<_>>();
+                let (value, label, color) = match columns.as_slice() {
+                    [value, label] => (value, label, None),
+                    [value, label, color] => (value, label, Some(color)),
+                    _ => bail!("Line {}: expected 'value,label[,color]'", no + 1),
+                };
+                Ok(ValueMappingEntry {
+                    value: parse_label_value(value).with_context(|| format!("Line {}", no + 1))?,
+                    label: label.to_string(),
+                    color: color
+                        .filter(|c| !c.is_empty())
+                        .map(|c| parse_color(c))
+                        .transpose()
+                        .with_context(|| format!("Line {}", no + 1))?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        matches_pattern(&self.pattern, path)
+    }
+}
+
+/// Match `path` against `pattern` where `*` matches any, possibly empty, sequence of characters
+pub fn matches_pattern(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let path = path.chars().collect::<Vec<_>>();
+    let (mut p, mut s) = (0, 0);
+    // Position of the last `*` in the pattern and of the path when it was reached
+    let mut backtrack = None;
+    while s < path.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, s));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == path[s] {
+            p += 1;
+            s += 1;
+        } else if let Some((star, star_s)) = backtrack {
+            p = star + 1;
+            s = star_s + 1;
+            backtrack = Some((star, star_s + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+struct CompiledMapping {
+    pattern: String,
+    entries: HashMap<BigUint, (String, Option<Color32>)>,
+}
+
+/// Translates variables matched by any of the user's value mappings. Rebuilt whenever the
+/// mappings change.
+pub struct ValueMappingTranslator {
+    mappings: Vec<CompiledMapping>,
+}
+
+impl ValueMappingTranslator {
+    pub fn new(mappings: &[ValueMapping]) -> Self {
+        Self {
+            mappings: mappings
+                .iter()
+                .map(|mapping| CompiledMapping {
+                    pattern: mapping.pattern.clone(),
+                    entries: mapping
+                        .entries
+                        .iter()
+                        .map(|e| (e.value.clone(), (e.label.clone(), e.color)))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    fn mapping_for(&self, variable: &VariableMeta) -> Option<&CompiledMapping> {
+        let path = variable.var.full_path_string();
+        self.mappings
+            .iter()
+            .find(|m| matches_pattern(&m.pattern, &path))
+    }
+}
+
+impl Translator<VarId, ScopeId, Message> for ValueMappingTranslator {
+    fn name(&self) -> String {
+        VALUE_MAPPING_TRANSLATOR_NAME.to_string()
+    }
+
+    fn translate(
+        &self,
+        variable: &VariableMeta,
+        value: &VariableValue,
+    ) -> Result<TranslationResult> {
+        let label = self.mapping_for(variable).and_then(|mapping| {
+            value
+                .clone()
+                .parse_biguint()
+                .ok()
+                .and_then(|v| mapping.entries.get(&v))
+        });
+        let (val, kind) = match label {
+            Some((label, color)) => (
+                label.clone(),
+                color.map_or(ValueKind::Normal, ValueKind::Custom),
+            ),
+            None => HexTranslator {}.basic_translate(variable.num_bits.unwrap_or(0) as u64, value),
+        };
+        Ok(TranslationResult {
+            val: ValueRepr::String(val),
+            kind,
+            subfields: vec![],
+        })
+    }
+
+    fn variable_info(&self, _variable: &VariableMeta) -> Result<VariableInfo> {
+        Ok(VariableInfo::Bits)
+    }
+
+    fn translates(&self, variable: &VariableMeta) -> Result<TranslationPreference> {
+        if self.mapping_for(variable).is_some() {
+            Ok(TranslationPreference::Prefer)
+        } else {
+            Ok(TranslationPreference::No)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn patterns_match_with_wildcards() {
+        assert!(matches_pattern("top.fsm.state", "top.fsm.state"));
+        assert!(!matches_pattern("top.fsm.state", "top.fsm.state_next"));
+        assert!(matches_pattern("*.state", "top.fsm.state"));
+        assert!(matches_pattern("top.*.state*", "top.a.b.state_next"));
+        assert!(!matches_pattern("top.*.state", "top.state"));
+        assert!(matches_pattern("*", ""));
+    }
+
+    #[test]
+    fn csv_mappings_are_parsed() {
+        let entries = ValueMapping::parse_csv(
+            "value,label,color\n# comment\n0,IDLE\n0x2, BUSY ,#00ff00\n\n0b11,DONE,",
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ValueMappingEntry {
+                    value: BigUint::from(0u8),
+                    label: "IDLE".to_string(),
+                    color: None
+                },
+                ValueMappingEntry {
+                    value: BigUint::from(2u8),
+                    label: "BUSY".to_string(),
+                    color: Some(Color32::from_rgb(0, 255, 0))
+                },
+                ValueMappingEntry {
+                    value: BigUint::from(3u8),
+                    label: "DONE".to_string(),
+                    color: None
+                },
+            ]
+        );
+        assert!(ValueMapping::parse_csv("1,ONE,#zzzzzz").is_err());
+        assert!(ValueMapping::parse_csv("ONE").is_err());
+    }
+
+    #[test]
+    fn toml_mappings_are_parsed() {
+        let entries = ValueMapping::parse_toml(
+            r##"
+            [[entries]]
+            value = 1
+            label = "ONE"
+
+            [[entries]]
+            value = "0x10"
+            label = "SIXTEEN"
+            color = "f00"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(entries[0].value, BigUint::from(1u8));
+        assert_eq!(entries[1].value, BigUint::from(16u8));
+        assert_eq!(entries[1].color, Some(Color32::from_rgb(255, 0, 0)));
+    }
+}
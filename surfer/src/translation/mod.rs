@@ -2,6 +2,7 @@ use std::collections::HashMap;
 #[cfg(not(target_arch = "wasm32"))]
 use std::path::Path;
 use std::sync::mpsc::Sender;
+use std::sync::Arc;
 
 use color_eyre::Result;
 #[cfg(not(target_arch = "wasm32"))]
@@ -16,21 +17,28 @@ mod basic_translators;
 pub mod clock;
 mod enum_translator;
 mod instruction_translators;
+pub mod mapping_translator;
 pub mod numeric_translators;
 #[cfg(feature = "python")]
 mod python_translators;
+mod register_translators;
+pub mod rhdl;
 pub mod spade;
+#[cfg(feature = "wasm_plugins")]
+pub mod wasm_translators;
 
 pub use basic_translators::*;
 use clock::ClockTranslator;
 use instruction_decoder::Decoder;
 pub use instruction_translators::*;
 use itertools::Itertools;
+use mapping_translator::ValueMappingTranslator;
 pub use numeric_translators::*;
+pub use register_translators::RegisterTranslator;
 use surfer_translation_types::{
     BasicTranslator, HierFormatResult, SubFieldFlatTranslationResult, TranslatedValue,
-    TranslationPreference, TranslationResult, Translator, ValueKind, ValueRepr, VariableEncoding,
-    VariableInfo, VariableValue,
+    TranslationPreference, TranslationResult, Translator, TranslatorArgument, TranslatorArguments,
+    ValueKind, ValueRepr, VariableEncoding, VariableInfo, VariableValue,
 };
 
 use crate::config::SurferTheme;
@@ -45,8 +53,10 @@ fn translate_with_basic(
     t: &DynBasicTranslator,
     variable: &VariableMeta,
     value: &VariableValue,
+    arguments: &TranslatorArguments,
 ) -> Result<TranslationResult> {
-    let (val, kind) = t.basic_translate(variable.num_bits.unwrap_or(0) as u64, value);
+    let (val, kind) =
+        t.basic_translate_with_arguments(variable.num_bits.unwrap_or(0) as u64, value, arguments);
     Ok(TranslationResult {
         val: ValueRepr::String(val),
         kind,
@@ -81,12 +91,30 @@ impl Translator<VarId, ScopeId, Message> for AnyTranslator {
         &self,
         variable: &VariableMeta,
         value: &VariableValue,
+    ) -> Result<TranslationResult> {
+        self.translate_with_arguments(variable, value, &TranslatorArguments::default())
+    }
+
+    fn translate_with_arguments(
+        &self,
+        variable: &VariableMeta,
+        value: &VariableValue,
+        arguments: &TranslatorArguments,
     ) -> Result<TranslationResult> {
         match self {
-            AnyTranslator::Full(t) => t.translate(variable, value),
-            AnyTranslator::Basic(t) => translate_with_basic(&**t, variable, value),
+            AnyTranslator::Full(t) => t.translate_with_arguments(variable, value, arguments),
+            AnyTranslator::Basic(t) => translate_with_basic(&**t, variable, value, arguments),
             #[cfg(feature = "python")]
-            AnyTranslator::Python(t) => translate_with_basic(t, variable, value),
+            AnyTranslator::Python(t) => t.translate_with_arguments(variable, value, arguments),
+        }
+    }
+
+    fn arguments(&self) -> Vec<TranslatorArgument> {
+        match self {
+            AnyTranslator::Full(t) => t.arguments(),
+            AnyTranslator::Basic(t) => t.arguments(),
+            #[cfg(feature = "python")]
+            AnyTranslator::Python(t) => t.arguments(),
         }
     }
 
@@ -226,8 +254,85 @@ fn find_user_decoders_at_path(path: &Path) -> Vec<Box<DynBasicTranslator>> {
     decoders
 }
 
-pub fn all_translators() -> TranslatorList {
-    let mut basic_translators: Vec<Box<DynBasicTranslator>> = vec![
+/// Look inside the config directory and inside "$(cwd)/.surfer" for user-defined register
+/// layouts. Each toml file in the `registers` directory describes the fields of one register
+/// and is loaded as a translator named after the register.
+#[cfg(not(target_arch = "wasm32"))]
+fn find_user_register_layouts() -> Vec<Box<DynTranslator>> {
+    let mut layouts: Vec<Box<DynTranslator>> = vec![];
+    if let Some(proj_dirs) = ProjectDirs::from("org", "surfer-project", "surfer") {
+        let mut config_layouts = find_user_register_layouts_at_path(proj_dirs.config_dir());
+        layouts.append(&mut config_layouts);
+    }
+
+    let mut project_layouts = find_user_register_layouts_at_path(Path::new(".surfer"));
+    layouts.append(&mut project_layouts);
+
+    layouts
+}
+
+/// Look for user defined register layouts in path.
+#[cfg(not(target_arch = "wasm32"))]
+fn find_user_register_layouts_at_path(path: &Path) -> Vec<Box<DynTranslator>> {
+    let mut layouts: Vec<Box<DynTranslator>> = vec![];
+    let Ok(toml_files) = std::fs::read_dir(path.join("registers")) else {
+        return layouts;
+    };
+
+    for toml_file in toml_files.flatten() {
+        let toml_path = toml_file.path();
+        if toml_path.extension().is_some_and(|ext| ext == "toml") {
+            match RegisterTranslator::new(&toml_path) {
+                Ok(layout) => layouts.push(Box::new(layout)),
+                Err(e) => warn!("Skipping register layout {toml_path:?}. {e:#}"),
+            }
+        }
+    }
+    layouts
+}
+
+/// Look inside the config directory and inside "$(cwd)/.surfer" for WebAssembly translator
+/// plugins. Every `.wasm` file in the `translators` directory is loaded as a translator.
+#[cfg(all(feature = "wasm_plugins", not(target_arch = "wasm32")))]
+fn find_user_wasm_translators() -> Vec<Box<DynTranslator>> {
+    let mut translators: Vec<Box<DynTranslator>> = vec![];
+    if let Some(proj_dirs) = ProjectDirs::from("org", "surfer-project", "surfer") {
+        let mut config_translators = find_user_wasm_translators_at_path(proj_dirs.config_dir());
+        translators.append(&mut config_translators);
+    }
+
+    let mut project_translators = find_user_wasm_translators_at_path(Path::new(".surfer"));
+    translators.append(&mut project_translators);
+
+    translators
+}
+
+/// Look for WebAssembly translator plugins in path.
+#[cfg(all(feature = "wasm_plugins", not(target_arch = "wasm32")))]
+fn find_user_wasm_translators_at_path(path: &Path) -> Vec<Box<DynTranslator>> {
+    let mut translators: Vec<Box<DynTranslator>> = vec![];
+    let Ok(plugin_files) = std::fs::read_dir(path.join("translators")) else {
+        return translators;
+    };
+
+    for plugin_file in plugin_files.flatten() {
+        let plugin_path = plugin_file.path();
+        if plugin_path.extension().is_some_and(|ext| ext == "wasm") {
+            match std::fs::read(&plugin_path)
+                .map_err(color_eyre::Report::from)
+                .and_then(|wasm| wasm_translators::WasmTranslator::new(&wasm))
+            {
+                Ok(translator) => translators.push(Box::new(translator)),
+                Err(e) => warn!("Skipping translator plugin {plugin_path:?}. {e:#}"),
+            }
+        }
+    }
+    translators
+}
+
+/// The basic translators that are built into Surfer
+pub fn builtin_basic_translators() -> Vec<Box<DynBasicTranslator>> {
+    vec![
         Box::new(BitTranslator {}),
         Box::new(HexTranslator {}),
         Box::new(OctalTranslator {}),
@@ -240,6 +345,8 @@ pub fn all_translators() -> TranslatorList {
         Box::new(LebTranslator {}),
         Box::new(UnsignedTranslator {}),
         Box::new(SignedTranslator {}),
+        Box::new(UnsignedFixedPointTranslator {}),
+        Box::new(SignedFixedPointTranslator {}),
         Box::new(SinglePrecisionTranslator {}),
         Box::new(DoublePrecisionTranslator {}),
         Box::new(HalfPrecisionTranslator {}),
@@ -259,26 +366,39 @@ pub fn all_translators() -> TranslatorList {
         Box::new(IdenticalMSBsTranslator {}),
         #[cfg(feature = "f128")]
         Box::new(QuadPrecisionTranslator {}),
-    ];
+    ]
+}
+
+pub fn all_translators() -> TranslatorList {
+    let mut basic_translators = builtin_basic_translators();
 
     #[cfg(not(target_arch = "wasm32"))]
     basic_translators.append(&mut find_user_decoders());
 
-    TranslatorList::new(
-        basic_translators,
-        vec![
-            Box::new(ClockTranslator::new()),
-            Box::new(StringTranslator {}),
-            Box::new(EnumTranslator {}),
-        ],
-    )
+    let mut translators: Vec<Box<DynTranslator>> = vec![
+        Box::new(ClockTranslator::new()),
+        Box::new(StringTranslator {}),
+        Box::new(EnumTranslator {}),
+        Box::new(ValueMappingTranslator::new(&[])),
+    ];
+
+    #[cfg(not(target_arch = "wasm32"))]
+    translators.append(&mut find_user_register_layouts());
+
+    #[cfg(all(feature = "wasm_plugins", not(target_arch = "wasm32")))]
+    translators.append(&mut find_user_wasm_translators());
+
+    TranslatorList::new(basic_translators, translators)
 }
 
-#[derive(Default)]
+/// The translators are shared between clones, so that values can be translated on worker
+/// threads
+#[derive(Default, Clone)]
 pub struct TranslatorList {
-    inner: HashMap<String, AnyTranslator>,
+    inner: HashMap<String, Arc<AnyTranslator>>,
+    /// The file the Python translators were loaded from and the translators in it
     #[cfg(feature = "python")]
-    python_translator: Option<(camino::Utf8PathBuf, String, AnyTranslator)>,
+    python_translators: Option<(camino::Utf8PathBuf, Vec<(String, Arc<AnyTranslator>)>)>,
     pub default: String,
 }
 
@@ -288,39 +408,46 @@ impl TranslatorList {
             default: "Hexadecimal".to_string(),
             inner: basic
                 .into_iter()
-                .map(|t| (t.name(), AnyTranslator::Basic(t)))
+                .map(|t| (t.name(), Arc::new(AnyTranslator::Basic(t))))
                 .chain(
                     translators
                         .into_iter()
-                        .map(|t| (t.name(), AnyTranslator::Full(t))),
+                        .map(|t| (t.name(), Arc::new(AnyTranslator::Full(t)))),
                 )
                 .collect(),
             #[cfg(feature = "python")]
-            python_translator: None,
+            python_translators: None,
         }
     }
 
     pub fn all_translator_names(&self) -> Vec<&str> {
         #[cfg(feature = "python")]
-        let python_name = self
-            .python_translator
-            .as_ref()
-            .map(|(_, name, _)| name.as_str());
+        let python_names = self
+            .python_translators
+            .iter()
+            .flat_map(|(_, translators)| translators.iter().map(|(name, _)| name.as_str()));
         #[cfg(not(feature = "python"))]
-        let python_name = None;
+        let python_names = None;
         self.inner
             .keys()
             .map(String::as_str)
-            .chain(python_name)
+            .chain(python_names)
             .collect()
     }
 
     pub fn all_translators(&self) -> Vec<&AnyTranslator> {
         #[cfg(feature = "python")]
-        let python_translator = self.python_translator.as_ref().map(|(_, _, t)| t);
+        let python_translators = self
+            .python_translators
+            .iter()
+            .flat_map(|(_, translators)| translators.iter().map(|(_, t)| &**t));
         #[cfg(not(feature = "python"))]
-        let python_translator = None;
-        self.inner.values().chain(python_translator).collect()
+        let python_translators = None;
+        self.inner
+            .values()
+            .map(|t| &**t)
+            .chain(python_translators)
+            .collect()
     }
 
     pub fn basic_translator_names(&self) -> Vec<&str> {
@@ -333,21 +460,23 @@ impl TranslatorList {
     pub fn get_translator(&self, name: &str) -> &AnyTranslator {
         #[cfg(feature = "python")]
         let python_translator = || {
-            self.python_translator
-                .as_ref()
-                .filter(|(_, python_name, _)| python_name == name)
-                .map(|(_, _, t)| t)
+            self.python_translators
+                .iter()
+                .flat_map(|(_, translators)| translators.iter())
+                .find(|(python_name, _)| python_name == name)
+                .map(|(_, t)| &**t)
         };
         #[cfg(not(feature = "python"))]
         let python_translator = || None;
         self.inner
             .get(name)
+            .map(|t| &**t)
             .or_else(python_translator)
             .unwrap_or_else(|| panic!("No translator called {name}"))
     }
 
     pub fn add_or_replace(&mut self, t: AnyTranslator) {
-        self.inner.insert(t.name(), t);
+        self.inner.insert(t.name(), Arc::new(t));
     }
 
     pub fn is_valid_translator(&self, meta: &VariableMeta, candidate: &str) -> bool {
@@ -361,27 +490,28 @@ impl TranslatorList {
     pub fn load_python_translator(&mut self, filename: camino::Utf8PathBuf) -> Result<()> {
         log::debug!("Reading Python code from disk: {filename}");
         let code = std::fs::read_to_string(&filename)?;
-        let mut translators = python_translators::PythonTranslator::new(&code)?;
-        if translators.len() != 1 {
-            color_eyre::eyre::bail!("Only one Python translator per file is supported for now");
+        let translators = python_translators::PythonTranslator::new(&code)?;
+        if translators.is_empty() {
+            color_eyre::eyre::bail!("No translators are listed in {filename}");
         }
-        let translator = translators.pop().unwrap();
-        self.python_translator = Some((
+        self.python_translators = Some((
             filename,
-            translator.name(),
-            AnyTranslator::Python(translator),
+            translators
+                .into_iter()
+                .map(|t| (t.name(), Arc::new(AnyTranslator::Python(t))))
+                .collect(),
         ));
         Ok(())
     }
 
     #[cfg(feature = "python")]
     pub fn has_python_translator(&self) -> bool {
-        self.python_translator.is_some()
+        self.python_translators.is_some()
     }
 
     #[cfg(feature = "python")]
     pub fn reload_python_translator(&mut self) -> Result<()> {
-        if let Some((path, _, _)) = self.python_translator.take() {
+        if let Some((path, _)) = self.python_translators.take() {
             self.load_python_translator(path)?;
         }
         Ok(())
@@ -392,6 +522,7 @@ fn format(
     val: &ValueRepr,
     kind: ValueKind,
     subtranslator_name: &String,
+    arguments: &TranslatorArguments,
     translators: &TranslatorList,
     subresults: &[HierFormatResult],
 ) -> Option<TranslatedValue> {
@@ -404,7 +535,11 @@ fn format(
             };
 
             Some(TranslatedValue::from_basic_translate(
-                subtranslator.basic_translate(1, &VariableValue::String(val.to_string())),
+                subtranslator.basic_translate_with_arguments(
+                    1,
+                    &VariableValue::String(val.to_string()),
+                    arguments,
+                ),
             ))
         }
         ValueRepr::Bits(bit_count, bits) => {
@@ -415,7 +550,11 @@ fn format(
             };
 
             Some(TranslatedValue::from_basic_translate(
-                subtranslator.basic_translate(*bit_count, &VariableValue::String(bits.clone())),
+                subtranslator.basic_translate_with_arguments(
+                    *bit_count,
+                    &VariableValue::String(bits.clone()),
+                    arguments,
+                ),
             ))
         }
         ValueRepr::String(sval) => Some(TranslatedValue {
@@ -490,15 +629,16 @@ impl TranslationResultExt for TranslationResult {
 
                 // we can consistently fall back to the default here since sub-fields
                 // are never checked for their preferred translator
-                let translator_name = formats
-                    .iter()
-                    .find(|e| e.field == sub_path)
+                let field_format = formats.iter().find(|e| e.field == sub_path);
+                let translator_name = field_format
                     .map(|e| e.format.clone())
                     .unwrap_or(translators.default.clone());
+                let no_arguments = TranslatorArguments::default();
                 let formatted = format(
                     &res.result.val,
                     res.result.kind,
                     &translator_name,
+                    field_format.map_or(&no_arguments, |e| &e.arguments),
                     translators,
                     &sub,
                 );
@@ -516,6 +656,7 @@ impl TranslationResultExt for TranslationResult {
     fn format_flat(
         &self,
         root_format: &Option<String>,
+        root_arguments: &TranslatorArguments,
         formats: &[crate::displayed_item::FieldFormat],
         translators: &TranslatorList,
     ) -> Vec<SubFieldFlatTranslationResult> {
@@ -528,6 +669,7 @@ impl TranslationResultExt for TranslationResult {
             &self.val,
             self.kind,
             root_format.as_ref().unwrap_or(&translators.default),
+            root_arguments,
             translators,
             &sub_result,
         );
@@ -636,8 +778,16 @@ impl Translator<VarId, ScopeId, Message> for StringTranslator {
 }
 
 fn check_single_wordlength(num_bits: Option<u32>, required: u32) -> Result<TranslationPreference> {
+    check_wordlength(num_bits, |num_bits| num_bits == required)
+}
+
+/// Translates variables whose width is accepted by `accepts`
+fn check_wordlength(
+    num_bits: Option<u32>,
+    accepts: impl FnOnce(u32) -> bool,
+) -> Result<TranslationPreference> {
     if let Some(num_bits) = num_bits {
-        if num_bits == required {
+        if accepts(num_bits) {
             Ok(TranslationPreference::Yes)
         } else {
             Ok(TranslationPreference::No)
@@ -3,13 +3,14 @@ use half::{bf16, f16};
 use num::BigUint;
 use softposit::{P16E1, P32E2, P8E0, Q16E1, Q8E0};
 use surfer_translation_types::{
-    translates_all_bit_types, BasicTranslator, ValueKind, VariableMeta, VariableValue,
+    translates_all_bit_types, BasicTranslator, TranslatorArgument, TranslatorArguments, ValueKind,
+    VariableMeta, VariableValue,
 };
 
 use crate::variable_type::INTEGER_TYPES;
 use crate::wave_container::{ScopeId, VarId};
 
-use super::{check_single_wordlength, TranslationPreference};
+use super::{check_single_wordlength, check_wordlength, TranslationPreference};
 
 #[inline]
 fn shortest_float_representation<T: std::fmt::LowerExp + std::fmt::Display>(v: T) -> String {
@@ -78,6 +79,121 @@ impl BasicTranslator<VarId, ScopeId> for SignedTranslator {
     }
 }
 
+const FRACTIONAL_BITS: &str = "fractional_bits";
+const DEFAULT_FRACTIONAL_BITS: i64 = 8;
+
+fn fixed_point_arguments() -> Vec<TranslatorArgument> {
+    vec![TranslatorArgument::integer(
+        FRACTIONAL_BITS,
+        "Number of bits after the binary point",
+        0,
+        4096,
+        DEFAULT_FRACTIONAL_BITS,
+    )]
+}
+
+fn fractional_bits(arguments: &TranslatorArguments) -> u64 {
+    arguments
+        .get_integer(FRACTIONAL_BITS)
+        .unwrap_or(DEFAULT_FRACTIONAL_BITS)
+        .max(0) as u64
+}
+
+/// Format `v / 2^fractional_bits` in decimal. One decimal more than needed to tell
+/// neighbouring values apart is shown, but never more than the exact value needs.
+/// Trailing zeros are removed.
+fn format_fixed_point(v: &BigUint, fractional_bits: u64) -> String {
+    let integer = v >> fractional_bits;
+    if fractional_bits == 0 {
+        return format!("{integer}");
+    }
+    let fraction = v - (&integer << fractional_bits);
+    let decimals = ((fractional_bits as f64 * std::f64::consts::LOG10_2).ceil() as u64 + 1)
+        .min(fractional_bits) as u32;
+    let scale = BigUint::from(10u8).pow(decimals);
+    // Round to nearest. As 10^decimals >= 2^fractional_bits this never rounds up to 1
+    let rounded = ((fraction * scale << 1u8) + (BigUint::from(1u8) << fractional_bits))
+        >> (fractional_bits + 1);
+    let decimals = format!("{rounded:0width$}", width = decimals as usize);
+    let decimals = decimals.trim_end_matches('0');
+    if decimals.is_empty() {
+        format!("{integer}")
+    } else {
+        format!("{integer}.{decimals}")
+    }
+}
+
+pub struct UnsignedFixedPointTranslator {}
+
+impl BasicTranslator<VarId, ScopeId> for UnsignedFixedPointTranslator {
+    fn name(&self) -> String {
+        String::from("Fixed point: unsigned")
+    }
+
+    fn basic_translate(&self, num_bits: u64, v: &VariableValue) -> (String, ValueKind) {
+        self.basic_translate_with_arguments(num_bits, v, &TranslatorArguments::default())
+    }
+
+    fn basic_translate_with_arguments(
+        &self,
+        _: u64,
+        v: &VariableValue,
+        arguments: &TranslatorArguments,
+    ) -> (String, ValueKind) {
+        let fractional_bits = fractional_bits(arguments);
+        translate_numeric(|v| format_fixed_point(&v, fractional_bits), v)
+    }
+
+    fn translates(&self, variable: &VariableMeta<VarId, ScopeId>) -> Result<TranslationPreference> {
+        check_wordlength(variable.num_bits, |num_bits| num_bits > 0)
+    }
+
+    fn arguments(&self) -> Vec<TranslatorArgument> {
+        fixed_point_arguments()
+    }
+}
+
+pub struct SignedFixedPointTranslator {}
+
+impl BasicTranslator<VarId, ScopeId> for SignedFixedPointTranslator {
+    fn name(&self) -> String {
+        String::from("Fixed point: signed")
+    }
+
+    fn basic_translate(&self, num_bits: u64, v: &VariableValue) -> (String, ValueKind) {
+        self.basic_translate_with_arguments(num_bits, v, &TranslatorArguments::default())
+    }
+
+    fn basic_translate_with_arguments(
+        &self,
+        num_bits: u64,
+        v: &VariableValue,
+        arguments: &TranslatorArguments,
+    ) -> (String, ValueKind) {
+        let fractional_bits = fractional_bits(arguments);
+        translate_numeric(
+            |v| {
+                let signweight = BigUint::from(1u8) << num_bits.saturating_sub(1);
+                if v < signweight {
+                    format_fixed_point(&v, fractional_bits)
+                } else {
+                    let v2 = (signweight << 1) - v;
+                    format!("-{}", format_fixed_point(&v2, fractional_bits))
+                }
+            },
+            v,
+        )
+    }
+
+    fn translates(&self, variable: &VariableMeta<VarId, ScopeId>) -> Result<TranslationPreference> {
+        check_wordlength(variable.num_bits, |num_bits| num_bits > 0)
+    }
+
+    fn arguments(&self) -> Vec<TranslatorArgument> {
+        fixed_point_arguments()
+    }
+}
+
 pub struct SinglePrecisionTranslator {}
 
 impl BasicTranslator<VarId, ScopeId> for SinglePrecisionTranslator {
@@ -447,6 +563,106 @@ mod test {
         );
     }
 
+    fn fractional_bits(n: i64) -> TranslatorArguments {
+        let mut arguments = TranslatorArguments::default();
+        arguments.set(
+            FRACTIONAL_BITS,
+            surfer_translation_types::TranslatorArgumentValue::Integer(n),
+        );
+        arguments
+    }
+
+    #[test]
+    fn unsigned_fixed_point_translation() {
+        assert_eq!(
+            UnsignedFixedPointTranslator {}
+                .basic_translate_with_arguments(
+                    8,
+                    &VariableValue::String("00011000".to_string()),
+                    &fractional_bits(4)
+                )
+                .0,
+            "1.5"
+        );
+        assert_eq!(
+            UnsignedFixedPointTranslator {}
+                .basic_translate(16, &VariableValue::BigUint(BigUint::from(0x0180u32)))
+                .0,
+            "1.5"
+        );
+        assert_eq!(
+            UnsignedFixedPointTranslator {}
+                .basic_translate_with_arguments(
+                    16,
+                    &VariableValue::BigUint(BigUint::from(1u32)),
+                    &fractional_bits(12)
+                )
+                .0,
+            "0.00024"
+        );
+        assert_eq!(
+            UnsignedFixedPointTranslator {}
+                .basic_translate_with_arguments(
+                    16,
+                    &VariableValue::BigUint(BigUint::from(0x1234u32)),
+                    &fractional_bits(0)
+                )
+                .0,
+            "4660"
+        );
+    }
+
+    #[test]
+    fn signed_fixed_point_translation() {
+        assert_eq!(
+            SignedFixedPointTranslator {}
+                .basic_translate_with_arguments(
+                    8,
+                    &VariableValue::String("11111000".to_string()),
+                    &fractional_bits(4)
+                )
+                .0,
+            "-0.5"
+        );
+        assert_eq!(
+            SignedFixedPointTranslator {}
+                .basic_translate_with_arguments(
+                    16,
+                    &VariableValue::BigUint(BigUint::from(0x8000u32)),
+                    &fractional_bits(12)
+                )
+                .0,
+            "-8"
+        );
+        assert_eq!(
+            SignedFixedPointTranslator {}
+                .basic_translate_with_arguments(
+                    16,
+                    &VariableValue::BigUint(BigUint::from(0x7fffu32)),
+                    &fractional_bits(12)
+                )
+                .0,
+            "7.99976"
+        );
+    }
+
+    #[test]
+    fn fixed_point_translation_of_undefined_values() {
+        assert_eq!(
+            SignedFixedPointTranslator {}.basic_translate_with_arguments(
+                8,
+                &VariableValue::String("1x111000".to_string()),
+                &fractional_bits(4)
+            ),
+            ("UNDEF".to_string(), ValueKind::Undef)
+        );
+        assert_eq!(
+            UnsignedFixedPointTranslator {}
+                .basic_translate(8, &VariableValue::String("zzzzzzzz".to_string())),
+            ("HIGHIMP".to_string(), ValueKind::HighImp)
+        );
+    }
+
     #[test]
     fn unsigned_translation_from_string() {
         assert_eq!(
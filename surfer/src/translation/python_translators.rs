@@ -2,12 +2,22 @@ use color_eyre::eyre::anyhow;
 use color_eyre::Result;
 use log::{error, info};
 use pyo3::types::{PyAnyMethods, PyDict, PyModule, PyStringMethods};
-use pyo3::{Bound, Py, Python};
-use surfer_translation_types::python::{surfer_pyo3_module, PythonValueKind};
-use surfer_translation_types::{BasicTranslator, ValueKind, VariableValue};
+use pyo3::{Bound, Py, PyAny, Python};
+use surfer_translation_types::python::{
+    surfer_pyo3_module, PythonTranslationPreference, PythonTranslationResult, PythonValueKind,
+    PythonVariableInfo, PythonVariableMeta,
+};
+use surfer_translation_types::{
+    translates_all_bit_types, TranslationPreference, TranslationResult, Translator, ValueKind,
+    ValueRepr, VariableInfo, VariableValue,
+};
 
-use crate::wave_container::{ScopeId, VarId};
+use crate::message::Message;
+use crate::wave_container::{ScopeId, VarId, VariableMeta};
 
+/// A translator class in a Python plugin. Classes deriving from `surfer.BasicTranslator`
+/// implement `basic_translate`, classes deriving from `surfer.Translator` implement
+/// `translate` and `variable_info` and can produce subfields.
 pub struct PythonTranslator {
     module: Py<PyModule>,
     class_name: String,
@@ -40,31 +50,24 @@ impl PythonTranslator {
         })
         .map_err(|e| anyhow!("Error initializing Python translator: {e}"))
     }
-}
 
-impl BasicTranslator<VarId, ScopeId> for PythonTranslator {
-    fn name(&self) -> String {
-        let name = Python::with_gil(|py| {
-            self.module
-                .bind(py)
-                .getattr(self.class_name.as_str())
-                .unwrap()
-                .getattr("name")
-                .unwrap()
-                .str()
-                .unwrap()
-                .to_string_lossy()
-                .to_string()
-        });
-        name
+    fn class<'py>(&self, py: Python<'py>) -> pyo3::PyResult<Bound<'py, PyAny>> {
+        self.module.bind(py).getattr(self.class_name.as_str())
+    }
+
+    /// True if the class implements the static method `method`
+    fn implements(&self, method: &str) -> bool {
+        Python::with_gil(|py| {
+            self.class(py)
+                .and_then(|class| class.hasattr(method))
+                .unwrap_or(false)
+        })
     }
 
     fn basic_translate(&self, num_bits: u64, value: &VariableValue) -> (String, ValueKind) {
         let result = Python::with_gil(|py| -> pyo3::PyResult<_> {
             let ret = self
-                .module
-                .bind(py)
-                .getattr(self.class_name.as_str())?
+                .class(py)?
                 .getattr("basic_translate")?
                 .call((num_bits, value.to_string()), None)?;
             let ret = ret.downcast()?;
@@ -91,3 +94,76 @@ impl BasicTranslator<VarId, ScopeId> for PythonTranslator {
         }
     }
 }
+
+impl Translator<VarId, ScopeId, Message> for PythonTranslator {
+    fn name(&self) -> String {
+        let name = Python::with_gil(|py| {
+            self.class(py)
+                .unwrap()
+                .getattr("name")
+                .unwrap()
+                .str()
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        });
+        name
+    }
+
+    fn translate(
+        &self,
+        variable: &VariableMeta,
+        value: &VariableValue,
+    ) -> Result<TranslationResult> {
+        let num_bits = variable.num_bits.unwrap_or(0) as u64;
+        if !self.implements("translate") {
+            let (val, kind) = self.basic_translate(num_bits, value);
+            return Ok(TranslationResult {
+                val: ValueRepr::String(val),
+                kind,
+                subfields: vec![],
+            });
+        }
+
+        let bits = match value {
+            VariableValue::BigUint(v) => format!("{v:0width$b}", width = num_bits as usize),
+            VariableValue::String(v) => v.clone(),
+        };
+        Python::with_gil(|py| -> pyo3::PyResult<_> {
+            self.class(py)?
+                .getattr("translate")?
+                .call1((PythonVariableMeta::from(variable), bits))?
+                .extract::<PythonTranslationResult>()
+        })
+        .map(TranslationResult::from)
+        .map_err(|e| anyhow!("Python translator '{}' failed: {e}", self.name()))
+    }
+
+    fn variable_info(&self, variable: &VariableMeta) -> Result<VariableInfo> {
+        if !self.implements("variable_info") {
+            return Ok(VariableInfo::Bits);
+        }
+        Python::with_gil(|py| -> pyo3::PyResult<_> {
+            self.class(py)?
+                .getattr("variable_info")?
+                .call1((PythonVariableMeta::from(variable),))?
+                .extract::<PythonVariableInfo>()
+        })
+        .map(VariableInfo::from)
+        .map_err(|e| anyhow!("Python translator '{}' failed: {e}", self.name()))
+    }
+
+    fn translates(&self, variable: &VariableMeta) -> Result<TranslationPreference> {
+        if !self.implements("translates") {
+            return translates_all_bit_types(variable);
+        }
+        Python::with_gil(|py| -> pyo3::PyResult<_> {
+            self.class(py)?
+                .getattr("translates")?
+                .call1((PythonVariableMeta::from(variable),))?
+                .extract::<PythonTranslationPreference>()
+        })
+        .map(TranslationPreference::from)
+        .map_err(|e| anyhow!("Python translator '{}' failed: {e}", self.name()))
+    }
+}
@@ -0,0 +1,369 @@
+//! Translators for register layouts described in toml files, for example
+//!
+//! ```toml
+//! name = "mstatus"
+//! width = 32
+//!
+//! [[fields]]
+//! name = "MIE"
+//! bits = 3
+//!
+//! [[fields]]
+//! name = "MPP"
+//! bits = "12:11"
+//! labels = { 0 = "U", 1 = "S", 3 = "M" }
+//!
+//! [[fields]]
+//! name = "count"
+//! bits = "31:16"
+//! translator = "Unsigned"
+//! ```
+//!
+//! Fields without labels or translator are shown with the format selected for the field.
+use std::collections::HashMap;
+use std::path::Path;
+
+use color_eyre::eyre::{anyhow, bail, Context};
+use color_eyre::Result;
+use num::{BigUint, Num};
+use serde::Deserialize;
+use surfer_translation_types::{
+    SubFieldTranslationResult, TranslationResult, Translator, ValueRepr, VariableValue,
+};
+
+use crate::message::Message;
+use crate::wave_container::{ScopeId, VarId, VariableMeta};
+
+use super::{
+    builtin_basic_translators, DynBasicTranslator, TranslationPreference, ValueKind, VariableInfo,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegisterLayoutToml {
+    name: Option<String>,
+    width: u64,
+    fields: Vec<RegisterFieldToml>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegisterFieldToml {
+    name: String,
+    bits: FieldBits,
+    translator: Option<String>,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+/// Either a single bit index or a `"msb:lsb"` range
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FieldBits {
+    Single(u64),
+    Range(String),
+}
+
+impl FieldBits {
+    fn msb_lsb(&self) -> Result<(u64, u64)> {
+        match self {
+            FieldBits::Single(bit) => Ok((*bit, *bit)),
+            FieldBits::Range(range) => {
+                let parse = |s: &str| {
+                    s.trim()
+                        .parse::<u64>()
+                        .with_context(|| format!("Invalid bit range '{range}'"))
+                };
+                match range.split_once(':') {
+                    Some((msb, lsb)) => Ok((parse(msb)?, parse(lsb)?)),
+                    None => parse(range).map(|bit| (bit, bit)),
+                }
+            }
+        }
+    }
+}
+
+/// Parse a label value, written in decimal or with a `0x` or `0b` prefix
+pub(super) fn parse_label_value(value: &str) -> Result<BigUint> {
+    let value = value.trim();
+    let (digits, radix) = if let Some(hex) = value.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(bin) = value.strip_prefix("0b") {
+        (bin, 2)
+    } else {
+        (value, 10)
+    };
+    BigUint::from_str_radix(&digits.replace('_', ""), radix)
+        .with_context(|| format!("Invalid label value '{value}'"))
+}
+
+struct RegisterField {
+    name: String,
+    msb: u64,
+    lsb: u64,
+    labels: HashMap<BigUint, String>,
+    translator: Option<Box<DynBasicTranslator>>,
+}
+
+impl RegisterField {
+    fn width(&self) -> u64 {
+        self.msb - self.lsb + 1
+    }
+
+    fn translate(&self, bits: &str) -> TranslationResult {
+        let label = BigUint::from_str_radix(bits, 2)
+            .ok()
+            .and_then(|v| self.labels.get(&v));
+        let (val, kind) = match (label, &self.translator) {
+            (Some(label), _) => (ValueRepr::String(label.clone()), ValueKind::Normal),
+            (None, Some(translator)) => {
+                let (val, kind) =
+                    translator.basic_translate(self.width(), &VariableValue::String(bits.into()));
+                (ValueRepr::String(val), kind)
+            }
+            (None, None) if self.width() == 1 => (
+                ValueRepr::Bit(bits.chars().next().unwrap_or('0')),
+                ValueKind::Normal,
+            ),
+            (None, None) => (
+                ValueRepr::Bits(self.width(), bits.to_string()),
+                ValueKind::Normal,
+            ),
+        };
+        TranslationResult {
+            val,
+            kind,
+            subfields: vec![],
+        }
+    }
+
+    fn info(&self) -> VariableInfo {
+        if !self.labels.is_empty() || self.translator.is_some() {
+            VariableInfo::String
+        } else if self.width() == 1 {
+            VariableInfo::Bool
+        } else {
+            VariableInfo::Bits
+        }
+    }
+}
+
+pub struct RegisterTranslator {
+    name: String,
+    width: u64,
+    fields: Vec<RegisterField>,
+}
+
+impl RegisterTranslator {
+    /// Load a register layout from a toml file. If the file does not set a name, the
+    /// file name is used.
+    pub fn new(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).context("Cannot read file")?;
+        let default_name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Self::new_from_str(&text, &default_name)
+    }
+
+    pub fn new_from_str(text: &str, default_name: &str) -> Result<Self> {
+        let layout: RegisterLayoutToml = toml::from_str(text).context("Cannot parse toml")?;
+        let name = layout.name.unwrap_or_else(|| default_name.to_string());
+        if layout.width == 0 {
+            bail!("Register {name} has width 0");
+        }
+
+        let fields = layout
+            .fields
+            .into_iter()
+            .map(|field| {
+                let (msb, lsb) = field.bits.msb_lsb()?;
+                if lsb > msb || msb >= layout.width {
+                    bail!(
+                        "Field {} has bits {msb}:{lsb} which is outside the {} bits of {name}",
+                        field.name,
+                        layout.width
+                    );
+                }
+                let translator = field
+                    .translator
+                    .map(|translator_name| {
+                        builtin_basic_translators()
+                            .into_iter()
+                            .find(|t| t.name() == translator_name)
+                            .ok_or_else(|| {
+                                anyhow!(
+                                    "Field {} uses unknown translator {translator_name}",
+                                    field.name
+                                )
+                            })
+                    })
+                    .transpose()?;
+                let labels = field
+                    .labels
+                    .into_iter()
+                    .map(|(value, label)| Ok((parse_label_value(&value)?, label)))
+                    .collect::<Result<_>>()?;
+                Ok(RegisterField {
+                    name: field.name,
+                    msb,
+                    lsb,
+                    labels,
+                    translator,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            name,
+            width: layout.width,
+            fields,
+        })
+    }
+
+    fn translate_bits(&self, val: &str) -> TranslationResult {
+        let subfields = self
+            .fields
+            .iter()
+            .map(|field| {
+                let end = val.len() - field.lsb as usize;
+                let bits = &val[end - field.width() as usize..end];
+                SubFieldTranslationResult::new(&field.name, field.translate(bits))
+            })
+            .collect();
+        TranslationResult {
+            val: ValueRepr::Struct,
+            kind: ValueKind::Normal,
+            subfields,
+        }
+    }
+}
+
+impl Translator<VarId, ScopeId, Message> for RegisterTranslator {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn translate(
+        &self,
+        variable: &VariableMeta,
+        value: &VariableValue,
+    ) -> Result<TranslationResult> {
+        let width = self.width as usize;
+        let val = match value {
+            VariableValue::BigUint(v) => format!("{v:0width$b}"),
+            VariableValue::String(v) => {
+                let extend_with = match v.chars().next() {
+                    Some('x') => 'x',
+                    Some('z') => 'z',
+                    _ => '0',
+                };
+                let extra = width.saturating_sub(v.len());
+                format!("{}{v}", extend_with.to_string().repeat(extra))
+            }
+        };
+        if val.len() != width {
+            bail!(
+                "{} has {} bits but register {} has {width}",
+                variable.var.name,
+                val.len(),
+                self.name
+            );
+        }
+        Ok(self.translate_bits(&val))
+    }
+
+    fn variable_info(&self, _variable: &VariableMeta) -> Result<VariableInfo> {
+        Ok(VariableInfo::Compound {
+            subfields: self
+                .fields
+                .iter()
+                .map(|field| (field.name.clone(), field.info()))
+                .collect(),
+        })
+    }
+
+    fn translates(&self, variable: &VariableMeta) -> Result<TranslationPreference> {
+        if variable.num_bits == Some(self.width as u32) {
+            Ok(TranslationPreference::Yes)
+        } else {
+            Ok(TranslationPreference::No)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::translation::{all_translators, TranslationResultExt};
+    use surfer_translation_types::TranslatorArguments;
+
+    const MSTATUS: &str = r#"
+        width = 16
+
+        [[fields]]
+        name = "MIE"
+        bits = 3
+
+        [[fields]]
+        name = "MPP"
+        bits = "12:11"
+        labels = { 0 = "U", 1 = "S", 0b11 = "M" }
+
+        [[fields]]
+        name = "count"
+        bits = "10:4"
+        translator = "Unsigned"
+    "#;
+
+    fn flat(translator: &RegisterTranslator, val: &str) -> Vec<(String, Option<String>)> {
+        let translators = all_translators();
+        translator
+            .translate_bits(val)
+            .format_flat(&None, &TranslatorArguments::default(), &[], &translators)
+            .into_iter()
+            .map(|r| (r.names.join("."), r.value.map(|v| v.value)))
+            .collect()
+    }
+
+    #[test]
+    fn register_fields_are_decoded() {
+        let translator = RegisterTranslator::new_from_str(MSTATUS, "mstatus").unwrap();
+        assert_eq!(translator.name(), "mstatus");
+        assert_eq!(
+            flat(&translator, "0001100001011000"),
+            vec![
+                (
+                    "".to_string(),
+                    Some("{MIE: 1, MPP: M, count: 5}".to_string())
+                ),
+                ("MIE".to_string(), Some("1".to_string())),
+                ("MPP".to_string(), Some("M".to_string())),
+                ("count".to_string(), Some("5".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn unlabeled_values_fall_back_to_the_field_format() {
+        let translator = RegisterTranslator::new_from_str(MSTATUS, "mstatus").unwrap();
+        assert_eq!(
+            flat(&translator, "0001000000000000")[2],
+            ("MPP".to_string(), Some("2".to_string()))
+        );
+    }
+
+    #[test]
+    fn invalid_layouts_are_rejected() {
+        assert!(RegisterTranslator::new_from_str(
+            "width = 8\n[[fields]]\nname = \"a\"\nbits = \"8:0\"",
+            "r"
+        )
+        .is_err());
+        assert!(RegisterTranslator::new_from_str(
+            "width = 8\n[[fields]]\nname = \"a\"\nbits = 0\ntranslator = \"Nope\"",
+            "r"
+        )
+        .is_err());
+    }
+}
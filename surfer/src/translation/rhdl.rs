@@ -0,0 +1,655 @@
+use std::{collections::HashMap, sync::mpsc::Sender};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::{
+    eyre::{anyhow, bail, Context},
+    Result,
+};
+use ecolor::Color32;
+use log::info;
+use serde::Deserialize;
+use surfer_translation_types::{
+    SubFieldTranslationResult, TranslationResult, Translator, ValueRepr,
+};
+
+use crate::wave_container::{ScopeId, VarId, VariableRefExt};
+use crate::{message::Message, wasm_util::perform_work, wave_container::VariableMeta, WaveSource};
+
+use super::{TranslationPreference, ValueKind, VariableInfo, VariableValue};
+
+/// Type of a value in an RHDL design. Mirrors `rhdl::Kind`.
+///
+/// Compound values are laid out starting from the least significant bit, i.e. the
+/// first field of a struct occupies the lowest bits of the variable.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub enum RhdlKind {
+    Empty,
+    Bits(usize),
+    Signed(usize),
+    Array {
+        base: Box<RhdlKind>,
+        size: usize,
+    },
+    Tuple {
+        elements: Vec<RhdlKind>,
+    },
+    Struct {
+        name: String,
+        fields: Vec<RhdlField>,
+    },
+    Enum {
+        name: String,
+        variants: Vec<RhdlVariant>,
+        discriminant_layout: RhdlDiscriminantLayout,
+    },
+    /// A value tagged with a clock domain. Translated as the inner value
+    Signal(Box<RhdlKind>),
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct RhdlField {
+    pub name: String,
+    pub kind: RhdlKind,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct RhdlVariant {
+    pub name: String,
+    pub discriminant: i64,
+    /// Payload of the variant, `Empty` for unit variants
+    pub kind: RhdlKind,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct RhdlDiscriminantLayout {
+    pub width: usize,
+    #[serde(default)]
+    pub alignment: RhdlDiscriminantAlignment,
+    #[serde(default)]
+    pub ty: RhdlDiscriminantType,
+}
+
+/// Where the discriminant of an enum is placed. The payload occupies the bits directly
+/// above an `Lsb` discriminant, or the lowest bits if the discriminant is `Msb`
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum RhdlDiscriminantAlignment {
+    Msb,
+    #[default]
+    Lsb,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum RhdlDiscriminantType {
+    #[default]
+    Unsigned,
+    Signed,
+}
+
+impl RhdlKind {
+    pub fn bits(&self) -> usize {
+        match self {
+            RhdlKind::Empty => 0,
+            RhdlKind::Bits(n) | RhdlKind::Signed(n) => *n,
+            RhdlKind::Array { base, size } => base.bits() * size,
+            RhdlKind::Tuple { elements } => elements.iter().map(RhdlKind::bits).sum(),
+            RhdlKind::Struct { fields, .. } => fields.iter().map(|f| f.kind.bits()).sum(),
+            RhdlKind::Enum {
+                variants,
+                discriminant_layout,
+                ..
+            } => {
+                discriminant_layout.width
+                    + variants.iter().map(|v| v.kind.bits()).max().unwrap_or(0)
+            }
+            RhdlKind::Signal(inner) => inner.bits(),
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        match self {
+            RhdlKind::Empty | RhdlKind::Bits(_) | RhdlKind::Signed(_) => true,
+            RhdlKind::Signal(inner) => inner.is_scalar(),
+            _ => false,
+        }
+    }
+}
+
+/// Contents of an RHDL type description file, written in RON.
+///
+/// ```ron
+/// (
+///     top: "top",
+///     signals: {
+///         "uut.state": Enum(
+///             name: "State",
+///             variants: [
+///                 (name: "Idle", discriminant: 0, kind: Empty),
+///                 (name: "Count", discriminant: 1, kind: Tuple(elements: [Bits(8)])),
+///             ],
+///             discriminant_layout: (width: 1),
+///         ),
+///     },
+/// )
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+pub struct RhdlTypes {
+    /// Name of the top module. Paths in `signals` are relative to this scope
+    pub top: String,
+    /// Types of variables, keyed by their `.`-separated path below `top`
+    pub signals: HashMap<String, RhdlKind>,
+}
+
+pub struct RhdlTranslator {
+    types: RhdlTypes,
+    types_file: Option<Utf8PathBuf>,
+}
+
+impl RhdlTranslator {
+    pub fn new(types_file: &Utf8Path) -> Result<Self> {
+        let file_content = std::fs::read_to_string(types_file)
+            .with_context(|| format!("Failed to read {types_file}"))?;
+
+        Self::new_from_string(&file_content, Some(types_file.to_path_buf()))
+            .with_context(|| format!("When loading RHDL types from {types_file}"))
+    }
+
+    pub fn new_from_string(content: &str, types_file: Option<Utf8PathBuf>) -> Result<Self> {
+        let types = ron::from_str::<RhdlTypes>(content).context("Failed to decode RHDL types")?;
+        Ok(Self { types, types_file })
+    }
+
+    /// Loads the type description given by `--rhdl-types`. If that is not specified,
+    /// look for `<wave file stem>.rhdl.ron` next to the loaded wave file.
+    pub fn load(
+        waves: &Option<WaveSource>,
+        types_file_cli: &Option<Utf8PathBuf>,
+        sender: Sender<Message>,
+    ) {
+        let types_file = match (waves, types_file_cli) {
+            (_, Some(types_file)) => Some(types_file.clone()),
+            (Some(WaveSource::File(file)), None) => {
+                let sidecar = file.with_extension("rhdl.ron");
+                if sidecar.exists() {
+                    Some(sidecar)
+                } else {
+                    info!("Did not find {sidecar} nor --rhdl-types. RHDL translator will not run");
+                    None
+                }
+            }
+            _ => None,
+        };
+        let Some(types_file) = types_file else {
+            return;
+        };
+        perform_work(move || {
+            let t = RhdlTranslator::new(&types_file);
+            match t {
+                Ok(result) => sender
+                    .send(Message::TranslatorLoaded(Box::new(result)))
+                    .unwrap(),
+                Err(e) => sender.send(Message::Error(e)).unwrap(),
+            }
+        });
+    }
+
+    fn kind_of(&self, variable: &VariableMeta) -> Option<&RhdlKind> {
+        let path = variable.var.full_path();
+        let relative = match path.split_first() {
+            Some((top, rest)) if top == &self.types.top => rest.join("."),
+            _ => path.join("."),
+        };
+        self.types.signals.get(&relative)
+    }
+}
+
+impl Translator<VarId, ScopeId, Message> for RhdlTranslator {
+    fn name(&self) -> String {
+        "RHDL".to_string()
+    }
+
+    fn translate(
+        &self,
+        variable: &VariableMeta,
+        value: &VariableValue,
+    ) -> Result<TranslationResult> {
+        let kind = self
+            .kind_of(variable)
+            .ok_or_else(|| anyhow!("No RHDL type for {}", variable.var.full_path_string()))?;
+        let width = kind.bits();
+
+        let val = match value {
+            VariableValue::BigUint(v) => format!("{v:0width$b}"),
+            VariableValue::String(v) => {
+                let extend_with = match v.chars().next() {
+                    Some('x') => 'x',
+                    Some('z') => 'z',
+                    Some('0' | '1') | None => '0',
+                    Some(other) => bail!("Found non-bit value in vcd ({other:?})"),
+                };
+                let extra = width.saturating_sub(v.len());
+                format!("{}{v}", extend_with.to_string().repeat(extra))
+            }
+        };
+        if val.len() != width {
+            bail!(
+                "{} has {} bits but its RHDL type has {width}",
+                variable.var.full_path_string(),
+                val.len()
+            );
+        }
+
+        translate_kind(&val, kind, &mut false)
+    }
+
+    fn variable_info(&self, variable: &VariableMeta) -> Result<VariableInfo> {
+        let kind = self
+            .kind_of(variable)
+            .ok_or_else(|| anyhow!("No RHDL type for {}", variable.var.full_path_string()))?;
+        Ok(info_from_kind(kind))
+    }
+
+    fn translates(&self, variable: &VariableMeta) -> Result<TranslationPreference> {
+        match self.kind_of(variable) {
+            Some(kind) if variable.num_bits.is_some_and(|n| n as usize != kind.bits()) => {
+                Ok(TranslationPreference::No)
+            }
+            Some(kind) if kind.is_scalar() => Ok(TranslationPreference::No),
+            Some(_) => Ok(TranslationPreference::Prefer),
+            None => Ok(TranslationPreference::No),
+        }
+    }
+
+    fn reload(&self, sender: Sender<Message>) {
+        Self::load(&None, &self.types_file, sender);
+    }
+}
+
+/// The bits of `val` (written MSB first) starting at bit `lsb`
+fn bit_range(val: &str, lsb: usize, width: usize) -> &str {
+    let end = val.len() - lsb;
+    &val[end - width..end]
+}
+
+fn not_present_value(kind: &RhdlKind) -> TranslationResult {
+    let subfields = match kind {
+        RhdlKind::Empty | RhdlKind::Bits(_) | RhdlKind::Signed(_) => vec![],
+        RhdlKind::Array { base, size } => (0..*size)
+            .map(|i| SubFieldTranslationResult::new(i, not_present_value(base)))
+            .collect(),
+        RhdlKind::Tuple { elements } => elements
+            .iter()
+            .enumerate()
+            .map(|(i, k)| SubFieldTranslationResult::new(i, not_present_value(k)))
+            .collect(),
+        RhdlKind::Struct { fields, .. } => fields
+            .iter()
+            .map(|f| SubFieldTranslationResult::new(&f.name, not_present_value(&f.kind)))
+            .collect(),
+        RhdlKind::Enum { variants, .. } => variants
+            .iter()
+            .map(|v| SubFieldTranslationResult::new(&v.name, not_present_value(&v.kind)))
+            .collect(),
+        RhdlKind::Signal(inner) => not_present_value(inner).subfields,
+    };
+
+    TranslationResult {
+        val: ValueRepr::NotPresent,
+        subfields,
+        kind: ValueKind::Normal,
+    }
+}
+
+fn translate_fields<'a>(
+    val: &str,
+    kinds: impl Iterator<Item = (String, &'a RhdlKind)>,
+    problematic: &mut bool,
+) -> Result<Vec<SubFieldTranslationResult>> {
+    let mut offset = 0;
+    kinds
+        .map(|(name, kind)| {
+            let width = kind.bits();
+            let mut local_problematic = false;
+            let result =
+                translate_kind(bit_range(val, offset, width), kind, &mut local_problematic)?;
+            *problematic |= local_problematic;
+            offset += width;
+            Ok(SubFieldTranslationResult::new(name, result))
+        })
+        .collect()
+}
+
+fn translate_kind(val: &str, kind: &RhdlKind, problematic: &mut bool) -> Result<TranslationResult> {
+    let (val, subfields, value_kind) = match kind {
+        RhdlKind::Empty => (ValueRepr::String(String::new()), vec![], ValueKind::Normal),
+        RhdlKind::Bits(1) => (
+            ValueRepr::Bit(val.chars().next().unwrap_or('0')),
+            vec![],
+            ValueKind::Normal,
+        ),
+        RhdlKind::Bits(n) | RhdlKind::Signed(n) => (
+            ValueRepr::Bits(*n as u64, val.to_string()),
+            vec![],
+            ValueKind::Normal,
+        ),
+        RhdlKind::Array { base, size } => {
+            let subfields = translate_fields(
+                val,
+                (0..*size).map(|i| (i.to_string(), base.as_ref())),
+                problematic,
+            )?;
+            (ValueRepr::Array, subfields, ValueKind::Normal)
+        }
+        RhdlKind::Tuple { elements } => {
+            let subfields = translate_fields(
+                val,
+                elements.iter().enumerate().map(|(i, k)| (i.to_string(), k)),
+                problematic,
+            )?;
+            (ValueRepr::Tuple, subfields, ValueKind::Normal)
+        }
+        RhdlKind::Struct { fields, .. } => {
+            let subfields = translate_fields(
+                val,
+                fields.iter().map(|f| (f.name.clone(), &f.kind)),
+                problematic,
+            )?;
+            (ValueRepr::Struct, subfields, ValueKind::Normal)
+        }
+        RhdlKind::Enum {
+            variants,
+            discriminant_layout,
+            ..
+        } => return translate_enum(val, variants, discriminant_layout, problematic),
+        RhdlKind::Signal(inner) => return translate_kind(val, inner, problematic),
+    };
+
+    Ok(TranslationResult {
+        val,
+        subfields,
+        kind: if *problematic {
+            ValueKind::Warn
+        } else {
+            value_kind
+        },
+    })
+}
+
+fn translate_enum(
+    val: &str,
+    variants: &[RhdlVariant],
+    layout: &RhdlDiscriminantLayout,
+    problematic: &mut bool,
+) -> Result<TranslationResult> {
+    let payload_width = val.len() - layout.width;
+    let (tag_section, payload_offset) = match layout.alignment {
+        RhdlDiscriminantAlignment::Lsb => (bit_range(val, 0, layout.width), layout.width),
+        RhdlDiscriminantAlignment::Msb => (bit_range(val, payload_width, layout.width), 0),
+    };
+    let not_present = |val: String, kind: ValueKind| TranslationResult {
+        val: ValueRepr::String(val),
+        subfields: variants
+            .iter()
+            .map(|v| SubFieldTranslationResult::new(&v.name, not_present_value(&v.kind)))
+            .collect(),
+        kind,
+    };
+
+    if tag_section.contains('x') {
+        *problematic = true;
+        return Ok(not_present(
+            format!("xTAG(0b{tag_section})"),
+            ValueKind::Undef,
+        ));
+    }
+    if tag_section.contains('z') {
+        *problematic = true;
+        return Ok(not_present(
+            format!("zTAG(0b{tag_section})"),
+            ValueKind::HighImp,
+        ));
+    }
+
+    let unsigned = u64::from_str_radix(tag_section, 2)
+        .with_context(|| format!("Unexpected characters in enum tag {tag_section}"))?;
+    let discriminant = match layout.ty {
+        RhdlDiscriminantType::Unsigned => unsigned as i64,
+        RhdlDiscriminantType::Signed if layout.width > 0 && layout.width < 64 => {
+            let shift = 64 - layout.width;
+            ((unsigned << shift) as i64) >> shift
+        }
+        RhdlDiscriminantType::Signed => unsigned as i64,
+    };
+
+    let Some(idx) = variants.iter().position(|v| v.discriminant == discriminant) else {
+        *problematic = true;
+        return Ok(not_present(
+            format!("?TAG(0b{tag_section})"),
+            ValueKind::Undef,
+        ));
+    };
+
+    let subfields = variants
+        .iter()
+        .enumerate()
+        .map(|(i, variant)| {
+            let result = if i == idx {
+                let mut local_problematic = false;
+                let result = translate_kind(
+                    bit_range(val, payload_offset, variant.kind.bits()),
+                    &variant.kind,
+                    &mut local_problematic,
+                )?;
+                *problematic |= local_problematic;
+                result
+            } else {
+                not_present_value(&variant.kind)
+            };
+            Ok(SubFieldTranslationResult::new(&variant.name, result))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(TranslationResult {
+        val: ValueRepr::Enum {
+            idx,
+            name: variants[idx].name.clone(),
+        },
+        subfields,
+        kind: if *problematic {
+            ValueKind::Warn
+        } else if variants[idx].name == "None" {
+            ValueKind::Custom(Color32::DARK_GRAY)
+        } else {
+            ValueKind::Normal
+        },
+    })
+}
+
+fn info_from_kind(kind: &RhdlKind) -> VariableInfo {
+    match kind {
+        RhdlKind::Empty => VariableInfo::Compound { subfields: vec![] },
+        RhdlKind::Bits(1) => VariableInfo::Bool,
+        RhdlKind::Bits(_) | RhdlKind::Signed(_) => VariableInfo::Bits,
+        RhdlKind::Array { base, size } => VariableInfo::Compound {
+            subfields: (0..*size)
+                .map(|i| (i.to_string(), info_from_kind(base)))
+                .collect(),
+        },
+        RhdlKind::Tuple { elements } => VariableInfo::Compound {
+            subfields: elements
+                .iter()
+                .enumerate()
+                .map(|(i, k)| (i.to_string(), info_from_kind(k)))
+                .collect(),
+        },
+        RhdlKind::Struct { fields, .. } => VariableInfo::Compound {
+            subfields: fields
+                .iter()
+                .map(|f| (f.name.clone(), info_from_kind(&f.kind)))
+                .collect(),
+        },
+        RhdlKind::Enum { variants, .. } => VariableInfo::Compound {
+            subfields: variants
+                .iter()
+                .map(|v| (v.name.clone(), info_from_kind(&v.kind)))
+                .collect(),
+        },
+        RhdlKind::Signal(inner) => info_from_kind(inner),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::translation::{all_translators, TranslationResultExt};
+    use surfer_translation_types::TranslatorArguments;
+
+    fn flat(val: &str, kind: &RhdlKind) -> Vec<(String, Option<String>)> {
+        let translators = all_translators();
+        translate_kind(val, kind, &mut false)
+            .unwrap()
+            .format_flat(&None, &TranslatorArguments::default(), &[], &translators)
+            .into_iter()
+            .map(|r| (r.names.join("."), r.value.map(|v| v.value)))
+            .collect()
+    }
+
+    fn state_kind(alignment: RhdlDiscriminantAlignment) -> RhdlKind {
+        RhdlKind::Enum {
+            name: "State".to_string(),
+            variants: vec![
+                RhdlVariant {
+                    name: "Idle".to_string(),
+                    discriminant: 0,
+                    kind: RhdlKind::Empty,
+                },
+                RhdlVariant {
+                    name: "Count".to_string(),
+                    discriminant: 1,
+                    kind: RhdlKind::Tuple {
+                        elements: vec![RhdlKind::Bits(4)],
+                    },
+                },
+            ],
+            discriminant_layout: RhdlDiscriminantLayout {
+                width: 2,
+                alignment,
+                ty: RhdlDiscriminantType::Unsigned,
+            },
+        }
+    }
+
+    #[test]
+    fn struct_fields_start_at_lsb() {
+        let kind = RhdlKind::Struct {
+            name: "Foo".to_string(),
+            fields: vec![
+                RhdlField {
+                    name: "a".to_string(),
+                    kind: RhdlKind::Bits(4),
+                },
+                RhdlField {
+                    name: "b".to_string(),
+                    kind: RhdlKind::Bits(1),
+                },
+            ],
+        };
+        assert_eq!(kind.bits(), 5);
+        assert_eq!(
+            flat("11010", &kind),
+            vec![
+                ("".to_string(), Some("{a: a, b: 1}".to_string())),
+                ("a".to_string(), Some("a".to_string())),
+                ("b".to_string(), Some("1".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_and_tuples_are_split_into_elements() {
+        let kind = RhdlKind::Tuple {
+            elements: vec![
+                RhdlKind::Array {
+                    base: Box::new(RhdlKind::Bits(4)),
+                    size: 2,
+                },
+                RhdlKind::Signed(4),
+            ],
+        };
+        assert_eq!(
+            flat("1111_0010_0001".replace('_', "").as_str(), &kind),
+            vec![
+                ("".to_string(), Some("([1, 2], f)".to_string())),
+                ("0".to_string(), Some("[1, 2]".to_string())),
+                ("0.0".to_string(), Some("1".to_string())),
+                ("0.1".to_string(), Some("2".to_string())),
+                ("1".to_string(), Some("f".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn enum_payload_follows_lsb_discriminant() {
+        let kind = state_kind(RhdlDiscriminantAlignment::Lsb);
+        assert_eq!(kind.bits(), 6);
+        assert_eq!(
+            flat("101001", &kind),
+            vec![
+                ("".to_string(), Some("Count{(a)}".to_string())),
+                ("Idle".to_string(), None),
+                ("Count".to_string(), Some("(a)".to_string())),
+                ("Count.0".to_string(), Some("a".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn enum_with_msb_discriminant_has_payload_at_lsb() {
+        let kind = state_kind(RhdlDiscriminantAlignment::Msb);
+        assert_eq!(
+            flat("010111", &kind),
+            vec![
+                ("".to_string(), Some("Count{(7)}".to_string())),
+                ("Idle".to_string(), None),
+                ("Count".to_string(), Some("(7)".to_string())),
+                ("Count.0".to_string(), Some("7".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn undefined_enum_tag_is_reported() {
+        let result = translate_kind(
+            "0000x1",
+            &state_kind(RhdlDiscriminantAlignment::Lsb),
+            &mut false,
+        )
+        .unwrap();
+        assert!(matches!(result.kind, ValueKind::Undef));
+        assert!(matches!(result.val, ValueRepr::String(s) if s == "xTAG(0bx1)"));
+    }
+
+    #[test]
+    fn type_file_is_parsed() {
+        let translator = RhdlTranslator::new_from_string(
+            r#"(
+                top: "top",
+                signals: {
+                    "uut.state": Enum(
+                        name: "State",
+                        variants: [
+                            (name: "Idle", discriminant: 0, kind: Empty),
+                            (name: "Count", discriminant: 1, kind: Tuple(elements: [Bits(4)])),
+                        ],
+                        discriminant_layout: (width: 2),
+                    ),
+                },
+            )"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            translator.types.signals["uut.state"],
+            state_kind(RhdlDiscriminantAlignment::Lsb)
+        );
+    }
+}
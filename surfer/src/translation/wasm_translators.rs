@@ -0,0 +1,318 @@
+//! Translators implemented as WebAssembly modules, run in an embedded interpreter so that
+//! the same plugin works in the native and in the web build. Plugins get no imports and
+//! a limited amount of fuel per call, so they can neither access the system nor hang Surfer.
+//!
+//! A plugin exports its `memory` and the following functions. Strings and JSON documents are
+//! passed as a pointer and length into the plugin memory, results are returned packed into an
+//! `i64` as `(ptr << 32) | len`.
+//!
+//! - `surfer_alloc(len: i32) -> i32` allocates a buffer which Surfer writes the input to
+//! - `surfer_free(ptr: i32, len: i32)` frees input and result buffers once Surfer is done
+//! - `name() -> i64` returns the name of the translator as UTF-8
+//! - `translates(ptr: i32, len: i32) -> i64` gets a [`VariableMeta`] and returns a
+//!   [`TranslationPreference`]
+//! - `variable_info(ptr: i32, len: i32) -> i64` gets a [`VariableMeta`] and returns a
+//!   [`VariableInfo`]
+//! - `translate(ptr: i32, len: i32) -> i64` gets
+//!   `{"variable": VariableMeta, "value": VariableValue}` and returns a [`TranslationResult`].
+//!   The value is always a bit string.
+//!
+//! Arguments and results are the JSON encoding of the `surfer-translation-types` data model.
+//! Results are wrapped as `{"Ok": result}` or `{"Err": "message"}`.
+use std::sync::Mutex;
+
+use color_eyre::eyre::{anyhow, bail, Context};
+use color_eyre::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use surfer_translation_types::{TranslationResult, Translator, VariableValue};
+use wasmi::{Config, Engine, Linker, Memory, Module, Store, TypedFunc};
+
+use crate::message::Message;
+use crate::wave_container::{ScopeId, VarId, VariableMeta};
+
+use super::{TranslationPreference, VariableInfo};
+
+/// Upper bound on the instructions a plugin may execute per call
+const FUEL_PER_CALL: u64 = 50_000_000;
+
+/// The exported functions taking JSON input
+#[derive(Clone, Copy)]
+enum Export {
+    Translates,
+    VariableInfo,
+    Translate,
+}
+
+struct Plugin {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    free: TypedFunc<(i32, i32), ()>,
+    name: TypedFunc<(), i64>,
+    translates: TypedFunc<(i32, i32), i64>,
+    variable_info: TypedFunc<(i32, i32), i64>,
+    translate: TypedFunc<(i32, i32), i64>,
+}
+
+impl Plugin {
+    fn new(wasm: &[u8]) -> Result<Self> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(|e| anyhow!("Invalid module: {e}"))?;
+        let mut store = Store::new(&engine, ());
+        // No host functions are linked, so the plugin can only compute on its own memory
+        let linker = Linker::<()>::new(&engine);
+        store.set_fuel(FUEL_PER_CALL).map_err(|e| anyhow!("{e}"))?;
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|e| anyhow!("Failed to instantiate plugin: {e}"))?;
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| anyhow!("Plugin does not export 'memory'"))?;
+        macro_rules! func {
+            ($name:literal) => {
+                instance
+                    .get_typed_func(&store, $name)
+                    .map_err(|e| anyhow!("Plugin does not export '{}': {e}", $name))?
+            };
+        }
+        Ok(Self {
+            memory,
+            alloc: func!("surfer_alloc"),
+            free: func!("surfer_free"),
+            name: func!("name"),
+            translates: func!("translates"),
+            variable_info: func!("variable_info"),
+            translate: func!("translate"),
+            store,
+        })
+    }
+
+    /// Read and free a result returned as `(ptr << 32) | len`
+    fn take_result(&mut self, packed: i64) -> Result<Vec<u8>> {
+        let ptr = (packed >> 32) as u32;
+        let len = packed as u32;
+        // the length comes from the plugin, so it has to be checked before allocating
+        let in_bounds = (ptr as usize)
+            .checked_add(len as usize)
+            .is_some_and(|end| end <= self.memory.data(&self.store).len());
+        if !in_bounds {
+            bail!("Plugin returned a buffer outside of its memory");
+        }
+        let mut result = vec![0; len as usize];
+        self.memory
+            .read(&self.store, ptr as usize, &mut result)
+            .map_err(|e| anyhow!("Plugin returned an invalid buffer: {e}"))?;
+        self.free
+            .call(&mut self.store, (ptr as i32, len as i32))
+            .map_err(|e| anyhow!("{e}"))?;
+        Ok(result)
+    }
+
+    fn call(&mut self, export: Export, input: &[u8]) -> Result<Vec<u8>> {
+        self.store
+            .set_fuel(FUEL_PER_CALL)
+            .map_err(|e| anyhow!("{e}"))?;
+        let len = i32::try_from(input.len()).context("Plugin input is too large")?;
+        let ptr = self
+            .alloc
+            .call(&mut self.store, len)
+            .map_err(|e| anyhow!("{e}"))?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, input)
+            .map_err(|e| anyhow!("Plugin allocated an invalid buffer: {e}"))?;
+        let func = match export {
+            Export::Translates => &self.translates,
+            Export::VariableInfo => &self.variable_info,
+            Export::Translate => &self.translate,
+        };
+        let packed = func
+            .call(&mut self.store, (ptr, len))
+            .map_err(|e| anyhow!("{e}"))?;
+        self.free
+            .call(&mut self.store, (ptr, len))
+            .map_err(|e| anyhow!("{e}"))?;
+        self.take_result(packed)
+    }
+
+    fn call_json<I: Serialize, O: DeserializeOwned>(
+        &mut self,
+        export: Export,
+        input: &I,
+    ) -> Result<O> {
+        let output = self.call(export, &serde_json::to_vec(input)?)?;
+        serde_json::from_slice::<std::result::Result<O, String>>(&output)
+            .context("Plugin returned invalid JSON")?
+            .map_err(|e| anyhow!(e))
+    }
+}
+
+#[derive(Serialize)]
+struct TranslateInput<'a> {
+    variable: &'a VariableMeta,
+    value: &'a VariableValue,
+}
+
+pub struct WasmTranslator {
+    name: String,
+    plugin: Mutex<Plugin>,
+}
+
+impl WasmTranslator {
+    pub fn new(wasm: &[u8]) -> Result<Self> {
+        let mut plugin = Plugin::new(wasm)?;
+        let packed = plugin
+            .name
+            .call(&mut plugin.store, ())
+            .map_err(|e| anyhow!("{e}"))?;
+        let name = String::from_utf8(plugin.take_result(packed)?)
+            .context("Plugin name is not valid UTF-8")?;
+        Ok(Self {
+            name,
+            plugin: Mutex::new(plugin),
+        })
+    }
+
+    fn call<I: Serialize, O: DeserializeOwned>(&self, export: Export, input: &I) -> Result<O> {
+        self.plugin
+            .lock()
+            .unwrap()
+            .call_json(export, input)
+            .with_context(|| format!("Error in translator plugin {}", self.name))
+    }
+}
+
+impl Translator<VarId, ScopeId, Message> for WasmTranslator {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn translate(
+        &self,
+        variable: &VariableMeta,
+        value: &VariableValue,
+    ) -> Result<TranslationResult> {
+        let value = match value {
+            VariableValue::BigUint(v) => VariableValue::String(format!(
+                "{v:0width$b}",
+                width = variable.num_bits.unwrap_or(0) as usize
+            )),
+            VariableValue::String(_) => value.clone(),
+        };
+        self.call(
+            Export::Translate,
+            &TranslateInput {
+                variable,
+                value: &value,
+            },
+        )
+    }
+
+    fn variable_info(&self, variable: &VariableMeta) -> Result<VariableInfo> {
+        self.call(Export::VariableInfo, variable)
+    }
+
+    fn translates(&self, variable: &VariableMeta) -> Result<TranslationPreference> {
+        self.call(Export::Translates, variable)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use surfer_translation_types::{ValueRepr, VariableEncoding};
+
+    use super::*;
+    use crate::wave_container::{VariableRef, VariableRefExt};
+
+    /// A plugin returning constant results from its data segments, with a bump allocator
+    /// for the input. `variable_info` returns a buffer far outside of its memory.
+    ///
+    /// ```wat
+    /// (module
+    ///   (memory (export "memory") 1)
+    ///   (global $heap (mut i32) (i32.const 1024))
+    ///   (data (i32.const 0) "Test plugin")
+    ///   (data (i32.const 16) "{\"Ok\":\"Prefer\"}")
+    ///   (data (i32.const 64) "{\"Ok\":{\"val\":{\"String\":\"ok\"},\"subfields\":[],\"kind\":\"Normal\"}}")
+    ///   (func (export "surfer_alloc") (param $len i32) (result i32)
+    ///     (global.get $heap)
+    ///     (global.set $heap (i32.add (global.get $heap) (local.get $len))))
+    ///   (func (export "surfer_free") (param i32 i32))
+    ///   (func (export "name") (result i64) (i64.const 11))
+    ///   (func (export "translates") (param i32 i32) (result i64) (i64.const 0x10_0000_000f))
+    ///   (func (export "variable_info") (param i32 i32) (result i64) (i64.const -1))
+    ///   (func (export "translate") (param i32 i32) (result i64) (i64.const 0x40_0000_003d)))
+    /// ```
+    #[rustfmt::skip]
+    const TEST_PLUGIN: &[u8] = &[
+        // magic number and version
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        // type section
+        0x01, 0x15, 0x04, 0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60, 0x02, 0x7f, 0x7f, 0x00, 0x60, 0x00, 0x01,
+        0x7e, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7e,
+        // function section
+        0x03, 0x07, 0x06, 0x00, 0x01, 0x02, 0x03, 0x03, 0x03,
+        // memory section
+        0x05, 0x03, 0x01, 0x00, 0x01,
+        // global section
+        0x06, 0x07, 0x01, 0x7f, 0x01, 0x41, 0x80, 0x08, 0x0b,
+        // export section
+        0x07, 0x57, 0x07, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, 0x0c, 0x73, 0x75, 0x72,
+        0x66, 0x65, 0x72, 0x5f, 0x61, 0x6c, 0x6c, 0x6f, 0x63, 0x00, 0x00, 0x0b, 0x73, 0x75, 0x72, 0x66,
+        0x65, 0x72, 0x5f, 0x66, 0x72, 0x65, 0x65, 0x00, 0x01, 0x04, 0x6e, 0x61, 0x6d, 0x65, 0x00, 0x02,
+        0x0a, 0x74, 0x72, 0x61, 0x6e, 0x73, 0x6c, 0x61, 0x74, 0x65, 0x73, 0x00, 0x03, 0x0d, 0x76, 0x61,
+        0x72, 0x69, 0x61, 0x62, 0x6c, 0x65, 0x5f, 0x69, 0x6e, 0x66, 0x6f, 0x00, 0x04, 0x09, 0x74, 0x72,
+        0x61, 0x6e, 0x73, 0x6c, 0x61, 0x74, 0x65, 0x00, 0x05,
+        // code section
+        0x0a, 0x2e, 0x06, 0x0b, 0x00, 0x23, 0x00, 0x23, 0x00, 0x20, 0x00, 0x6a, 0x24, 0x00, 0x0b, 0x02,
+        0x00, 0x0b, 0x04, 0x00, 0x42, 0x0b, 0x0b, 0x09, 0x00, 0x42, 0x8f, 0x80, 0x80, 0x80, 0x80, 0x02,
+        0x0b, 0x04, 0x00, 0x42, 0x7f, 0x0b, 0x09, 0x00, 0x42, 0xbd, 0x80, 0x80, 0x80, 0x80, 0x08, 0x0b,
+        // data section
+        0x0b, 0x68, 0x03, 0x00, 0x41, 0x00, 0x0b, 0x0b, 0x54, 0x65, 0x73, 0x74, 0x20, 0x70, 0x6c, 0x75,
+        0x67, 0x69, 0x6e, 0x00, 0x41, 0x10, 0x0b, 0x0f, 0x7b, 0x22, 0x4f, 0x6b, 0x22, 0x3a, 0x22, 0x50,
+        0x72, 0x65, 0x66, 0x65, 0x72, 0x22, 0x7d, 0x00, 0x41, 0xc0, 0x00, 0x0b, 0x3d, 0x7b, 0x22, 0x4f,
+        0x6b, 0x22, 0x3a, 0x7b, 0x22, 0x76, 0x61, 0x6c, 0x22, 0x3a, 0x7b, 0x22, 0x53, 0x74, 0x72, 0x69,
+        0x6e, 0x67, 0x22, 0x3a, 0x22, 0x6f, 0x6b, 0x22, 0x7d, 0x2c, 0x22, 0x73, 0x75, 0x62, 0x66, 0x69,
+        0x65, 0x6c, 0x64, 0x73, 0x22, 0x3a, 0x5b, 0x5d, 0x2c, 0x22, 0x6b, 0x69, 0x6e, 0x64, 0x22, 0x3a,
+        0x22, 0x4e, 0x6f, 0x72, 0x6d, 0x61, 0x6c, 0x22, 0x7d, 0x7d,
+    ];
+
+    fn meta() -> VariableMeta {
+        VariableMeta {
+            var: VariableRef::from_hierarchy_string("tb.x"),
+            num_bits: Some(8),
+            variable_type: None,
+            index: None,
+            direction: None,
+            enum_map: HashMap::new(),
+            encoding: VariableEncoding::BitVector,
+        }
+    }
+
+    #[test]
+    fn plugin_results_are_read_from_its_memory() {
+        let translator = WasmTranslator::new(TEST_PLUGIN).unwrap();
+        assert_eq!(translator.name(), "Test plugin");
+        assert!(translator.translates(&meta()).unwrap() == TranslationPreference::Prefer);
+        let result = translator
+            .translate(&meta(), &VariableValue::BigUint(5u32.into()))
+            .unwrap();
+        assert!(matches!(result.val, ValueRepr::String(s) if s == "ok"));
+        assert!(result.subfields.is_empty());
+    }
+
+    #[test]
+    fn out_of_bounds_results_are_rejected() {
+        let translator = WasmTranslator::new(TEST_PLUGIN).unwrap();
+        assert!(translator.variable_info(&meta()).is_err());
+        // the plugin is still usable afterwards
+        assert_eq!(translator.name(), "Test plugin");
+        assert!(translator.translates(&meta()).is_ok());
+    }
+}
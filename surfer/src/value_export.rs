@@ -0,0 +1,546 @@
+//! Exporting the values of the displayed variables and derived signals to CSV or JSON. Each
+//! displayed field, including expanded subfields, becomes a column holding the value as
+//! formatted by the translator of the item. There is either one row per change of any of
+//! the values, or one row per rising edge of a clock.
+//!
+//! Times are written as timestamps in the timescale of the file. Exports are limited to
+//! [`MAX_EXPORTED_SAMPLES`] rows, as the values are looked up on the UI thread.
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use color_eyre::eyre::{anyhow, Context};
+use color_eyre::Result;
+use log::{error, info, warn};
+use num::{BigUint, ToPrimitive};
+use rfd::AsyncFileDialog;
+use serde_json::{Map, Value};
+
+use crate::derived::Signal;
+use crate::displayed_item::DisplayedFieldRef;
+use crate::message::Message;
+use crate::view::ItemDrawingInfo;
+use crate::wasm_util::perform_async_work;
+use crate::wave_container::{VariableRef, VariableRefExt};
+use crate::wave_data::WaveData;
+use crate::State;
+
+/// Most rows of a value export or steps of a WaveDrom export. Later times are left out, and
+/// can be exported by narrowing the range.
+pub const MAX_EXPORTED_SAMPLES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    /// Files ending with `.json` are written as JSON, everything else as CSV
+    pub fn from_file_name(name: &str) -> Self {
+        if name.to_lowercase().ends_with(".json") {
+            ExportFormat::Json
+        } else {
+            ExportFormat::Csv
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueTable {
+    pub columns: Vec<String>,
+    /// The time of the row and the value of each column, `None` if the field has no value
+    pub rows: Vec<(BigUint, Vec<Option<String>>)>,
+}
+
+impl ValueTable {
+    pub fn to_csv(&self) -> String {
+        let mut result = std::iter::once("time")
+            .chain(self.columns.iter().map(String::as_str))
+            .map(csv_field)
+            .collect::<Vec<_>>()
+            .join(",");
+        result.push('\n');
+        for (time, values) in &self.rows {
+            result.push_str(&time.to_string());
+            for value in values {
+                result.push(',');
+                result.push_str(&value.as_deref().map(csv_field).unwrap_or_default());
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    /// One object per row, with the time under `time` and the values under the column names
+    pub fn to_json(&self) -> Result<String> {
+        let rows = self
+            .rows
+            .iter()
+            .map(|(time, values)| {
+                let mut row = Map::new();
+                let time = time
+                    .to_u64()
+                    .map(Value::from)
+                    .unwrap_or_else(|| Value::from(time.to_string()));
+                row.insert("time".to_string(), time);
+                for (column, value) in self.columns.iter().zip(values) {
+                    row.insert(
+                        column.clone(),
+                        value.clone().map_or(Value::Null, Value::from),
+                    );
+                }
+                Value::Object(row)
+            })
+            .collect::<Vec<_>>();
+        Ok(serde_json::to_string_pretty(&rows)?)
+    }
+
+    pub fn encode(&self, format: ExportFormat) -> Result<String> {
+        match format {
+            ExportFormat::Csv => Ok(self.to_csv()),
+            ExportFormat::Json => self.to_json(),
+        }
+    }
+}
+
+/// Quotes `field` if it contains characters that have a meaning in CSV
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The names of the columns, with the index of the item appended to names that occur more
+/// than once, so that they can be told apart and do not overwrite each other in JSON
+fn unique_columns(columns: Vec<(String, usize)>) -> Vec<String> {
+    let mut counts = HashMap::<&str, usize>::new();
+    for (name, _) in &columns {
+        *counts.entry(name).or_default() += 1;
+    }
+    columns
+        .iter()
+        .map(|(name, index)| {
+            if counts[name.as_str()] > 1 {
+                format!("{name}#{index}")
+            } else {
+                name.clone()
+            }
+        })
+        .collect()
+}
+
+/// Splits the arguments of the export command into the file, the sampling clock and the
+/// start and end times, e.g. `values.csv clock=tb.clk from=100 to=2000`
+pub fn parse_export_arguments(
+    query: &str,
+) -> Option<(
+    PathBuf,
+    Option<VariableRef>,
+    Option<BigUint>,
+    Option<BigUint>,
+)> {
+    let mut words = query.split_whitespace();
+    let path = PathBuf::from(words.next()?);
+    let (mut clock, mut start, mut end) = (None, None, None);
+    for word in words {
+        let (key, value) = word.split_once('=')?;
+        match key {
+            "clock" => clock = Some(VariableRef::from_hierarchy_string(value)),
+            "from" => start = Some(value.parse().ok()?),
+            "to" => end = Some(value.parse().ok()?),
+            _ => return None,
+        }
+    }
+    Some((path, clock, start, end))
+}
+
+impl WaveData {
+    /// The displayed fields in the order they are shown in the item list. Subfields are only
+    /// included if they are expanded, which is only known once the item list has been drawn.
+    fn exported_fields(&self) -> Vec<DisplayedFieldRef> {
+        self.displayed_items_order
+            .iter()
+            .filter(|id| {
+                self.displayed_items
+                    .get(id)
+                    .and_then(Signal::from_item)
+                    .is_some()
+            })
+            .flat_map(|id| {
+                let mut drawn = self
+                    .drawing_infos
+                    .iter()
+                    .filter_map(|info| match info {
+                        ItemDrawingInfo::Variable(info) => {
+                            Some((info.top, &info.displayed_field_ref))
+                        }
+                        ItemDrawingInfo::Derived(info) => {
+                            Some((info.top, &info.displayed_field_ref))
+                        }
+                        _ => None,
+                    })
+                    .filter(|(_, field_ref)| field_ref.item == *id)
+                    .collect::<Vec<_>>();
+                drawn.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+                if drawn.is_empty() {
+                    vec![DisplayedFieldRef::from(*id)]
+                } else {
+                    drawn
+                        .into_iter()
+                        .map(|(_, field_ref)| field_ref.clone())
+                        .collect()
+                }
+            })
+            .collect()
+    }
+
+    /// The start and end time shown in the first viewport, clipped to the file
+    pub fn visible_range(&self) -> (Option<BigUint>, Option<BigUint>) {
+        let num_timestamps = self.num_timestamps();
+        let Some(viewport) = self.viewports.first() else {
+            return (None, None);
+        };
+        (
+            viewport.left_edge_time(&num_timestamps).to_biguint(),
+            viewport.right_edge_time(&num_timestamps).to_biguint(),
+        )
+    }
+
+    /// The first `limit` times from `start` to `end` at which any of the displayed items
+    /// changes, including `start`
+    pub fn change_times(&self, start: &BigUint, end: &BigUint, limit: usize) -> BTreeSet<BigUint> {
+        let Some(waves) = self.inner.as_waves() else {
+            return BTreeSet::new();
+        };
+        let mut times = BTreeSet::from([start.clone()]);
+        for item in self.displayed_items.values() {
+            let Some(signal) = Signal::from_item(item) else {
+                continue;
+            };
+            let mut time = start.clone();
+            // the first `limit` changes of all items are among the first `limit` of each
+            for _ in 0..limit {
+                let Some(next) = signal
+                    .query(waves, &time)
+                    .ok()
+                    .flatten()
+                    .and_then(|result| result.next)
+                    .filter(|next| next > &time && next <= end)
+                else {
+                    break;
+                };
+                times.insert(next.clone());
+                time = next;
+            }
+        }
+        while times.len() > limit {
+            times.pop_last();
+        }
+        times
+    }
+
+    /// The times from `start` to `end` at which an export samples the values, the rising
+    /// edges of a clock given by `clock_edges` or the changes of the displayed items. At most
+    /// [`MAX_EXPORTED_SAMPLES`] times are returned.
+    pub fn export_times(
+        &self,
+        clock_edges: Option<&[BigUint]>,
+        start: &BigUint,
+        end: &BigUint,
+    ) -> Vec<BigUint> {
+        let mut times = match clock_edges {
+            Some(edges) => edges[edges.partition_point(|edge| edge < start)..]
+                .iter()
+                .take_while(|edge| *edge <= end)
+                .take(MAX_EXPORTED_SAMPLES + 1)
+                .cloned()
+                .collect::<Vec<_>>(),
+            None => self
+                .change_times(start, end, MAX_EXPORTED_SAMPLES + 1)
+                .into_iter()
+                .collect(),
+        };
+        if times.len() > MAX_EXPORTED_SAMPLES {
+            times.truncate(MAX_EXPORTED_SAMPLES);
+            warn!(
+                "Exporting only the first {MAX_EXPORTED_SAMPLES} samples, up to time {}",
+                times.last().unwrap()
+            );
+        }
+        times
+    }
+}
+
+impl State {
+    /// The values of the displayed fields from `start` to `end`, at every change or at the
+    /// rising edges of a clock given by `clock_edges`
+    pub fn value_table(
+        &self,
+        waves: &WaveData,
+        clock_edges: Option<&[BigUint]>,
+        start: &BigUint,
+        end: &BigUint,
+    ) -> Result<ValueTable> {
+        let wave_container = waves
+            .inner
+            .as_waves()
+            .ok_or_else(|| anyhow!("No waves to export values from"))?;
+        let fields = waves.exported_fields();
+        let columns = unique_columns(
+            fields
+                .iter()
+                .filter_map(|field_ref| {
+                    let signal = Signal::from_item(waves.displayed_items.get(&field_ref.item)?)?;
+                    let name = std::iter::once(signal.name())
+                        .chain(field_ref.field.iter().cloned())
+                        .collect::<Vec<_>>()
+                        .join(".");
+                    let index = waves
+                        .displayed_items_order
+                        .iter()
+                        .position(|id| *id == field_ref.item)?;
+                    Some((name, index))
+                })
+                .collect(),
+        );
+
+        let times = waves.export_times(clock_edges, start, end);
+
+        let mut rows: Vec<(BigUint, Vec<Option<String>>)> = vec![];
+        for time in times {
+            let values = fields
+                .iter()
+                .map(|field_ref| {
+                    let signal = Signal::from_item(waves.displayed_items.get(&field_ref.item)?)?;
+                    let (_, value) = signal
+                        .query(wave_container, &time)
+                        .ok()
+                        .flatten()
+                        .and_then(|result| result.current)?;
+                    self.translate_field_value(waves, field_ref, &value)
+                        .flatten()
+                        .map(|value| value.value)
+                })
+                .collect::<Vec<_>>();
+            // Changes of bits that are not visible in any field do not get a row of their own
+            if clock.is_none() && rows.last().is_some_and(|(_, last)| *last == values) {
+                continue;
+            }
+            rows.push((time, values));
+        }
+        Ok(ValueTable { columns, rows })
+    }
+
+    /// Resolves the sampling clock of an export in the loaded waves and returns its rising
+    /// edges. Returns `None` if the clock does not exist, or if it has to be loaded or its
+    /// edges found first, in which case the message returned by `retry` is sent once they are
+    /// available.
+    pub fn export_clock_edges(
+        &mut self,
+        clock: &VariableRef,
+        retry: impl FnOnce(VariableRef) -> Message,
+    ) -> Option<Arc<Vec<BigUint>>> {
+        let inner = self.waves.as_mut()?.inner.as_waves_mut()?;
+        let Some(clock) = inner.update_variable_ref(clock) else {
+            error!("Failed to export: no variable {}", clock.full_path_string());
+            return None;
+        };
+        match inner.load_variables(std::iter::once(&clock)) {
+            Ok(Some(cmd)) => {
+                self.load_variables(cmd);
+                // batch commands wait until the loading has completed
+                self.sys.batch_commands.push_front(retry(clock));
+                None
+            }
+            Ok(None) => {
+                let retry = retry(clock.clone());
+                self.current_clock_edges_or_retry(&clock, retry)
+            }
+            Err(e) => {
+                error!("Failed to load {}: {e:#}", clock.full_path_string());
+                None
+            }
+        }
+    }
+
+    /// Writes the values of the displayed items to `path`, or a file picked by the user, as
+    /// CSV or JSON depending on the file extension. The whole file is exported unless `start`
+    /// or `end` is given. If `clock` has to be loaded or its edges found first, the export is
+    /// retried once they are available.
+    pub fn export_values(
+        &mut self,
+        path: Option<PathBuf>,
+        clock: Option<VariableRef>,
+        start: Option<BigUint>,
+        end: Option<BigUint>,
+    ) {
+        let clock_edges = match clock {
+            Some(clock) => {
+                let retry = |clock| Message::ExportValues {
+                    path: path.clone(),
+                    clock: Some(clock),
+                    start: start.clone(),
+                    end: end.clone(),
+                };
+                let Some(edges) = self.export_clock_edges(&clock, retry) else {
+                    return;
+                };
+                Some(edges)
+            }
+            None => None,
+        };
+        let Some(waves) = &self.waves else {
+            return;
+        };
+        let start = start.unwrap_or_default();
+        let end = end.unwrap_or_else(|| waves.inner.max_timestamp().unwrap_or_default());
+        let table = match self.value_table(
+            waves,
+            clock_edges.as_deref().map(Vec::as_slice),
+            &start,
+            &end,
+        ) {
+            Ok(table) => table,
+            Err(e) => {
+                error!("Failed to export values: {e:#}");
+                return;
+            }
+        };
+
+        save_export(
+            path,
+            "Export values",
+            &[
+                ("CSV files (*.csv)", &["csv"]),
+                ("JSON files (*.json)", &["json"]),
+            ],
+            move |file_name| table.encode(ExportFormat::from_file_name(file_name)),
+        );
+    }
+}
+
+/// Writes the result of `encode` to `path`, or a file picked by the user. `encode` gets the
+/// name of the file, to pick the format from its extension.
+pub fn save_export<F>(
+    path: Option<PathBuf>,
+    title: &'static str,
+    filters: &'static [(&'static str, &'static [&'static str])],
+    encode: F,
+) where
+    F: FnOnce(&str) -> Result<String> + Send + 'static,
+{
+    perform_async_work(async move {
+        let destination = if let Some(_path) = path {
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                Some(_path.into())
+            }
+            #[cfg(target_arch = "wasm32")]
+            {
+                None
+            }
+        } else {
+            filters
+                .iter()
+                .fold(AsyncFileDialog::new().set_title(title), |dialog, filter| {
+                    dialog.add_filter(filter.0, filter.1)
+                })
+                .add_filter("All files", &["*"])
+                .save_file()
+                .await
+        };
+        let Some(destination) = destination else {
+            return;
+        };
+
+        let file_name = destination.file_name();
+        let encoded = match encode(&file_name) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                error!("Failed to encode {file_name}: {e:#}");
+                return;
+            }
+        };
+        match destination
+            .write(encoded.as_bytes())
+            .await
+            .with_context(|| format!("Failed to write {destination:?}"))
+        {
+            Ok(()) => info!("Wrote {file_name}"),
+            Err(e) => error!("{e:#}"),
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table() -> ValueTable {
+        ValueTable {
+            columns: vec!["tb.a".to_string(), "tb.s.x".to_string()],
+            rows: vec![
+                (BigUint::from(0u8), vec![Some("0".to_string()), None]),
+                (
+                    BigUint::from(10u8),
+                    vec![Some("1,2".to_string()), Some("say \"hi\"".to_string())],
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn csv_quotes_special_characters() {
+        assert_eq!(
+            table().to_csv(),
+            "time,tb.a,tb.s.x\n0,0,\n10,\"1,2\",\"say \"\"hi\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn json_has_one_object_per_row() {
+        let json: Value = serde_json::from_str(&table().to_json().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"time": 0, "tb.a": "0", "tb.s.x": null},
+                {"time": 10, "tb.a": "1,2", "tb.s.x": "say \"hi\""},
+            ])
+        );
+    }
+
+    #[test]
+    fn duplicate_column_names_get_the_item_index() {
+        assert_eq!(
+            unique_columns(vec![
+                ("tb.a".to_string(), 0),
+                ("tb.b".to_string(), 1),
+                ("tb.a".to_string(), 2),
+            ]),
+            vec!["tb.a#0", "tb.b", "tb.a#2"]
+        );
+    }
+
+    #[test]
+    fn format_follows_extension() {
+        assert_eq!(ExportFormat::from_file_name("v.JSON"), ExportFormat::Json);
+        assert_eq!(ExportFormat::from_file_name("v.csv"), ExportFormat::Csv);
+        assert_eq!(ExportFormat::from_file_name("values"), ExportFormat::Csv);
+    }
+
+    #[test]
+    fn export_arguments_are_parsed() {
+        let (path, clock, start, end) =
+            parse_export_arguments("out.csv clock=tb.clk from=100").unwrap();
+        assert_eq!(path, PathBuf::from("out.csv"));
+        assert_eq!(clock, Some(VariableRef::from_hierarchy_string("tb.clk")));
+        assert_eq!(start, Some(BigUint::from(100u8)));
+        assert_eq!(end, None);
+        assert!(parse_export_arguments("out.csv to=x").is_none());
+        assert!(parse_export_arguments("out.csv speed=1").is_none());
+        assert!(parse_export_arguments("").is_none());
+    }
+}
@@ -0,0 +1,359 @@
+//! Searching for the times at which a variable matches a condition on its value, e.g.
+//! `== FETCH`, `> 10` or `in 0x8000..0x8FFF`.
+//!
+//! A search checks at most [`MAX_SEARCHED_CHANGES`] changes, as the values are translated on
+//! the UI thread. If none of them match, the cursor is moved to the last checked change so
+//! that searching again continues from there.
+use std::cmp::Ordering;
+
+use color_eyre::eyre::{anyhow, bail};
+use color_eyre::Result;
+use egui::{Context, Key, TextEdit, Window};
+use num::{BigInt, BigUint, Num, ToPrimitive, Zero};
+use regex::Regex;
+use surfer_translation_types::VariableValue;
+
+use crate::displayed_item::{DisplayedFieldRef, DisplayedItem, DisplayedItemRef};
+use crate::message::Message;
+use crate::wave_data::WaveData;
+use crate::State;
+
+/// Most changes of the variable that are checked by one search
+pub const MAX_SEARCHED_CHANGES: usize = 100_000;
+
+/// A number parsed from a query or a value. Integers are kept exact so that wide buses
+/// can be compared without loss of precision.
+#[derive(Debug, Clone)]
+pub enum Number {
+    Integer(BigInt),
+    Float(f64),
+}
+
+impl Number {
+    /// Parses decimal, `0x` hexadecimal, `0o` octal and `0b` binary integers with an optional
+    /// sign and `_` separators, as well as floating point numbers.
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().replace('_', "");
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s.strip_prefix('+').unwrap_or(&s)),
+        };
+        let (radix, digits) = match digits.get(..2).map(str::to_ascii_lowercase).as_deref() {
+            Some("0x") => (16, &digits[2..]),
+            Some("0o") => (8, &digits[2..]),
+            Some("0b") => (2, &digits[2..]),
+            _ => (10, digits),
+        };
+        if let Ok(value) = BigInt::from_str_radix(digits, radix) {
+            return Some(Number::Integer(if negative { -value } else { value }));
+        }
+        s.parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .map(Number::Float)
+    }
+
+    /// The numeric value of a variable. If the translated value is the raw value written in
+    /// some radix, e.g. by the hexadecimal translator, the raw value is used. Otherwise, the
+    /// translated value is parsed so that signed, fixed point and floating point formats
+    /// compare as they are shown.
+    pub fn from_value(translated: &str, raw: &VariableValue) -> Option<Self> {
+        if let VariableValue::BigUint(raw) = raw {
+            let digits = translated.trim().replace('_', "");
+            if [10, 16, 8, 2]
+                .iter()
+                .any(|radix| BigUint::from_str_radix(&digits, *radix).as_ref() == Ok(raw))
+            {
+                return Some(Number::Integer(raw.clone().into()));
+            }
+        }
+        Self::parse(translated)
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Integer(v) => v.to_f64().unwrap_or(f64::NAN),
+            Number::Float(v) => *v,
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Integer(a), Number::Integer(b)) => Some(a.cmp(b)),
+            _ => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ValueCondition {
+    /// Matches the translated value, or the numeric value if both sides are numbers
+    Equal(String, Option<Number>),
+    NotEqual(String, Option<Number>),
+    Compare(Ordering, bool, Number),
+    /// Inclusive on both ends
+    InRange(Number, Number),
+    Regex(Regex),
+}
+
+impl ValueCondition {
+    fn parse(query: &str) -> Result<Self> {
+        let query = query.trim();
+        if query.is_empty() {
+            bail!("Empty value search");
+        }
+        let number = |operand: &str| {
+            Number::parse(operand).ok_or_else(|| anyhow!("'{}' is not a number", operand.trim()))
+        };
+
+        if let Some(pattern) = query.strip_prefix('~') {
+            return Ok(ValueCondition::Regex(Regex::new(pattern.trim())?));
+        }
+        if let Some(range) = query.strip_prefix("in ") {
+            let (low, high) = range
+                .split_once("..")
+                .ok_or_else(|| anyhow!("Expected a range like 'in 10..20'"))?;
+            let high = high.strip_prefix('=').unwrap_or(high);
+            return Ok(ValueCondition::InRange(number(low)?, number(high)?));
+        }
+        for (operator, ordering, or_equal) in [
+            ("<=", Ordering::Less, true),
+            (">=", Ordering::Greater, true),
+            ("<", Ordering::Less, false),
+            (">", Ordering::Greater, false),
+        ] {
+            if let Some(operand) = query.strip_prefix(operator) {
+                return Ok(ValueCondition::Compare(
+                    ordering,
+                    or_equal,
+                    number(operand)?,
+                ));
+            }
+        }
+        if let Some(operand) = query.strip_prefix("!=") {
+            let operand = operand.trim();
+            return Ok(ValueCondition::NotEqual(
+                operand.to_string(),
+                Number::parse(operand),
+            ));
+        }
+        let operand = query.strip_prefix("==").unwrap_or(query).trim();
+        Ok(ValueCondition::Equal(
+            operand.to_string(),
+            Number::parse(operand),
+        ))
+    }
+
+    fn matches(&self, translated: &str, raw: &VariableValue) -> bool {
+        let value = || Number::from_value(translated, raw);
+        let equal = |operand: &String, number: &Option<Number>| {
+            translated == operand
+                || number
+                    .as_ref()
+                    .is_some_and(|number| value().is_some_and(|v| &v == number))
+        };
+        match self {
+            ValueCondition::Equal(operand, number) => equal(operand, number),
+            ValueCondition::NotEqual(operand, number) => !equal(operand, number),
+            ValueCondition::Compare(ordering, or_equal, operand) => value()
+                .and_then(|v| v.partial_cmp(operand))
+                .is_some_and(|o| o == *ordering || (*or_equal && o.is_eq())),
+            ValueCondition::InRange(low, high) => value().is_some_and(|v| &v >= low && &v <= high),
+            ValueCondition::Regex(regex) => regex.is_match(translated),
+        }
+    }
+}
+
+/// The active value search, which is always on the root value of a variable
+#[derive(Debug, Clone)]
+pub struct ValueSearch {
+    pub item: DisplayedItemRef,
+    pub query: String,
+    condition: ValueCondition,
+}
+
+impl ValueSearch {
+    pub fn new(item: DisplayedItemRef, query: &str) -> Result<Self> {
+        Ok(Self {
+            item,
+            query: query.trim().to_string(),
+            condition: ValueCondition::parse(query)?,
+        })
+    }
+
+    pub fn matches(&self, translated: &str, raw: &VariableValue) -> bool {
+        self.condition.matches(translated, raw)
+    }
+}
+
+/// A time found by [`State::find_value_match`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueMatch {
+    /// The variable takes a matching value at this time
+    Found(BigUint),
+    /// None of the checked changes match. This is the time of the last checked change.
+    Stopped(BigUint),
+}
+
+impl State {
+    /// Finds the closest time strictly after, or strictly before, `time` at which the searched
+    /// variable takes a matching value, checking at most `max_changes` changes. `None` if there
+    /// are no more matches.
+    pub fn find_value_match(
+        &self,
+        waves: &WaveData,
+        time: &BigUint,
+        next: bool,
+        max_changes: usize,
+    ) -> Option<ValueMatch> {
+        let search = waves.value_search.as_ref()?;
+        let Some(DisplayedItem::Variable(variable)) = waves.displayed_items.get(&search.item)
+        else {
+            return None;
+        };
+        let wave_container = waves.inner.as_waves()?;
+        let field_ref = DisplayedFieldRef::from(search.item);
+        let matches = |value: &VariableValue| {
+            self.translate_field_value(waves, &field_ref, value)
+                .flatten()
+                .is_some_and(|translated| search.matches(&translated.value, value))
+        };
+        let query = |time: &BigUint| {
+            wave_container
+                .query_variable(&variable.variable_ref, time)
+                .ok()
+                .flatten()
+        };
+
+        let mut time = time.clone();
+        for _ in 0..max_changes {
+            let (change_time, value) = if next {
+                let next_time = query(&time)?.next?;
+                (next_time.clone(), query(&next_time)?.current?.1)
+            } else if time.is_zero() {
+                return None;
+            } else {
+                query(&(&time - 1u8))?.current?
+            };
+            if matches(&value) {
+                return Some(ValueMatch::Found(change_time));
+            }
+            time = change_time;
+        }
+        Some(ValueMatch::Stopped(time))
+    }
+
+    pub fn draw_value_search_window(&self, ctx: &Context, msgs: &mut Vec<Message>) {
+        let Some(waves) = &self.waves else { return };
+        let mut open = true;
+        let mut query = self.sys.value_search_string.borrow_mut();
+        Window::new("Value search")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let focused = waves
+                    .focused_item
+                    .and_then(|idx| waves.displayed_items_order.get(idx.0))
+                    .and_then(|id| waves.displayed_items.get(id));
+                let Some(DisplayedItem::Variable(variable)) = focused else {
+                    ui.label("Focus a variable to search its values");
+                    return;
+                };
+                ui.label(format!("Search in {}", variable.display_name));
+                let response = ui.add(
+                    TextEdit::singleline(&mut *query)
+                        .hint_text("== FETCH, > 10, in 0x8000..0x8FFF, ~regex"),
+                );
+                let search = |next| {
+                    Message::Batch(vec![
+                        Message::SetValueSearch(None, query.clone()),
+                        Message::MoveToValueMatch { next },
+                    ])
+                };
+                if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                    msgs.push(search(true));
+                    response.request_focus();
+                }
+                if let Err(e) = ValueCondition::parse(&query) {
+                    if !query.trim().is_empty() {
+                        ui.colored_label(self.config.theme.accent_error.background, e.to_string());
+                    }
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Previous").clicked() {
+                        msgs.push(search(false));
+                    }
+                    if ui.button("Next").clicked() {
+                        msgs.push(search(true));
+                    }
+                    if ui.button("Clear").clicked() {
+                        msgs.push(Message::ClearValueSearch);
+                    }
+                });
+            });
+        if !open {
+            msgs.push(Message::SetValueSearchVisible(false));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn matches(query: &str, translated: &str, raw: u64) -> bool {
+        ValueCondition::parse(query)
+            .unwrap()
+            .matches(translated, &VariableValue::BigUint(BigUint::from(raw)))
+    }
+
+    #[test]
+    fn equality_matches_names_and_numbers() {
+        assert!(matches("FETCH", "FETCH", 1));
+        assert!(matches("== FETCH", "FETCH", 1));
+        assert!(!matches("== FETCH", "DECODE", 2));
+        assert!(matches("!= FETCH", "DECODE", 2));
+        assert!(matches("== 0x10", "16", 16));
+        assert!(matches("== 16", "10", 16));
+    }
+
+    #[test]
+    fn comparisons_use_raw_value_of_radix_formats() {
+        assert!(matches("in 0x8000..0x8FFF", "8000", 0x8000));
+        assert!(matches("in 0x8000..0x8FFF", "8fff", 0x8fff));
+        assert!(!matches("in 0x8000..0x8FFF", "9000", 0x9000));
+        assert!(matches("> 9", "10", 16));
+        assert!(matches(">= 16", "10", 16));
+        assert!(!matches("< 16", "10", 16));
+    }
+
+    #[test]
+    fn comparisons_use_translated_value_of_numeric_formats() {
+        assert!(matches("< 0", "-3", 0b101));
+        assert!(matches("in -1..1", "0.5", 0b01));
+        assert!(!matches("> 0", "x", 0));
+    }
+
+    #[test]
+    fn regex_matches_translated_value() {
+        assert!(matches("~^ERR_", "ERR_TIMEOUT", 3));
+        assert!(!matches("~^ERR_", "OK", 0));
+    }
+
+    #[test]
+    fn invalid_queries_are_rejected() {
+        assert!(ValueCondition::parse("").is_err());
+        assert!(ValueCondition::parse("> abc").is_err());
+        assert!(ValueCondition::parse("in 10").is_err());
+        assert!(ValueCondition::parse("~(").is_err());
+    }
+}
@@ -121,6 +121,8 @@ impl WaveData {
                     DisplayedItem::TimeLine(_) => {}
                     DisplayedItem::Placeholder(_) => {}
                     DisplayedItem::Stream(_) => {}
+                    DisplayedItem::Derived(_) => {}
+                    DisplayedItem::Decoder(_) => {}
                 });
         }
     }
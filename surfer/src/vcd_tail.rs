@@ -0,0 +1,620 @@
+//! Following VCD files that a simulator is still writing. Instead of reloading the file
+//! whenever it changes, the value changes appended to it are parsed and added to the
+//! [`WellenContainer`], so that long simulations can be watched while they run.
+//!
+//! Parsing starts again at the last time step of the loaded body, as the simulator may still
+//! have been writing it when the body was read. Only complete lines are parsed.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use camino::Utf8PathBuf;
+use color_eyre::eyre::{bail, Context};
+use color_eyre::Result;
+use log::{error, info, warn};
+use num::{BigUint, ToPrimitive};
+use surfer_translation_types::VariableValue;
+use surver::compression::Compression;
+use wellen::{Hierarchy, SignalRef, Time};
+
+use crate::file_watcher::FileWatcher;
+use crate::message::Message;
+use crate::wasm_util::perform_work;
+use crate::wave_container::WaveContainer;
+use crate::wave_source::{WaveFormat, WaveSource};
+use crate::wellen::{index_to_string, WellenContainer};
+use crate::State;
+
+static TAIL_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// The signals and widths of the variables by their VCD identifier codes
+type Identifiers = HashMap<String, (SignalRef, u32)>;
+
+/// Incremental parser of the value changes appended to a VCD file
+pub struct VcdTail {
+    id: u64,
+    /// Unique id of the container that the value changes are added to
+    container_id: u64,
+    path: Utf8PathBuf,
+    /// Offset of the first byte that has not been parsed yet
+    offset: u64,
+    time: Time,
+    identifiers: Identifiers,
+    in_comment: bool,
+}
+
+/// The value changes read by a [`VcdTail`], which is handed back with them
+pub struct TailChunk {
+    tail: VcdTail,
+    changes: Vec<(Time, SignalRef, VariableValue)>,
+}
+
+impl VcdTail {
+    /// Starts parsing the file at the last time step of the body loaded into `waves`
+    pub fn new(path: Utf8PathBuf, waves: &WellenContainer) -> Result<Self> {
+        let mut file = File::open(&path).with_context(|| format!("Failed to open {path}"))?;
+        let (identifiers, body_start) =
+            read_identifiers(BufReader::new(&mut file), waves.hierarchy())
+                .with_context(|| format!("Failed to read the header of {path}"))?;
+        let end = file.metadata()?.len();
+        let time = waves.max_timestamp().and_then(|time| time.to_u64());
+        let offset = match time {
+            Some(time) => match find_last_time(&mut file, time, body_start, end)? {
+                Some(offset) => offset,
+                None => {
+                    warn!("Did not find #{time} in {path}, following it from its end");
+                    end
+                }
+            },
+            None => body_start,
+        };
+
+        Ok(Self {
+            id: TAIL_COUNT.fetch_add(1, Ordering::SeqCst),
+            container_id: waves.unique_id(),
+            path,
+            offset,
+            time: time.unwrap_or_default(),
+            identifiers,
+            in_comment: false,
+        })
+    }
+
+    /// Parses what has been appended to the file since the last call
+    fn read_appended(mut self) -> Result<TailChunk> {
+        let mut file = File::open(&self.path)?;
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            bail!("{} is shorter than what has been read of it", self.path);
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut bytes = vec![];
+        file.take(len - self.offset).read_to_end(&mut bytes)?;
+        // the simulator may still be writing the last line
+        let complete = bytes
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |pos| pos + 1);
+        self.offset += complete as u64;
+        let changes = self.parse(&String::from_utf8_lossy(&bytes[..complete]));
+        Ok(TailChunk {
+            tail: self,
+            changes,
+        })
+    }
+
+    /// Parses the value changes in `text`, which has to end at the end of a line
+    fn parse(&mut self, text: &str) -> Vec<(Time, SignalRef, VariableValue)> {
+        let mut changes = vec![];
+        let mut tokens = text.split_ascii_whitespace();
+        while let Some(token) = tokens.next() {
+            if self.in_comment {
+                self.in_comment = token != "$end";
+                continue;
+            }
+            let (value, id) = match token.as_bytes()[0] {
+                b'#' => {
+                    match token[1..].parse() {
+                        Ok(time) => self.time = time,
+                        Err(_) => warn!("Ignoring the malformed time step {token}"),
+                    }
+                    continue;
+                }
+                // the values of `$dumpvars` and similar sections are parsed like any other
+                b'$' => {
+                    self.in_comment = token == "$comment";
+                    continue;
+                }
+                b'b' | b'B' | b'r' | b'R' | b's' | b'S' => match tokens.next() {
+                    Some(id) => (token, id),
+                    None => break,
+                },
+                _ if token.is_char_boundary(1) => token.split_at(1),
+                _ => continue,
+            };
+            let Some((signal, width)) = self.identifiers.get(id) else {
+                continue;
+            };
+            if let Some(value) = parse_value(value, *width) {
+                changes.push((self.time, *signal, value));
+            }
+        }
+        changes
+    }
+}
+
+/// Reads the identifier codes of the variables in the header and the offset at which the body
+/// starts. The variables are looked up in `hierarchy` by their full path and index, as
+/// several variables may share an identifier code.
+fn read_identifiers<R: BufRead>(
+    mut reader: R,
+    hierarchy: &Hierarchy,
+) -> Result<(Identifiers, u64)> {
+    let vars = hierarchy
+        .iter_vars()
+        .map(|var| {
+            (
+                (var.full_name(hierarchy), var.index().map(index_to_string)),
+                (var.signal_ref(), var.length().unwrap_or(1)),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut identifiers = HashMap::new();
+    let mut scopes = vec![];
+    // the keyword and the tokens of the `$scope` or `$var` that is being read
+    let mut declaration: Option<(&str, Vec<String>)> = None;
+    let mut offset = 0;
+    let mut line = vec![];
+    let mut header_done = false;
+    while !header_done {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0 {
+            bail!("The header does not end with $enddefinitions");
+        }
+        offset += read as u64;
+        for token in String::from_utf8_lossy(&line).split_ascii_whitespace() {
+            match token {
+                "$enddefinitions" => header_done = true,
+                "$scope" => declaration = Some(("$scope", vec![])),
+                "$var" => declaration = Some(("$var", vec![])),
+                "$upscope" => {
+                    scopes.pop();
+                }
+                "$end" => match declaration.take() {
+                    Some(("$scope", tokens)) => {
+                        let [_, name, ..] = tokens.as_slice() else {
+                            bail!(
+                                "Malformed scope declaration $scope {} $end",
+                                tokens.join(" ")
+                            );
+                        };
+                        scopes.push(name.clone());
+                    }
+                    Some((_, tokens)) => {
+                        let [_, _, id, reference @ ..] = tokens.as_slice() else {
+                            bail!(
+                                "Malformed variable declaration $var {} $end",
+                                tokens.join(" ")
+                            );
+                        };
+                        // the index is either part of the name or a token of its own
+                        let reference = reference.concat();
+                        let (name, index) = match reference.find('[') {
+                            Some(split) => {
+                                (&reference[..split], Some(reference[split..].to_string()))
+                            }
+                            None => (reference.as_str(), None),
+                        };
+                        let path = scopes
+                            .iter()
+                            .map(String::as_str)
+                            .chain(std::iter::once(name))
+                            .collect::<Vec<_>>()
+                            .join(".");
+                        let Some(var) = vars.get(&(path.clone(), index.clone())).or_else(|| {
+                            vars.get(&(format!("{path}{}", index.unwrap_or_default()), None))
+                        }) else {
+                            bail!("The variable {path} of the header was not loaded");
+                        };
+                        identifiers.insert(id.clone(), *var);
+                    }
+                    None => {}
+                },
+                token => {
+                    if let Some((_, tokens)) = &mut declaration {
+                        tokens.push(token.to_string());
+                    }
+                }
+            }
+        }
+    }
+    Ok((identifiers, offset))
+}
+
+/// The offset of the last `#time` between `start` and `end`. The file is searched backwards
+/// as the last loaded time step is usually close to its end.
+fn find_last_time<F: Read + Seek>(
+    file: &mut F,
+    time: Time,
+    start: u64,
+    end: u64,
+) -> Result<Option<u64>> {
+    const CHUNK_SIZE: u64 = 1 << 16;
+    let needle = format!("#{time}").into_bytes();
+    let mut pos = end;
+    // the bytes from `pos`, keeping enough of the previous chunk to find a time step that
+    // crosses the chunk boundary
+    let mut bytes = vec![];
+    while pos > start {
+        let chunk_start = pos.saturating_sub(CHUNK_SIZE).max(start);
+        let mut chunk = vec![0; (pos - chunk_start) as usize];
+        file.seek(SeekFrom::Start(chunk_start))?;
+        file.read_exact(&mut chunk)?;
+        bytes.truncate(needle.len() + 1);
+        chunk.append(&mut bytes);
+        bytes = chunk;
+        pos = chunk_start;
+
+        let preceded = |idx: usize| {
+            if idx == 0 {
+                pos == start
+            } else {
+                bytes[idx - 1].is_ascii_whitespace()
+            }
+        };
+        let followed = |idx: usize| {
+            bytes
+                .get(idx)
+                .map_or(pos + idx as u64 == end, u8::is_ascii_whitespace)
+        };
+        if let Some(idx) = (0..=bytes.len().saturating_sub(needle.len()))
+            .rev()
+            .find(|&idx| {
+                bytes[idx..].starts_with(&needle) && preceded(idx) && followed(idx + needle.len())
+            })
+        {
+            return Ok(Some(pos + idx as u64));
+        }
+    }
+    Ok(None)
+}
+
+/// Converts a VCD value to the value that `wellen` returns for it
+fn parse_value(value: &str, width: u32) -> Option<VariableValue> {
+    match value.as_bytes()[0] {
+        b'b' | b'B' => Some(bits_value(&value[1..], width)),
+        b'r' | b'R' => value[1..]
+            .parse::<f64>()
+            .ok()
+            .map(|real| VariableValue::String(format!("{real}"))),
+        b's' | b'S' => Some(VariableValue::String(value[1..].to_string())),
+        bit if b"01xXzZuUwWlLhH-".contains(&bit) => Some(bits_value(value, width)),
+        _ => None,
+    }
+}
+
+fn bits_value(bits: &str, width: u32) -> VariableValue {
+    let bits = bits.to_ascii_lowercase();
+    if bits.bytes().all(|bit| bit == b'0' || bit == b'1') {
+        VariableValue::BigUint(BigUint::parse_bytes(bits.as_bytes(), 2).unwrap_or_default())
+    } else {
+        // values are extended by their first bit if it is `x` or `z`, and by `0` otherwise
+        let extension = match bits.chars().next() {
+            Some(bit @ ('x' | 'z')) => bit,
+            _ => '0',
+        };
+        let padding = (width as usize).saturating_sub(bits.len());
+        VariableValue::String(
+            std::iter::repeat(extension)
+                .take(padding)
+                .chain(bits.chars())
+                .collect(),
+        )
+    }
+}
+
+/// Reads what is appended to the followed file whenever it changes
+pub struct Follower {
+    id: u64,
+    /// `None` while the parser is reading on a worker thread
+    tail: Option<VcdTail>,
+    /// Set if the file changed while the parser was reading
+    changed: bool,
+    _watcher: Option<FileWatcher>,
+}
+
+fn request_repaint() {
+    if let Some(ctx) = crate::EGUI_CONTEXT.read().unwrap().as_ref() {
+        ctx.request_repaint();
+    }
+}
+
+impl State {
+    pub fn set_follow(&mut self, follow: bool) {
+        self.follow = follow;
+        if follow {
+            self.start_following();
+        } else {
+            self.sys.follower = None;
+        }
+    }
+
+    /// Starts reading what is appended to the file of the waves. Has to be called again once
+    /// the body of the file has been loaded.
+    pub fn start_following(&mut self) {
+        self.sys.follower = None;
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        let path = match &waves.source {
+            WaveSource::File(path) | WaveSource::DragAndDrop(Some(path)) => path.clone(),
+            source => {
+                warn!("Only local files can be followed, not {source}");
+                return;
+            }
+        };
+        if waves.format != WaveFormat::Vcd || Compression::of_file(path.as_std_path()).is_some() {
+            warn!("Only uncompressed VCD files can be followed, not {path}");
+            return;
+        }
+        let source = waves.source.clone();
+        let Some(container) = waves
+            .waves_from_source_mut(&source)
+            .and_then(WaveContainer::as_wellen_mut)
+            .filter(|container| container.body_loaded())
+        else {
+            return;
+        };
+        let tail = match VcdTail::new(path.clone(), container) {
+            Ok(tail) => tail,
+            Err(e) => {
+                error!("Cannot follow {path}\n{e:#}");
+                return;
+            }
+        };
+
+        let sender = self.sys.channels.msg_sender.clone();
+        let watcher = FileWatcher::new(&path, move || {
+            if let Err(err) = sender.send(Message::FollowedFileChanged) {
+                error!("Message FollowedFileChanged did not send:\n{err}");
+            }
+            request_repaint();
+        })
+        .inspect_err(|err| error!("Cannot set up the file watcher:\n{err}"))
+        .ok();
+        info!("Following {path}");
+        self.sys.follower = Some(Follower {
+            id: tail.id,
+            tail: Some(tail),
+            changed: false,
+            _watcher: watcher,
+        });
+        self.read_followed_file();
+    }
+
+    /// Reads what has been appended to the followed file on a worker thread
+    pub fn read_followed_file(&mut self) {
+        let Some(follower) = &mut self.sys.follower else {
+            return;
+        };
+        let Some(tail) = follower.tail.take() else {
+            // read again once the parser is done
+            follower.changed = true;
+            return;
+        };
+        follower.changed = false;
+        let sender = self.sys.channels.msg_sender.clone();
+        perform_work(move || {
+            let path = tail.path.clone();
+            let msg = match tail.read_appended() {
+                Ok(chunk) => Message::FollowedFileRead(Box::new(chunk)),
+                Err(e) => {
+                    error!("Failed to read what was appended to {path}, reloading it\n{e:#}");
+                    Message::ReloadWaveform(true)
+                }
+            };
+            sender.send(msg).unwrap();
+            request_repaint();
+        });
+    }
+
+    /// Adds the value changes read from the followed file to the waves
+    pub fn add_followed_changes(&mut self, chunk: TailChunk) {
+        let TailChunk { tail, changes } = chunk;
+        // the file may have been reloaded or no longer be followed
+        let (Some(follower), Some(waves)) = (
+            self.sys
+                .follower
+                .as_mut()
+                .filter(|follower| follower.id == tail.id),
+            self.waves.as_mut(),
+        ) else {
+            return;
+        };
+        let source = waves.source.clone();
+        let old_num_timestamps = waves.num_timestamps();
+        let Some(container) = waves
+            .waves_from_source_mut(&source)
+            .and_then(WaveContainer::as_wellen_mut)
+            .filter(|container| container.unique_id() == tail.container_id)
+        else {
+            return;
+        };
+        container.append_changes(changes, tail.time);
+        follower.tail = Some(tail);
+        let changed = follower.changed;
+
+        let new_num_timestamps = waves.num_timestamps();
+        if new_num_timestamps != old_num_timestamps {
+            for viewport in waves.viewports.iter_mut() {
+                *viewport = viewport.extend_to(
+                    &old_num_timestamps,
+                    &new_num_timestamps,
+                    self.follow_pinned,
+                );
+            }
+        }
+        waves.values_changed();
+        // count the activity again with the new value changes
+        waves.activity = None;
+        self.find_clock_edges();
+        self.decode_transactions();
+        self.invalidate_draw_commands();
+        if changed {
+            self.read_followed_file();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+    use std::sync::Arc;
+
+    use surver::WELLEN_SURFER_DEFAULT_OPTIONS;
+
+    use super::*;
+    use crate::message::BodyResult;
+    use crate::wave_container::{VariableRef, VariableRefExt};
+    use crate::wellen::{LoadSignalPayload, LoadSignalsResult};
+
+    #[test]
+    fn last_time_step_is_found() {
+        let body = b"#1\n1!\n#10\n0!\n#100\n1!\n";
+        let end = body.len() as u64;
+        let mut file = Cursor::new(body);
+        assert_eq!(find_last_time(&mut file, 1, 0, end).unwrap(), Some(0));
+        assert_eq!(find_last_time(&mut file, 10, 0, end).unwrap(), Some(6));
+        assert_eq!(find_last_time(&mut file, 100, 0, end).unwrap(), Some(13));
+        assert_eq!(find_last_time(&mut file, 5, 0, end).unwrap(), None);
+    }
+
+    #[test]
+    fn values_are_extended_to_the_width() {
+        assert_eq!(
+            parse_value("b101", 8),
+            Some(VariableValue::BigUint(BigUint::from(5u32)))
+        );
+        assert_eq!(
+            parse_value("b1x", 4),
+            Some(VariableValue::String("001x".to_string()))
+        );
+        assert_eq!(
+            parse_value("bZ1", 4),
+            Some(VariableValue::String("zzz1".to_string()))
+        );
+        assert_eq!(
+            parse_value("r1.5", 64),
+            Some(VariableValue::String("1.5".to_string()))
+        );
+        assert_eq!(parse_value("q", 1), None);
+    }
+
+    #[test]
+    fn aliased_identifiers_are_found_by_path() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "$timescale 1ns $end\n$scope module top $end\n$var wire 1 ! clk $end\n\
+             $scope module sub $end\n$var wire 1 # en $end\n$var wire 1 ! clk $end\n\
+             $var wire 2 % bits [1:0] $end\n$upscope $end\n$var wire 1 # en $end\n\
+             $upscope $end\n$enddefinitions $end\n#0\n"
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        let header = wellen::viewers::read_header(
+            file.path().to_str().unwrap(),
+            &WELLEN_SURFER_DEFAULT_OPTIONS,
+        )
+        .unwrap();
+        let hierarchy = header.hierarchy;
+        let (identifiers, _) =
+            read_identifiers(BufReader::new(File::open(file.path()).unwrap()), &hierarchy).unwrap();
+        let var = |name: &str| {
+            let var = hierarchy
+                .iter_vars()
+                .find(|var| var.full_name(&hierarchy) == name)
+                .unwrap();
+            (var.signal_ref(), var.length().unwrap_or(1))
+        };
+        assert_eq!(identifiers.len(), 3);
+        assert_eq!(identifiers["!"], var("top.clk"));
+        assert_eq!(identifiers["!"], var("top.sub.clk"));
+        assert_eq!(identifiers["#"], var("top.en"));
+        assert_eq!(identifiers["#"], var("top.sub.en"));
+        assert_eq!(identifiers["%"], var("top.sub.bits"));
+    }
+
+    #[test]
+    fn appended_value_changes_extend_the_waves() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            file,
+            "$timescale 1ns $end\n$scope module top $end\n$var wire 1 ! clk $end\n\
+             $var wire 4 \" data [3:0] $end\n$upscope $end\n$enddefinitions $end\n\
+             #0\n$dumpvars\n0!\nb0 \"\n$end\n#10\n1!\nb101 \"\n"
+        )
+        .unwrap();
+        file.flush().unwrap();
+
+        let header = wellen::viewers::read_header(
+            file.path().to_str().unwrap(),
+            &WELLEN_SURFER_DEFAULT_OPTIONS,
+        )
+        .unwrap();
+        let hierarchy = Arc::new(header.hierarchy);
+        let body = wellen::viewers::read_body(header.body, &hierarchy, None).unwrap();
+        let mut waves = WellenContainer::new(hierarchy, None);
+        waves.add_body(BodyResult::Local(body)).unwrap();
+        let variables = waves.variables();
+        let (signals, from_unique_id, payload, _) = waves
+            .load_variables(variables.into_iter())
+            .unwrap()
+            .unwrap()
+            .destruct();
+        let LoadSignalPayload::Local(mut source, hierarchy) = payload else {
+            panic!("The file should have been loaded locally");
+        };
+        let loaded = source.load_signals(&signals, &hierarchy, true);
+        waves
+            .on_signals_loaded(LoadSignalsResult::local(source, loaded, from_unique_id))
+            .unwrap();
+
+        // the simulator continues the last time step and is writing the next line
+        write!(file, "0!\n#20\nb1x \"\n#30\n1").unwrap();
+        file.flush().unwrap();
+        let path = Utf8PathBuf::from_path_buf(file.path().to_path_buf()).unwrap();
+        let chunk = VcdTail::new(path, &waves).unwrap().read_appended().unwrap();
+        waves.append_changes(chunk.changes, chunk.tail.time);
+
+        let query = |name: &str, time: u32| {
+            waves
+                .query_variable(
+                    &VariableRef::from_hierarchy_string(name),
+                    &BigUint::from(time),
+                )
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(waves.max_timestamp(), Some(BigUint::from(30u32)));
+        assert_eq!(
+            query("top.clk", 15).current,
+            Some((
+                BigUint::from(10u32),
+                VariableValue::BigUint(BigUint::from(0u32))
+            ))
+        );
+        assert_eq!(query("top.data", 10).next, Some(BigUint::from(20u32)));
+        assert_eq!(
+            query("top.data", 25).current,
+            Some((
+                BigUint::from(20u32),
+                VariableValue::String("001x".to_string())
+            ))
+        );
+    }
+}
@@ -2,7 +2,9 @@ use color_eyre::eyre::Context;
 use ecolor::Color32;
 #[cfg(not(target_arch = "wasm32"))]
 use egui::ViewportCommand;
-use egui::{Frame, Layout, Painter, RichText, ScrollArea, Sense, TextStyle, WidgetText};
+use egui::{
+    CursorIcon, Frame, Layout, Painter, RichText, ScrollArea, Sense, TextStyle, WidgetText,
+};
 use egui_extras::{Column, TableBuilder};
 use egui_remixicon::icons;
 use emath::{Align, Pos2, Rect, RectTransform, Vec2};
@@ -15,22 +17,19 @@ use itertools::Itertools;
 use log::{info, warn};
 
 use num::BigUint;
-use surfer_translation_types::{
-    SubFieldFlatTranslationResult, TranslatedValue, VariableInfo, VariableType,
-};
+use surfer_translation_types::{TranslatedValue, VariableInfo, VariableType, VariableValue};
 
 #[cfg(feature = "performance_plot")]
 use crate::benchmark::NUM_PERF_SAMPLES;
 use crate::data_container::VariableType as VarType;
+use crate::derived::Signal;
 use crate::displayed_item::{
     draw_rename_window, DisplayedFieldRef, DisplayedItem, DisplayedItemIndex, DisplayedItemRef,
 };
 use crate::help::{
     draw_about_window, draw_control_help_window, draw_license_window, draw_quickstart_help_window,
 };
-use crate::time::time_string;
 use crate::transaction_container::{StreamScopeRef, TransactionStreamRef};
-use crate::translation::TranslationResultExt;
 use crate::util::uint_idx_to_alpha_idx;
 use crate::variable_direction::VariableDirectionExt;
 use crate::wave_container::{
@@ -101,6 +100,14 @@ pub struct TimeLineDrawingInfo {
     pub bottom: f32,
 }
 
+#[derive(Debug)]
+pub struct DerivedDrawingInfo {
+    pub displayed_field_ref: DisplayedFieldRef,
+    pub item_list_idx: DisplayedItemIndex,
+    pub top: f32,
+    pub bottom: f32,
+}
+
 #[derive(Debug)]
 pub struct StreamDrawingInfo {
     pub transaction_stream_ref: TransactionStreamRef,
@@ -109,12 +116,21 @@ pub struct StreamDrawingInfo {
     pub bottom: f32,
 }
 
+#[derive(Debug)]
+pub struct DecoderDrawingInfo {
+    pub item_list_idx: DisplayedItemIndex,
+    pub top: f32,
+    pub bottom: f32,
+}
+
 pub enum ItemDrawingInfo {
     Variable(VariableDrawingInfo),
     Divider(DividerDrawingInfo),
     Marker(MarkerDrawingInfo),
     TimeLine(TimeLineDrawingInfo),
     Stream(StreamDrawingInfo),
+    Derived(DerivedDrawingInfo),
+    Decoder(DecoderDrawingInfo),
 }
 
 impl ItemDrawingInfo {
@@ -125,6 +141,8 @@ impl ItemDrawingInfo {
             ItemDrawingInfo::Marker(drawing_info) => drawing_info.top,
             ItemDrawingInfo::TimeLine(drawing_info) => drawing_info.top,
             ItemDrawingInfo::Stream(drawing_info) => drawing_info.top,
+            ItemDrawingInfo::Derived(drawing_info) => drawing_info.top,
+            ItemDrawingInfo::Decoder(drawing_info) => drawing_info.top,
         }
     }
     pub fn bottom(&self) -> f32 {
@@ -134,6 +152,8 @@ impl ItemDrawingInfo {
             ItemDrawingInfo::Marker(drawing_info) => drawing_info.bottom,
             ItemDrawingInfo::TimeLine(drawing_info) => drawing_info.bottom,
             ItemDrawingInfo::Stream(drawing_info) => drawing_info.bottom,
+            ItemDrawingInfo::Derived(drawing_info) => drawing_info.bottom,
+            ItemDrawingInfo::Decoder(drawing_info) => drawing_info.bottom,
         }
     }
     pub fn item_list_idx(&self) -> usize {
@@ -143,6 +163,8 @@ impl ItemDrawingInfo {
             ItemDrawingInfo::Marker(drawing_info) => drawing_info.item_list_idx.0,
             ItemDrawingInfo::TimeLine(drawing_info) => drawing_info.item_list_idx.0,
             ItemDrawingInfo::Stream(drawing_info) => drawing_info.item_list_idx.0,
+            ItemDrawingInfo::Derived(drawing_info) => drawing_info.item_list_idx.0,
+            ItemDrawingInfo::Decoder(drawing_info) => drawing_info.item_list_idx.0,
         }
     }
 }
@@ -289,6 +311,39 @@ impl State {
             }
         }
 
+        if self.show_value_search {
+            self.draw_value_search_window(ctx, &mut msgs);
+        }
+
+        if self.show_measurement {
+            self.draw_measurement_window(ctx, &mut msgs);
+        }
+
+        if self.show_protocol_decoder {
+            self.draw_protocol_decoder_window(ctx, &mut msgs);
+        }
+
+        if self.show_differences {
+            self.draw_differences_window(ctx, &mut msgs);
+        }
+        if self
+            .waves
+            .as_ref()
+            .is_some_and(WaveData::comparison_outdated)
+        {
+            msgs.push(Message::UpdateComparison);
+        }
+        if self
+            .waves
+            .as_ref()
+            .is_some_and(WaveData::clock_edges_outdated)
+        {
+            msgs.push(Message::FindClockEdges);
+        }
+        if self.waves.as_ref().is_some_and(WaveData::decoders_outdated) {
+            msgs.push(Message::DecodeTransactions);
+        }
+
         if let Some(idx) = self.rename_target {
             draw_rename_window(
                 ctx,
@@ -850,22 +905,19 @@ impl State {
         ui.with_layout(
             Layout::top_down(alignment).with_cross_justify(false),
             |ui| {
-                for (vidx, _) in self
-                    .waves
-                    .as_ref()
-                    .unwrap()
-                    .displayed_items_order
-                    .iter()
-                    .enumerate()
-                {
+                let waves = self.waves.as_ref().unwrap();
+                for (vidx, id) in waves.displayed_items_order.iter().enumerate() {
                     let vidx = vidx.into();
-                    ui.scope(|ui| {
+                    let label = ui.scope(|ui| {
                         ui.style_mut().visuals.selection.bg_fill =
                             self.config.theme.accent_warn.background;
                         ui.style_mut().visuals.override_text_color =
                             Some(self.config.theme.accent_warn.foreground);
-                        let _ = ui.selectable_label(true, self.get_alpha_focus_id(vidx));
+                        ui.selectable_label(true, self.get_alpha_focus_id(vidx))
                     });
+                    if let Some(displayed_item) = waves.displayed_items.get(id) {
+                        scale_item_rect(ui, displayed_item, label.inner.rect);
+                    }
                 }
             },
         );
@@ -958,7 +1010,24 @@ impl State {
                             &mut item_offsets,
                             ui,
                         ),
+                        DisplayedItem::Derived(_) => self.draw_plain_item(
+                            msgs,
+                            vidx,
+                            *displayed_item_id,
+                            displayed_item,
+                            &mut item_offsets,
+                            ui,
+                        ),
+                        DisplayedItem::Decoder(_) => self.draw_plain_item(
+                            msgs,
+                            vidx,
+                            *displayed_item_id,
+                            displayed_item,
+                            &mut item_offsets,
+                            ui,
+                        ),
                     };
+                    self.draw_resize_handle(msgs, vidx, displayed_item, item_rect, ui);
                     self.draw_drag_target(
                         msgs,
                         vidx,
@@ -1403,14 +1472,57 @@ impl State {
             | VariableInfo::Real => {
                 let label = draw_label(ui);
                 self.draw_drag_source(msgs, vidx, &label);
+                let rect = scale_item_rect(ui, displayed_item, label.rect);
                 drawing_infos.push(ItemDrawingInfo::Variable(VariableDrawingInfo {
                     displayed_field_ref,
                     field_ref: field.clone(),
                     item_list_idx: vidx,
-                    top: label.rect.top(),
-                    bottom: label.rect.bottom(),
+                    top: rect.top(),
+                    bottom: rect.bottom(),
                 }));
-                label.rect
+                rect
+            }
+        }
+    }
+
+    /// Lets the user change the height of an item by dragging its bottom border
+    fn draw_resize_handle(
+        &self,
+        msgs: &mut Vec<Message>,
+        vidx: DisplayedItemIndex,
+        displayed_item: &DisplayedItem,
+        item_rect: Rect,
+        ui: &mut egui::Ui,
+    ) {
+        // The rect of a compound variable is the one of its header, which is not scaled
+        if let DisplayedItem::Variable(variable) = displayed_item {
+            if matches!(variable.info, VariableInfo::Compound { .. }) {
+                return;
+            }
+        }
+        let half_width = 2.;
+        let handle_rect = Rect::from_min_max(
+            Pos2::new(item_rect.left(), item_rect.bottom() - half_width),
+            Pos2::new(item_rect.right(), item_rect.bottom() + half_width),
+        );
+        let response = ui.interact(
+            handle_rect,
+            egui::Id::new(("item resize handle", vidx)),
+            Sense::drag(),
+        );
+        if response.hovered() || response.dragged() {
+            ui.ctx().set_cursor_icon(CursorIcon::ResizeVertical);
+        }
+        if response.drag_started_by(egui::PointerButton::Primary) {
+            msgs.push(Message::ItemHeightChangeStarted);
+        }
+        if response.dragged_by(egui::PointerButton::Primary) {
+            if let Some(pointer) = response.interact_pointer_pos() {
+                let unscaled_height = item_rect.height() / displayed_item.height_scaling_factor();
+                msgs.push(Message::ItemHeightScalingFactorChange(
+                    Some(vidx),
+                    (pointer.y - item_rect.top()) / unscaled_height,
+                ));
             }
         }
     }
@@ -1546,41 +1658,57 @@ impl State {
 
         let label = draw_label(ui);
         self.draw_drag_source(msgs, vidx, &label);
+        let rect = scale_item_rect(ui, displayed_item, label.rect);
         match displayed_item {
             DisplayedItem::Divider(_) => {
                 drawing_infos.push(ItemDrawingInfo::Divider(DividerDrawingInfo {
                     item_list_idx: vidx,
-                    top: label.rect.top(),
-                    bottom: label.rect.bottom(),
+                    top: rect.top(),
+                    bottom: rect.bottom(),
                 }));
             }
             DisplayedItem::Marker(cursor) => {
                 drawing_infos.push(ItemDrawingInfo::Marker(MarkerDrawingInfo {
                     item_list_idx: vidx,
-                    top: label.rect.top(),
-                    bottom: label.rect.bottom(),
+                    top: rect.top(),
+                    bottom: rect.bottom(),
                     idx: cursor.idx,
                 }));
             }
             DisplayedItem::TimeLine(_) => {
                 drawing_infos.push(ItemDrawingInfo::TimeLine(TimeLineDrawingInfo {
                     item_list_idx: vidx,
-                    top: label.rect.top(),
-                    bottom: label.rect.bottom(),
+                    top: rect.top(),
+                    bottom: rect.bottom(),
                 }));
             }
             DisplayedItem::Stream(stream) => {
                 drawing_infos.push(ItemDrawingInfo::Stream(StreamDrawingInfo {
                     transaction_stream_ref: stream.transaction_stream_ref.clone(),
                     item_list_idx: vidx,
-                    top: label.rect.top(),
-                    bottom: label.rect.bottom(),
+                    top: rect.top(),
+                    bottom: rect.bottom(),
+                }));
+            }
+            DisplayedItem::Derived(_) => {
+                drawing_infos.push(ItemDrawingInfo::Derived(DerivedDrawingInfo {
+                    displayed_field_ref: displayed_id.into(),
+                    item_list_idx: vidx,
+                    top: rect.top(),
+                    bottom: rect.bottom(),
+                }));
+            }
+            DisplayedItem::Decoder(_) => {
+                drawing_infos.push(ItemDrawingInfo::Decoder(DecoderDrawingInfo {
+                    item_list_idx: vidx,
+                    top: rect.top(),
+                    bottom: rect.bottom(),
                 }));
             }
             &DisplayedItem::Variable(_) => {}
             &DisplayedItem::Placeholder(_) => {}
         }
-        label.rect
+        rect
     }
 
     fn get_alpha_focus_id(&self, vidx: DisplayedItemIndex) -> RichText {
@@ -1680,11 +1808,19 @@ impl State {
                             continue;
                         }
 
-                        let v = self.get_variable_value(
-                            waves,
-                            &drawing_info.displayed_field_ref,
-                            &ucursor,
-                        );
+                        let v = match &waves.delta_expansion {
+                            Some(expansion) if ucursor.as_ref() == Some(&expansion.time) => self
+                                .delta_values(waves, &drawing_info.displayed_field_ref)
+                                .get(expansion.cursor)
+                                .cloned()
+                                .flatten()
+                                .map(|value| value.value),
+                            _ => self.get_variable_value(
+                                waves,
+                                &drawing_info.displayed_field_ref,
+                                &ucursor,
+                            ),
+                        };
                         if let Some(v) = v {
                             ui.label(
                                 RichText::new(v)
@@ -1708,9 +1844,9 @@ impl State {
                     }
                     ItemDrawingInfo::Marker(numbered_cursor) => {
                         if let Some(cursor) = &waves.cursor {
-                            let delta = time_string(
-                                &(waves.numbered_marker_time(numbered_cursor.idx) - cursor),
-                                &waves.inner.metadata().timescale,
+                            let delta = waves.time_delta_string(
+                                cursor,
+                                waves.numbered_marker_time(numbered_cursor.idx),
                                 &self.wanted_timeunit,
                                 &self.get_time_format(),
                             );
@@ -1732,6 +1868,49 @@ impl State {
                     ItemDrawingInfo::Stream(_) => {
                         ui.label("");
                     }
+                    ItemDrawingInfo::Decoder(drawing_info) => {
+                        let transaction = waves
+                            .displayed_items_order
+                            .get(drawing_info.item_list_idx.0)
+                            .and_then(|id| waves.displayed_items.get(id))
+                            .zip(ucursor.as_ref())
+                            .and_then(|(item, cursor)| match item {
+                                DisplayedItem::Decoder(decoder) => decoder.transaction_at(cursor),
+                                _ => None,
+                            });
+                        match transaction {
+                            Some(transaction) => {
+                                ui.label(RichText::new(&transaction.label).color(
+                                    *self.config.theme.get_best_text_color(backgroundcolor),
+                                ))
+                                .context_menu(|ui| {
+                                    self.item_context_menu(None, msgs, ui, vidx);
+                                });
+                            }
+                            None => {
+                                ui.label("");
+                            }
+                        }
+                    }
+                    ItemDrawingInfo::Derived(drawing_info) => {
+                        match self.get_variable_value(
+                            waves,
+                            &drawing_info.displayed_field_ref,
+                            &ucursor,
+                        ) {
+                            Some(v) => {
+                                ui.label(RichText::new(v).color(
+                                    *self.config.theme.get_best_text_color(backgroundcolor),
+                                ))
+                                .context_menu(|ui| {
+                                    self.item_context_menu(None, msgs, ui, vidx);
+                                });
+                            }
+                            None => {
+                                ui.label("");
+                            }
+                        }
+                    }
                 }
             }
         });
@@ -1743,53 +1922,36 @@ impl State {
         displayed_field_ref: &DisplayedFieldRef,
         ucursor: &Option<num::BigUint>,
     ) -> Option<String> {
-        if let Some(ucursor) = ucursor {
-            let Some(DisplayedItem::Variable(displayed_variable)) =
-                waves.displayed_items.get(&displayed_field_ref.item)
-            else {
-                return None;
-            };
-            let variable = &displayed_variable.variable_ref;
-            let translator = waves
-                .variable_translator(&displayed_field_ref.without_field(), &self.sys.translators);
-            let meta = waves.inner.as_waves().unwrap().variable_meta(variable);
-
-            let translation_result = waves
-                .inner
-                .as_waves()
-                .unwrap()
-                .query_variable(variable, ucursor)
-                .ok()
-                .flatten()
-                .and_then(|q| q.current)
-                .map(|(_time, value)| meta.and_then(|meta| translator.translate(&meta, &value)));
-
-            if let Some(Ok(s)) = translation_result {
-                let fields = s.format_flat(
-                    &displayed_variable.format,
-                    &displayed_variable.field_formats,
-                    &self.sys.translators,
-                );
-
-                let subfield = fields
-                    .iter()
-                    .find(|res| res.names == displayed_field_ref.field);
+        let ucursor = ucursor.as_ref()?;
+        let signal = Signal::from_item(waves.displayed_items.get(&displayed_field_ref.item)?)?;
+        let (_time, value) = signal
+            .query(waves.inner.as_waves().unwrap(), ucursor)
+            .ok()
+            .flatten()
+            .and_then(|q| q.current)?;
+        self.translate_field_value(waves, displayed_field_ref, &value)
+            .map(|value| value.map_or_else(|| "-".to_string(), |value| value.value))
+    }
 
-                if let Some(SubFieldFlatTranslationResult {
-                    names: _,
-                    value: Some(TranslatedValue { value: v, kind: _ }),
-                }) = subfield
-                {
-                    Some(v.clone())
-                } else {
-                    Some("-".to_string())
-                }
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+    /// Translates a value of the variable in `displayed_field_ref` and returns the part shown
+    /// for the field. Returns `None` if the value could not be translated and `Some(None)` if
+    /// the field has no value.
+    pub fn translate_field_value(
+        &self,
+        waves: &WaveData,
+        displayed_field_ref: &DisplayedFieldRef,
+        value: &VariableValue,
+    ) -> Option<Option<TranslatedValue>> {
+        let signal = Signal::from_item(waves.displayed_items.get(&displayed_field_ref.item)?)?;
+        let translator =
+            waves.variable_translator(&displayed_field_ref.without_field(), &self.sys.translators);
+        signal.translate_field_value(
+            waves.inner.as_waves()?,
+            &displayed_field_ref.field,
+            translator,
+            &self.sys.translators,
+            value,
+        )
     }
 
     pub fn draw_background(
@@ -1834,6 +1996,17 @@ impl State {
     }
 }
 
+/// Adds space below an item label drawn at `rect` according to the height scaling factor
+/// of the item and returns the rect of the whole row
+fn scale_item_rect(ui: &mut egui::Ui, displayed_item: &DisplayedItem, rect: Rect) -> Rect {
+    let extra_height = (displayed_item.height_scaling_factor() - 1.) * rect.height();
+    if extra_height <= 0. {
+        return rect;
+    }
+    ui.add_space(extra_height);
+    Rect::from_min_max(rect.min, Pos2::new(rect.max.x, rect.max.y + extra_height))
+}
+
 fn variable_tooltip_text(meta: &Option<VariableMeta>, variable: &VariableRef) -> String {
     format!(
         "{}\nNum bits: {}\nType: {}\nDirection: {}",
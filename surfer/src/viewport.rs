@@ -265,6 +265,36 @@ impl Viewport {
         }
     }
 
+    /// Keeps the viewport at the same times when the waves grow from `old_num_timestamps` to
+    /// `new_num_timestamps`, or moves it to the new end if `pin_to_end` is set
+    pub fn extend_to(
+        &self,
+        old_num_timestamps: &BigInt,
+        new_num_timestamps: &BigInt,
+        pin_to_end: bool,
+    ) -> Viewport {
+        let resize_factor =
+            (Absolute::from(old_num_timestamps) / Absolute::from(new_num_timestamps)).0;
+        let (left, right) = if pin_to_end {
+            (Relative(1.0) - self.width() * resize_factor, Relative(1.0))
+        } else {
+            (
+                self.curr_left * resize_factor,
+                self.curr_right * resize_factor,
+            )
+        };
+        Viewport {
+            curr_left: left,
+            curr_right: right,
+            target_left: left,
+            target_right: right,
+            move_start_left: left,
+            move_start_right: right,
+            move_duration: None,
+            move_strategy: self.move_strategy,
+        }
+    }
+
     #[inline]
     fn width(&self) -> Relative {
         self.curr_right - self.curr_left
@@ -448,3 +478,56 @@ pub enum ViewportStrategy {
     Instant,
     EaseInOut { duration: f32 },
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The times at the edges of `viewport` with `num_timestamps` time steps
+    fn edge_times(viewport: &Viewport, num_timestamps: u32) -> (f64, f64) {
+        let num_timestamps = BigInt::from(num_timestamps);
+        (
+            viewport.curr_left.absolute(&num_timestamps).0,
+            viewport.curr_right.absolute(&num_timestamps).0,
+        )
+    }
+
+    fn assert_edge_times(viewport: &Viewport, num_timestamps: u32, expected: (f64, f64)) {
+        let (left, right) = edge_times(viewport, num_timestamps);
+        assert!(
+            (left - expected.0).abs() < 1e-6 && (right - expected.1).abs() < 1e-6,
+            "Expected edges at {expected:?}, got ({left}, {right})"
+        );
+    }
+
+    fn showing_20_to_40_of_100() -> Viewport {
+        Viewport {
+            curr_left: Relative(0.2),
+            curr_right: Relative(0.4),
+            target_left: Relative(0.2),
+            target_right: Relative(0.4),
+            ..Viewport::default()
+        }
+    }
+
+    #[test]
+    fn extended_viewport_keeps_its_times() {
+        let viewport =
+            showing_20_to_40_of_100().extend_to(&BigInt::from(100), &BigInt::from(200), false);
+        assert_edge_times(&viewport, 200, (20., 40.));
+        assert!(!viewport.is_moving());
+        assert_eq!(viewport.target_left, viewport.curr_left);
+        assert_eq!(viewport.target_right, viewport.curr_right);
+    }
+
+    #[test]
+    fn pinned_viewport_moves_to_the_new_end() {
+        let viewport =
+            showing_20_to_40_of_100().extend_to(&BigInt::from(100), &BigInt::from(200), true);
+        // the width in time is kept
+        assert_edge_times(&viewport, 200, (180., 200.));
+        assert!(!viewport.is_moving());
+        assert_eq!(viewport.target_left, viewport.curr_left);
+        assert_eq!(viewport.target_right, viewport.curr_right);
+    }
+}
@@ -10,6 +10,7 @@ use surfer_translation_types::{VariableType, VariableValue};
 #[cfg(not(target_arch = "wasm32"))]
 use crate::cxxrtl_container::CxxrtlContainer;
 use crate::message::BodyResult;
+use crate::multi_wave_container::MultiWaveContainer;
 use crate::time::{TimeScale, TimeUnit};
 use crate::wellen::{var_to_meta, LoadSignalsCmd, LoadSignalsResult, WellenContainer};
 
@@ -208,6 +209,8 @@ pub enum WaveContainer {
     Empty,
     #[cfg(not(target_arch = "wasm32"))]
     Cxxrtl(Mutex<CxxrtlContainer>),
+    /// Several files opened side by side
+    Multi(Box<MultiWaveContainer>),
 }
 
 impl WaveContainer {
@@ -232,6 +235,7 @@ impl WaveContainer {
     pub fn wants_anti_aliasing(&self) -> bool {
         match self {
             WaveContainer::Wellen(_) => true,
+            WaveContainer::Multi(m) => m.wants_anti_aliasing(),
             WaveContainer::Empty => true,
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(_) => false,
@@ -244,6 +248,7 @@ impl WaveContainer {
     pub fn is_fully_loaded(&self) -> bool {
         match self {
             WaveContainer::Wellen(f) => f.is_fully_loaded(),
+            WaveContainer::Multi(m) => m.is_fully_loaded(),
             WaveContainer::Empty => true,
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(_) => true,
@@ -254,6 +259,7 @@ impl WaveContainer {
     pub fn variable_names(&self) -> Vec<String> {
         match self {
             WaveContainer::Wellen(f) => f.variable_names(),
+            WaveContainer::Multi(m) => m.variable_names(),
             WaveContainer::Empty => vec![],
             // I don't know if we can do
             #[cfg(not(target_arch = "wasm32"))]
@@ -264,6 +270,7 @@ impl WaveContainer {
     pub fn variables(&self) -> Vec<VariableRef> {
         match self {
             WaveContainer::Wellen(f) => f.variables(),
+            WaveContainer::Multi(m) => m.variables(),
             WaveContainer::Empty => vec![],
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(_) => vec![],
@@ -273,6 +280,7 @@ impl WaveContainer {
     pub fn variables_in_scope(&self, scope: &ScopeRef) -> Vec<VariableRef> {
         match self {
             WaveContainer::Wellen(f) => f.variables_in_scope(scope),
+            WaveContainer::Multi(m) => m.variables_in_scope(scope),
             WaveContainer::Empty => vec![],
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => c.lock().unwrap().variables_in_module(scope),
@@ -282,6 +290,7 @@ impl WaveContainer {
     pub fn no_variables_in_scope(&self, scope: &ScopeRef) -> bool {
         match self {
             WaveContainer::Wellen(f) => f.no_variables_in_scope(scope),
+            WaveContainer::Multi(m) => m.no_variables_in_scope(scope),
             WaveContainer::Empty => true,
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => c.lock().unwrap().no_variables_in_module(scope),
@@ -295,6 +304,7 @@ impl WaveContainer {
     ) -> Result<Option<LoadSignalsCmd>> {
         match self {
             WaveContainer::Wellen(f) => f.load_variables(variables),
+            WaveContainer::Multi(m) => m.load_variables(variables),
             WaveContainer::Empty => bail!("Cannot load variables from empty container."),
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => {
@@ -341,6 +351,7 @@ impl WaveContainer {
     pub fn on_signals_loaded(&mut self, res: LoadSignalsResult) -> Result<Option<LoadSignalsCmd>> {
         match self {
             WaveContainer::Wellen(f) => f.on_signals_loaded(res),
+            WaveContainer::Multi(m) => m.on_signals_loaded(res),
             WaveContainer::Empty => {
                 bail!("on_load_signals should only be called with the wellen backend.")
             }
@@ -357,6 +368,7 @@ impl WaveContainer {
                 let var = f.get_var(r)?;
                 Ok(var_to_meta(var, f.get_enum_map(var), r))
             }
+            WaveContainer::Multi(m) => m.variable_meta(r),
             WaveContainer::Empty => bail!("Getting meta from empty wave container"),
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => c.lock().unwrap().variable_meta(r),
@@ -373,16 +385,49 @@ impl WaveContainer {
     ) -> Result<Option<QueryResult>> {
         match self {
             WaveContainer::Wellen(f) => f.query_variable(variable, time),
+            WaveContainer::Multi(m) => m.query_variable(variable, time),
             WaveContainer::Empty => bail!("Querying variable from empty wave container"),
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => Ok(c.lock().unwrap().query_variable(variable, time)),
         }
     }
 
+    /// True if the values of the variable have been loaded
+    pub fn is_loaded(&self, variable: &VariableRef) -> bool {
+        self.query_variable(variable, &BigUint::default())
+            .ok()
+            .flatten()
+            .is_some()
+    }
+
+    /// Values of the variable in the delta cycles at `time`. Formats which do not record
+    /// delta cycles return the single value at `time`.
+    pub fn query_variable_deltas(
+        &self,
+        variable: &VariableRef,
+        time: &BigUint,
+    ) -> Result<Vec<VariableValue>> {
+        match self {
+            WaveContainer::Wellen(f) => f.query_variable_deltas(variable, time),
+            WaveContainer::Multi(m) => m.query_variable_deltas(variable, time),
+            WaveContainer::Empty => bail!("Querying variable from empty wave container"),
+            #[cfg(not(target_arch = "wasm32"))]
+            WaveContainer::Cxxrtl(c) => Ok(c
+                .lock()
+                .unwrap()
+                .query_variable(variable, time)
+                .and_then(|result| result.current)
+                .map(|(_, value)| value)
+                .into_iter()
+                .collect()),
+        }
+    }
+
     /// Looks up the variable _by name_ and returns a new reference with an updated `id` if the variable is found.
     pub fn update_variable_ref(&self, variable: &VariableRef) -> Option<VariableRef> {
         match self {
             WaveContainer::Wellen(f) => f.update_variable_ref(variable),
+            WaveContainer::Multi(m) => m.update_variable_ref(variable),
             WaveContainer::Empty => None,
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(_) => None,
@@ -393,6 +438,7 @@ impl WaveContainer {
     pub fn scope_names(&self) -> Vec<String> {
         match self {
             WaveContainer::Wellen(f) => f.scope_names(),
+            WaveContainer::Multi(m) => m.scope_names(),
             WaveContainer::Empty => vec![],
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => c
@@ -408,6 +454,7 @@ impl WaveContainer {
     pub fn metadata(&self) -> MetaData {
         match self {
             WaveContainer::Wellen(f) => f.metadata(),
+            WaveContainer::Multi(m) => m.metadata(),
             WaveContainer::Empty => MetaData {
                 date: None,
                 version: None,
@@ -434,6 +481,7 @@ impl WaveContainer {
     pub fn root_scopes(&self) -> Vec<ScopeRef> {
         match self {
             WaveContainer::Wellen(f) => f.root_scopes(),
+            WaveContainer::Multi(m) => m.root_scopes(),
             WaveContainer::Empty => vec![],
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => c.lock().unwrap().root_modules(),
@@ -443,15 +491,42 @@ impl WaveContainer {
     pub fn child_scopes(&self, scope: &ScopeRef) -> Result<Vec<ScopeRef>> {
         match self {
             WaveContainer::Wellen(f) => f.child_scopes(scope),
+            WaveContainer::Multi(m) => m.child_scopes(scope),
             WaveContainer::Empty => bail!("Getting child modules from empty wave container"),
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => Ok(c.lock().unwrap().child_scopes(scope)),
         }
     }
 
+    /// Indices into the list of timestamps at which the variable changes. `None` if the
+    /// variable has not been loaded, or if the wave container does not keep a list of
+    /// timestamps.
+    pub fn change_time_indices(&self, variable: &VariableRef) -> Option<Vec<u32>> {
+        match self {
+            WaveContainer::Wellen(f) => f.change_time_indices(variable).ok().flatten(),
+            WaveContainer::Multi(m) => m.change_time_indices(variable),
+            WaveContainer::Empty => None,
+            #[cfg(not(target_arch = "wasm32"))]
+            WaveContainer::Cxxrtl(_) => None,
+        }
+    }
+
+    /// Index of the first timestamp at or after `time` in the list of timestamps that
+    /// [`Self::change_time_indices`] indexes into
+    pub fn time_table_position(&self, time: &BigUint) -> Option<usize> {
+        match self {
+            WaveContainer::Wellen(f) => Some(f.time_table_position(time)),
+            WaveContainer::Multi(m) => m.time_table_position(time),
+            WaveContainer::Empty => None,
+            #[cfg(not(target_arch = "wasm32"))]
+            WaveContainer::Cxxrtl(_) => None,
+        }
+    }
+
     pub fn max_timestamp(&self) -> Option<BigUint> {
         match self {
             WaveContainer::Wellen(f) => f.max_timestamp(),
+            WaveContainer::Multi(m) => m.max_timestamp(),
             WaveContainer::Empty => None,
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => c
@@ -465,6 +540,7 @@ impl WaveContainer {
     pub fn scope_exists(&self, scope: &ScopeRef) -> bool {
         match self {
             WaveContainer::Wellen(f) => f.scope_exists(scope),
+            WaveContainer::Multi(m) => m.scope_exists(scope),
             WaveContainer::Empty => false,
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => c.lock().unwrap().module_exists(scope),
@@ -476,6 +552,7 @@ impl WaveContainer {
     pub fn get_scope_tooltip_data(&self, scope: &ScopeRef) -> String {
         match self {
             WaveContainer::Wellen(f) => f.get_scope_tooltip_data(scope),
+            WaveContainer::Multi(m) => m.get_scope_tooltip_data(scope),
             WaveContainer::Empty => String::new(),
             // FIXME: Tooltip
             #[cfg(not(target_arch = "wasm32"))]
@@ -489,6 +566,7 @@ impl WaveContainer {
     pub fn simulation_status(&self) -> Option<SimulationStatus> {
         match self {
             WaveContainer::Wellen(_) => None,
+            WaveContainer::Multi(m) => m.simulation_status(),
             WaveContainer::Empty => None,
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => c.lock().unwrap().simulation_status(),
@@ -500,6 +578,7 @@ impl WaveContainer {
     pub fn unpause_simulation(&self) {
         match self {
             WaveContainer::Wellen(_) => {}
+            WaveContainer::Multi(m) => m.unpause_simulation(),
             WaveContainer::Empty => {}
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => c.lock().unwrap().unpause(),
@@ -510,6 +589,7 @@ impl WaveContainer {
     pub fn pause_simulation(&self) {
         match self {
             WaveContainer::Wellen(_) => {}
+            WaveContainer::Multi(m) => m.pause_simulation(),
             WaveContainer::Empty => {}
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(c) => c.lock().unwrap().pause(),
@@ -526,12 +606,33 @@ impl WaveContainer {
         }
     }
 
+    /// The container of a file loaded by `wellen`
+    pub fn as_wellen_mut(&mut self) -> Option<&mut WellenContainer> {
+        match self {
+            WaveContainer::Wellen(inner) => Some(inner),
+            _ => None,
+        }
+    }
+
     pub fn body_loaded(&self) -> bool {
         match self {
             WaveContainer::Wellen(inner) => inner.body_loaded(),
+            WaveContainer::Multi(m) => m.body_loaded(),
             WaveContainer::Empty => true,
             #[cfg(not(target_arch = "wasm32"))]
             WaveContainer::Cxxrtl(_) => true,
         }
     }
+    /// A copy of the loaded values that can be queried on another thread, see
+    /// [`WellenContainer::snapshot`]. `None` for a running simulation, whose values are
+    /// only available through the connection to it.
+    pub fn snapshot(&self) -> Option<WaveContainer> {
+        match self {
+            WaveContainer::Wellen(inner) => Some(WaveContainer::Wellen(Box::new(inner.snapshot()))),
+            WaveContainer::Multi(m) => Some(WaveContainer::Multi(Box::new(m.snapshot()?))),
+            WaveContainer::Empty => Some(WaveContainer::Empty),
+            #[cfg(not(target_arch = "wasm32"))]
+            WaveContainer::Cxxrtl(_) => None,
+        }
+    }
 }
@@ -1,24 +1,32 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use color_eyre::eyre::{Result, WrapErr};
 use log::{error, info, warn};
 use num::bigint::ToBigInt as _;
 use num::{BigInt, BigUint, Zero};
 use serde::{Deserialize, Serialize};
-use surfer_translation_types::{TranslationPreference, Translator, VariableValue};
+use surfer_translation_types::{
+    TranslationPreference, Translator, TranslatorArguments, VariableValue,
+};
 
+use crate::activity::Activity;
+use crate::clock_cycles::{ClockCycles, ClockEdges};
 use crate::data_container::DataContainer;
+use crate::delta_cycles::DeltaExpansion;
 use crate::displayed_item::{
     DisplayedDivider, DisplayedFieldRef, DisplayedItem, DisplayedItemIndex, DisplayedItemRef,
     DisplayedStream, DisplayedTimeLine, DisplayedVariable,
 };
 use crate::graphics::{Graphic, GraphicId};
+use crate::multi_wave_container::AddedFile;
 use crate::transaction_container::{StreamScopeRef, TransactionRef, TransactionStreamRef};
 use crate::translation::{DynTranslator, TranslatorList, VariableInfoExt};
+use crate::value_search::ValueSearch;
 use crate::variable_name_type::VariableNameType;
 use crate::view::ItemDrawingInfo;
 use crate::viewport::Viewport;
 use crate::wave_container::{ScopeRef, VariableMeta, VariableRef, VariableRefExt, WaveContainer};
+use crate::wave_diff::Comparison;
 use crate::wave_source::{WaveFormat, WaveSource};
 use crate::wellen::LoadSignalsCmd;
 use ftr_parser::types::Transaction;
@@ -75,6 +83,33 @@ pub struct WaveData {
     /// used by the `update_viewports` method after loading a new file
     #[serde(skip)]
     pub old_num_timestamps: Option<BigInt>,
+    /// Timestamp currently expanded into its delta cycles
+    #[serde(skip)]
+    pub delta_expansion: Option<DeltaExpansion>,
+    #[serde(skip)]
+    pub value_search: Option<ValueSearch>,
+    /// Clock whose cycles are used as the time axis
+    #[serde(default)]
+    pub cycle_clock: Option<ClockCycles>,
+    /// Rising edges of the clocks that are sampled on
+    #[serde(skip)]
+    pub clock_edges: HashMap<VariableRef, ClockEdges>,
+    /// Incremented when the values may have changed, to find the clock edges and decode the
+    /// transactions again
+    #[serde(skip)]
+    pub values_version: u64,
+    /// Decoders whose transactions are being decoded on a worker thread
+    #[serde(skip)]
+    pub decoding: HashSet<DisplayedItemRef>,
+    /// Value change density shown in the overview
+    #[serde(skip)]
+    pub activity: Option<Activity>,
+    /// Files opened next to the first one
+    #[serde(default)]
+    pub added_files: Vec<AddedFile>,
+    /// Differences to an added file
+    #[serde(skip)]
+    pub comparison: Option<Comparison>,
 }
 
 fn select_preferred_translator(var: &VariableMeta, translators: &TranslatorList) -> String {
@@ -157,6 +192,15 @@ impl WaveData {
         translators: &TranslatorList,
         keep_unavailable: bool,
     ) -> (WaveData, Option<LoadSignalsCmd>) {
+        let old_num_timestamps = Some(self.num_timestamps());
+        // keep the added files when the first file is reloaded or switched
+        let new_waves = match std::mem::replace(&mut self.inner, DataContainer::Empty) {
+            DataContainer::Waves(WaveContainer::Multi(mut multi)) => {
+                multi.primary = *new_waves;
+                Box::new(WaveContainer::Multi(multi))
+            }
+            _ => new_waves,
+        };
         let active_scope = self.active_scope.take().filter(|m| {
             if let ScopeType::WaveScope(w) = m {
                 new_waves.scope_exists(w)
@@ -171,7 +215,12 @@ impl WaveData {
             translators,
         );
 
-        let old_num_timestamps = Some(self.num_timestamps());
+        let cycle_clock = self
+            .cycle_clock
+            .take()
+            .and_then(|cycles| new_waves.update_variable_ref(&cycles.clock))
+            .map(ClockCycles::new);
+
         let mut new_wavedata = WaveData {
             inner: DataContainer::Waves(*new_waves),
             source,
@@ -194,6 +243,17 @@ impl WaveData {
             graphics: HashMap::new(),
             total_height: 0.,
             old_num_timestamps,
+            delta_expansion: None,
+            value_search: self.value_search,
+            cycle_clock,
+            clock_edges: HashMap::new(),
+            // results of the old waves that arrive later are outdated
+            values_version: self.values_version + 1,
+            decoding: HashSet::new(),
+            activity: None,
+            added_files: self.added_files,
+            // the variables have to be paired again since their references may have changed
+            comparison: self.comparison.map(Comparison::reset),
         };
 
         new_wavedata.update_metadata(translators);
@@ -222,7 +282,15 @@ impl WaveData {
             .unwrap_or(0);
 
         self.update_metadata(translators);
-        self.load_waves()
+        let cmd = self.load_waves();
+        self.values_changed();
+        cmd
+    }
+
+    /// The clock edges are found and the transactions are decoded again, once the UI asks
+    /// for them
+    pub fn values_changed(&mut self) {
+        self.values_version += 1;
     }
 
     /// Go through all signals and update the metadata for all signals
@@ -259,10 +327,16 @@ impl WaveData {
     ///
     /// This is needed for wave containers that lazy-load signals.
     fn load_waves(&mut self) -> Option<LoadSignalsCmd> {
-        let variables = self.displayed_items.values().filter_map(|item| match item {
-            DisplayedItem::Variable(r) => Some(&r.variable_ref),
-            _ => None,
-        });
+        let variables = self
+            .displayed_items
+            .values()
+            .flat_map(|item| match item {
+                DisplayedItem::Variable(r) => vec![&r.variable_ref],
+                DisplayedItem::Derived(d) => d.expr.variables(),
+                DisplayedItem::Decoder(d) => d.decoder.variables(),
+                _ => vec![],
+            })
+            .chain(self.cycle_clock.iter().map(|cycles| &cycles.clock));
         self.inner
             .as_waves_mut()
             .unwrap()
@@ -309,6 +383,12 @@ impl WaveData {
                     DisplayedItem::Variable(s) => {
                         s.update(waves, keep_unavailable).map(|r| (*id, r))
                     }
+                    DisplayedItem::Derived(d) => {
+                        d.update(waves, keep_unavailable).map(|r| (*id, r))
+                    }
+                    DisplayedItem::Decoder(d) => {
+                        d.update(waves, keep_unavailable).map(|r| (*id, r))
+                    }
                     DisplayedItem::Placeholder(p) => {
                         match waves.update_variable_ref(&p.variable_ref) {
                             None => {
@@ -360,10 +440,14 @@ impl WaveData {
         field: &DisplayedFieldRef,
         translators: &'a TranslatorList,
     ) -> &'a DynTranslator {
-        let Some(DisplayedItem::Variable(displayed_variable)) =
-            self.displayed_items.get(&field.item)
-        else {
-            panic!("asking for translator for a non DisplayItem::Variable item")
+        let displayed_variable = match self.displayed_items.get(&field.item) {
+            Some(DisplayedItem::Variable(displayed_variable)) => displayed_variable,
+            Some(DisplayedItem::Derived(derived)) => {
+                return variable_translator(derived.format.as_ref(), &[], translators, || {
+                    Ok(derived.meta())
+                })
+            }
+            _ => panic!("asking for translator for a non DisplayItem::Variable item"),
         };
 
         variable_translator(
@@ -423,7 +507,10 @@ impl WaveData {
                 display_name_type: self.default_variable_name_type,
                 manual_name: None,
                 format: None,
+                format_arguments: TranslatorArguments::default(),
                 field_formats: vec![],
+                analog: None,
+                height_scaling_factor: None,
             });
 
             self.insert_item(new_variable, None);
@@ -471,6 +558,7 @@ impl WaveData {
                 color: None,
                 background_color: None,
                 name,
+                height_scaling_factor: None,
             }),
             vidx,
         );
@@ -482,6 +570,7 @@ impl WaveData {
                 color: None,
                 background_color: None,
                 name: None,
+                height_scaling_factor: None,
             }),
             vidx,
         );
@@ -528,6 +617,7 @@ impl WaveData {
             background_color: None,
             manual_name: None,
             rows: last_times_on_row.len(),
+            height_scaling_factor: None,
         });
 
         self.insert_item(new_gen, None);
@@ -584,6 +674,7 @@ impl WaveData {
             background_color: None,
             manual_name: None,
             rows: last_times_on_row.len(),
+            height_scaling_factor: None,
         });
 
         self.insert_item(new_stream, None);
@@ -635,6 +726,23 @@ impl WaveData {
         }
     }
 
+    /// The time to search from when moving the cursor to the next or previous match of
+    /// something. Without a cursor, this is the edge of the visible area like
+    /// `MoveCursorToTransition` does.
+    pub fn search_start(&self, next: bool) -> Option<BigUint> {
+        let num_timestamps = self.num_timestamps();
+        let start = self.cursor.clone().or_else(|| {
+            self.viewports.first().map(|vp| {
+                if next {
+                    vp.left_edge_time(&num_timestamps)
+                } else {
+                    vp.right_edge_time(&num_timestamps)
+                }
+            })
+        });
+        start.and_then(|t| t.to_biguint())
+    }
+
     #[inline]
     pub fn numbered_marker_location(&self, idx: u8, viewport: &Viewport, view_width: f32) -> f32 {
         viewport.pixel_from_time(
@@ -0,0 +1,591 @@
+//! Comparing the waves of two files. The variables of the file that was opened first are
+//! paired with the variables that have the same full path in an added file, optionally after
+//! renaming the leading scopes of the path. The displayed variables are compared with their
+//! counterparts, and the time ranges where the values of a pair differ are marked on the
+//! canvas.
+use std::collections::HashMap;
+
+use color_eyre::eyre::anyhow;
+use color_eyre::Result;
+use egui::{Context, Grid, ScrollArea, Window};
+use log::{error, info, warn};
+use num::{BigInt, BigUint, Num, Zero};
+use serde::{Deserialize, Serialize};
+use surfer_translation_types::VariableValue;
+
+use crate::displayed_item::DisplayedItem;
+use crate::message::Message;
+use crate::multi_wave_container::AddedWaves;
+use crate::wave_container::{QueryResult, VariableRef, VariableRefExt, WaveContainer};
+use crate::wave_data::WaveData;
+use crate::wave_source::WaveSource;
+use crate::wellen::LoadSignalsCmd;
+use crate::State;
+
+/// Replaces the leading scopes `from` by `to` in the full paths of the first file to get the
+/// paths in the file it is compared with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenameRule {
+    pub from: String,
+    pub to: String,
+}
+
+impl RenameRule {
+    /// Parses rules written as `from=to`
+    pub fn parse(rule: &str) -> Result<Self> {
+        let (from, to) = rule
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected a rename rule like 'tb.dut=top', got '{rule}'"))?;
+        if from.is_empty() {
+            return Err(anyhow!("The rename rule '{rule}' has nothing to replace"));
+        }
+        Ok(Self {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+
+    fn apply(rules: &[RenameRule], path: &str) -> String {
+        rules.iter().fold(path.to_string(), |path, rule| {
+            // only whole scopes match, `tb.dut` does not rename `tb.dut2.x`
+            match path.strip_prefix(&rule.from) {
+                Some("") => rule.to.clone(),
+                Some(rest) if rest.starts_with('.') && rule.to.is_empty() => rest[1..].to_string(),
+                Some(rest) if rest.starts_with('.') => format!("{}{rest}", rule.to),
+                _ => path,
+            }
+        })
+    }
+}
+
+/// Splits the arguments of the compare commands into the file and the rename rules
+pub fn parse_compare_arguments(query: &str) -> Option<(String, Vec<RenameRule>)> {
+    let mut words = query.split_whitespace();
+    let file = words.next()?.to_string();
+    let rules = words
+        .map(RenameRule::parse)
+        .collect::<Result<Vec<_>>>()
+        .ok()?;
+    Some((file, rules))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariablePair {
+    /// The variable in the first file
+    pub reference: VariableRef,
+    /// The variable in the compared file
+    pub other: VariableRef,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub pair: VariablePair,
+    /// Start and end of the time ranges in which the values differ, in order
+    pub ranges: Vec<(BigUint, BigUint)>,
+}
+
+impl Mismatch {
+    pub fn first_difference(&self) -> &BigUint {
+        &self.ranges[0].0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    /// Name of the added file that the first file is compared with
+    pub file: String,
+    pub rename_rules: Vec<RenameRule>,
+    /// The pair of each variable of both files that has a counterpart. Found once both files
+    /// have been loaded.
+    pairs: Option<HashMap<VariableRef, VariablePair>>,
+    /// Number of variables in either file without a counterpart in the other
+    unpaired: usize,
+    /// The pairs of the displayed variables, in the order they are displayed
+    compared: Vec<VariablePair>,
+    /// Mismatching pairs of `compared` sorted by the time of their first difference. Found
+    /// once the variables have been loaded.
+    mismatches: Option<Vec<Mismatch>>,
+}
+
+impl Comparison {
+    pub fn new(file: String, rename_rules: Vec<RenameRule>) -> Self {
+        Self {
+            file,
+            rename_rules,
+            pairs: None,
+            unpaired: 0,
+            compared: vec![],
+            mismatches: None,
+        }
+    }
+
+    /// Forgets the pairs, e.g. because the variable references have changed after a reload
+    pub fn reset(self) -> Self {
+        Self::new(self.file, self.rename_rules)
+    }
+
+    /// Forgets the differences, e.g. because the time offset of the file has changed
+    pub fn invalidate(&mut self) {
+        self.mismatches = None;
+    }
+
+    pub fn mismatches(&self) -> Option<&[Mismatch]> {
+        self.mismatches.as_deref()
+    }
+
+    /// Ranges in which `variable` differs from its counterpart
+    pub fn differences(&self, variable: &VariableRef) -> Option<&[(BigUint, BigUint)]> {
+        self.mismatches
+            .as_ref()?
+            .iter()
+            .find(|m| &m.pair.reference == variable || &m.pair.other == variable)
+            .map(|m| m.ranges.as_slice())
+    }
+}
+
+/// Pairs the variables of both files. The pairs are keyed by both of their variables.
+fn pair_variables(
+    primary: &WaveContainer,
+    added: &AddedWaves,
+    rules: &[RenameRule],
+) -> (HashMap<VariableRef, VariablePair>, usize) {
+    let mut others = added
+        .waves
+        .variables()
+        .into_iter()
+        .map(|variable| (variable.full_path_string(), variable))
+        .collect::<HashMap<_, _>>();
+    let references = primary.variables();
+    let num_references = references.len();
+    let pairs = references
+        .into_iter()
+        .filter_map(|reference| {
+            let path = RenameRule::apply(rules, &reference.full_path_string());
+            let other = others.remove(&path)?;
+            Some(VariablePair {
+                reference,
+                other: added.to_session_variable(other),
+            })
+        })
+        .collect::<Vec<_>>();
+    let unpaired = num_references - pairs.len() + others.len();
+    let pairs = pairs
+        .into_iter()
+        .flat_map(|pair| {
+            [
+                (pair.reference.clone(), pair.clone()),
+                (pair.other.clone(), pair),
+            ]
+        })
+        .collect();
+    (pairs, unpaired)
+}
+
+fn values_equal(a: &VariableValue, b: &VariableValue) -> bool {
+    match (a, b) {
+        (VariableValue::BigUint(a), VariableValue::BigUint(b)) => a == b,
+        (VariableValue::String(a), VariableValue::String(b)) => a == b,
+        // values with x or z bits are strings, other values may be numbers in one file and
+        // binary strings in the other
+        (VariableValue::BigUint(n), VariableValue::String(s))
+        | (VariableValue::String(s), VariableValue::BigUint(n)) => {
+            BigUint::from_str_radix(s, 2).is_ok_and(|s| &s == n)
+        }
+    }
+}
+
+/// The time ranges up to `end` in which the values returned by `query_a` and `query_b`
+/// differ. `None` if a query fails.
+fn differing_ranges(
+    query_a: impl Fn(&BigUint) -> Option<QueryResult>,
+    query_b: impl Fn(&BigUint) -> Option<QueryResult>,
+    end: &BigUint,
+) -> Option<Vec<(BigUint, BigUint)>> {
+    let mut ranges = vec![];
+    let mut difference_start: Option<BigUint> = None;
+    let mut time = BigUint::zero();
+    loop {
+        let a = query_a(&time)?;
+        let b = query_b(&time)?;
+        let differ = match (&a.current, &b.current) {
+            (Some((_, a)), Some((_, b))) => !values_equal(a, b),
+            (None, None) => false,
+            _ => true,
+        };
+        match (differ, difference_start.take()) {
+            (true, None) => difference_start = Some(time.clone()),
+            (false, Some(start)) => ranges.push((start, time.clone())),
+            (_, start) => difference_start = start,
+        }
+        let next = [a.next, b.next]
+            .into_iter()
+            .flatten()
+            .filter(|next| next > &time)
+            .min();
+        match next {
+            Some(next) => time = next,
+            None => break,
+        }
+    }
+    if let Some(start) = difference_start {
+        let end = end.max(&(&start + 1u8)).clone();
+        ranges.push((start, end));
+    }
+    Some(ranges)
+}
+
+impl WaveData {
+    /// The pairs of the displayed variables, each pair once. Empty until the variables of
+    /// both files have been paired.
+    fn displayed_pairs(&self) -> Vec<VariablePair> {
+        let Some(pairs) = self
+            .comparison
+            .as_ref()
+            .and_then(|comparison| comparison.pairs.as_ref())
+        else {
+            return vec![];
+        };
+        let mut displayed: Vec<VariablePair> = vec![];
+        for id in &self.displayed_items_order {
+            let Some(DisplayedItem::Variable(variable)) = self.displayed_items.get(id) else {
+                continue;
+            };
+            if let Some(pair) = pairs.get(&variable.variable_ref) {
+                if !displayed.contains(pair) {
+                    displayed.push(pair.clone());
+                }
+            }
+        }
+        displayed
+    }
+
+    /// True if the displayed variables have changed since they were compared
+    pub fn comparison_outdated(&self) -> bool {
+        self.comparison
+            .as_ref()
+            .is_some_and(|comparison| comparison.compared != self.displayed_pairs())
+    }
+
+    /// Pairs the variables of the compared files, loads the displayed variables and their
+    /// counterparts, and finds the differences once they have been loaded. Has to be called
+    /// again as the files and variables are loaded, and when the displayed variables change.
+    pub fn update_comparison(&mut self) -> Option<LoadSignalsCmd> {
+        let comparison = self.comparison.as_mut()?;
+        let Some(WaveContainer::Multi(multi)) = self.inner.as_waves_mut() else {
+            return None;
+        };
+        if comparison.pairs.is_none() {
+            let added = multi
+                .added
+                .iter()
+                .find(|added| added.name == comparison.file)?;
+            if !multi.primary.body_loaded() || !added.waves.body_loaded() {
+                return None;
+            }
+            let (pairs, unpaired) = pair_variables(&multi.primary, added, &comparison.rename_rules);
+            info!(
+                "Paired {} variables with {}, {unpaired} variables are unpaired",
+                pairs.len() / 2,
+                comparison.file
+            );
+            comparison.pairs = Some(pairs);
+            comparison.unpaired = unpaired;
+        }
+
+        let displayed = self.displayed_pairs();
+        let comparison = self.comparison.as_mut()?;
+        let Some(WaveContainer::Multi(multi)) = self.inner.as_waves_mut() else {
+            return None;
+        };
+        if displayed != comparison.compared {
+            comparison.compared = displayed;
+            comparison.mismatches = None;
+            let variables = comparison
+                .compared
+                .iter()
+                .flat_map(|pair| [&pair.reference, &pair.other]);
+            match multi.load_variables(variables) {
+                Ok(Some(cmd)) => return Some(cmd),
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Failed to load the compared variables: {e:#?}");
+                    return None;
+                }
+            }
+        }
+        if comparison.mismatches.is_some() {
+            return None;
+        }
+
+        let end = multi.max_timestamp().unwrap_or_default();
+        let mut mismatches = vec![];
+        for pair in &comparison.compared {
+            // `None` until the variables have been loaded
+            let ranges = differing_ranges(
+                |time| multi.query_variable(&pair.reference, time).ok().flatten(),
+                |time| multi.query_variable(&pair.other, time).ok().flatten(),
+                &end,
+            )?;
+            if !ranges.is_empty() {
+                mismatches.push(Mismatch {
+                    pair: pair.clone(),
+                    ranges,
+                });
+            }
+        }
+        mismatches.sort_by(|a, b| {
+            a.first_difference()
+                .cmp(b.first_difference())
+                .then_with(|| {
+                    a.pair
+                        .reference
+                        .full_path_string()
+                        .cmp(&b.pair.reference.full_path_string())
+                })
+        });
+        comparison.mismatches = Some(mismatches);
+        None
+    }
+
+    /// Ranges in which `variable` differs from the variable it is compared with
+    pub fn differences(&self, variable: &VariableRef) -> Option<&[(BigUint, BigUint)]> {
+        self.comparison.as_ref()?.differences(variable)
+    }
+
+    /// The closest start of a difference strictly after, or strictly before, `time`
+    pub fn find_difference(&self, time: &BigUint, next: bool) -> Option<BigUint> {
+        let starts = self
+            .comparison
+            .as_ref()?
+            .mismatches()?
+            .iter()
+            .flat_map(|mismatch| mismatch.ranges.iter().map(|(start, _)| start));
+        if next {
+            starts.filter(|start| *start > time).min().cloned()
+        } else {
+            starts.filter(|start| *start < time).max().cloned()
+        }
+    }
+}
+
+impl State {
+    /// Compares the first file with the added file `file`
+    pub fn compare(&mut self, file: String, rename_rules: Vec<RenameRule>) {
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        if !waves.added_files.iter().any(|added| added.name == file) {
+            warn!("No added file named {file} to compare with");
+            return;
+        }
+        waves.comparison = Some(Comparison::new(file, rename_rules));
+        self.update_comparison();
+        self.show_differences = true;
+    }
+
+    /// Adds `source` to the session and compares the first file with it
+    pub fn compare_with_file(&mut self, source: WaveSource, rename_rules: Vec<RenameRule>) {
+        if !self
+            .waves
+            .as_ref()
+            .is_some_and(|waves| waves.inner.is_waves())
+        {
+            warn!("Open a file before comparing it with another one");
+            return;
+        }
+        self.add_wave_file(source.clone());
+        let name = self.waves.as_ref().and_then(|waves| {
+            waves
+                .added_files
+                .iter()
+                .find(|added| added.source == source)
+                .map(|added| added.name.clone())
+        });
+        if let Some(name) = name {
+            self.compare(name, rename_rules);
+        }
+    }
+
+    pub fn update_comparison(&mut self) {
+        let Some(waves) = self.waves.as_mut() else {
+            return;
+        };
+        if let Some(cmd) = waves.update_comparison() {
+            self.load_variables(cmd);
+        }
+        self.invalidate_draw_commands();
+    }
+
+    pub fn draw_differences_window(&self, ctx: &Context, msgs: &mut Vec<Message>) {
+        let Some(waves) = &self.waves else { return };
+        let mut open = true;
+        Window::new("Differences")
+            .open(&mut open)
+            .collapsible(true)
+            .resizable(true)
+            .show(ctx, |ui| {
+                let Some(comparison) = &waves.comparison else {
+                    if waves.added_files.is_empty() {
+                        ui.label("Add a file to compare the waves with");
+                    }
+                    for file in &waves.added_files {
+                        if ui.button(format!("Compare with {}", file.name)).clicked() {
+                            msgs.push(Message::Compare {
+                                file: file.name.clone(),
+                                rename_rules: vec![],
+                            });
+                        }
+                    }
+                    return;
+                };
+                let (Some(_), Some(mismatches)) = (&comparison.pairs, comparison.mismatches())
+                else {
+                    ui.label(format!("Comparing with {}...", comparison.file));
+                    return;
+                };
+                if comparison.compared.is_empty() {
+                    ui.label(format!(
+                        "Add variables to compare them with {}",
+                        comparison.file
+                    ));
+                } else {
+                    ui.label(format!(
+                        "{} of {} displayed variables differ from {}",
+                        mismatches.len(),
+                        comparison.compared.len(),
+                        comparison.file,
+                    ));
+                }
+                ui.label(format!(
+                    "{} variables have no counterpart",
+                    comparison.unpaired
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button("Previous").clicked() {
+                        msgs.push(Message::MoveToDifference { next: false });
+                    }
+                    if ui.button("Next").clicked() {
+                        msgs.push(Message::MoveToDifference { next: true });
+                    }
+                    if ui.button("Clear").clicked() {
+                        msgs.push(Message::ClearComparison);
+                    }
+                });
+                ScrollArea::vertical().show(ui, |ui| {
+                    Grid::new("differences")
+                        .num_columns(3)
+                        .striped(true)
+                        .spacing([10., 5.])
+                        .show(ui, |ui| {
+                            for mismatch in mismatches {
+                                let pair = &mismatch.pair;
+                                if ui
+                                    .link(pair.reference.full_path_string())
+                                    .on_hover_text("Add both variables")
+                                    .clicked()
+                                {
+                                    msgs.push(Message::AddVariables(vec![
+                                        pair.reference.clone(),
+                                        pair.other.clone(),
+                                    ]));
+                                }
+                                let time = BigInt::from(mismatch.first_difference().clone());
+                                let time_string = waves.time_string(
+                                    &time,
+                                    &self.wanted_timeunit,
+                                    &self.get_time_format(),
+                                );
+                                if ui
+                                    .link(time_string)
+                                    .on_hover_text("Move the cursor to the first difference")
+                                    .clicked()
+                                {
+                                    msgs.push(Message::CursorSet(time.clone()));
+                                    msgs.push(Message::GoToTime(Some(time), 0));
+                                }
+                                ui.label(format!("{} ranges", mismatch.ranges.len()));
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+        if !open {
+            msgs.push(Message::SetDifferencesVisible(false));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use num::ToPrimitive;
+
+    use super::*;
+
+    /// Queries a variable which takes the values in `changes` at the given times
+    fn query(changes: &[(u32, u32)]) -> impl Fn(&BigUint) -> Option<QueryResult> + '_ {
+        move |time: &BigUint| {
+            let idx = changes.partition_point(|(t, _)| BigUint::from(*t) <= *time);
+            Some(QueryResult {
+                current: idx.checked_sub(1).map(|i| {
+                    let (t, v) = changes[i];
+                    (BigUint::from(t), VariableValue::BigUint(BigUint::from(v)))
+                }),
+                next: changes.get(idx).map(|(t, _)| BigUint::from(*t)),
+            })
+        }
+    }
+
+    fn ranges(a: &[(u32, u32)], b: &[(u32, u32)], end: u32) -> Vec<(u32, u32)> {
+        differing_ranges(query(a), query(b), &BigUint::from(end))
+            .unwrap()
+            .into_iter()
+            .map(|(start, end)| (start.to_u32().unwrap(), end.to_u32().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn equal_waves_have_no_differences() {
+        let wave = [(0, 1), (10, 0), (20, 1)];
+        assert_eq!(ranges(&wave, &wave, 30), vec![]);
+        // changes to the same value do not matter
+        assert_eq!(
+            ranges(&wave, &[(0, 1), (5, 1), (10, 0), (20, 1)], 30),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn differences_span_until_the_values_are_equal_again() {
+        let a = [(0, 1), (10, 0), (20, 1)];
+        let b = [(0, 1), (12, 0), (20, 2), (25, 1)];
+        assert_eq!(ranges(&a, &b, 30), vec![(10, 12), (20, 25)]);
+        // a difference that lasts until the end
+        assert_eq!(ranges(&a, &[(0, 1), (10, 0), (20, 3)], 30), vec![(20, 30)]);
+        // a missing value is different from any value
+        assert_eq!(ranges(&a, &[(5, 1), (10, 0), (20, 1)], 30), vec![(0, 5)]);
+    }
+
+    #[test]
+    fn numbers_equal_binary_strings() {
+        let n = |v: u32| VariableValue::BigUint(BigUint::from(v));
+        let s = |v: &str| VariableValue::String(v.to_string());
+        assert!(values_equal(&n(5), &s("0101")));
+        assert!(!values_equal(&s("01x1"), &n(5)));
+        assert!(values_equal(&s("01x1"), &s("01x1")));
+    }
+
+    #[test]
+    fn rename_rules_are_applied_in_order() {
+        let (file, rules) = parse_compare_arguments("fail tb.dut=top top.core=cpu").unwrap();
+        assert_eq!(file, "fail");
+        assert_eq!(RenameRule::apply(&rules, "tb.dut.core.pc"), "cpu.pc");
+        assert_eq!(RenameRule::apply(&rules, "tb.clk"), "tb.clk");
+        // only whole leading scopes are renamed
+        assert_eq!(RenameRule::apply(&rules, "tb.dut2.x"), "tb.dut2.x");
+        assert_eq!(RenameRule::apply(&rules, "sim.tb.dut.x"), "sim.tb.dut.x");
+        let strip = [RenameRule::parse("tb=").unwrap()];
+        assert_eq!(RenameRule::apply(&strip, "tb.dut.x"), "dut.x");
+        assert!(parse_compare_arguments("fail tb.dut").is_none());
+        assert!(parse_compare_arguments("fail =top").is_none());
+    }
+}
@@ -1,5 +1,5 @@
 use std::fmt::{Display, Formatter};
-use std::io::Cursor;
+use std::io::{Cursor, Read};
 use std::path::PathBuf;
 use std::sync::atomic::AtomicU64;
 use std::sync::mpsc::Sender;
@@ -22,10 +22,13 @@ use web_time::Instant;
 
 use crate::message::{AsyncJob, BodyResult, HeaderResult};
 use crate::transaction_container::TransactionContainer;
+#[cfg(feature = "wasm_plugins")]
+use crate::translation::wasm_translators::WasmTranslator;
 #[cfg(not(target_arch = "wasm32"))]
 use crate::wave_container::WaveContainer;
 use crate::wellen::{LoadSignalPayload, LoadSignalsCmd, LoadSignalsResult};
 use crate::{message::Message, State};
+use surver::compression::{decompress, Compression};
 use surver::{Status, HTTP_SERVER_KEY, HTTP_SERVER_VALUE_SURFER, WELLEN_SURFER_DEFAULT_OPTIONS};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
@@ -148,6 +151,9 @@ impl LoadProgress {
 
 pub enum LoadProgressStatus {
     Downloading(String),
+    /// Decompressing a compressed file, with the number of compressed bytes and how many of
+    /// them have been read
+    Decompressing(WaveSource, u64, Arc<AtomicU64>),
     ReadingHeader(WaveSource),
     ReadingBody(WaveSource, u64, Arc<AtomicU64>),
     LoadingVariables(u64),
@@ -194,6 +200,20 @@ impl State {
         load_options: LoadOptions,
     ) -> Result<()> {
         info!("Loading a waveform file: {filename}");
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(compression) = Compression::of_file(filename.as_std_path()) {
+            let file = std::fs::File::open(&filename)
+                .with_context(|| format!("Failed to open wave file: {filename}"))?;
+            let len = file.metadata().map(|meta| meta.len()).unwrap_or_default();
+            self.load_compressed_wave(
+                WaveSource::File(filename),
+                std::io::BufReader::new(file),
+                len,
+                compression,
+                load_options,
+            );
+            return Ok(());
+        }
         let start = web_time::Instant::now();
         let source = WaveSource::File(filename.clone());
         let source_copy = source.clone();
@@ -204,19 +224,7 @@ impl State {
                 wellen::viewers::read_header(filename.as_str(), &WELLEN_SURFER_DEFAULT_OPTIONS)
                     .map_err(|e| anyhow!("{e:?}"))
                     .with_context(|| format!("Failed to parse wave file: {source}"));
-
-            match header_result {
-                Ok(header) => {
-                    let msg = Message::WaveHeaderLoaded(
-                        start,
-                        source,
-                        load_options,
-                        HeaderResult::Local(Box::new(header)),
-                    );
-                    sender.send(msg).unwrap();
-                }
-                Err(e) => sender.send(Message::Error(e)).unwrap(),
-            }
+            send_header_result(&sender, start, source, load_options, header_result);
         });
 
         self.sys.progress_tracker = Some(LoadProgress::new(LoadProgressStatus::ReadingHeader(
@@ -488,6 +496,11 @@ impl State {
         bytes: Vec<u8>,
         load_options: LoadOptions,
     ) {
+        if let Some(compression) = Compression::detect(&source.to_string(), &bytes) {
+            let len = bytes.len() as u64;
+            self.load_compressed_wave(source, Cursor::new(bytes), len, compression, load_options);
+            return;
+        }
         let start = web_time::Instant::now();
         let sender = self.sys.channels.msg_sender.clone();
         let source_copy = source.clone();
@@ -496,19 +509,7 @@ impl State {
                 wellen::viewers::read_header_from_bytes(bytes, &WELLEN_SURFER_DEFAULT_OPTIONS)
                     .map_err(|e| anyhow!("{e:?}"))
                     .with_context(|| format!("Failed to parse wave file: {source}"));
-
-            match header_result {
-                Ok(header) => {
-                    let msg = Message::WaveHeaderLoaded(
-                        start,
-                        source,
-                        load_options,
-                        HeaderResult::Local(Box::new(header)),
-                    );
-                    sender.send(msg).unwrap();
-                }
-                Err(e) => sender.send(Message::Error(e)).unwrap(),
-            }
+            send_header_result(&sender, start, source, load_options, header_result);
         });
 
         self.sys.progress_tracker = Some(LoadProgress::new(LoadProgressStatus::ReadingHeader(
@@ -516,6 +517,39 @@ impl State {
         )));
     }
 
+    /// Decompresses the waves on a worker thread before reading the header from the
+    /// decompressed bytes. The progress is the number of compressed bytes read out of `len`.
+    fn load_compressed_wave<R: Read + Send + 'static>(
+        &mut self,
+        source: WaveSource,
+        reader: R,
+        len: u64,
+        compression: Compression,
+        load_options: LoadOptions,
+    ) {
+        info!("Decompressing {compression:?} compressed waves from {source}");
+        let start = web_time::Instant::now();
+        let sender = self.sys.channels.msg_sender.clone();
+        let progress = Arc::new(AtomicU64::new(0));
+        let progress_copy = progress.clone();
+        let source_copy = source.clone();
+        perform_work(move || {
+            let header_result = decompress(compression, reader, progress_copy)
+                .and_then(|bytes| {
+                    wellen::viewers::read_header_from_bytes(bytes, &WELLEN_SURFER_DEFAULT_OPTIONS)
+                        .map_err(|e| anyhow!("{e:?}"))
+                })
+                .with_context(|| format!("Failed to parse wave file: {source}"));
+            send_header_result(&sender, start, source, load_options, header_result);
+        });
+
+        self.sys.progress_tracker = Some(LoadProgress::new(LoadProgressStatus::Decompressing(
+            source_copy,
+            len,
+            progress,
+        )));
+    }
+
     fn get_thread_pool() -> Option<rayon::ThreadPool> {
         // try to create a new rayon thread pool so that we do not block drawing functionality
         // which might be blocked by the waveform reader using up all the threads in the global pool
@@ -573,7 +607,10 @@ impl State {
     }
 
     pub fn load_variables(&mut self, cmd: LoadSignalsCmd) {
-        let (signals, from_unique_id, payload) = cmd.destruct();
+        let (signals, from_unique_id, payload, others) = cmd.destruct();
+        for other in others {
+            self.load_variables(other);
+        }
         if signals.is_empty() {
             return;
         }
@@ -704,6 +741,18 @@ impl State {
         );
     }
 
+    /// Opens a file next to the loaded waves, see [`State::add_wave_file`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open_add_file_dialog(&mut self) {
+        self.file_dialog(
+            (
+                "Waveform files (*.vcd, *.fst, *.ghw)".to_string(),
+                vec!["vcd".to_string(), "fst".to_string(), "ghw".to_string()],
+            ),
+            |file| Message::AddFile(Utf8PathBuf::from_path_buf(file).unwrap()),
+        );
+    }
+
     #[cfg(feature = "python")]
     pub fn open_python_file_dialog(&mut self) {
         self.file_dialog(
@@ -712,6 +761,30 @@ impl State {
         );
     }
 
+    #[cfg(feature = "wasm_plugins")]
+    pub fn open_wasm_translator_dialog(&mut self) {
+        fn translator_loaded(wasm: Result<Vec<u8>>) -> Message {
+            match wasm.and_then(|wasm| WasmTranslator::new(&wasm)) {
+                Ok(translator) => Message::TranslatorLoaded(Box::new(translator)),
+                Err(e) => Message::Error(e.wrap_err("Failed to load translator plugin")),
+            }
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let message = |file: PathBuf| translator_loaded(std::fs::read(file).map_err(Into::into));
+
+        #[cfg(target_arch = "wasm32")]
+        let message = |file: Vec<u8>| translator_loaded(Ok(file));
+
+        self.file_dialog(
+            (
+                "WebAssembly translators (*.wasm)".to_string(),
+                vec!["wasm".to_string()],
+            ),
+            message,
+        );
+    }
+
     pub fn load_state_file(&mut self, path: Option<PathBuf>) {
         let sender = self.sys.channels.msg_sender.clone();
 
@@ -794,6 +867,27 @@ impl State {
     }
 }
 
+fn send_header_result(
+    sender: &Sender<Message>,
+    start: Instant,
+    source: WaveSource,
+    load_options: LoadOptions,
+    header_result: Result<wellen::viewers::HeaderResult>,
+) {
+    match header_result {
+        Ok(header) => {
+            let msg = Message::WaveHeaderLoaded(
+                start,
+                source,
+                load_options,
+                HeaderResult::Local(Box::new(header)),
+            );
+            sender.send(msg).unwrap();
+        }
+        Err(e) => sender.send(Message::Error(e)).unwrap(),
+    }
+}
+
 pub fn draw_progress_information(ui: &mut egui::Ui, progress_data: &LoadProgress) {
     match &progress_data.progress {
         LoadProgressStatus::Downloading(url) => {
@@ -802,6 +896,19 @@ pub fn draw_progress_information(ui: &mut egui::Ui, progress_data: &LoadProgress
                 ui.monospace(format!("Downloading {url}"));
             });
         }
+        LoadProgressStatus::Decompressing(source, total, bytes_done) => {
+            let num_bytes = bytes_done.load(std::sync::atomic::Ordering::SeqCst);
+            let progress = num_bytes as f32 / (*total).max(1) as f32;
+            ui.monospace(format!(
+                "Decompressing {source}. {} / {}",
+                bytesize::ByteSize::b(num_bytes),
+                bytesize::ByteSize::b(*total),
+            ));
+            let progress_bar = egui::ProgressBar::new(progress)
+                .show_percentage()
+                .desired_width(300.);
+            ui.add(progress_bar);
+        }
         LoadProgressStatus::ReadingHeader(source) => {
             ui.spinner();
             ui.monospace(format!("Loading variable names from {source}"));
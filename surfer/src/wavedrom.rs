@@ -0,0 +1,300 @@
+//! Exporting the displayed items between two markers as WaveDrom WaveJSON. Every sample
+//! becomes one step of the waves, where the samples are either the rising edges of a clock
+//! or the times at which any of the displayed items changes.
+//!
+//! Single bit variables are drawn as levels, other variables as data with the string of
+//! their translator, and clocks as `p`/`n` periods when sampled on a clock or as `h`/`l`
+//! levels otherwise. Dividers start a group holding the items below them.
+use std::path::PathBuf;
+
+use color_eyre::eyre::anyhow;
+use color_eyre::Result;
+use log::error;
+use num::BigUint;
+use serde_json::{json, Value};
+use surfer_translation_types::{ValueKind, VariableInfo};
+
+use crate::derived::Signal;
+use crate::displayed_item::{DisplayedFieldRef, DisplayedItem, DisplayedItemRef};
+use crate::message::Message;
+use crate::value_export::save_export;
+use crate::wave_container::{VariableRef, VariableRefExt};
+use crate::wave_data::WaveData;
+use crate::State;
+
+/// The value of an item at a sample
+#[derive(Debug, Clone, PartialEq)]
+pub enum WaveSample {
+    /// One of the WaveDrom levels `0`, `1`, `x` or `z`
+    Level(char),
+    /// A clock that is high if `true`
+    Clock(bool),
+    Data(String),
+}
+
+/// Encodes the samples of an item as a WaveDrom wave and the data of its data steps
+pub fn encode_wave(samples: &[WaveSample], clock_sampled: bool) -> (String, Vec<String>) {
+    let mut wave = String::new();
+    let mut data = vec![];
+    let mut previous = None;
+    for sample in samples {
+        if previous == Some(sample) {
+            wave.push('.');
+            continue;
+        }
+        match sample {
+            WaveSample::Level(level) => wave.push(*level),
+            WaveSample::Clock(high) => wave.push(match (clock_sampled, high) {
+                (true, true) => 'p',
+                (true, false) => 'n',
+                (false, true) => 'h',
+                (false, false) => 'l',
+            }),
+            WaveSample::Data(value) => {
+                wave.push('=');
+                data.push(value.clone());
+            }
+        }
+        previous = Some(sample);
+    }
+    (wave, data)
+}
+
+fn wave_entry(name: String, samples: &[WaveSample], clock_sampled: bool) -> Value {
+    let (wave, data) = encode_wave(samples, clock_sampled);
+    if data.is_empty() {
+        json!({ "name": name, "wave": wave })
+    } else {
+        json!({ "name": name, "wave": wave, "data": data })
+    }
+}
+
+/// Splits the arguments of the WaveDrom export command into the file, the indices of the two
+/// markers and the sampling clock, e.g. `timing.json 1 2 clock=tb.clk`
+pub fn parse_wavedrom_arguments(query: &str) -> Option<(PathBuf, (u8, u8), Option<VariableRef>)> {
+    let mut words = query.split_whitespace();
+    let path = PathBuf::from(words.next()?);
+    let markers = (words.next()?.parse().ok()?, words.next()?.parse().ok()?);
+    let clock = match words.next() {
+        Some(word) => Some(VariableRef::from_hierarchy_string(
+            word.strip_prefix("clock=")?,
+        )),
+        None => None,
+    };
+    if words.next().is_some() {
+        return None;
+    }
+    Some((path, markers, clock))
+}
+
+impl WaveData {
+    /// The two markers with the lowest times, which the menu exports between
+    pub fn first_two_markers(&self) -> Option<(u8, u8)> {
+        let mut markers = self.markers.iter().collect::<Vec<_>>();
+        markers.sort_by_key(|(_, time)| *time);
+        match markers.as_slice() {
+            [(first, _), (second, _), ..] => Some((**first, **second)),
+            _ => None,
+        }
+    }
+}
+
+impl State {
+    fn wave_sample(&self, waves: &WaveData, id: DisplayedItemRef, time: &BigUint) -> WaveSample {
+        let missing = WaveSample::Level('x');
+        let (Some(signal), Some(wave_container)) = (
+            waves.displayed_items.get(&id).and_then(Signal::from_item),
+            waves.inner.as_waves(),
+        ) else {
+            return missing;
+        };
+        let Some((_, value)) = signal
+            .query(wave_container, time)
+            .ok()
+            .flatten()
+            .and_then(|result| result.current)
+        else {
+            return missing;
+        };
+        let field_ref = DisplayedFieldRef::from(id);
+        let Some(translated) = self
+            .translate_field_value(waves, &field_ref, &value)
+            .flatten()
+        else {
+            return missing;
+        };
+        let info = signal.meta(wave_container).and_then(|meta| {
+            waves
+                .variable_translator(&field_ref, &self.sys.translators)
+                .variable_info(&meta)
+        });
+
+        match (info, translated.kind) {
+            (_, ValueKind::HighImp) => WaveSample::Level('z'),
+            (_, ValueKind::Undef) => WaveSample::Level('x'),
+            (Ok(VariableInfo::Clock), _) => WaveSample::Clock(translated.value == "1"),
+            (Ok(VariableInfo::Bool), _) => WaveSample::Level(match translated.value.as_str() {
+                "1" | "H" => '1',
+                "0" | "L" => '0',
+                _ => 'x',
+            }),
+            _ => WaveSample::Data(translated.value),
+        }
+    }
+
+    /// The WaveJSON of the displayed items between the markers, sampled at the rising edges
+    /// of a clock given by `clock_edges` or at every change
+    pub fn wavedrom(
+        &self,
+        waves: &WaveData,
+        markers: (u8, u8),
+        clock_edges: Option<&[BigUint]>,
+    ) -> Result<Value> {
+        let marker_time = |idx| {
+            waves
+                .markers
+                .get(&idx)
+                .and_then(|time| time.to_biguint())
+                .ok_or_else(|| anyhow!("No marker {idx}"))
+        };
+        let (start, end) = (marker_time(markers.0)?, marker_time(markers.1)?);
+        let (start, end) = if start <= end {
+            (start, end)
+        } else {
+            (end, start)
+        };
+
+        let times = waves.export_times(clock_edges, &start, &end);
+
+        let mut signal = vec![];
+        let mut group: Option<Vec<Value>> = None;
+        for (id, item) in waves
+            .displayed_items_order
+            .iter()
+            .filter_map(|id| Some((*id, waves.displayed_items.get(id)?)))
+        {
+            match item {
+                DisplayedItem::Divider(_) => {
+                    if let Some(group) = group.take() {
+                        signal.push(Value::Array(group));
+                    }
+                    group = Some(vec![Value::from(item.name())]);
+                }
+                DisplayedItem::Variable(_) | DisplayedItem::Derived(_) => {
+                    let samples = times
+                        .iter()
+                        .map(|time| self.wave_sample(waves, id, time))
+                        .collect::<Vec<_>>();
+                    let entry = wave_entry(item.name(), &samples, clock_edges.is_some());
+                    match &mut group {
+                        Some(group) => group.push(entry),
+                        None => signal.push(entry),
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(group) = group {
+            signal.push(Value::Array(group));
+        }
+        Ok(json!({ "signal": signal }))
+    }
+
+    /// Writes the WaveJSON of the displayed items between the markers to `path`, or a file
+    /// picked by the user. If `clock` has to be loaded or its edges found first, the export
+    /// is retried once they are available.
+    pub fn export_wavedrom(
+        &mut self,
+        path: Option<PathBuf>,
+        markers: (u8, u8),
+        clock: Option<VariableRef>,
+    ) {
+        let clock_edges = match clock {
+            Some(clock) => {
+                let retry = |clock| Message::ExportWaveDrom {
+                    path: path.clone(),
+                    markers,
+                    clock: Some(clock),
+                };
+                let Some(edges) = self.export_clock_edges(&clock, retry) else {
+                    return;
+                };
+                Some(edges)
+            }
+            None => None,
+        };
+        let Some(waves) = &self.waves else {
+            return;
+        };
+        let wavedrom =
+            match self.wavedrom(waves, markers, clock_edges.as_deref().map(Vec::as_slice)) {
+                Ok(wavedrom) => wavedrom,
+                Err(e) => {
+                    error!("Failed to export WaveDrom: {e:#}");
+                    return;
+                }
+            };
+        save_export(
+            path,
+            "Export WaveDrom",
+            &[("WaveJSON files (*.json)", &["json"])],
+            move |_| Ok(serde_json::to_string_pretty(&wavedrom)?),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn repeated_samples_become_dots() {
+        let samples = [
+            WaveSample::Level('0'),
+            WaveSample::Level('1'),
+            WaveSample::Level('1'),
+            WaveSample::Level('z'),
+        ];
+        assert_eq!(encode_wave(&samples, false), ("01.z".to_string(), vec![]));
+    }
+
+    #[test]
+    fn data_is_listed_once_per_change() {
+        let samples = [
+            WaveSample::Data("IDLE".to_string()),
+            WaveSample::Data("IDLE".to_string()),
+            WaveSample::Data("BUSY".to_string()),
+            WaveSample::Level('x'),
+            WaveSample::Data("BUSY".to_string()),
+        ];
+        assert_eq!(
+            encode_wave(&samples, true),
+            (
+                "=.=x=".to_string(),
+                vec!["IDLE".to_string(), "BUSY".to_string(), "BUSY".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn clocks_are_periods_only_when_sampled() {
+        let samples = [
+            WaveSample::Clock(true),
+            WaveSample::Clock(true),
+            WaveSample::Clock(false),
+        ];
+        assert_eq!(encode_wave(&samples, true).0, "p.n");
+        assert_eq!(encode_wave(&samples, false).0, "h.l");
+    }
+
+    #[test]
+    fn wavedrom_arguments_are_parsed() {
+        let (path, markers, clock) =
+            parse_wavedrom_arguments("timing.json 2 1 clock=tb.clk").unwrap();
+        assert_eq!(path, PathBuf::from("timing.json"));
+        assert_eq!(markers, (2, 1));
+        assert_eq!(clock, Some(VariableRef::from_hierarchy_string("tb.clk")));
+        assert!(parse_wavedrom_arguments("timing.json 1").is_none());
+        assert!(parse_wavedrom_arguments("timing.json 1 2 tb.clk").is_none());
+    }
+}
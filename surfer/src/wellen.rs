@@ -29,16 +29,20 @@ pub struct WellenContainer {
     hierarchy: std::sync::Arc<Hierarchy>,
     /// the url of a remote server, None if waveforms are loaded locally
     server: Option<String>,
-    scopes: Vec<String>,
-    vars: Vec<String>,
-    signals: HashMap<SignalRef, Signal>,
+    scopes: std::sync::Arc<Vec<String>>,
+    vars: std::sync::Arc<Vec<String>>,
+    /// shared with the snapshots that are queried on other threads, see [`Self::snapshot`]
+    signals: HashMap<SignalRef, std::sync::Arc<Signal>>,
     /// keeps track of signals that need to be loaded once the body of the waveform file has been loaded
     signals_to_be_loaded: HashSet<SignalRef>,
-    time_table: TimeTable,
+    time_table: std::sync::Arc<TimeTable>,
     #[derivative(Debug = "ignore")]
     source: Option<SignalSource>,
     unique_id: u64,
     body_loaded: bool,
+    /// Value changes appended to the file after its body was loaded, by signal. Only used
+    /// while following a file that a simulator is still writing, see [`crate::vcd_tail`]
+    tail: HashMap<SignalRef, Vec<(TimeTableIdx, VariableValue)>>,
 }
 
 /// Returned by `load_variables` if we want to load the variables on a background thread.
@@ -47,6 +51,9 @@ pub struct LoadSignalsCmd {
     signals: Vec<SignalRef>,
     from_unique_id: u64,
     payload: LoadSignalPayload,
+    /// Commands for other files of a [`crate::multi_wave_container::MultiWaveContainer`]
+    /// that are started together with this one
+    others: Vec<LoadSignalsCmd>,
 }
 
 pub enum LoadSignalPayload {
@@ -55,8 +62,16 @@ pub enum LoadSignalPayload {
 }
 
 impl LoadSignalsCmd {
-    pub fn destruct(self) -> (Vec<SignalRef>, u64, LoadSignalPayload) {
-        (self.signals, self.from_unique_id, self.payload)
+    /// Combines the commands of several files into one
+    pub fn merge(cmds: impl IntoIterator<Item = LoadSignalsCmd>) -> Option<LoadSignalsCmd> {
+        let mut cmds = cmds.into_iter();
+        let mut first = cmds.next()?;
+        first.others.extend(cmds);
+        Some(first)
+    }
+
+    pub fn destruct(self) -> (Vec<SignalRef>, u64, LoadSignalPayload, Vec<LoadSignalsCmd>) {
+        (self.signals, self.from_unique_id, self.payload, self.others)
     }
 }
 
@@ -122,14 +137,15 @@ impl WellenContainer {
         Self {
             hierarchy,
             server,
-            scopes,
-            vars,
+            scopes: std::sync::Arc::new(scopes),
+            vars: std::sync::Arc::new(vars),
             signals: HashMap::new(),
             signals_to_be_loaded: HashSet::new(),
-            time_table: vec![],
+            time_table: std::sync::Arc::default(),
             source: None,
             unique_id,
             body_loaded: false,
+            tail: HashMap::new(),
         }
     }
 
@@ -137,6 +153,66 @@ impl WellenContainer {
         self.body_loaded
     }
 
+    /// A copy of the loaded values that can be queried on another thread. The values are
+    /// shared rather than copied, and nothing can be loaded through the snapshot.
+    pub fn snapshot(&self) -> Self {
+        Self {
+            hierarchy: self.hierarchy.clone(),
+            server: None,
+            scopes: self.scopes.clone(),
+            vars: self.vars.clone(),
+            signals: self.signals.clone(),
+            signals_to_be_loaded: HashSet::new(),
+            time_table: self.time_table.clone(),
+            source: None,
+            unique_id: self.unique_id,
+            body_loaded: self.body_loaded,
+            tail: self.tail.clone(),
+        }
+    }
+
+    pub fn unique_id(&self) -> u64 {
+        self.unique_id
+    }
+
+    pub fn hierarchy(&self) -> &Hierarchy {
+        &self.hierarchy
+    }
+
+    /// Extends the time table and the signals by value changes appended to the file, which
+    /// have to be in time order and not before the last time of the time table. The time table
+    /// is extended to `end_time` even if nothing changes then.
+    pub fn append_changes(
+        &mut self,
+        changes: Vec<(Time, SignalRef, VariableValue)>,
+        end_time: Time,
+    ) {
+        for (time, signal, value) in changes {
+            if self.time_table.last().map_or(true, |last| *last < time) {
+                std::sync::Arc::make_mut(&mut self.time_table).push(time);
+            }
+            let idx = (self.time_table.len() - 1) as TimeTableIdx;
+            let changes = self.tail.entry(signal).or_default();
+            match changes.last_mut() {
+                // only the last value of a time step is kept, like for the loaded signals
+                Some((last_idx, last_value)) if *last_idx == idx => *last_value = value,
+                Some((_, last_value)) if *last_value == value => {}
+                _ => changes.push((idx, value)),
+            }
+        }
+        if self.time_table.last().map_or(true, |last| *last < end_time) {
+            std::sync::Arc::make_mut(&mut self.time_table).push(end_time);
+        }
+    }
+
+    /// The appended value changes of a signal
+    fn tail_changes(&self, signal_ref: &SignalRef) -> &[(TimeTableIdx, VariableValue)] {
+        self.tail
+            .get(signal_ref)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
     pub fn add_body(&mut self, body: BodyResult) -> Result<Option<LoadSignalsCmd>> {
         if self.body_loaded {
             bail!("Did we just parse the body twice? That should not happen!");
@@ -146,7 +222,7 @@ impl WellenContainer {
                 if self.server.is_some() {
                     bail!("We are connected to a server, but also received the result of parsing a file locally. Something is going wrong here!");
                 }
-                self.time_table = body.time_table;
+                self.time_table = std::sync::Arc::new(body.time_table);
                 self.source = Some(body.source);
             }
             BodyResult::Remote(time_table, server) => {
@@ -157,7 +233,7 @@ impl WellenContainer {
                 } else {
                     bail!("Missing server URL!");
                 }
-                self.time_table = time_table;
+                self.time_table = std::sync::Arc::new(time_table);
             }
         }
         self.body_loaded = true;
@@ -192,7 +268,7 @@ impl WellenContainer {
     }
 
     pub fn variable_names(&self) -> Vec<String> {
-        self.vars.clone()
+        self.vars.to_vec()
     }
 
     fn lookup_scope(&self, scope: &ScopeRef) -> Option<wellen::ScopeRef> {
@@ -321,6 +397,11 @@ impl WellenContainer {
         Ok(self.load_signals(&signal_refs))
     }
 
+    /// True if `res` was loaded for this container
+    pub fn requested(&self, res: &LoadSignalsResult) -> bool {
+        res.from_unique_id == self.unique_id
+    }
+
     pub fn on_signals_loaded(&mut self, res: LoadSignalsResult) -> Result<Option<LoadSignalsCmd>> {
         // check to see if this command came from our container, or from a previous file that was open
         if res.from_unique_id == self.unique_id {
@@ -332,7 +413,7 @@ impl WellenContainer {
             debug_assert!(self.server.is_some() || self.source.is_some());
             // install signals
             for (id, signal) in res.signals {
-                self.signals.insert(id, signal);
+                self.signals.insert(id, std::sync::Arc::new(signal));
             }
         }
 
@@ -368,6 +449,7 @@ impl WellenContainer {
                 signals,
                 payload: LoadSignalPayload::Remote(server),
                 from_unique_id: self.unique_id,
+                others: vec![],
             };
             Some(cmd)
         } else if let Some(source) = std::mem::take(&mut self.source) {
@@ -378,6 +460,7 @@ impl WellenContainer {
                 signals,
                 payload: LoadSignalPayload::Local(source, self.hierarchy.clone()),
                 from_unique_id: self.unique_id,
+                others: vec![],
             };
             Some(cmd)
         } else {
@@ -416,9 +499,28 @@ impl WellenContainer {
             }
         };
         let time_table = &self.time_table;
+        let tail = self.tail_changes(&signal_ref);
+        let tail_start = tail
+            .first()
+            .map(|(idx, _)| BigUint::from(time_table[*idx as usize]));
 
         // convert time to index
         if let Some(idx) = self.time_to_time_table_idx(time) {
+            // appended value changes take precedence over the loaded ones
+            let pos = tail.partition_point(|(change_idx, _)| *change_idx <= idx);
+            if pos > 0 {
+                let (change_idx, value) = &tail[pos - 1];
+                let result = QueryResult {
+                    current: Some((
+                        BigUint::from(time_table[*change_idx as usize]),
+                        value.clone(),
+                    )),
+                    next: tail
+                        .get(pos)
+                        .map(|(next_idx, _)| BigUint::from(time_table[*next_idx as usize])),
+                };
+                return Ok(Some(result));
+            }
             // get data offset
             if let Some(offset) = sig.get_offset(idx) {
                 // which time did we actually get the value for?
@@ -434,7 +536,7 @@ impl WellenContainer {
                 let converted_value = convert_variable_value(current_value);
                 let result = QueryResult {
                     current: Some((BigUint::from(offset_time), converted_value)),
-                    next: next_time.map(|t| BigUint::from(*t)),
+                    next: next_time.map(|t| BigUint::from(*t)).or(tail_start),
                 };
                 return Ok(Some(result));
             }
@@ -445,13 +547,72 @@ impl WellenContainer {
         let next_time = first_index.and_then(|i| time_table.get(i as usize));
         let result = QueryResult {
             current: None,
-            next: next_time.map(|t| BigUint::from(*t)),
+            next: next_time.map(|t| BigUint::from(*t)).or(tail_start),
         };
         Ok(Some(result))
     }
 
+    /// All values the variable takes at `time`, in the order of the delta cycles they were
+    /// recorded in. If the variable does not change at `time`, its current value is returned.
+    pub fn query_variable_deltas(
+        &self,
+        variable: &VariableRef,
+        time: &BigUint,
+    ) -> Result<Vec<VariableValue>> {
+        let var_ref = self.get_var_ref(variable)?;
+        let signal_ref = self.hierarchy.get(var_ref).signal_ref();
+        let Some(sig) = self.signals.get(&signal_ref) else {
+            return Ok(vec![]);
+        };
+        let Some(idx) = self.time_to_time_table_idx(time) else {
+            return Ok(vec![]);
+        };
+        // delta cycles of appended value changes are not kept
+        let tail = self.tail_changes(&signal_ref);
+        let pos = tail.partition_point(|(change_idx, _)| *change_idx <= idx);
+        if pos > 0 {
+            return Ok(vec![tail[pos - 1].1.clone()]);
+        }
+        let Some(offset) = sig.get_offset(idx) else {
+            return Ok(vec![]);
+        };
+        let offset_time = self.time_table[sig.get_time_idx_at(&offset) as usize];
+        let elements = if BigUint::from(offset_time) == *time {
+            0..offset.elements
+        } else {
+            offset.elements - 1..offset.elements
+        };
+        Ok(elements
+            .map(|element| convert_variable_value(sig.get_value_at(&offset, element)))
+            .collect())
+    }
+
+    /// Indices into the time table of the times at which the variable changes, or `None` if
+    /// it has not been loaded yet
+    pub fn change_time_indices(&self, variable: &VariableRef) -> Result<Option<Vec<TimeTableIdx>>> {
+        let var_ref = self.get_var_ref(variable)?;
+        let signal_ref = self.hierarchy.get(var_ref).signal_ref();
+        Ok(self.signals.get(&signal_ref).map(|sig| {
+            let mut indices = sig.time_indices().to_vec();
+            let last = indices.last().copied();
+            indices.extend(
+                self.tail_changes(&signal_ref)
+                    .iter()
+                    .map(|(idx, _)| *idx)
+                    .filter(|idx| last.map_or(true, |last| *idx > last)),
+            );
+            indices
+        }))
+    }
+
+    /// Index of the first entry of the time table at or after `time`
+    pub fn time_table_position(&self, time: &BigUint) -> usize {
+        let time: Time = time.to_u64().unwrap_or(Time::MAX);
+        self.time_table.partition_point(|t| *t < time)
+    }
+
     pub fn scope_names(&self) -> Vec<String> {
-        self.scopes.clone()
+        self.scopes.to_vec()
     }
 
     pub fn root_scopes(&self) -> Vec<ScopeRef> {
@@ -578,7 +739,7 @@ pub(crate) fn var_to_meta(
     }
 }
 
-fn index_to_string(index: wellen::VarIndex) -> String {
+pub(crate) fn index_to_string(index: wellen::VarIndex) -> String {
     if index.msb() == index.lsb() {
         format!("[{}]", index.lsb())
     } else {
@@ -651,6 +812,34 @@ fn binary_search(times: &[Time], needle: Time) -> usize {
     lower_idx - 1
 }
 
+/// Reads `vcd` and loads all of its variables
+#[cfg(test)]
+pub fn load_test_vcd(vcd: &str) -> WellenContainer {
+    let header = wellen::viewers::read_header_from_bytes(
+        vcd.as_bytes().to_vec(),
+        &surver::WELLEN_SURFER_DEFAULT_OPTIONS,
+    )
+    .unwrap();
+    let hierarchy = std::sync::Arc::new(header.hierarchy);
+    let body = wellen::viewers::read_body(header.body, &hierarchy, None).unwrap();
+    let mut waves = WellenContainer::new(hierarchy, None);
+    waves.add_body(BodyResult::Local(body)).unwrap();
+    let variables = waves.variables();
+    let (signals, from_unique_id, payload, _) = waves
+        .load_variables(variables.into_iter())
+        .unwrap()
+        .unwrap()
+        .destruct();
+    let LoadSignalPayload::Local(mut source, hierarchy) = payload else {
+        panic!("The file should have been loaded locally");
+    };
+    let loaded = source.load_signals(&signals, &hierarchy, true);
+    waves
+        .on_signals_loaded(LoadSignalsResult::local(source, loaded, from_unique_id))
+        .unwrap();
+    waves
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -661,4 +850,25 @@ mod tests {
         let out0 = convert_variable_value(wellen::SignalValue::Binary(inp0, 32));
         assert_eq!(out0, VariableValue::BigUint(BigUint::from(0x80000003u64)));
     }
+
+    #[test]
+    fn delta_cycles_are_returned_in_order() {
+        let waves = load_test_vcd(
+            "$timescale 1ns $end\n$scope module top $end\n$var wire 4 ! state [3:0] $end\n\
+             $upscope $end\n$enddefinitions $end\n#0\nb0 !\n#10\nb1 !\nb10 !\nb11 !\n#20\nb100 !\n",
+        );
+        let deltas = |time: u32| {
+            waves
+                .query_variable_deltas(
+                    &VariableRef::from_hierarchy_string("top.state"),
+                    &BigUint::from(time),
+                )
+                .unwrap()
+        };
+        let value = |v: u32| VariableValue::BigUint(BigUint::from(v));
+        assert_eq!(deltas(10), vec![value(1), value(2), value(3)]);
+        // between changes, only the final value of the last change is current
+        assert_eq!(deltas(15), vec![value(3)]);
+        assert_eq!(deltas(20), vec![value(4)]);
+    }
 }
@@ -0,0 +1,146 @@
+//! Decompression of compressed wave files. wellen parses uncompressed files only, so
+//! compressed files are decompressed into memory before their header is read.
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use color_eyre::eyre::{anyhow, Context};
+use color_eyre::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Detects the compression from the first bytes of a file
+    pub fn from_magic(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if bytes.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_extension(name: &str) -> Option<Self> {
+        let (_, extension) = name.rsplit_once('.')?;
+        match extension.to_lowercase().as_str() {
+            "gz" | "gzip" => Some(Compression::Gzip),
+            "zst" | "zstd" => Some(Compression::Zstd),
+            "bz2" => Some(Compression::Bzip2),
+            _ => None,
+        }
+    }
+
+    /// Detects the compression from the magic number of `bytes`. The extension of `name` is
+    /// only used if there are too few bytes to tell.
+    pub fn detect(name: &str, bytes: &[u8]) -> Option<Self> {
+        if bytes.len() >= 4 {
+            Self::from_magic(bytes)
+        } else {
+            Self::from_magic(bytes).or_else(|| Self::from_extension(name))
+        }
+    }
+
+    /// Detects the compression of the file at `path` from its first bytes
+    pub fn of_file(path: &Path) -> Option<Self> {
+        let mut magic = Vec::with_capacity(4);
+        std::fs::File::open(path)
+            .and_then(|file| file.take(4).read_to_end(&mut magic))
+            .ok()?;
+        Self::detect(&path.to_string_lossy(), &magic)
+    }
+
+    fn decoder<'a, R: Read + 'a>(self, reader: R) -> Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Compression::Zstd => {
+                Box::new(ruzstd::StreamingDecoder::new(reader).map_err(|e| anyhow!("{e:?}"))?)
+            }
+            Compression::Bzip2 => Box::new(bzip2_rs::DecoderReader::new(reader)),
+        })
+    }
+}
+
+/// Counts the bytes read from the inner reader
+struct ProgressReader<R> {
+    inner: R,
+    progress: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.progress.fetch_add(read as u64, Ordering::SeqCst);
+        Ok(read)
+    }
+}
+
+/// Decompresses all of `reader`. The number of compressed bytes that have been read is
+/// counted in `progress`.
+pub fn decompress<R: Read>(
+    compression: Compression,
+    reader: R,
+    progress: Arc<AtomicU64>,
+) -> Result<Vec<u8>> {
+    let reader = ProgressReader {
+        inner: reader,
+        progress,
+    };
+    let mut bytes = vec![];
+    compression
+        .decoder(reader)?
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to decompress {compression:?} data"))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Write};
+
+    use super::*;
+
+    #[test]
+    fn magic_number_wins_over_extension() {
+        let gzip = [0x1f, 0x8b, 0x08, 0x00];
+        assert_eq!(
+            Compression::detect("waves.vcd", &gzip),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(Compression::detect("waves.vcd.gz", b"$dat"), None);
+        assert_eq!(
+            Compression::detect("waves.vcd.zst", b""),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            Compression::from_magic(b"BZh91AY"),
+            Some(Compression::Bzip2)
+        );
+    }
+
+    #[test]
+    fn gzip_is_decompressed_with_progress() {
+        let vcd = b"$timescale 1ns $end\n$enddefinitions $end\n#0\n".repeat(10);
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&vcd).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let progress = Arc::new(AtomicU64::new(0));
+        let bytes = decompress(
+            Compression::Gzip,
+            Cursor::new(&compressed),
+            progress.clone(),
+        )
+        .unwrap();
+        assert_eq!(bytes, vcd);
+        assert_eq!(progress.load(Ordering::SeqCst), compressed.len() as u64);
+    }
+}
@@ -2,6 +2,7 @@
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 
+pub mod compression;
 #[cfg(not(target_arch = "wasm32"))]
 mod server;
 #[cfg(not(target_arch = "wasm32"))]
@@ -12,12 +12,14 @@ use log::{error, info, warn};
 use rand::distributions::{Alphanumeric, DistString};
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, RwLock};
 use tokio::net::TcpListener;
 use wellen::{viewers, FileFormat, Hierarchy, Signal, SignalRef, Time};
 
+use crate::compression::{decompress, Compression};
 use crate::{
     Status, BINCODE_OPTIONS, HTTP_SERVER_KEY, HTTP_SERVER_VALUE_SURFER, SURFER_VERSION,
     WELLEN_SURFER_DEFAULT_OPTIONS, WELLEN_VERSION, X_SURFER_VERSION, X_WELLEN_VERSION,
@@ -295,10 +297,22 @@ pub async fn server_main(
 
     // load file
     let start_read_header = web_time::Instant::now();
-    let header_result =
-        wellen::viewers::read_header(filename.as_str(), &WELLEN_SURFER_DEFAULT_OPTIONS)
-            .map_err(|e| anyhow!("{e:?}"))
-            .with_context(|| format!("Failed to parse wave file: {filename}"))?;
+    let header_result = match Compression::of_file(Path::new(&filename)) {
+        Some(compression) => {
+            let file = std::fs::File::open(&filename)
+                .with_context(|| format!("Failed to open wave file: {filename}"))?;
+            let bytes = decompress(
+                compression,
+                std::io::BufReader::new(file),
+                Arc::new(AtomicU64::new(0)),
+            )
+            .with_context(|| format!("Failed to decompress wave file: {filename}"))?;
+            wellen::viewers::read_header_from_bytes(bytes, &WELLEN_SURFER_DEFAULT_OPTIONS)
+        }
+        None => wellen::viewers::read_header(filename.as_str(), &WELLEN_SURFER_DEFAULT_OPTIONS),
+    }
+    .map_err(|e| anyhow!("{e:?}"))
+    .with_context(|| format!("Failed to parse wave file: {filename}"))?;
     info!(
         "Loaded header of {filename} in {:?}",
         start_read_header.elapsed()